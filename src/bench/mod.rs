@@ -0,0 +1,273 @@
+pub mod bench {
+    // rusty-redis-benchmark, modelled on redis-benchmark: every workload runs N connections that share
+    // the request budget, each connection sends P commands per round trip over random keys out of K,
+    // the latency of a command is the round trip time of the batch it was sent in
+    use std::{sync::{atomic::{AtomicUsize, Ordering}, Arc}, time::{Duration, Instant, SystemTime, UNIX_EPOCH}};
+    use clap::Parser;
+    use serde::Serialize;
+    use tokio::runtime::Runtime;
+    use crate::client::client::*;
+
+    pub const _WORKLOADS_: [&str; 7] = ["set", "get", "lpush", "lpop", "xadd", "zadd", "geoadd"];
+
+    #[derive(Parser, Debug, Clone)]
+    #[command(name = "rusty-redis-benchmark", disable_help_flag = true)]
+    pub struct BenchArgs {
+        #[arg(short = 'h', long, default_value_t = String::from("127.0.0.1"))]
+        pub host: String,
+
+        #[arg(short, long, default_value_t = 6379)]
+        pub port: u16,
+
+        // number of parallel connections
+        #[arg(short = 'c', long, default_value_t = 50)]
+        pub clients: usize,
+
+        // total number of requests of each workload
+        #[arg(short = 'n', long, default_value_t = 100000)]
+        pub requests: usize,
+
+        // commands sent per round trip
+        #[arg(short = 'P', long, default_value_t = 1)]
+        pub pipeline: usize,
+
+        // number of distinct keys, every command picks one at random
+        #[arg(short = 'r', long, default_value_t = 10000)]
+        pub keyspace: usize,
+
+        // size in bytes of SET/LPUSH/XADD values
+        #[arg(short = 'd', long, default_value_t = 3)]
+        pub data_size: usize,
+
+        // comma separated workloads to run, all of them by default
+        #[arg(short = 't', long, value_delimiter = ',', default_values_t = _WORKLOADS_.map(String::from))]
+        pub tests: Vec<String>,
+
+        #[arg(long, default_value_t = false)]
+        pub csv: bool,
+
+        #[arg(long, default_value_t = false)]
+        pub json: bool,
+
+        // one line per workload
+        #[arg(short = 'q', long, default_value_t = false)]
+        pub quiet: bool,
+
+        #[arg(long, action = clap::ArgAction::Help)]
+        pub help: Option<bool>,
+    }
+
+    // results of one workload, latencies are in milliseconds
+    #[derive(Debug, Clone, Serialize)]
+    pub struct BenchResult {
+        pub test: String,
+        pub requests: usize,
+        pub errors: usize,
+        pub clients: usize,
+        pub pipeline: usize,
+        pub seconds: f64,
+        pub rps: f64,
+        pub avg_latency_ms: f64,
+        pub min_latency_ms: f64,
+        pub p50_latency_ms: f64,
+        pub p99_latency_ms: f64,
+        pub p999_latency_ms: f64,
+        pub max_latency_ms: f64,
+        // (percentile, latency in ms) pairs for the human readable report
+        #[serde(skip)]
+        pub distribution: Vec<(f64, f64)>,
+    }
+
+    // percentiles listed in the latency distribution of the report
+    const _DISTRIBUTION_: [f64; 8] = [0.0, 50.0, 75.0, 90.0, 95.0, 99.0, 99.9, 100.0];
+
+    // xorshift, good enough to spread keys and we don't need a dependency for it
+    struct Rng(u64);
+
+    impl Rng {
+        fn new(seed: u64) -> Rng {
+            Rng(seed | 1)
+        }
+
+        fn below(&mut self, n: usize) -> usize {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            (self.0 % n.max(1) as u64) as usize
+        }
+    }
+
+    fn round3(x: f64) -> f64 {
+        (x * 1000.0).round() / 1000.0
+    }
+
+    // value at the given percentile(0-100) of sorted samples
+    pub fn percentile(sorted: &[u64], p: f64) -> u64 {
+        if sorted.is_empty() {
+            return 0;
+        }
+        let rank = ((p / 100.0) * sorted.len() as f64).ceil() as usize;
+        sorted[rank.clamp(1, sorted.len()) - 1]
+    }
+
+    fn workload_cmd(test: &str, rng: &mut Rng, args: &BenchArgs, value: &[u8]) -> Vec<Vec<u8>> {
+        let key = rng.below(args.keyspace);
+        let member = format!("member:{}", rng.below(args.keyspace)).into_bytes();
+        match test {
+            "set" => vec![b"SET".to_vec(), format!("key:{}", key).into_bytes(), value.to_vec()],
+            "get" => vec![b"GET".to_vec(), format!("key:{}", key).into_bytes()],
+            "lpush" => vec![b"LPUSH".to_vec(), format!("list:{}", key).into_bytes(), value.to_vec()],
+            "lpop" => vec![b"LPOP".to_vec(), format!("list:{}", key).into_bytes()],
+            "xadd" => vec![b"XADD".to_vec(), format!("stream:{}", key).into_bytes(), b"*".to_vec(), b"field".to_vec(), value.to_vec()],
+            "zadd" => vec![b"ZADD".to_vec(), format!("zset:{}", key).into_bytes(), rng.below(1000).to_string().into_bytes(), member],
+            // coordinates stay inside the range GEOADD accepts
+            "geoadd" => vec![b"GEOADD".to_vec(), format!("geo:{}", key).into_bytes(),
+                format!("{:.6}", rng.below(360_000_000) as f64 / 1e6 - 180.0).into_bytes(),
+                format!("{:.6}", rng.below(170_000_000) as f64 / 1e6 - 85.0).into_bytes(), member],
+            _ => unreachable!("workloads are validated before running"),
+        }
+    }
+
+    // one connection's share of the workload, returns (latency of every command in microseconds, error replies)
+    async fn run_client(test: String, args: Arc<BenchArgs>, remaining: Arc<AtomicUsize>, seed: u64) -> ClientResult<(Vec<u64>, usize)> {
+        let mut conn = Connection::connect(&format!("{}:{}", args.host, args.port)).await?;
+        let mut rng = Rng::new(seed);
+        let value = vec![b'x'; args.data_size];
+        let mut latencies = vec![];
+        let mut errors = 0;
+
+        loop {
+            // claim the next batch out of the shared request budget
+            let left = remaining.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |left| Some(left.saturating_sub(args.pipeline))).unwrap();
+            let batch = left.min(args.pipeline);
+            if batch == 0 {
+                break;
+            }
+
+            let mut pipeline = Pipeline::new();
+            for _ in 0..batch {
+                pipeline.cmd(&workload_cmd(&test, &mut rng, &args, &value));
+            }
+            let start = Instant::now();
+            let replies = conn.pipeline(&pipeline).await?;
+            let elapsed = start.elapsed().as_micros() as u64;
+
+            errors += replies.iter().filter(|reply| reply.is_error()).count();
+            latencies.extend(std::iter::repeat_n(elapsed, batch));
+        }
+
+        Ok((latencies, errors))
+    }
+
+    pub async fn run_workload(test: &str, args: Arc<BenchArgs>) -> ClientResult<BenchResult> {
+        let remaining = Arc::new(AtomicUsize::new(args.requests));
+        let seed = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_nanos() as u64;
+
+        let start = Instant::now();
+        let mut handles = vec![];
+        for i in 0..args.clients {
+            handles.push(tokio::spawn(run_client(test.to_owned(), args.clone(), remaining.clone(), seed.wrapping_add(i as u64 * 7919))));
+        }
+        let mut latencies = vec![];
+        let mut errors = 0;
+        for handle in handles {
+            let (client_latencies, client_errors) = handle.await.expect("benchmark client panicked")?;
+            latencies.extend(client_latencies);
+            errors += client_errors;
+        }
+        let seconds = start.elapsed().as_secs_f64();
+
+        latencies.sort_unstable();
+        let ms = |micros: u64| micros as f64 / 1000.0;
+        let avg = if latencies.is_empty() { 0.0 } else { latencies.iter().sum::<u64>() as f64 / latencies.len() as f64 };
+        Ok(BenchResult {
+            test: test.to_uppercase(),
+            requests: latencies.len(),
+            errors,
+            clients: args.clients,
+            pipeline: args.pipeline,
+            seconds: round3(seconds),
+            rps: round3(latencies.len() as f64 / seconds.max(f64::EPSILON)),
+            avg_latency_ms: round3(avg / 1000.0),
+            min_latency_ms: ms(latencies.first().copied().unwrap_or(0)),
+            p50_latency_ms: ms(percentile(&latencies, 50.0)),
+            p99_latency_ms: ms(percentile(&latencies, 99.0)),
+            p999_latency_ms: ms(percentile(&latencies, 99.9)),
+            max_latency_ms: ms(latencies.last().copied().unwrap_or(0)),
+            distribution: _DISTRIBUTION_.iter().map(|&p| (p, ms(percentile(&latencies, p)))).collect(),
+        })
+    }
+
+    fn print_report(result: &BenchResult, args: &BenchArgs) {
+        if args.quiet {
+            println!("{}: {:.2} requests per second, p50={:.3} msec", result.test, result.rps, result.p50_latency_ms);
+            return;
+        }
+        println!("====== {} ======", result.test);
+        println!("  {} requests completed in {:.2} seconds", result.requests, result.seconds);
+        println!("  {} parallel clients", result.clients);
+        println!("  {} bytes payload", args.data_size);
+        println!("  {} commands per pipeline", result.pipeline);
+        if result.errors > 0 {
+            println!("  {} error replies", result.errors);
+        }
+        println!();
+        println!("Latency by percentile distribution:");
+        for (p, latency) in &result.distribution {
+            println!("  {:>7.3}% <= {:.3} milliseconds", p, latency);
+        }
+        println!();
+        println!("Summary:");
+        println!("  throughput summary: {:.2} requests per second", result.rps);
+        println!("  latency summary (msec):");
+        println!("  {:>9} {:>9} {:>9} {:>9} {:>9} {:>9}", "avg", "min", "p50", "p99", "p999", "max");
+        println!("  {:>9.3} {:>9.3} {:>9.3} {:>9.3} {:>9.3} {:>9.3}", result.avg_latency_ms, result.min_latency_ms,
+            result.p50_latency_ms, result.p99_latency_ms, result.p999_latency_ms, result.max_latency_ms);
+        println!();
+    }
+
+    // entry point of the rusty-redis-benchmark binary, returns the exit code
+    pub fn bench_main<I: Iterator<Item = String>>(argv: I) -> i32 {
+        let mut args = BenchArgs::parse_from(argv);
+        args.tests = args.tests.iter().map(|test| test.trim().to_lowercase()).filter(|test| !test.is_empty()).collect();
+        if let Some(test) = args.tests.iter().find(|test| !_WORKLOADS_.contains(&test.as_str())) {
+            eprintln!("Unknown test '{}', available: {}", test, _WORKLOADS_.join(","));
+            return 1;
+        }
+        if args.clients == 0 || args.pipeline == 0 || args.keyspace == 0 {
+            eprintln!("-c, -P and -r have to be at least 1");
+            return 1;
+        }
+
+        let args = Arc::new(args);
+        let rt = Runtime::new().unwrap();
+        let mut results = vec![];
+        for test in &args.tests {
+            match rt.block_on(run_workload(test, args.clone())) {
+                Ok(result) => {
+                    if !args.csv && !args.json {
+                        print_report(&result, &args);
+                    }
+                    results.push(result);
+                },
+                Err(e) => {
+                    eprintln!("{}: {}", test.to_uppercase(), e);
+                    return 1;
+                }
+            }
+            // let the server settle before the next workload
+            std::thread::sleep(Duration::from_millis(10));
+        }
+
+        if args.json {
+            println!("{}", serde_json::to_string_pretty(&results).unwrap());
+        } else if args.csv {
+            let mut writer = csv::Writer::from_writer(std::io::stdout());
+            for result in &results {
+                writer.serialize(result).unwrap();
+            }
+            writer.flush().unwrap();
+        }
+        0
+    }
+}
@@ -0,0 +1,5 @@
+use ::codecrafters_redis::bench::bench::bench_main;
+
+fn main() {
+    std::process::exit(bench_main(std::env::args()));
+}
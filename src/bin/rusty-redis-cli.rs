@@ -0,0 +1,5 @@
+use ::codecrafters_redis::cli::cli::cli_main;
+
+fn main() {
+    std::process::exit(cli_main(std::env::args()));
+}
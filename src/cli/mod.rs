@@ -0,0 +1,452 @@
+pub mod cli {
+    // rusty-redis-cli, a command line client along the lines of redis-cli:
+    // runs the command given on the command line(optionally repeated with -r/-i), reads commands
+    // interactively with line editing and history when none is given, or mass inserts RESP with --pipe
+    use std::{io::{IsTerminal, Read, Write}, time::{Duration, SystemTime, UNIX_EPOCH}};
+    use clap::Parser;
+    use rustyline::{error::ReadlineError, DefaultEditor};
+    use tokio::{io::{AsyncReadExt, AsyncWriteExt}, net::TcpStream, runtime::Runtime};
+    use crate::client::client::*;
+    use crate::utils::utils::*;
+
+    // -h is the host like in redis-cli, so clap's help flag only gets the long form
+    #[derive(Parser, Debug, Clone)]
+    #[command(name = "rusty-redis-cli", disable_help_flag = true)]
+    pub struct CliArgs {
+        #[arg(short = 'h', long, default_value_t = String::from("127.0.0.1"))]
+        pub host: String,
+
+        #[arg(short, long, default_value_t = 6379)]
+        pub port: u16,
+
+        // password, sent with AUTH right after connecting
+        #[arg(short = 'a', long)]
+        pub pass: Option<String>,
+
+        #[arg(long)]
+        pub user: Option<String>,
+
+        #[arg(long, default_value_t = false)]
+        pub no_auth_warning: bool,
+
+        // negotiate RESP3 with HELLO 3
+        #[arg(short = '3', default_value_t = false)]
+        pub resp3: bool,
+
+        // print replies as they are, the default when stdout isn't a terminal
+        #[arg(long, default_value_t = false)]
+        pub raw: bool,
+
+        // pretty print replies even when stdout isn't a terminal
+        #[arg(long, default_value_t = false)]
+        pub no_raw: bool,
+
+        // run the command this many times, -1 repeats forever
+        #[arg(short = 'r', default_value_t = 1, allow_negative_numbers = true)]
+        pub repeat: i64,
+
+        // seconds to wait between repeats
+        #[arg(short = 'i', default_value_t = 0.0)]
+        pub interval: f64,
+
+        // send the raw RESP commands of the file(stdin without one) as fast as possible
+        #[arg(long, num_args = 0..=1, default_missing_value = "-")]
+        pub pipe: Option<String>,
+
+        #[arg(long, action = clap::ArgAction::Help)]
+        pub help: Option<bool>,
+
+        // command to run instead of starting the interactive prompt
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+        pub command: Vec<String>,
+    }
+
+    // ---REPLY FORMATTING---
+
+    // quote a string the way redis-cli(sdscatrepr) does, non printable bytes become \xHH escapes
+    pub fn repr(data: &[u8]) -> Vec<u8> {
+        let mut out = vec![b'"'];
+        for &ch in data {
+            match ch {
+                b'\\' | b'"' => out.extend_from_slice(&[b'\\', ch]),
+                b'\n' => out.extend_from_slice(b"\\n"),
+                b'\r' => out.extend_from_slice(b"\\r"),
+                b'\t' => out.extend_from_slice(b"\\t"),
+                0x07 => out.extend_from_slice(b"\\a"),
+                0x08 => out.extend_from_slice(b"\\b"),
+                ch if ch.is_ascii_graphic() || ch == b' ' => out.push(ch),
+                ch => out.extend_from_slice(format!("\\x{:02x}", ch).as_bytes()),
+            }
+        }
+        out.push(b'"');
+        out
+    }
+
+    // human readable form of a reply, nested aggregates are indented under their index like in redis-cli
+    pub fn format_reply_tty(reply: &RespValue, prefix: &str) -> Vec<u8> {
+        let mut out = vec![];
+        match reply {
+            RespValue::Simple(s) => out.extend_from_slice(s.as_bytes()),
+            RespValue::Error(e) => out.extend_from_slice(format!("(error) {}", e).as_bytes()),
+            RespValue::Integer(n) => out.extend_from_slice(format!("(integer) {}", n).as_bytes()),
+            RespValue::Double(f) => out.extend_from_slice(format!("(double) {}", f).as_bytes()),
+            RespValue::Boolean(b) => out.extend_from_slice(if *b { b"(true)" } else { b"(false)" }),
+            RespValue::BigNumber(digits) => out.extend_from_slice(format!("(big number) {}", digits).as_bytes()),
+            RespValue::Bulk(data) => out.extend(repr(data)),
+            RespValue::Verbatim(_, data) | RespValue::Raw(data) => out.extend_from_slice(data),
+            RespValue::Null | RespValue::NullArray => out.extend_from_slice(b"(nil)"),
+            RespValue::Array(vals) | RespValue::Set(vals) | RespValue::Push(vals) if vals.is_empty() => {
+                out.extend_from_slice(match reply {
+                    RespValue::Set(_) => b"(empty set)".as_slice(),
+                    RespValue::Push(_) => b"(empty push)",
+                    _ => b"(empty array)",
+                });
+            },
+            RespValue::Map(pairs) if pairs.is_empty() => out.extend_from_slice(b"(empty hash)"),
+            RespValue::Array(vals) | RespValue::Set(vals) | RespValue::Push(vals) => {
+                let sep = if matches!(reply, RespValue::Set(_)) { '~' } else { ')' };
+                let width = vals.len().to_string().len();
+                // entries of a nested aggregate line up after the index of their parent
+                let nested = format!("{}{}", prefix, " ".repeat(width + 2));
+                for (i, val) in vals.iter().enumerate() {
+                    // the first entry goes on the line where the parent already printed its index
+                    out.extend_from_slice(format!("{}{:>width$}{} ", if i == 0 { "" } else { prefix }, i + 1, sep).as_bytes());
+                    out.extend(format_reply_tty(val, &nested));
+                }
+                return out;
+            },
+            RespValue::Map(pairs) => {
+                let width = pairs.len().to_string().len();
+                let nested = format!("{}{}", prefix, " ".repeat(width + 2));
+                for (i, (k, v)) in pairs.iter().enumerate() {
+                    out.extend_from_slice(format!("{}{:>width$}# ", if i == 0 { "" } else { prefix }, i + 1).as_bytes());
+                    out.extend(format_reply_tty(k, &nested));
+                    out.pop();
+                    out.extend_from_slice(b" => ");
+                    out.extend(format_reply_tty(v, &nested));
+                }
+                return out;
+            },
+        }
+        out.push(b'\n');
+        out
+    }
+
+    // --raw output: strings as they are, one aggregate entry per line, nil prints nothing
+    pub fn format_reply_raw(reply: &RespValue) -> Vec<u8> {
+        match reply {
+            RespValue::Simple(s) | RespValue::Error(s) | RespValue::BigNumber(s) => s.as_bytes().to_vec(),
+            RespValue::Integer(n) => n.to_string().into_bytes(),
+            RespValue::Double(f) => f.to_string().into_bytes(),
+            RespValue::Boolean(b) => if *b { b"(true)".to_vec() } else { b"(false)".to_vec() },
+            RespValue::Bulk(data) | RespValue::Verbatim(_, data) | RespValue::Raw(data) => data.clone(),
+            RespValue::Null | RespValue::NullArray => vec![],
+            RespValue::Array(vals) | RespValue::Set(vals) | RespValue::Push(vals) => {
+                vals.iter().map(format_reply_raw).collect::<Vec<_>>().join(&b'\n')
+            },
+            RespValue::Map(pairs) => {
+                pairs.iter().flat_map(|(k, v)| [format_reply_raw(k), format_reply_raw(v)]).collect::<Vec<_>>().join(&b'\n')
+            },
+        }
+    }
+
+    pub fn format_reply(reply: &RespValue, raw: bool) -> Vec<u8> {
+        if raw {
+            let mut out = format_reply_raw(reply);
+            out.push(b'\n');
+            return out;
+        }
+        format_reply_tty(reply, "")
+    }
+
+    // ---SESSION---
+
+    struct Session {
+        args: CliArgs,
+        raw: bool,
+        conn: Option<Connection>,
+    }
+
+    impl Session {
+        fn addr(&self) -> String {
+            format!("{}:{}", self.args.host, self.args.port)
+        }
+
+        fn prompt(&self) -> String {
+            match self.conn {
+                Some(_) => format!("{}> ", self.addr()),
+                None => "not connected> ".to_owned(),
+            }
+        }
+
+        async fn connect(&mut self) -> bool {
+            let mut conn = match Connection::connect(&self.addr()).await {
+                Ok(conn) => conn,
+                Err(e) => {
+                    eprintln!("Could not connect to Redis at {}: {}", self.addr(), e);
+                    self.conn = None;
+                    return false;
+                }
+            };
+
+            let mut hello = vec![];
+            if self.args.resp3 {
+                hello = vec!["HELLO".to_owned(), "3".to_owned()];
+            }
+            if let Some(pass) = &self.args.pass {
+                let user = self.args.user.clone().unwrap_or("default".to_owned());
+                if self.args.resp3 {
+                    hello.extend(["AUTH".to_owned(), user, pass.clone()]);
+                } else if self.args.user.is_some() {
+                    hello = vec!["AUTH".to_owned(), user, pass.clone()];
+                } else {
+                    hello = vec!["AUTH".to_owned(), pass.clone()];
+                }
+            }
+            if !hello.is_empty() {
+                match conn.command(&hello).await {
+                    Err(ClientError::Server(e)) => eprintln!("AUTH failed: {}", e),
+                    Err(e) => {
+                        eprintln!("Could not connect to Redis at {}: {}", self.addr(), e);
+                        return false;
+                    },
+                    Ok(_) => {},
+                }
+            }
+            self.conn = Some(conn);
+            true
+        }
+
+        fn print(&self, reply: &RespValue) {
+            let mut stdout = std::io::stdout().lock();
+            stdout.write_all(&format_reply(reply, self.raw)).ok();
+            stdout.flush().ok();
+        }
+
+        // run a command `repeat` times, returns false if the connection was lost
+        async fn run(&mut self, cmd: &[Vec<u8>], repeat: i64, interval: f64) -> bool {
+            let mut i = 0;
+            while repeat < 0 || i < repeat {
+                if self.conn.is_none() && !self.connect().await {
+                    return false;
+                }
+                let conn = self.conn.as_mut().unwrap();
+                let reply = match conn.send(cmd).await {
+                    Ok(_) => conn.read_reply().await,
+                    Err(e) => Err(e),
+                };
+                match reply {
+                    Ok(reply) => self.print(&reply),
+                    Err(e) => {
+                        eprintln!("Error: {}", e);
+                        self.conn = None;
+                        return false;
+                    }
+                }
+
+                // once subscribed the connection only receives messages
+                let name = arg_str(&cmd[0]).to_uppercase();
+                if name == "SUBSCRIBE" || name == "PSUBSCRIBE" {
+                    if !self.raw {
+                        println!("Reading messages... (press Ctrl-C to quit)");
+                    }
+                    loop {
+                        match self.conn.as_mut().unwrap().read_reply().await {
+                            Ok(reply) => self.print(&reply),
+                            Err(e) => {
+                                eprintln!("Error: {}", e);
+                                self.conn = None;
+                                return false;
+                            }
+                        }
+                    }
+                }
+
+                i += 1;
+                if interval > 0.0 && (repeat < 0 || i < repeat) {
+                    tokio::time::sleep(Duration::from_secs_f64(interval)).await;
+                }
+            }
+            true
+        }
+    }
+
+    fn history_file() -> Option<String> {
+        std::env::var("HOME").ok().map(|home| format!("{}/.rusty_redis_cli_history", home))
+    }
+
+    // interactive prompt, a leading number repeats the command like in redis-cli("3 INCR counter")
+    fn repl(session: &mut Session, rt: &Runtime) {
+        let mut editor = match DefaultEditor::new() {
+            Ok(editor) => editor,
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                return;
+            }
+        };
+        let history = history_file();
+        if let Some(path) = &history {
+            editor.load_history(path).ok();
+        }
+
+        loop {
+            let line = match editor.readline(&session.prompt()) {
+                Ok(line) => line,
+                Err(ReadlineError::Interrupted | ReadlineError::Eof) => break,
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    break;
+                }
+            };
+            let mut cmd = match split_args(line.as_bytes()) {
+                Some(cmd) => cmd,
+                None => {
+                    println!("Invalid argument(s)");
+                    continue;
+                }
+            };
+            if cmd.is_empty() {
+                continue;
+            }
+            editor.add_history_entry(line.as_str()).ok();
+
+            let mut repeat = 1;
+            if cmd.len() > 1 {
+                if let Some(n) = parse_arg::<i64>(&cmd[0]) {
+                    repeat = n;
+                    cmd.remove(0);
+                }
+            }
+
+            match arg_str(&cmd[0]).to_lowercase().as_str() {
+                "quit" | "exit" => break,
+                "clear" => {
+                    print!("\x1b[H\x1b[2J");
+                    std::io::stdout().flush().ok();
+                },
+                "connect" if cmd.len() >= 2 => {
+                    session.args.host = arg_str(&cmd[1]);
+                    if let Some(port) = cmd.get(2).and_then(|port| parse_arg(port)) {
+                        session.args.port = port;
+                    }
+                    rt.block_on(session.connect());
+                },
+                _ => {
+                    rt.block_on(session.run(&cmd, repeat, session.args.interval));
+                }
+            }
+        }
+
+        if let Some(path) = &history {
+            editor.save_history(path).ok();
+        }
+    }
+
+    // ---PIPE MODE---
+
+    // write every command of the input, then an ECHO with a random marker, and count replies until the marker
+    // comes back, reading and writing happen at the same time so the server never blocks on a full socket
+    async fn pipe(args: &CliArgs, input: Vec<u8>) -> i32 {
+        let stream = match TcpStream::connect(format!("{}:{}", args.host, args.port)).await {
+            Ok(stream) => stream,
+            Err(e) => {
+                eprintln!("Could not connect to Redis at {}:{}: {}", args.host, args.port, e);
+                return 1;
+            }
+        };
+        let (mut reader, mut writer) = stream.into_split();
+
+        let nanos = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_nanos();
+        let marker = format!("{:020x}", nanos).into_bytes();
+        let echo = encode_command(&[b"ECHO".to_vec(), marker.clone()]);
+        let sender = tokio::spawn(async move {
+            writer.write_all(&input).await?;
+            writer.write_all(&echo).await?;
+            eprintln!("All data transferred. Waiting for the last reply...");
+            // keep the write half open until the last reply arrives
+            Ok::<_, std::io::Error>(writer)
+        });
+
+        let (mut errors, mut replies) = (0, 0);
+        let mut buf = Vec::with_capacity(16 * 1024);
+        let mut chunk = vec![0; 16 * 1024];
+        'read: loop {
+            let n = match reader.read(&mut chunk).await {
+                Ok(0) | Err(_) => {
+                    eprintln!("Connection closed before the last reply was received.");
+                    break;
+                },
+                Ok(n) => n,
+            };
+            buf.extend_from_slice(&chunk[..n]);
+
+            let mut consumed = 0;
+            loop {
+                match parse_reply(&buf[consumed..]) {
+                    Ok(Some((size, reply))) => {
+                        consumed += size;
+                        if reply == RespValue::Bulk(marker.clone()) {
+                            eprintln!("Last reply received from server.");
+                            break 'read;
+                        }
+                        if let RespValue::Error(e) = reply {
+                            errors += 1;
+                            println!("{}", e);
+                        }
+                        replies += 1;
+                    },
+                    Ok(None) => break,
+                    Err(e) => {
+                        eprintln!("Error: {}", e);
+                        break 'read;
+                    }
+                }
+            }
+            buf.drain(..consumed);
+        }
+
+        if let Ok(Err(e)) = sender.await {
+            eprintln!("Error writing to the server: {}", e);
+            return 1;
+        }
+        eprintln!("errors: {}, replies: {}", errors, replies);
+        if errors > 0 { 1 } else { 0 }
+    }
+
+    // entry point of the rusty-redis-cli binary, returns the exit code
+    pub fn cli_main<I: Iterator<Item = String>>(argv: I) -> i32 {
+        let args = CliArgs::parse_from(argv);
+        let rt = Runtime::new().unwrap();
+
+        if args.pass.is_some() && !args.no_auth_warning {
+            eprintln!("Warning: Using a password with '-a' option on the command line interface may not be safe.");
+        }
+
+        if let Some(path) = &args.pipe {
+            let mut input = vec![];
+            let res = if path == "-" {
+                std::io::stdin().read_to_end(&mut input).map(|_| ())
+            } else {
+                std::fs::read(path).map(|data| input = data)
+            };
+            if let Err(e) = res {
+                eprintln!("Error reading {}: {}", path, e);
+                return 1;
+            }
+            return rt.block_on(pipe(&args, input));
+        }
+
+        // like redis-cli, replies are only pretty printed for a terminal
+        let raw = args.raw || (!args.no_raw && !std::io::stdout().is_terminal());
+        let mut session = Session { args: args.clone(), raw, conn: None };
+
+        if args.command.is_empty() {
+            rt.block_on(session.connect());
+            repl(&mut session, &rt);
+            return 0;
+        }
+
+        let cmd: Vec<Vec<u8>> = args.command.iter().map(|arg| arg.as_bytes().to_vec()).collect();
+        if rt.block_on(session.run(&cmd, args.repeat, args.interval)) { 0 } else { 1 }
+    }
+}
@@ -0,0 +1,642 @@
+pub mod client {
+    // async client for talking to the server(or any redis) from rust code:
+    // Client holds the address and hands out connections, Connection sends commands and decodes the replies,
+    // Pipeline and Transaction batch commands into a single write, Subscriber receives pub/sub messages
+    // and Pool keeps a bounded set of connections around for reuse
+    use std::{ops::{Deref, DerefMut}, sync::Arc};
+    use bytes::{Buf, BytesMut};
+    use thiserror::Error;
+    use tokio::{io::{AsyncReadExt, AsyncWriteExt}, net::TcpStream, sync::{OwnedSemaphorePermit, Semaphore}};
+    use crate::utils::utils::*;
+
+    #[derive(Debug, Error)]
+    pub enum ClientError {
+        #[error("io error: {0}")]
+        Io(#[from] std::io::Error),
+        #[error("{0}")]
+        Protocol(String),
+        // error reply sent by the server, e.g. "WRONGTYPE Operation against a key holding the wrong kind of value"
+        #[error("{0}")]
+        Server(String),
+        #[error("unexpected reply: {0:?}")]
+        UnexpectedReply(RespValue),
+        #[error("connection closed by the server")]
+        Closed,
+    }
+
+    pub type ClientResult<T> = Result<T, ClientError>;
+
+    // ---REPLY CONVERSIONS---
+    // typed helpers turn the reply into a rust value, error replies become ClientError::Server
+
+    fn check(reply: RespValue) -> ClientResult<RespValue> {
+        match reply {
+            RespValue::Error(e) => Err(ClientError::Server(e)),
+            reply => Ok(reply),
+        }
+    }
+
+    fn into_ok(reply: RespValue) -> ClientResult<()> {
+        match check(reply)? {
+            RespValue::Simple(_) => Ok(()),
+            reply => Err(ClientError::UnexpectedReply(reply)),
+        }
+    }
+
+    fn into_int(reply: RespValue) -> ClientResult<i64> {
+        match check(reply)? {
+            RespValue::Integer(n) => Ok(n),
+            RespValue::Boolean(b) => Ok(b as i64),
+            reply => Err(ClientError::UnexpectedReply(reply)),
+        }
+    }
+
+    fn into_opt_int(reply: RespValue) -> ClientResult<Option<i64>> {
+        match check(reply)? {
+            RespValue::Null | RespValue::NullArray => Ok(None),
+            reply => into_int(reply).map(Some),
+        }
+    }
+
+    fn into_opt_bulk(reply: RespValue) -> ClientResult<Option<Vec<u8>>> {
+        match check(reply)? {
+            RespValue::Null | RespValue::NullArray => Ok(None),
+            RespValue::Bulk(data) | RespValue::Verbatim(_, data) => Ok(Some(data)),
+            RespValue::Simple(s) | RespValue::BigNumber(s) => Ok(Some(s.into_bytes())),
+            RespValue::Integer(n) => Ok(Some(n.to_string().into_bytes())),
+            RespValue::Double(f) => Ok(Some(f.to_string().into_bytes())),
+            reply => Err(ClientError::UnexpectedReply(reply)),
+        }
+    }
+
+    fn into_bulk(reply: RespValue) -> ClientResult<Vec<u8>> {
+        match into_opt_bulk(reply)? {
+            Some(data) => Ok(data),
+            None => Err(ClientError::UnexpectedReply(RespValue::Null)),
+        }
+    }
+
+    fn into_string(reply: RespValue) -> ClientResult<String> {
+        into_bulk(reply).map(|data| arg_str(&data))
+    }
+
+    fn into_opt_float(reply: RespValue) -> ClientResult<Option<f64>> {
+        match check(reply)? {
+            RespValue::Double(f) => Ok(Some(f)),
+            reply => match into_opt_bulk(reply)? {
+                Some(data) => parse_arg(&data).map(Some).ok_or_else(|| ClientError::Protocol(format!("not a float: {}", arg_str(&data)))),
+                None => Ok(None),
+            },
+        }
+    }
+
+    fn into_array(reply: RespValue) -> ClientResult<Vec<RespValue>> {
+        match check(reply)? {
+            RespValue::Array(vals) | RespValue::Set(vals) | RespValue::Push(vals) => Ok(vals),
+            RespValue::NullArray | RespValue::Null => Ok(vec![]),
+            reply => Err(ClientError::UnexpectedReply(reply)),
+        }
+    }
+
+    fn into_bulk_array(reply: RespValue) -> ClientResult<Vec<Vec<u8>>> {
+        into_array(reply)?.into_iter().map(into_bulk).collect()
+    }
+
+    // a single command, built up argument by argument
+    fn args<K: AsRef<[u8]>>(name: &str, rest: &[K]) -> Vec<Vec<u8>> {
+        let mut cmd = vec![name.as_bytes().to_vec()];
+        cmd.extend(rest.iter().map(|arg| arg.as_ref().to_vec()));
+        cmd
+    }
+
+    // ---CLIENT---
+
+    #[derive(Debug, Clone)]
+    pub struct Client {
+        addr: String,
+    }
+
+    impl Client {
+        // addr is "host:port"
+        pub fn open(addr: &str) -> Client {
+            Client { addr: addr.to_owned() }
+        }
+
+        pub async fn get_connection(&self) -> ClientResult<Connection> {
+            Connection::connect(&self.addr).await
+        }
+
+        // a pool that keeps at most max_size connections open
+        pub fn pool(&self, max_size: usize) -> Pool {
+            Pool {
+                client: self.clone(),
+                idle: Arc::new(std::sync::Mutex::new(vec![])),
+                permits: Arc::new(Semaphore::new(max_size)),
+            }
+        }
+    }
+
+    // ---CONNECTION---
+
+    #[derive(Debug)]
+    pub struct Connection {
+        stream: TcpStream,
+        // reply bytes read from the socket that haven't been decoded yet
+        buf: BytesMut,
+        // set once an io or protocol error leaves the stream in an unknown state, the pool drops broken connections
+        broken: bool,
+        // replies sent for but not read yet, a caller that gave up halfway leaves them queued on the stream
+        pending: usize,
+        // between a MULTI and its EXEC or DISCARD, the server queues whatever comes next
+        in_multi: bool,
+    }
+
+    impl Connection {
+        pub async fn connect(addr: &str) -> ClientResult<Connection> {
+            let stream = TcpStream::connect(addr).await?;
+            stream.set_nodelay(true)?;
+            Ok(Connection { stream, buf: BytesMut::with_capacity(16 * 1024), broken: false, pending: 0, in_multi: false })
+        }
+
+        pub fn is_broken(&self) -> bool {
+            self.broken
+        }
+
+        // whether the next command sent gets its own reply back, the pool only keeps connections that are
+        pub fn is_reusable(&self) -> bool {
+            !self.broken && self.pending == 0 && !self.in_multi
+        }
+
+        // counted before writing so a write cancelled halfway leaves the connection unusable too
+        async fn write_all(&mut self, bytes: &[u8], replies: usize) -> ClientResult<()> {
+            self.pending += replies;
+            if let Err(e) = self.stream.write_all(bytes).await {
+                self.broken = true;
+                return Err(e.into());
+            }
+            Ok(())
+        }
+
+        // send a command without waiting for its reply
+        pub async fn send<T: AsRef<[u8]>>(&mut self, cmd: &[T]) -> ClientResult<()> {
+            match cmd.first().map(|name| name.as_ref().to_ascii_uppercase()).as_deref() {
+                Some(b"MULTI") => self.in_multi = true,
+                Some(b"EXEC") | Some(b"DISCARD") => self.in_multi = false,
+                _ => {},
+            }
+            self.write_all(&encode_command(cmd), 1).await
+        }
+
+        // read the next reply off the connection, error replies are returned as RespValue::Error
+        pub async fn read_reply(&mut self) -> ClientResult<RespValue> {
+            loop {
+                match parse_reply(&self.buf) {
+                    Ok(Some((consumed, reply))) => {
+                        self.buf.advance(consumed);
+                        // SUBSCRIBE gets one reply per channel
+                        self.pending = self.pending.saturating_sub(1);
+                        return Ok(reply);
+                    },
+                    Ok(None) => {},
+                    Err(e) => {
+                        self.broken = true;
+                        return Err(ClientError::Protocol(e));
+                    }
+                }
+
+                self.buf.reserve(16 * 1024);
+                match self.stream.read_buf(&mut self.buf).await {
+                    Ok(0) => {
+                        self.broken = true;
+                        return Err(ClientError::Closed);
+                    },
+                    Ok(_) => {},
+                    Err(e) => {
+                        self.broken = true;
+                        return Err(e.into());
+                    }
+                }
+            }
+        }
+
+        // send a command and wait for its reply, error replies become ClientError::Server
+        pub async fn command<T: AsRef<[u8]>>(&mut self, cmd: &[T]) -> ClientResult<RespValue> {
+            self.send(cmd).await?;
+            check(self.read_reply().await?)
+        }
+
+        // send every command of the pipeline in one write, then read all the replies in order
+        pub async fn pipeline(&mut self, pipeline: &Pipeline) -> ClientResult<Vec<RespValue>> {
+            self.write_all(&pipeline.encoded, pipeline.len).await?;
+            let mut replies = Vec::with_capacity(pipeline.len);
+            for _ in 0..pipeline.len {
+                replies.push(self.read_reply().await?);
+            }
+            Ok(replies)
+        }
+
+        // run the transaction with MULTI/EXEC, returns the reply of every queued command
+        pub async fn transaction(&mut self, transaction: &Transaction) -> ClientResult<Vec<RespValue>> {
+            let mut pipeline = Pipeline::new();
+            pipeline.cmd(&["MULTI"]);
+            pipeline.encoded.extend_from_slice(&transaction.pipeline.encoded);
+            pipeline.len += transaction.pipeline.len;
+            pipeline.cmd(&["EXEC"]);
+
+            // MULTI and every queued command only confirm the queueing, EXEC carries the results
+            let mut replies = self.pipeline(&pipeline).await?;
+            into_array(replies.pop().unwrap())
+        }
+
+        // turn this connection into a subscriber of the given channels
+        pub async fn subscribe<T: AsRef<[u8]>>(self, chans: &[T]) -> ClientResult<Subscriber> {
+            let mut subscriber = Subscriber { conn: self, chans: 0 };
+            subscriber.subscribe(chans).await?;
+            Ok(subscriber)
+        }
+
+        // ---TYPED COMMANDS---
+
+        pub async fn ping(&mut self) -> ClientResult<()> {
+            into_ok(self.command(&["PING"]).await?)
+        }
+
+        pub async fn echo<V: AsRef<[u8]>>(&mut self, msg: V) -> ClientResult<Vec<u8>> {
+            into_bulk(self.command(&args("ECHO", &[msg])).await?)
+        }
+
+        pub async fn get<K: AsRef<[u8]>>(&mut self, key: K) -> ClientResult<Option<Vec<u8>>> {
+            into_opt_bulk(self.command(&args("GET", &[key])).await?)
+        }
+
+        pub async fn set<K: AsRef<[u8]>, V: AsRef<[u8]>>(&mut self, key: K, value: V) -> ClientResult<()> {
+            into_ok(self.command(&[b"SET".as_slice(), key.as_ref(), value.as_ref()]).await?)
+        }
+
+        // SET with an expiry in milliseconds
+        pub async fn set_px<K: AsRef<[u8]>, V: AsRef<[u8]>>(&mut self, key: K, value: V, ttl_ms: u64) -> ClientResult<()> {
+            let cmd = [b"SET".to_vec(), key.as_ref().to_vec(), value.as_ref().to_vec(), b"PX".to_vec(), ttl_ms.to_string().into_bytes()];
+            into_ok(self.command(&cmd).await?)
+        }
+
+        pub async fn incr<K: AsRef<[u8]>>(&mut self, key: K) -> ClientResult<i64> {
+            into_int(self.command(&args("INCR", &[key])).await?)
+        }
+
+        pub async fn key_type<K: AsRef<[u8]>>(&mut self, key: K) -> ClientResult<String> {
+            into_string(self.command(&args("TYPE", &[key])).await?)
+        }
+
+        pub async fn keys(&mut self, pattern: &str) -> ClientResult<Vec<Vec<u8>>> {
+            into_bulk_array(self.command(&["KEYS", pattern]).await?)
+        }
+
+        // one SCAN step, returns the next cursor(0 once the scan is done) and a batch of keys
+        pub async fn scan(&mut self, cursor: u64, pattern: Option<&str>, count: Option<usize>) -> ClientResult<(u64, Vec<Vec<u8>>)> {
+            let mut cmd = vec!["SCAN".to_owned(), cursor.to_string()];
+            if let Some(pattern) = pattern {
+                cmd.extend(["MATCH".to_owned(), pattern.to_owned()]);
+            }
+            if let Some(count) = count {
+                cmd.extend(["COUNT".to_owned(), count.to_string()]);
+            }
+            let mut reply = into_array(self.command(&cmd).await?)?.into_iter();
+            match (reply.next(), reply.next()) {
+                (Some(cursor), Some(keys)) => {
+                    let cursor = into_string(cursor)?;
+                    let cursor = cursor.parse().map_err(|_| ClientError::Protocol(format!("not a cursor: {}", cursor)))?;
+                    Ok((cursor, into_bulk_array(keys)?))
+                },
+                _ => Err(ClientError::Protocol("SCAN reply should have a cursor and keys".to_owned())),
+            }
+        }
+
+        pub async fn info(&mut self) -> ClientResult<String> {
+            into_string(self.command(&["INFO"]).await?)
+        }
+
+        pub async fn lpush<K: AsRef<[u8]>, V: AsRef<[u8]>>(&mut self, key: K, values: &[V]) -> ClientResult<i64> {
+            let mut cmd = args("LPUSH", &[key]);
+            cmd.extend(values.iter().map(|v| v.as_ref().to_vec()));
+            into_int(self.command(&cmd).await?)
+        }
+
+        pub async fn rpush<K: AsRef<[u8]>, V: AsRef<[u8]>>(&mut self, key: K, values: &[V]) -> ClientResult<i64> {
+            let mut cmd = args("RPUSH", &[key]);
+            cmd.extend(values.iter().map(|v| v.as_ref().to_vec()));
+            into_int(self.command(&cmd).await?)
+        }
+
+        pub async fn lpop<K: AsRef<[u8]>>(&mut self, key: K) -> ClientResult<Option<Vec<u8>>> {
+            into_opt_bulk(self.command(&args("LPOP", &[key])).await?)
+        }
+
+        pub async fn lrange<K: AsRef<[u8]>>(&mut self, key: K, start: i64, stop: i64) -> ClientResult<Vec<Vec<u8>>> {
+            let cmd = [b"LRANGE".to_vec(), key.as_ref().to_vec(), start.to_string().into_bytes(), stop.to_string().into_bytes()];
+            into_bulk_array(self.command(&cmd).await?)
+        }
+
+        pub async fn llen<K: AsRef<[u8]>>(&mut self, key: K) -> ClientResult<i64> {
+            into_int(self.command(&args("LLEN", &[key])).await?)
+        }
+
+        // BLPOP on a single key, None when the timeout(in seconds, 0 blocks forever) runs out
+        pub async fn blpop<K: AsRef<[u8]>>(&mut self, key: K, timeout: f64) -> ClientResult<Option<(Vec<u8>, Vec<u8>)>> {
+            let cmd = [b"BLPOP".to_vec(), key.as_ref().to_vec(), timeout.to_string().into_bytes()];
+            let mut popped = into_bulk_array(self.command(&cmd).await?)?;
+            if popped.len() != 2 {
+                return Ok(None);
+            }
+            let value = popped.pop().unwrap();
+            Ok(Some((popped.pop().unwrap(), value)))
+        }
+
+        // XADD key id field value [field value ...], returns the id of the new entry
+        pub async fn xadd<K: AsRef<[u8]>, F: AsRef<[u8]>, V: AsRef<[u8]>>(&mut self, key: K, id: &str, fields: &[(F, V)]) -> ClientResult<String> {
+            let mut cmd = vec![b"XADD".to_vec(), key.as_ref().to_vec(), id.as_bytes().to_vec()];
+            for (field, value) in fields {
+                cmd.push(field.as_ref().to_vec());
+                cmd.push(value.as_ref().to_vec());
+            }
+            into_string(self.command(&cmd).await?)
+        }
+
+        // entries between start and end as (id, [(field, value)]) pairs
+        pub async fn xrange<K: AsRef<[u8]>>(&mut self, key: K, start: &str, end: &str) -> ClientResult<Vec<StreamReplyEntry>> {
+            let cmd = [b"XRANGE".to_vec(), key.as_ref().to_vec(), start.as_bytes().to_vec(), end.as_bytes().to_vec()];
+            into_array(self.command(&cmd).await?)?.into_iter().map(into_stream_entry).collect()
+        }
+
+        pub async fn zadd<K: AsRef<[u8]>, M: AsRef<[u8]>>(&mut self, key: K, score: f64, member: M) -> ClientResult<i64> {
+            let cmd = [b"ZADD".to_vec(), key.as_ref().to_vec(), score.to_string().into_bytes(), member.as_ref().to_vec()];
+            into_int(self.command(&cmd).await?)
+        }
+
+        pub async fn zrank<K: AsRef<[u8]>, M: AsRef<[u8]>>(&mut self, key: K, member: M) -> ClientResult<Option<i64>> {
+            into_opt_int(self.command(&[b"ZRANK".as_slice(), key.as_ref(), member.as_ref()]).await?)
+        }
+
+        pub async fn zscore<K: AsRef<[u8]>, M: AsRef<[u8]>>(&mut self, key: K, member: M) -> ClientResult<Option<f64>> {
+            into_opt_float(self.command(&[b"ZSCORE".as_slice(), key.as_ref(), member.as_ref()]).await?)
+        }
+
+        pub async fn zrange<K: AsRef<[u8]>>(&mut self, key: K, start: i64, stop: i64) -> ClientResult<Vec<Vec<u8>>> {
+            let cmd = [b"ZRANGE".to_vec(), key.as_ref().to_vec(), start.to_string().into_bytes(), stop.to_string().into_bytes()];
+            into_bulk_array(self.command(&cmd).await?)
+        }
+
+        pub async fn zcard<K: AsRef<[u8]>>(&mut self, key: K) -> ClientResult<i64> {
+            into_int(self.command(&args("ZCARD", &[key])).await?)
+        }
+
+        pub async fn zrem<K: AsRef<[u8]>, M: AsRef<[u8]>>(&mut self, key: K, member: M) -> ClientResult<i64> {
+            into_int(self.command(&[b"ZREM".as_slice(), key.as_ref(), member.as_ref()]).await?)
+        }
+
+        pub async fn geoadd<K: AsRef<[u8]>, M: AsRef<[u8]>>(&mut self, key: K, longitude: f64, latitude: f64, member: M) -> ClientResult<i64> {
+            let cmd = [b"GEOADD".to_vec(), key.as_ref().to_vec(), longitude.to_string().into_bytes(), latitude.to_string().into_bytes(), member.as_ref().to_vec()];
+            into_int(self.command(&cmd).await?)
+        }
+
+        // (longitude, latitude) of every member, None for missing members
+        pub async fn geopos<K: AsRef<[u8]>, M: AsRef<[u8]>>(&mut self, key: K, members: &[M]) -> ClientResult<Vec<Option<(f64, f64)>>> {
+            let mut cmd = args("GEOPOS", &[key]);
+            cmd.extend(members.iter().map(|m| m.as_ref().to_vec()));
+            into_array(self.command(&cmd).await?)?.into_iter().map(|pos| {
+                let coords = into_array(pos)?;
+                if coords.len() != 2 {
+                    return Ok(None);
+                }
+                let mut coords = coords.into_iter().map(into_opt_float);
+                match (coords.next().unwrap()?, coords.next().unwrap()?) {
+                    (Some(lon), Some(lat)) => Ok(Some((lon, lat))),
+                    _ => Ok(None),
+                }
+            }).collect()
+        }
+
+        // distance in meters, None if either member is missing
+        pub async fn geodist<K: AsRef<[u8]>, M: AsRef<[u8]>>(&mut self, key: K, member1: M, member2: M) -> ClientResult<Option<f64>> {
+            into_opt_float(self.command(&[b"GEODIST".as_slice(), key.as_ref(), member1.as_ref(), member2.as_ref()]).await?)
+        }
+
+        // returns the number of subscribers that received the message
+        pub async fn publish<C: AsRef<[u8]>, M: AsRef<[u8]>>(&mut self, chan: C, msg: M) -> ClientResult<i64> {
+            into_int(self.command(&[b"PUBLISH".as_slice(), chan.as_ref(), msg.as_ref()]).await?)
+        }
+    }
+
+    // (entry id, field value pairs)
+    pub type StreamReplyEntry = (String, Vec<(Vec<u8>, Vec<u8>)>);
+
+    fn into_stream_entry(reply: RespValue) -> ClientResult<StreamReplyEntry> {
+        let mut entry = into_array(reply)?.into_iter();
+        let (id, fields) = match (entry.next(), entry.next()) {
+            (Some(id), Some(fields)) => (into_string(id)?, into_bulk_array(fields)?),
+            _ => return Err(ClientError::Protocol("malformed stream entry".to_owned())),
+        };
+        let mut pairs = vec![];
+        let mut fields = fields.into_iter();
+        while let (Some(field), Some(value)) = (fields.next(), fields.next()) {
+            pairs.push((field, value));
+        }
+        Ok((id, pairs))
+    }
+
+    // ---PIPELINE---
+
+    // commands encoded back to back, sent with a single write
+    #[derive(Debug, Default, Clone)]
+    pub struct Pipeline {
+        encoded: Vec<u8>,
+        len: usize,
+    }
+
+    impl Pipeline {
+        pub fn new() -> Pipeline {
+            Pipeline::default()
+        }
+
+        pub fn cmd<T: AsRef<[u8]>>(&mut self, cmd: &[T]) -> &mut Pipeline {
+            self.encoded.extend(encode_command(cmd));
+            self.len += 1;
+            self
+        }
+
+        pub fn len(&self) -> usize {
+            self.len
+        }
+
+        pub fn is_empty(&self) -> bool {
+            self.len == 0
+        }
+    }
+
+    // ---TRANSACTION---
+
+    // commands to run between MULTI and EXEC, if any of them is rejected while queueing the server
+    // discards the whole transaction and Connection::transaction returns the EXECABORT error
+    #[derive(Debug, Default, Clone)]
+    pub struct Transaction {
+        pipeline: Pipeline,
+    }
+
+    impl Transaction {
+        pub fn new() -> Transaction {
+            Transaction::default()
+        }
+
+        pub fn cmd<T: AsRef<[u8]>>(&mut self, cmd: &[T]) -> &mut Transaction {
+            self.pipeline.cmd(cmd);
+            self
+        }
+    }
+
+    // ---PUB/SUB---
+
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct Message {
+        pub channel: Vec<u8>,
+        pub payload: Vec<u8>,
+        // the pattern the channel matched, for messages received through PSUBSCRIBE
+        pub pattern: Option<Vec<u8>>,
+    }
+
+    // a connection in subscribed mode, only (un)subscribing and receiving messages is allowed
+    #[derive(Debug)]
+    pub struct Subscriber {
+        conn: Connection,
+        // channels and patterns this connection is subscribed to, as last reported by the server
+        chans: i64,
+    }
+
+    impl Subscriber {
+        pub async fn subscribe<T: AsRef<[u8]>>(&mut self, chans: &[T]) -> ClientResult<()> {
+            self.conn.send(&args("SUBSCRIBE", chans)).await?;
+            // one confirmation per channel
+            for _ in chans {
+                self.read_confirmation().await?;
+            }
+            Ok(())
+        }
+
+        pub async fn unsubscribe<T: AsRef<[u8]>>(&mut self, chans: &[T]) -> ClientResult<()> {
+            self.conn.send(&args("UNSUBSCRIBE", chans)).await?;
+            for _ in chans {
+                self.read_confirmation().await?;
+            }
+            Ok(())
+        }
+
+        pub async fn psubscribe<T: AsRef<[u8]>>(&mut self, patterns: &[T]) -> ClientResult<()> {
+            self.conn.send(&args("PSUBSCRIBE", patterns)).await?;
+            for _ in patterns {
+                self.read_confirmation().await?;
+            }
+            Ok(())
+        }
+
+        pub async fn punsubscribe<T: AsRef<[u8]>>(&mut self, patterns: &[T]) -> ClientResult<()> {
+            self.conn.send(&args("PUNSUBSCRIBE", patterns)).await?;
+            for _ in patterns {
+                self.read_confirmation().await?;
+            }
+            Ok(())
+        }
+
+        pub fn subscriptions(&self) -> i64 {
+            self.chans
+        }
+
+        async fn read_confirmation(&mut self) -> ClientResult<()> {
+            let reply = into_array(self.conn.read_reply().await?)?;
+            match reply.as_slice() {
+                [RespValue::Bulk(kind), _, RespValue::Integer(count)]
+                    if [b"subscribe".as_slice(), b"unsubscribe", b"psubscribe", b"punsubscribe"].contains(&kind.as_slice()) => {
+                    self.chans = *count;
+                    Ok(())
+                },
+                _ => Err(ClientError::UnexpectedReply(RespValue::Array(reply))),
+            }
+        }
+
+        // wait for the next message published on one of the subscribed channels or matching one of the patterns
+        pub async fn next_message(&mut self) -> ClientResult<Message> {
+            loop {
+                let reply = into_array(self.conn.read_reply().await?)?;
+                let mut parts = reply.into_iter();
+                match (parts.next(), parts.next(), parts.next(), parts.next()) {
+                    (Some(RespValue::Bulk(kind)), Some(RespValue::Bulk(channel)), Some(RespValue::Bulk(payload)), None) if kind == b"message" => {
+                        return Ok(Message { channel, payload, pattern: None });
+                    },
+                    (Some(RespValue::Bulk(kind)), Some(RespValue::Bulk(pattern)), Some(RespValue::Bulk(channel)), Some(RespValue::Bulk(payload)))
+                        if kind == b"pmessage" => {
+                        return Ok(Message { channel, payload, pattern: Some(pattern) });
+                    },
+                    // late (un)subscribe confirmations and pongs are skipped
+                    _ => continue,
+                }
+            }
+        }
+    }
+
+    // ---POOL---
+
+    // connections are opened lazily up to max_size, get() waits while all of them are in use
+    #[derive(Debug, Clone)]
+    pub struct Pool {
+        client: Client,
+        idle: Arc<std::sync::Mutex<Vec<Connection>>>,
+        permits: Arc<Semaphore>,
+    }
+
+    impl Pool {
+        pub async fn get(&self) -> ClientResult<PooledConnection> {
+            let permit = self.permits.clone().acquire_owned().await.expect("pool semaphore is never closed");
+            let idle = self.idle.lock().unwrap().pop();
+            let conn = match idle {
+                Some(conn) => conn,
+                None => self.client.get_connection().await?,
+            };
+            Ok(PooledConnection { conn: Some(conn), idle: self.idle.clone(), _permit: permit })
+        }
+
+        // connections currently sitting in the pool
+        pub fn idle_count(&self) -> usize {
+            self.idle.lock().unwrap().len()
+        }
+    }
+
+    // a connection borrowed from a Pool, goes back to the pool when dropped unless it broke while in use or was
+    // dropped with replies still owed or inside a MULTI, where the next user would read someone else's replies
+    #[derive(Debug)]
+    pub struct PooledConnection {
+        conn: Option<Connection>,
+        idle: Arc<std::sync::Mutex<Vec<Connection>>>,
+        _permit: OwnedSemaphorePermit,
+    }
+
+    impl Deref for PooledConnection {
+        type Target = Connection;
+
+        fn deref(&self) -> &Connection {
+            self.conn.as_ref().unwrap()
+        }
+    }
+
+    impl DerefMut for PooledConnection {
+        fn deref_mut(&mut self) -> &mut Connection {
+            self.conn.as_mut().unwrap()
+        }
+    }
+
+    impl Drop for PooledConnection {
+        fn drop(&mut self) {
+            if let Some(conn) = self.conn.take() {
+                if conn.is_reusable() {
+                    self.idle.lock().unwrap().push(conn);
+                }
+            }
+        }
+    }
+}
@@ -1,13 +1,14 @@
-use std::{collections::{HashMap, HashSet}, io::ErrorKind, sync::Arc, time::SystemTime, vec};
+#![allow(clippy::module_inception)]
+use std::{collections::{HashMap, HashSet}, sync::Arc};
 use clap::Parser;
 use tokio::{io::{AsyncReadExt, AsyncWriteExt}, net::{TcpListener, TcpStream}, select, sync::{broadcast, Mutex}};
 use crate::utils::utils::*;
 use crate::methods::methods::*;
 pub mod methods;
 pub mod utils;
-
-// EXTRA things to do 
-//  1-> find out why using read_buf doesnt work in the conn fn 
+pub mod client;
+pub mod cli;
+pub mod bench;
 
 #[tokio::main]
 pub async fn redis_cli<I: Iterator<Item=String>>(argv: I) {
@@ -22,6 +23,7 @@ pub async fn redis_cli<I: Iterator<Item=String>>(argv: I) {
         config_args.queueing = false; 
     }  
     config_args.subbed_chans = HashMap::new();
+    config_args.subbed_patterns = HashMap::new();
     config_args.client_in_sub_mode = false; 
 
     let listener = TcpListener::bind(format!("127.0.0.1:{}", config_args.port)).await.unwrap();
@@ -40,15 +42,14 @@ pub async fn redis_cli<I: Iterator<Item=String>>(argv: I) {
 async fn slave_conn(listener :TcpListener, config_args: Args) {
     println!("is a slave");
 
-    let _db: Arc<Mutex<HashMap<String, (RDBValue, Option<SystemTime>)>>>  = Arc::new(Mutex::new(HashMap::new()));
-    let _sorted_set: Arc<Mutex<HashMap<String, SortedSet>>> = Arc::new(Mutex::new(HashMap::new()));
+    let dbs = Databases::new(config_args.databases);
        
     // handshake stage
     let mut input_buf: Vec<u8> = vec![0; 1024];
     let (master_addr, master_port) = config_args.replicaof.split_once(' ').unwrap();
     let mut master_stream = connect_to_master(master_addr, master_port).await;
     println!("connected to master");
-    master_stream.write_all(encode_array(&vec![String::from("PING")], true).as_bytes()).await.unwrap();
+    master_stream.write_all(&encode_command(&["PING"])).await.unwrap();
     // expect PONG
     // input_buf.fill(0);
     input_buf.clear();
@@ -57,7 +58,7 @@ async fn slave_conn(listener :TcpListener, config_args: Args) {
     // expect PONG
 
     // send 2 replconf commands
-    master_stream.write_all(encode_array(&vec![format!("REPLCONF"), format!("listening-port"), format!("{}", config_args.port)], true).as_bytes()).await.unwrap();
+    master_stream.write_all(&encode_command(&["REPLCONF".to_owned(), "listening-port".to_owned(), config_args.port.to_string()])).await.unwrap();
     // input_buf.fill(0);
     input_buf.clear();
     master_stream.read_buf(&mut input_buf).await.unwrap();  // single call works because we arent transmitting large amounts of data
@@ -65,14 +66,14 @@ async fn slave_conn(listener :TcpListener, config_args: Args) {
     // expect OK
     // pbas(&input_buf);
 
-    master_stream.write_all(encode_array(&vec![format!("REPLCONF"), format!("capa"), format!("npsnyc2")], true).as_bytes()).await.unwrap();
+    master_stream.write_all(&encode_command(&["REPLCONF", "capa", "npsnyc2"])).await.unwrap();
     // input_buf.fill(0);
     input_buf.clear();
     master_stream.read_buf(&mut input_buf).await.unwrap();
     // expect OK
     // pbas(&input_buf);
 
-    master_stream.write_all(encode_array(&vec![format!("PSYNC"), format!("?"), format!("{}", -1)], true).as_bytes()).await.unwrap();
+    master_stream.write_all(&encode_command(&["PSYNC", "?", "-1"])).await.unwrap();
     // input_buf.fill(0);
     // input_buf.clear();
     // dont read from stream here, read it from the thread so cmds dont get lost in case they arrive in same packets 
@@ -84,26 +85,27 @@ async fn slave_conn(listener :TcpListener, config_args: Args) {
         replicas: HashMap::new(),
         blocked_clients: HashMap::new(),
         subscriptions: HashMap::new(),
+        pattern_subscriptions: HashMap::new(),
         users: HashMap::new(), 
+        notify_keyspace_events: config_args.notify_keyspace_events,
     }));
 
     let (tx, _) = broadcast::channel::<Vec<u8>>(1024);
-    let db_ref = _db.clone();
-    let _sorted_set_ref = _sorted_set.clone();
-    let args_copy = config_args.clone();
+    let dbs_ref = dbs.clone();
+    let mut args_copy = config_args.clone();
+    args_copy.master_link = true;
     let tx1 = tx.clone();
     let rx1 = tx.subscribe();
     let glob_config_ref_copy = glob_config_ref.clone();
     tokio::spawn(async move {
         println!("lauching conn for slave-master");
-        conn(master_stream, args_copy, db_ref, _sorted_set_ref, tx1, rx1, glob_config_ref_copy).await;
+        conn(master_stream, args_copy, dbs_ref, tx1, rx1, glob_config_ref_copy).await;
     });
 
     loop {
         // listen for client connections
         let (stream, sockaddr)  = listener.accept().await.unwrap();
-        let db_ref = _db.clone();
-        let _sorted_set_ref = _sorted_set.clone();
+        let dbs_ref = dbs.clone();
         let mut args_copy = config_args.clone();
         args_copy.other_port = sockaddr.port();
         let glob_config_ref_copy = glob_config_ref.clone();
@@ -112,22 +114,23 @@ async fn slave_conn(listener :TcpListener, config_args: Args) {
         let rx1 = tx.subscribe();
         tokio::spawn(async move {
             // println!("lauching conn for slave-master");
-            conn(stream, args_copy, db_ref, _sorted_set_ref ,tx1, rx1, glob_config_ref_copy).await;
+            conn(stream, args_copy, dbs_ref, tx1, rx1, glob_config_ref_copy).await;
         });
     }
 }
 
 async fn master_conn(listener :TcpListener, config_args: Args) {
     // println!("master connection");
-    let _db: Arc<Mutex<HashMap<String, (RDBValue, Option<SystemTime>)>>>  = Arc::new(Mutex::new(HashMap::new()));
-    let _sorted_set = Arc::new(Mutex::new(HashMap::new()));
+    let dbs = Databases::new(config_args.databases);
 
 
     let master_config_ref = Arc::new(Mutex::new(GlobConfig{ 
         replicas: HashMap::new(),
         blocked_clients: HashMap::new(),
         subscriptions: HashMap::new(),
+        pattern_subscriptions: HashMap::new(),
         users: HashMap::new(),
+        notify_keyspace_events: config_args.notify_keyspace_events,
     }));
     
     if !config_args.dir.starts_with("UNSET") { 
@@ -136,11 +139,13 @@ async fn master_conn(listener :TcpListener, config_args: Args) {
         
         let dbfilepath = "".to_owned() + &config_args.dir + "/" + &config_args.dbfilename;
         if !dbfilepath.starts_with("UNSET") {
-            replication::replication::cmd_sync(&dbfilepath, _db.clone()).await;
+            replication::replication::cmd_sync(&dbfilepath, &dbs).await;
         }
     }
 
     let (tx, _) = broadcast::channel::<Vec<u8>>(1024); 
+    // deletes expired keys nobody asks for anymore
+    tokio::spawn(expiry::expiry::active_expire(dbs.clone(), tx.clone(), master_config_ref.clone()));
     loop {
         // println!("waiting for new clients or replicas");
         // this could be a replication connection or a client connection 
@@ -148,8 +153,7 @@ async fn master_conn(listener :TcpListener, config_args: Args) {
         // println!("new connection to master from {}:{}", &sockaddr.ip(), &sockaddr.port());
        
         // let new_shared_config_args = shared_config_args.clone();
-        let db_ref = _db.clone();
-        let _sorted_set_ref = _sorted_set.clone();
+        let dbs_ref = dbs.clone();
         let master_config_ref_copy = master_config_ref.clone();
         
         // slave will probably not communicate among its connections 
@@ -162,8 +166,7 @@ async fn master_conn(listener :TcpListener, config_args: Args) {
             // print!("{:?}\n", &new_shared_config_args);
             conn(stream, 
                 args_copy, 
-                db_ref, 
-                _sorted_set_ref,
+                dbs_ref, 
                 tx1, 
                 rx1, master_config_ref_copy).await;
         });
@@ -173,8 +176,7 @@ async fn master_conn(listener :TcpListener, config_args: Args) {
 
 async fn conn(mut _stream: TcpStream, 
     mut config_args: Args, 
-    storage_ref: Arc<Mutex<HashMap<String, (RDBValue, Option<SystemTime>)>>>,
-    sorted_set_ref: Arc<Mutex<HashMap<String, SortedSet>>>,
+    dbs: Arc<Databases>,
     tx: broadcast::Sender<Vec<u8>>,
     mut rx:  broadcast::Receiver<Vec<u8>>,
     glob_config: Arc<Mutex<GlobConfig>>) { 
@@ -182,109 +184,159 @@ async fn conn(mut _stream: TcpStream,
     // need to convert thi storage int Arc<Mutex<>> so it can be shared across different connections,
     // if one connection updates, the other can see them
     
-    let mut input_buf: Vec<u8> = vec![0; 1024];
-    let mut cmds = vec![];
-    let mut output: Vec<Vec<u8>> = Vec::new();
+    // bytes read from the socket accumulate here until they form complete frames
+    let mut decoder = FrameDecoder::new();
+    // commands issued after MULTI, executed on EXEC
+    let mut queued: Vec<Command> = vec![];
+    // set when a queued command was rejected, EXEC then discards the whole transaction
+    let mut queue_failed = false;
+    // replies are serialized straight into this buffer, using the protocol the client has at that moment
+    let mut out: Vec<u8> = Vec::new();
 
     loop {
-        // 0s are needed to mark end of cmds 
-        input_buf.fill(0);
+        let mut cmds = vec![];
+        decoder.reserve();
         select! {
-            // using read_buf doesnt work here, find why
-            _ = _stream.readable() => {
-                match _stream.try_read(&mut input_buf) {
-                    Ok(bytes_rx) => {
-                        if bytes_rx == 0 {
-                           break;
-                        }
-                        // pbas(&input_buf);
-                    },
-                    Err(ref e) if e.kind() == ErrorKind::WouldBlock => {
-                        // this error handling in necessary otherwise it would necessarily block
-                        continue;
+            res = _stream.read_buf(&mut decoder.buf) => {
+                match res {
+                    Ok(0) => {
+                        break;
                     },
+                    Ok(_) => {},
                     Err(e) => {
                         println!("{}", e);
                         break;
                     } 
                 }
+
+                match decoder.decode() {
+                    Ok(decoded) => {
+                        cmds = decoded;
+                    },
+                    Err(e) => {
+                        // like redis, reply with the error and drop the connection since we can't tell where the next frame starts
+                        _stream.write_all(&redis_err(&format!("ERR {}", e)).to_bytes(config_args.resp3)).await.ok();
+                        break;
+                    }
+                }
             },
             msg = rx.recv() => {
                 let mut flag = false;
-                output = vec![msg.unwrap()];
+                let mut msg = match msg {
+                    Ok(msg) => msg,
+                    // lagged behind the broadcast channel, the skipped messages are lost
+                    Err(_) => continue,
+                };
+                // internal notifications between connections, never sent over the wire
+                let is_event = msg.starts_with(_EVENT_DB_UPDATED_.as_bytes());
+                let is_pmessage = msg.starts_with("*4\r\n$8\r\npmessage".as_bytes());
+                let is_message = is_pmessage || msg.starts_with("*3\r\n$7\r\nmessage".as_bytes());
                 // check if its an encoded message recvd from a publisher
-                if output[0].starts_with("*3\r\n$7\r\nmessage".as_bytes()) {
-                    let (_, contents) = parse_array(0, &output[0]);
-                    //  if this client is not subbed to this channel continue 
-                    if glob_config.lock().await.subscriptions.get(&contents[1]).unwrap_or(&HashSet::new()).get(&config_args.other_port).is_some() {
-                        flag = true;
+                if is_message {
+                    if let Ok(FrameStatus::Complete(_, Some(contents))) = parse_frame(&msg) {
+                        //  if this client is not subbed to this channel (or pattern) continue 
+                        let glob_config = glob_config.lock().await;
+                        let subscriptions = if is_pmessage { &glob_config.pattern_subscriptions } else { &glob_config.subscriptions };
+                        if subscriptions.get(&contents[1]).unwrap_or(&HashSet::new()).get(&config_args.other_port).is_some() {
+                            flag = true;
+                            // messages are broadcast as RESP2 arrays, RESP3 subscribers expect push frames
+                            if config_args.resp3 {
+                                msg[0] = b'>';
+                            }
+                        }
                     }
                 }
 
-                if !flag && !config_args.replica_conn {  
-                    output.clear();
+                // replicas only get the propagated write commands
+                let forward = flag || (config_args.replica_conn && !is_event && !is_message);
+                if !forward {
                     continue;
                 }
-                // print!("recvd on rx: ");
-                // pbas(&output[0]);
+                out.extend(msg);
             }
         }
 
-        if output.is_empty() {  // only parse input_buf if commands recvd over client
-            let mut flag = false;   // for detecting if last issued command was exec
-            for cmd in parse(0, &input_buf) {
-                if cmd.1[0].to_uppercase() == "EXEC" {
-                    if !config_args.queueing {  // if MULTI wasnt issued
-                        output = vec![redis_err(_ERROR_EXEC_WITHOUT_MULTI_).as_bytes().to_owned()];
-                    }
-                    config_args.queueing = false;
-                    flag = true;
+        // commands are handled one at a time so that pipelined MULTI/EXEC blocks are queued correctly
+        for cmd in cmds {
+            // nothing after QUIT is executed
+            if config_args.closing {
+                break;
+            }
+            let cmd_name = arg_str(&cmd.1[0]).to_uppercase();
+            if cmd_name == "EXEC" {
+                if !config_args.queueing {  // if MULTI wasnt issued
+                    redis_err(_ERROR_EXEC_WITHOUT_MULTI_).write_to(&mut out, config_args.resp3);
                     continue;
-                } else if cmd.1[0].to_uppercase() == "DISCARD" {
-                    if !config_args.queueing {
-                        output = vec![redis_err(_ERROR_DISCARD_WITHOUT_MULTI_).as_bytes().to_owned()];
-                        continue;
-                    }
-                    config_args.queueing = false;
-                    cmds.clear();
                 }
-                cmds.push(cmd);
-            }  // these are basically commands, at one point we will have to parse commands with their parameters, they could be int, boolean etc.   
-            
-            // println!("exec: {:?}", cmds);
-            if config_args.queueing {
-                output = vec![encode_simple(&vec!["QUEUED"]).as_bytes().to_owned()];
-            } else {
-                if output.is_empty() {
-                    output = cmd_exec(&cmds,
-                        &mut config_args,
-                        storage_ref.clone(),
-                        sorted_set_ref.clone(),
-                        tx.clone(),
-                        glob_config.clone()).await;
-
-                    if flag {
-                        // if last command was EXEC then encode all the output into an array
-                        output = vec![encode_array(&output.iter().map(|bytes| {
-                            let mut cmd = String::new();
-                            for &byte in bytes {
-                                cmd.push(byte as char);
-                            }
+                config_args.queueing = false;
+                if queue_failed {
+                    queued.clear();
+                    queue_failed = false;
+                    redis_err(_ERROR_EXECABORT_).write_to(&mut out, config_args.resp3);
+                    continue;
+                }
+                let results = cmd_exec(&queued,
+                    &mut config_args,
+                    dbs.clone(),
+                    tx.clone(),
+                    glob_config.clone()).await;
+                queued.clear();
 
-                            cmd
-                        }).collect(), false).as_bytes().to_vec().to_owned()];
-                    }
+                // all the output of the transaction goes out as one array
+                RespValue::Array(results).write_to(&mut out, config_args.resp3);
+            } else if cmd_name == "DISCARD" {
+                if !config_args.queueing {
+                    redis_err(_ERROR_DISCARD_WITHOUT_MULTI_).write_to(&mut out, config_args.resp3);
+                    continue;
+                }
+                queued.clear();
+                queue_failed = false;
+                for reply in cmd_exec(&[cmd],
+                    &mut config_args,
+                    dbs.clone(),
+                    tx.clone(),
+                    glob_config.clone()).await {
+                    reply.write_to(&mut out, config_args.resp3);
+                }
+            } else if config_args.queueing {
+                if cmd_name == "MULTI" {
+                    redis_err(_ERROR_NESTED_MULTI_).write_to(&mut out, config_args.resp3);
+                    continue;
+                }
+                if let Err(e) = command::command::lookup_command(&cmd.1) {
+                    e.write_to(&mut out, config_args.resp3);
+                    queue_failed = true;
+                    continue;
+                }
+                queued.push(cmd);
+                RespValue::simple("QUEUED").write_to(&mut out, config_args.resp3);
+            } else {
+                // HELLO switches the protocol, so each reply is serialized right after its command runs
+                for reply in cmd_exec(&[cmd],
+                    &mut config_args,
+                    dbs.clone(),
+                    tx.clone(),
+                    glob_config.clone()).await {
+                    reply.write_to(&mut out, config_args.resp3);
                 }
-                cmds.clear();
             }
-        } 
+        }
+
+        if out.is_empty() {
+            continue;
+        }
 
-        for out in &output {
-            // for debugging
-            // println!("sending: {}", out.iter().map(|ch| {*ch as char}).collect::<String>());
-            _stream.write_all(out).await.unwrap();
-            // if you wanna use try_write() you will need to manually send data piece by piece by tracking bytes sent
+        // write all the replies of this batch in one go
+        // for debugging
+        // println!("sending: {}", out.iter().map(|ch| {*ch as char}).collect::<String>());
+        let res = _stream.write_all(&out).await;
+        out.clear();
+        if let Err(e) = res {
+            println!("{}", e);
+            break;
+        }
+        if config_args.closing {
+            break;
         }
-        output.clear();
     }
 }
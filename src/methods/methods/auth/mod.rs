@@ -5,12 +5,13 @@ pub mod auth {
 
     use crate::utils::utils::*;
 
-    fn cmd_list(cmds: &Vec<(usize, Vec<String>)>, 
+    #[allow(dead_code)]
+    fn cmd_list(_cmds: &[(usize, Vec<Vec<u8>>)],
         // config_args: &mut Args,
         // storage_ref: Arc<Mutex<HashMap<String, (RDBValue, Option<SystemTime>)>>>,
         // sorted_set_ref: Arc<Mutex<HashMap<String, SortedSet>>>,
         // tx: broadcast::Sender<Vec<u8>>,
-        glob_config: Arc<Mutex<GlobConfig>>) -> String {
+        _glob_config: Arc<Mutex<GlobConfig>>) -> RespValue {
 
         // return an array of [for each user: bulk string encoded "{username} "on/off" {password}"]; on/off stand for enabled disabled  
 
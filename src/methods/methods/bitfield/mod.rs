@@ -0,0 +1,215 @@
+pub mod bitfield {
+    // BITFIELD treats a string as an array of integers of any width from 1 to 64 bits(63 for unsigned ones),
+    // stored most significant bit first at any bit offset, the same bit order SETBIT uses
+    use crate::methods::methods::bitmaps::bitmaps::*;
+    use crate::utils::utils::*;
+
+    const _ERROR_BITFIELD_TYPE_: &str = "ERR Invalid bitfield type. Use something like i16 u8. Note that u64 is not supported but i64 is.";
+
+    #[derive(Debug, Clone, Copy)]
+    struct FieldType {
+        signed: bool,
+        bits: usize,
+    }
+
+    impl FieldType {
+        fn min(&self) -> i128 {
+            if self.signed { -(1 << (self.bits - 1)) } else { 0 }
+        }
+
+        fn max(&self) -> i128 {
+            if self.signed { (1 << (self.bits - 1)) - 1 } else { (1 << self.bits) - 1 }
+        }
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    enum Overflow {
+        Wrap,
+        Sat,
+        Fail,
+    }
+
+    #[derive(Debug)]
+    enum FieldOp {
+        Get(FieldType, usize),
+        Set(FieldType, usize, i64, Overflow),
+        IncrBy(FieldType, usize, i64, Overflow),
+    }
+
+    // i8, u16 and friends
+    fn parse_type(arg: &[u8]) -> Result<FieldType, RespValue> {
+        let signed = match arg.first().map(u8::to_ascii_lowercase) {
+            Some(b'i') => true,
+            Some(b'u') => false,
+            _ => return Err(redis_err(_ERROR_BITFIELD_TYPE_)),
+        };
+        match parse_arg::<usize>(&arg[1..]) {
+            Some(bits) if bits >= 1 && (bits <= 63 || (signed && bits == 64)) => Ok(FieldType { signed, bits }),
+            _ => Err(redis_err(_ERROR_BITFIELD_TYPE_)),
+        }
+    }
+
+    // a bit offset, or with a leading '#' an index into the array of fields of this width
+    fn parse_offset(arg: &[u8], field: FieldType) -> Result<usize, RespValue> {
+        let offset = match arg.strip_prefix(b"#") {
+            Some(index) => parse_arg::<i64>(index).and_then(|index| index.checked_mul(field.bits as i64)),
+            None => parse_arg::<i64>(arg),
+        };
+        match offset {
+            Some(offset) if offset >= 0 && offset + field.bits as i64 - 1 <= _MAX_BIT_OFFSET_ => Ok(offset as usize),
+            _ => Err(redis_err(_ERROR_BIT_OFFSET_)),
+        }
+    }
+
+    fn parse_ops(cmd_args: &[Vec<u8>], read_only: bool) -> Result<Vec<FieldOp>, RespValue> {
+        let mut ops = vec![];
+        let mut overflow = Overflow::Wrap;
+
+        let mut i = 2;
+        while i < cmd_args.len() {
+            let subcommand = arg_str(&cmd_args[i]).to_uppercase();
+            let args_needed = match subcommand.as_str() {
+                "GET" => 2,
+                "SET" | "INCRBY" => 3,
+                "OVERFLOW" => 1,
+                _ => return Err(redis_err(_ERROR_SYNTAX_)),
+            };
+            if i + args_needed >= cmd_args.len() {
+                return Err(redis_err(_ERROR_SYNTAX_));
+            }
+            if read_only && subcommand != "GET" {
+                return Err(redis_err("ERR BITFIELD_RO only supports the GET subcommand"));
+            }
+            let args = &cmd_args[i + 1..=i + args_needed];
+            i += args_needed + 1;
+
+            if subcommand == "OVERFLOW" {
+                overflow = match arg_str(&args[0]).to_uppercase().as_str() {
+                    "WRAP" => Overflow::Wrap,
+                    "SAT" => Overflow::Sat,
+                    "FAIL" => Overflow::Fail,
+                    _ => return Err(redis_err("ERR Invalid OVERFLOW type specified")),
+                };
+                continue;
+            }
+
+            let field = parse_type(&args[0])?;
+            let offset = parse_offset(&args[1], field)?;
+            if subcommand == "GET" {
+                ops.push(FieldOp::Get(field, offset));
+                continue;
+            }
+            let n = match parse_arg::<i64>(&args[2]) {
+                Some(n) => n,
+                None => return Err(redis_err(_ERROR_INCR_NOT_AN_INT_)),
+            };
+            ops.push(match subcommand.as_str() {
+                "SET" => FieldOp::Set(field, offset, n, overflow),
+                _ => FieldOp::IncrBy(field, offset, n, overflow),
+            });
+        }
+
+        Ok(ops)
+    }
+
+    fn read_field(bytes: &[u8], field: FieldType, offset: usize) -> i128 {
+        let mut raw: u64 = 0;
+        for pos in offset..offset + field.bits {
+            let bit = bytes.get(pos >> 3).map_or(0, |byte| (byte >> (7 - (pos & 7))) & 1);
+            raw = (raw << 1) | bit as u64;
+        }
+        if field.signed && field.bits < 64 && raw >> (field.bits - 1) & 1 == 1 {
+            // sign extension
+            raw |= u64::MAX << field.bits;
+        }
+        if field.signed { raw as i64 as i128 } else { raw as i128 }
+    }
+
+    fn write_field(bytes: &mut [u8], field: FieldType, offset: usize, value: i128) {
+        let raw = value as u64;
+        for (k, pos) in (offset..offset + field.bits).enumerate() {
+            let mask = 1 << (7 - (pos & 7));
+            if (raw >> (field.bits - 1 - k)) & 1 == 1 {
+                bytes[pos >> 3] |= mask;
+            } else {
+                bytes[pos >> 3] &= !mask;
+            }
+        }
+    }
+
+    // fit a value into the range of the field, None when it doesn't fit and the policy is FAIL
+    fn apply_overflow(value: i128, field: FieldType, overflow: Overflow) -> Option<i128> {
+        if (field.min()..=field.max()).contains(&value) {
+            return Some(value);
+        }
+        match overflow {
+            Overflow::Fail => None,
+            Overflow::Sat => Some(value.clamp(field.min(), field.max())),
+            Overflow::Wrap => {
+                // keep the low bits and read them back as the field type
+                let wrapped = value.rem_euclid(1 << field.bits);
+                Some(if field.signed && wrapped > field.max() { wrapped - (1 << field.bits) } else { wrapped })
+            }
+        }
+    }
+
+    // BITFIELD key [GET type offset | SET type offset value | INCRBY type offset increment | OVERFLOW WRAP | SAT | FAIL ...]
+    // and BITFIELD_RO key [GET type offset ...], replies with one entry per GET, SET(the old value) and INCRBY(the new
+    // value), nil for writes refused by OVERFLOW FAIL. Returns the command to replicate when the key was written
+    pub async fn cmd_bitfield(cmd_args: &[Vec<u8>], read_only: bool, storage_ref: Storage) -> (RespValue, Option<Vec<Vec<u8>>>) {
+        let ops = match parse_ops(cmd_args, read_only) {
+            Ok(ops) => ops,
+            Err(e) => return (e, None),
+        };
+        let key = &cmd_args[1];
+        let mut storage = storage_ref.lock().await;
+
+        // highest byte touched by a write, the string grows to it up front like SETBIT would
+        let write_len = ops.iter().filter_map(|op| match op {
+            FieldOp::Set(field, offset, ..) | FieldOp::IncrBy(field, offset, ..) => Some(((offset + field.bits - 1) >> 3) + 1),
+            FieldOp::Get(..) => None,
+        }).max();
+        let Some(write_len) = write_len else {
+            let bytes = match bitmap(&storage, key) {
+                Ok(bytes) => bytes.unwrap_or_default(),
+                Err(e) => return (e, None),
+            };
+            let values = ops.iter().map(|op| match op {
+                FieldOp::Get(field, offset) => RespValue::Integer(read_field(bytes, *field, *offset) as i64),
+                _ => unreachable!("only reads are left"),
+            }).collect();
+            return (RespValue::Array(values), None);
+        };
+
+        let bytes = match bitmap_mut(&mut storage, key) {
+            Ok(bytes) => bytes,
+            Err(e) => return (e, None),
+        };
+        if bytes.len() < write_len {
+            bytes.resize(write_len, 0);
+        }
+
+        let mut values = vec![];
+        for op in &ops {
+            let value = match *op {
+                FieldOp::Get(field, offset) => Some(read_field(bytes, field, offset)),
+                FieldOp::Set(field, offset, value, overflow) => {
+                    let old = read_field(bytes, field, offset);
+                    apply_overflow(value as i128, field, overflow).map(|value| {
+                        write_field(bytes, field, offset, value);
+                        old
+                    })
+                },
+                FieldOp::IncrBy(field, offset, increment, overflow) => {
+                    let old = read_field(bytes, field, offset);
+                    apply_overflow(old + increment as i128, field, overflow).inspect(|&value| {
+                        write_field(bytes, field, offset, value);
+                    })
+                },
+            };
+            values.push(value.map_or(RespValue::Null, |value| RespValue::Integer(value as i64)));
+        }
+
+        (RespValue::Array(values), Some(cmd_args.to_vec()))
+    }
+}
@@ -0,0 +1,260 @@
+pub mod bitmaps {
+    // bit level commands on string values, bit 0 is the most significant bit of the first byte like in redis.
+    // bitmaps can be megabytes big so values are read and changed in place rather than copied out of the storage
+    use crate::utils::utils::*;
+
+    // SETBIT can address 2^32 bits, which is the 512MB a string may hold
+    pub const _MAX_BIT_OFFSET_: i64 = (1 << 32) - 1;
+    pub const _ERROR_BIT_OFFSET_: &str = "ERR bit offset is not an integer or out of range";
+
+    // string value of a key without copying it, None for missing or expired keys
+    pub fn bitmap<'a>(storage: &'a Entries, key: &[u8]) -> Result<Option<&'a [u8]>, RespValue> {
+        match storage.get(key) {
+            Some((_, exp_ts)) if is_expired(exp_ts) => Ok(None),
+            Some((RDBValue::String(s), _)) => Ok(Some(s)),
+            Some(_) => Err(redis_err(_ERROR_WRONGTYPE_)),
+            None => Ok(None),
+        }
+    }
+
+    // string value of a key to change in place, missing or expired keys start out as an empty string
+    pub fn bitmap_mut<'a>(storage: &'a mut Entries, key: &[u8]) -> Result<&'a mut Vec<u8>, RespValue> {
+        let live = storage.get(key).is_some_and(|(_, exp_ts)| !is_expired(exp_ts));
+        if !live {
+            storage.insert(key.to_vec(), (RDBValue::String(vec![]), None));
+        }
+        match storage.get_mut(key) {
+            Some((RDBValue::String(s), _)) => Ok(s),
+            _ => Err(redis_err(_ERROR_WRONGTYPE_)),
+        }
+    }
+
+    fn parse_bit_offset(arg: &[u8]) -> Result<usize, RespValue> {
+        match parse_arg::<i64>(arg) {
+            Some(offset) if (0..=_MAX_BIT_OFFSET_).contains(&offset) => Ok(offset as usize),
+            _ => Err(redis_err(_ERROR_BIT_OFFSET_)),
+        }
+    }
+
+    fn get_bit(bytes: &[u8], offset: usize) -> u8 {
+        bytes.get(offset >> 3).map_or(0, |byte| (byte >> (7 - (offset & 7))) & 1)
+    }
+
+    // SETBIT key offset value, grows the string with zero bytes as needed and returns the previous bit
+    pub async fn cmd_setbit(key: &[u8], offset: &[u8], value: &[u8], storage_ref: Storage) -> RespValue {
+        let offset = match parse_bit_offset(offset) {
+            Ok(offset) => offset,
+            Err(e) => return e,
+        };
+        let on = match value {
+            b"0" => false,
+            b"1" => true,
+            _ => return redis_err("ERR bit is not an integer or out of range"),
+        };
+
+        let mut storage = storage_ref.lock().await;
+        let bytes = match bitmap_mut(&mut storage, key) {
+            Ok(bytes) => bytes,
+            Err(e) => return e,
+        };
+        let byte = offset >> 3;
+        if bytes.len() <= byte {
+            bytes.resize(byte + 1, 0);
+        }
+        let mask = 1 << (7 - (offset & 7));
+        let old = (bytes[byte] & mask != 0) as i64;
+        if on {
+            bytes[byte] |= mask;
+        } else {
+            bytes[byte] &= !mask;
+        }
+
+        RespValue::Integer(old)
+    }
+
+    // GETBIT key offset, bits past the end of the string are 0
+    pub async fn cmd_getbit(key: &[u8], offset: &[u8], storage_ref: Storage) -> RespValue {
+        let offset = match parse_bit_offset(offset) {
+            Ok(offset) => offset,
+            Err(e) => return e,
+        };
+
+        let storage = storage_ref.lock().await;
+        match bitmap(&storage, key) {
+            Ok(bytes) => RespValue::Integer(get_bit(bytes.unwrap_or_default(), offset) as i64),
+            Err(e) => e,
+        }
+    }
+
+    // optional `start [end [BYTE | BIT]]` range of BITCOUNT and BITPOS, returns (start, end, counts in bits)
+    fn parse_range(args: &[Vec<u8>]) -> Result<(Option<i64>, Option<i64>, bool), RespValue> {
+        let mut bounds = [None, None];
+        for (bound, arg) in bounds.iter_mut().zip(args) {
+            match parse_arg::<i64>(arg) {
+                Some(n) => *bound = Some(n),
+                None => return Err(redis_err(_ERROR_INCR_NOT_AN_INT_)),
+            }
+        }
+        let bit_mode = match args.get(2).map(|unit| arg_str(unit).to_uppercase()) {
+            None => false,
+            Some(unit) if unit == "BYTE" && args.len() == 3 => false,
+            Some(unit) if unit == "BIT" && args.len() == 3 => true,
+            Some(_) => return Err(redis_err(_ERROR_SYNTAX_)),
+        };
+        Ok((bounds[0], bounds[1], bit_mode))
+    }
+
+    // inclusive range clamped to a value of len units, negative indexes count from the end, None when it is empty
+    fn clamp_range(start: i64, end: i64, len: usize) -> Option<(usize, usize)> {
+        let len = len as i64;
+        if (start < 0 && end < 0 && start > end) || len == 0 {
+            return None;
+        }
+        let start = if start < 0 { (len + start).max(0) } else { start };
+        let end = if end < 0 { (len + end).max(0) } else { end.min(len - 1) };
+        (start <= end).then_some((start as usize, end as usize))
+    }
+
+    fn popcount(bytes: &[u8]) -> usize {
+        let words = bytes.chunks_exact(8);
+        let tail = words.remainder().iter().map(|byte| byte.count_ones() as usize).sum::<usize>();
+        words.map(|word| u64::from_ne_bytes(word.try_into().unwrap()).count_ones() as usize).sum::<usize>() + tail
+    }
+
+    // BITCOUNT key [start end [BYTE | BIT]]
+    pub async fn cmd_bitcount(cmd_args: &[Vec<u8>], storage_ref: Storage) -> RespValue {
+        let (start, end, bit_mode) = match parse_range(&cmd_args[2..]) {
+            Ok((Some(_), None, _)) => return redis_err(_ERROR_SYNTAX_),
+            Ok(range) => range,
+            Err(e) => return e,
+        };
+
+        let storage = storage_ref.lock().await;
+        let bytes = match bitmap(&storage, &cmd_args[1]) {
+            Ok(bytes) => bytes.unwrap_or_default(),
+            Err(e) => return e,
+        };
+
+        let len = if bit_mode { bytes.len() * 8 } else { bytes.len() };
+        let (first, last) = match clamp_range(start.unwrap_or(0), end.unwrap_or(-1), len) {
+            Some(range) if bit_mode => range,
+            Some((first, last)) => (first * 8, last * 8 + 7),
+            None => return RespValue::Integer(0),
+        };
+
+        // whole bytes of the range, minus the bits of the first and last byte that fall outside of it
+        let mut count = popcount(&bytes[first >> 3..=last >> 3]);
+        count -= (bytes[first >> 3] & !(0xff >> (first & 7))).count_ones() as usize;
+        count -= (bytes[last >> 3] & ((1u16 << (7 - (last & 7))) - 1) as u8).count_ones() as usize;
+
+        RespValue::Integer(count as i64)
+    }
+
+    // first bit set to `bit` in the inclusive bit range, whole bytes that can't hold it are skipped
+    fn find_bit(bytes: &[u8], bit: u8, first: usize, last: usize) -> Option<usize> {
+        let skip = if bit == 1 { 0x00 } else { 0xff };
+        let mut pos = first;
+        while pos <= last {
+            if pos & 7 == 0 && pos + 7 <= last {
+                let (from, to) = (pos >> 3, (last + 1) >> 3);
+                match bytes[from..to].iter().position(|&byte| byte != skip) {
+                    Some(k) => pos = (from + k) * 8,
+                    None => {
+                        pos = to * 8;
+                        continue;
+                    }
+                }
+            }
+            if get_bit(bytes, pos) == bit {
+                return Some(pos);
+            }
+            pos += 1;
+        }
+        None
+    }
+
+    // BITPOS key bit [start [end [BYTE | BIT]]]
+    pub async fn cmd_bitpos(cmd_args: &[Vec<u8>], storage_ref: Storage) -> RespValue {
+        let bit = match cmd_args[2].as_slice() {
+            b"0" => 0,
+            b"1" => 1,
+            _ => return redis_err("ERR The bit argument must be 1 or 0."),
+        };
+        let (start, end, bit_mode) = match parse_range(&cmd_args[3..]) {
+            Ok(range) => range,
+            Err(e) => return e,
+        };
+
+        let storage = storage_ref.lock().await;
+        let bytes = match bitmap(&storage, &cmd_args[1]) {
+            Ok(Some(bytes)) => bytes,
+            // a missing key is an empty string of zeros
+            Ok(None) => return RespValue::Integer(if bit == 1 { -1 } else { 0 }),
+            Err(e) => return e,
+        };
+
+        let len = if bit_mode { bytes.len() * 8 } else { bytes.len() };
+        let (first, last) = match clamp_range(start.unwrap_or(0), end.unwrap_or(-1), len) {
+            Some(range) if bit_mode => range,
+            Some((first, last)) => (first * 8, last * 8 + 7),
+            None => return RespValue::Integer(-1),
+        };
+
+        match find_bit(bytes, bit, first, last) {
+            Some(pos) => RespValue::Integer(pos as i64),
+            // without an explicit end the string counts as padded with zeros on the right
+            None if bit == 0 && end.is_none() => RespValue::Integer(last as i64 + 1),
+            None => RespValue::Integer(-1),
+        }
+    }
+
+    // BITOP AND | OR | XOR | NOT destkey key [key ...], shorter strings are padded with zeros, returns the length
+    // of the result which replaces the destination, an empty result deletes it
+    pub async fn cmd_bitop(cmd_args: &[Vec<u8>], storage_ref: Storage) -> RespValue {
+        let op = arg_str(&cmd_args[1]).to_uppercase();
+        if !["AND", "OR", "XOR", "NOT"].contains(&op.as_str()) {
+            return redis_err(_ERROR_SYNTAX_);
+        }
+        if op == "NOT" && cmd_args.len() != 4 {
+            return redis_err("ERR BITOP NOT must be called with a single source key.");
+        }
+
+        let mut storage = storage_ref.lock().await;
+        let mut sources = vec![];
+        for key in &cmd_args[3..] {
+            match bitmap(&storage, key) {
+                Ok(bytes) => sources.push(bytes.unwrap_or_default()),
+                Err(e) => return e,
+            }
+        }
+
+        let len = sources.iter().map(|bytes| bytes.len()).max().unwrap_or(0);
+        let mut result = sources[0].to_vec();
+        result.resize(len, 0);
+        match op.as_str() {
+            "NOT" => result.iter_mut().for_each(|byte| *byte = !*byte),
+            _ => {
+                for source in &sources[1..] {
+                    match op.as_str() {
+                        "AND" => {
+                            result.iter_mut().zip(source.iter()).for_each(|(byte, other)| *byte &= other);
+                            // past its end the source reads as zeros
+                            result[source.len()..].fill(0);
+                        },
+                        "OR" => result.iter_mut().zip(source.iter()).for_each(|(byte, other)| *byte |= other),
+                        _ => result.iter_mut().zip(source.iter()).for_each(|(byte, other)| *byte ^= other),
+                    }
+                }
+            },
+        }
+
+        let dest = cmd_args[2].clone();
+        if result.is_empty() {
+            storage.remove(&dest);
+        } else {
+            storage.insert(dest, (RDBValue::String(result), None));
+        }
+
+        RespValue::Integer(len as i64)
+    }
+}
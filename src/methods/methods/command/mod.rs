@@ -0,0 +1,1309 @@
+// the command table, every command the server understands is declared here along with its arity,
+// flags and key positions, cmd_exec dispatches through it and COMMAND reports from it
+pub mod command {
+    use std::future::Future;
+    use std::pin::Pin;
+    use std::sync::Arc;
+    use tokio::sync::{broadcast, Mutex};
+    use crate::methods::methods::*;
+    use crate::utils::utils::*;
+
+    // everything a handler can touch while running a single command
+    pub struct CommandCtx<'a> {
+        pub config_args: &'a mut Args,
+        pub cmd_args: &'a [Vec<u8>],
+        // size of this command on the wire
+        pub bytes_rx: usize,
+        pub storage_ref: Storage,
+        // all the databases, storage_ref is the one of the selected database
+        pub dbs: Arc<Databases>,
+        pub tx: broadcast::Sender<Vec<u8>>,
+        pub glob_config: Arc<Mutex<GlobConfig>>,
+        pub dbfilepath: String,
+    }
+
+    // handlers return the replies to send back, possibly none(replicas don't answer their master) or several(PSYNC)
+    pub type HandlerFuture<'a> = Pin<Box<dyn Future<Output = Vec<RespValue>> + Send + 'a>>;
+    pub type Handler = for<'a, 'b> fn(&'a mut CommandCtx<'b>) -> HandlerFuture<'a>;
+    // for commands whose keys can't be described by first/last/step, returns the key indexes
+    pub type GetKeys = fn(&[Vec<u8>]) -> Vec<usize>;
+
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub enum CommandFlag {
+        Write,
+        Readonly,
+        // may grow memory usage
+        Denyoom,
+        Admin,
+        Pubsub,
+        Blocking,
+        Fast,
+    }
+
+    impl CommandFlag {
+        pub fn name(&self) -> &'static str {
+            match self {
+                CommandFlag::Write => "write",
+                CommandFlag::Readonly => "readonly",
+                CommandFlag::Denyoom => "denyoom",
+                CommandFlag::Admin => "admin",
+                CommandFlag::Pubsub => "pubsub",
+                CommandFlag::Blocking => "blocking",
+                CommandFlag::Fast => "fast",
+            }
+        }
+    }
+
+    pub struct RedisCommand {
+        // lowercase, like redis reports it
+        pub name: &'static str,
+        // positive means exactly that many arguments(command name included), negative means at least -arity
+        pub arity: i64,
+        pub flags: &'static [CommandFlag],
+        // key positions, a negative last_key counts from the end, 0 0 0 for commands without keys
+        pub first_key: i64,
+        pub last_key: i64,
+        pub step: i64,
+        pub getkeys: Option<GetKeys>,
+        pub group: &'static str,
+        pub since: &'static str,
+        pub summary: &'static str,
+        pub handler: Handler,
+    }
+
+    impl RedisCommand {
+        pub fn has_flag(&self, flag: CommandFlag) -> bool {
+            self.flags.contains(&flag)
+        }
+
+        pub fn arity_ok(&self, argc: usize) -> bool {
+            if self.arity >= 0 {
+                argc as i64 == self.arity
+            } else {
+                argc as i64 >= -self.arity
+            }
+        }
+
+        // indexes of the key arguments of cmd_args
+        pub fn key_indexes(&self, cmd_args: &[Vec<u8>]) -> Vec<usize> {
+            if let Some(getkeys) = self.getkeys {
+                return getkeys(cmd_args);
+            }
+            if self.first_key <= 0 {
+                return vec![];
+            }
+
+            let argc = cmd_args.len() as i64;
+            let last = if self.last_key < 0 { argc + self.last_key } else { self.last_key };
+            (self.first_key..=last.min(argc - 1)).step_by(self.step as usize).map(|i| i as usize).collect()
+        }
+
+        fn acl_categories(&self) -> Vec<String> {
+            let mut categories = vec![];
+            let group = match self.group {
+                "sorted-set" => Some("sortedset"),
+                "transactions" => Some("transaction"),
+                "generic" => Some("keyspace"),
+                "server" => None,
+                group => Some(group),
+            };
+            if let Some(group) = group {
+                categories.push(format!("@{}", group));
+            }
+            if self.has_flag(CommandFlag::Write) {
+                categories.push("@write".to_owned());
+            }
+            if self.has_flag(CommandFlag::Readonly) {
+                categories.push("@read".to_owned());
+            }
+            if self.has_flag(CommandFlag::Admin) {
+                categories.push("@admin".to_owned());
+                categories.push("@dangerous".to_owned());
+            }
+            if self.has_flag(CommandFlag::Pubsub) && group != Some("pubsub") {
+                categories.push("@pubsub".to_owned());
+            }
+            if self.has_flag(CommandFlag::Blocking) {
+                categories.push("@blocking".to_owned());
+            }
+            categories.push(if self.has_flag(CommandFlag::Fast) { "@fast" } else { "@slow" }.to_owned());
+
+            categories
+        }
+
+        // reply entry of COMMAND and COMMAND INFO
+        fn info(&self) -> RespValue {
+            let mut flags: Vec<&str> = self.flags.iter().map(|flag| flag.name()).collect();
+            if self.getkeys.is_some() {
+                flags.push("movablekeys");
+            }
+            let (first_key, last_key, step) = if self.getkeys.is_some() { (0, 0, 0) } else { (self.first_key, self.last_key, self.step) };
+
+            RespValue::Array(vec![
+                RespValue::bulk(self.name),
+                RespValue::Integer(self.arity),
+                RespValue::Set(flags.iter().map(|flag| RespValue::simple(flag)).collect()),
+                RespValue::Integer(first_key),
+                RespValue::Integer(last_key),
+                RespValue::Integer(step),
+                RespValue::Set(self.acl_categories().iter().map(|category| RespValue::simple(category)).collect()),
+                RespValue::Array(vec![]),    // tips
+                RespValue::Array(vec![]),    // key specs
+                RespValue::Array(vec![]),    // subcommands
+            ])
+        }
+
+        // reply entry of COMMAND DOCS
+        fn docs(&self) -> RespValue {
+            RespValue::Map(vec![
+                (RespValue::bulk("summary"), RespValue::bulk(self.summary)),
+                (RespValue::bulk("since"), RespValue::bulk(self.since)),
+                (RespValue::bulk("group"), RespValue::bulk(self.group)),
+            ])
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    const fn cmd(name: &'static str, arity: i64, flags: &'static [CommandFlag], keys: (i64, i64, i64),
+        group: &'static str, since: &'static str, summary: &'static str, handler: Handler) -> RedisCommand {
+        RedisCommand { name, arity, flags, first_key: keys.0, last_key: keys.1, step: keys.2, getkeys: None, group, since, summary, handler }
+    }
+
+    const NO_KEYS: (i64, i64, i64) = (0, 0, 0);
+    const ONE_KEY: (i64, i64, i64) = (1, 1, 1);
+
+    use CommandFlag::*;
+
+    pub static COMMAND_TABLE: &[RedisCommand] = &[
+        // connection
+        cmd("ping", -1, &[Fast], NO_KEYS, "connection", "1.0.0", "Returns the server's liveliness response.", run_ping),
+        cmd("echo", 2, &[Fast], NO_KEYS, "connection", "1.0.0", "Returns the given string.", run_echo),
+        cmd("hello", -1, &[Fast], NO_KEYS, "connection", "6.0.0", "Handshakes with the Redis server.", run_hello),
+        cmd("client", -2, &[], NO_KEYS, "connection", "2.4.0", "A container for client connection commands.", run_client),
+        cmd("select", 2, &[Fast], NO_KEYS, "connection", "2.0.0", "Changes the selected database.", run_select),
+        cmd("quit", -1, &[Fast], NO_KEYS, "connection", "1.0.0", "Closes the connection.", run_quit),
+        // strings
+        cmd("get", 2, &[Readonly, Fast], ONE_KEY, "string", "1.0.0", "Returns the string value of a key.", run_get),
+        cmd("set", -3, &[Write, Denyoom], ONE_KEY, "string", "1.0.0", "Sets the string value of a key, ignoring its type. The key is created if it doesn't exist.", run_set),
+        cmd("incr", 2, &[Write, Denyoom, Fast], ONE_KEY, "string", "1.0.0", "Increments the integer value of a key by one. Uses 0 as initial value if the key doesn't exist.", run_incr),
+        cmd("decr", 2, &[Write, Denyoom, Fast], ONE_KEY, "string", "1.0.0", "Decrements the integer value of a key by one. Uses 0 as initial value if the key doesn't exist.", run_decr),
+        cmd("incrby", 3, &[Write, Denyoom, Fast], ONE_KEY, "string", "1.0.0", "Increments the integer value of a key by a number. Uses 0 as initial value if the key doesn't exist.", run_incrby),
+        cmd("decrby", 3, &[Write, Denyoom, Fast], ONE_KEY, "string", "1.0.0", "Decrements a number from the integer value of a key. Uses 0 as initial value if the key doesn't exist.", run_decrby),
+        cmd("incrbyfloat", 3, &[Write, Denyoom, Fast], ONE_KEY, "string", "2.6.0", "Increment the floating point value of a key by a number. Uses 0 as initial value if the key doesn't exist.", run_incrbyfloat),
+        cmd("mget", -2, &[Readonly, Fast], (1, -1, 1), "string", "1.0.0", "Atomically returns the string values of one or more keys.", run_mget),
+        cmd("mset", -3, &[Write, Denyoom], (1, -1, 2), "string", "1.0.1", "Atomically creates or modifies the string values of one or more keys.", run_mset),
+        cmd("msetnx", -3, &[Write, Denyoom], (1, -1, 2), "string", "1.0.1", "Atomically modifies the string values of one or more keys only when all keys don't exist.", run_msetnx),
+        cmd("append", 3, &[Write, Denyoom, Fast], ONE_KEY, "string", "2.0.0", "Appends a string to the value of a key. Creates the key if it doesn't exist.", run_append),
+        cmd("strlen", 2, &[Readonly, Fast], ONE_KEY, "string", "2.2.0", "Returns the length of a string value.", run_strlen),
+        cmd("getrange", 4, &[Readonly], ONE_KEY, "string", "2.4.0", "Returns a substring of the string stored at a key.", run_getrange),
+        cmd("setrange", 4, &[Write, Denyoom], ONE_KEY, "string", "2.2.0", "Overwrites a part of a string value with another by an offset. Creates the key if it doesn't exist.", run_setrange),
+        cmd("getdel", 2, &[Write, Fast], ONE_KEY, "string", "6.2.0", "Returns the string value of a key after deleting the key.", run_getdel),
+        cmd("getex", -2, &[Write, Fast], ONE_KEY, "string", "6.2.0", "Returns the string value of a key after setting its expiration time.", run_getex),
+        cmd("getset", 3, &[Write, Denyoom, Fast], ONE_KEY, "string", "1.0.0", "Returns the previous string value of a key after setting it to a new value.", run_getset),
+        // bitmaps
+        cmd("setbit", 4, &[Write, Denyoom], ONE_KEY, "bitmap", "2.2.0", "Sets or clears the bit at offset of the string value. Creates the key if it doesn't exist.", run_setbit),
+        cmd("getbit", 3, &[Readonly, Fast], ONE_KEY, "bitmap", "2.2.0", "Returns a bit value by offset.", run_getbit),
+        cmd("bitcount", -2, &[Readonly], ONE_KEY, "bitmap", "2.6.0", "Counts the number of set bits (population counting) in a string.", run_bitcount),
+        cmd("bitpos", -3, &[Readonly], ONE_KEY, "bitmap", "2.8.7", "Finds the first set (1) or clear (0) bit in a string.", run_bitpos),
+        cmd("bitop", -4, &[Write, Denyoom], (2, -1, 1), "bitmap", "2.6.0", "Performs bitwise operations on multiple strings, and stores the result.", run_bitop),
+        cmd("bitfield", -2, &[Write, Denyoom], ONE_KEY, "bitmap", "3.2.0", "Performs arbitrary bitfield integer operations on strings.", run_bitfield),
+        cmd("bitfield_ro", -2, &[Readonly, Fast], ONE_KEY, "bitmap", "6.0.0", "Performs arbitrary read-only bitfield integer operations on strings.", run_bitfield_ro),
+        // hyperloglog
+        cmd("pfadd", -2, &[Write, Denyoom, Fast], ONE_KEY, "hyperloglog", "2.8.9", "Adds elements to a HyperLogLog key. Creates the key if it doesn't exist.", run_pfadd),
+        cmd("pfcount", -2, &[Readonly], (1, -1, 1), "hyperloglog", "2.8.9", "Returns the approximated cardinality of the set(s) observed by the HyperLogLog key(s).", run_pfcount),
+        cmd("pfmerge", -2, &[Write, Denyoom], (1, -1, 1), "hyperloglog", "2.8.9", "Merges one or more HyperLogLog values into a single key.", run_pfmerge),
+        // generic
+        cmd("del", -2, &[Write], (1, -1, 1), "generic", "1.0.0", "Deletes one or more keys.", run_del),
+        cmd("unlink", -2, &[Write, Fast], (1, -1, 1), "generic", "4.0.0", "Asynchronously deletes one or more keys.", run_unlink),
+        cmd("exists", -2, &[Readonly, Fast], (1, -1, 1), "generic", "1.0.0", "Determines whether one or more keys exist.", run_exists),
+        cmd("rename", 3, &[Write], (1, 2, 1), "generic", "1.0.0", "Renames a key and overwrites the destination.", run_rename),
+        cmd("renamenx", 3, &[Write, Fast], (1, 2, 1), "generic", "1.0.0", "Renames a key only when the target key name doesn't exist.", run_renamenx),
+        cmd("move", 3, &[Write, Fast], ONE_KEY, "generic", "1.0.0", "Moves a key to another database.", run_move),
+        cmd("copy", -3, &[Write, Denyoom], (1, 2, 1), "generic", "6.2.0", "Copies the value of a key to a new key.", run_copy),
+        cmd("expire", -3, &[Write, Fast], ONE_KEY, "generic", "1.0.0", "Sets the expiration time of a key in seconds.", run_expire),
+        cmd("pexpire", -3, &[Write, Fast], ONE_KEY, "generic", "2.6.0", "Sets the expiration time of a key in milliseconds.", run_pexpire),
+        cmd("expireat", -3, &[Write, Fast], ONE_KEY, "generic", "1.2.0", "Sets the expiration time of a key to a Unix timestamp.", run_expireat),
+        cmd("pexpireat", -3, &[Write, Fast], ONE_KEY, "generic", "2.6.0", "Sets the expiration time of a key to a Unix milliseconds timestamp.", run_pexpireat),
+        cmd("ttl", 2, &[Readonly, Fast], ONE_KEY, "generic", "1.0.0", "Returns the expiration time in seconds of a key.", run_ttl),
+        cmd("pttl", 2, &[Readonly, Fast], ONE_KEY, "generic", "2.6.0", "Returns the expiration time in milliseconds of a key.", run_pttl),
+        cmd("expiretime", 2, &[Readonly, Fast], ONE_KEY, "generic", "7.0.0", "Returns the expiration time of a key as a Unix timestamp.", run_expiretime),
+        cmd("pexpiretime", 2, &[Readonly, Fast], ONE_KEY, "generic", "7.0.0", "Returns the expiration time of a key as a Unix milliseconds timestamp.", run_pexpiretime),
+        cmd("dump", 2, &[Readonly], ONE_KEY, "generic", "2.6.0", "Returns a serialized representation of the value stored at a key.", run_dump),
+        cmd("restore", -4, &[Write, Denyoom], ONE_KEY, "generic", "2.6.0", "Creates a key from the serialized representation of a value.", run_restore),
+        cmd("persist", 2, &[Write, Fast], ONE_KEY, "generic", "2.2.0", "Removes the expiration time of a key.", run_persist),
+        cmd("keys", 2, &[Readonly], NO_KEYS, "generic", "1.0.0", "Returns all key names that match a pattern.", run_keys),
+        cmd("scan", -2, &[Readonly], NO_KEYS, "generic", "2.8.0", "Iterates over the key names in the database.", run_scan),
+        cmd("object", -2, &[Readonly], (2, 2, 1), "generic", "2.2.3", "A container for object introspection commands.", run_object),
+        cmd("type", 2, &[Readonly, Fast], ONE_KEY, "generic", "1.0.0", "Determines the type of value stored at a key.", run_type),
+        cmd("wait", 3, &[], NO_KEYS, "generic", "3.0.0", "Blocks until the asynchronous replication of all preceding write commands sent by the connection is completed.", run_wait),
+        // server
+        cmd("info", -1, &[], NO_KEYS, "server", "1.0.0", "Returns information and statistics about the server.", run_info),
+        cmd("config", -2, &[Admin], NO_KEYS, "server", "2.0.0", "A container for server configuration commands.", run_config),
+        cmd("dbsize", 1, &[Readonly, Fast], NO_KEYS, "server", "1.0.0", "Returns the number of keys in the database.", run_dbsize),
+        cmd("flushdb", -1, &[Write], NO_KEYS, "server", "1.0.0", "Removes all keys from the current database.", run_flushdb),
+        cmd("flushall", -1, &[Write], NO_KEYS, "server", "1.0.0", "Removes all keys from all databases.", run_flushall),
+        cmd("swapdb", 3, &[Write, Fast], NO_KEYS, "server", "4.0.0", "Swaps two Redis databases.", run_swapdb),
+        cmd("save", 1, &[Admin], NO_KEYS, "server", "1.0.0", "Synchronously saves the database(s) to disk.", run_save),
+        cmd("replconf", -1, &[Admin], NO_KEYS, "server", "3.0.0", "An internal command for configuring the replication stream.", run_replconf),
+        cmd("psync", -3, &[Admin], NO_KEYS, "server", "2.8.0", "An internal command used in replication.", run_psync),
+        cmd("acl", -2, &[], NO_KEYS, "server", "6.0.0", "A container for Access List Control commands.", run_acl),
+        cmd("command", -1, &[], NO_KEYS, "server", "2.8.13", "Returns detailed information about all commands.", run_command),
+        // transactions, EXEC is handled by the connection itself since it needs the queued commands
+        cmd("multi", 1, &[Fast], NO_KEYS, "transactions", "1.2.0", "Starts a transaction.", run_multi),
+        cmd("exec", 1, &[], NO_KEYS, "transactions", "1.2.0", "Executes all commands in a transaction.", run_exec),
+        cmd("discard", 1, &[Fast], NO_KEYS, "transactions", "2.0.0", "Discards a transaction.", run_discard),
+        // lists
+        cmd("rpush", -3, &[Write, Denyoom, Fast], ONE_KEY, "list", "1.0.0", "Appends one or more elements to a list. Creates the key if it doesn't exist.", run_rpush),
+        cmd("lpush", -3, &[Write, Denyoom, Fast], ONE_KEY, "list", "1.0.0", "Prepends one or more elements to a list. Creates the key if it doesn't exist.", run_lpush),
+        cmd("lrange", 4, &[Readonly], ONE_KEY, "list", "1.0.0", "Returns a range of elements from a list.", run_lrange),
+        cmd("llen", 2, &[Readonly, Fast], ONE_KEY, "list", "1.0.0", "Returns the length of a list.", run_llen),
+        cmd("lpop", -2, &[Write, Fast], ONE_KEY, "list", "1.0.0", "Returns the first elements in a list after removing it. Deletes the list if the last element was popped.", run_lpop),
+        cmd("blpop", -3, &[Write, Blocking], (1, -2, 1), "list", "2.0.0", "Removes and returns the first element in a list. Blocks until an element is available otherwise.", run_blpop),
+        // streams
+        cmd("xadd", -5, &[Write, Denyoom, Fast], ONE_KEY, "stream", "5.0.0", "Appends a new message to a stream. Creates the key if it doesn't exist.", run_xadd),
+        cmd("xrange", -4, &[Readonly], ONE_KEY, "stream", "5.0.0", "Returns the messages from a stream within a range of IDs.", run_xrange),
+        RedisCommand {
+            getkeys: Some(xread_keys),
+            ..cmd("xread", -4, &[Readonly, Blocking], NO_KEYS, "stream", "5.0.0", "Returns messages from multiple streams with IDs greater than the ones requested.", run_xread)
+        },
+        // pub/sub
+        cmd("subscribe", -2, &[Pubsub], NO_KEYS, "pubsub", "2.0.0", "Listens for messages published to channels.", run_subscribe),
+        cmd("unsubscribe", -1, &[Pubsub], NO_KEYS, "pubsub", "2.0.0", "Stops listening to messages posted to channels.", run_unsubscribe),
+        cmd("psubscribe", -2, &[Pubsub], NO_KEYS, "pubsub", "2.0.0", "Listens for messages published to channels that match one or more patterns.", run_psubscribe),
+        cmd("punsubscribe", -1, &[Pubsub], NO_KEYS, "pubsub", "2.0.0", "Stops listening to messages published to channels that match one or more patterns.", run_punsubscribe),
+        cmd("publish", 3, &[Pubsub, Fast], NO_KEYS, "pubsub", "2.0.0", "Posts a message to a channel.", run_publish),
+        // sorted sets
+        cmd("zadd", -4, &[Write, Denyoom, Fast], ONE_KEY, "sorted-set", "1.2.0", "Adds one or more members to a sorted set, or updates their scores. Creates the key if it doesn't exist.", run_zadd),
+        cmd("zrank", -3, &[Readonly, Fast], ONE_KEY, "sorted-set", "2.0.0", "Returns the index of a member in a sorted set ordered by ascending scores.", run_zrank),
+        cmd("zrange", -4, &[Readonly], ONE_KEY, "sorted-set", "1.2.0", "Returns members in a sorted set within a range of indexes.", run_zrange),
+        cmd("zcard", 2, &[Readonly, Fast], ONE_KEY, "sorted-set", "1.2.0", "Returns the number of members in a sorted set.", run_zcard),
+        cmd("zscore", 3, &[Readonly, Fast], ONE_KEY, "sorted-set", "1.2.0", "Returns the score of a member in a sorted set.", run_zscore),
+        cmd("zscan", -3, &[Readonly], ONE_KEY, "sorted-set", "2.8.0", "Iterates over members and scores of a sorted set.", run_zscan),
+        cmd("zrem", -3, &[Write, Fast], ONE_KEY, "sorted-set", "1.2.0", "Removes one or more members from a sorted set. Deletes the sorted set if all members were removed.", run_zrem),
+        // geo
+        cmd("geoadd", -5, &[Write, Denyoom], ONE_KEY, "geo", "3.2.0", "Adds one or more members to a geospatial index. The key is created if it doesn't exist.", run_geoadd),
+        cmd("geopos", -2, &[Readonly], ONE_KEY, "geo", "3.2.0", "Returns the longitude and latitude of members from a geospatial index.", run_geopos),
+        cmd("geodist", -4, &[Readonly], ONE_KEY, "geo", "3.2.0", "Returns the distance between two members of a geospatial index.", run_geodist),
+        cmd("geosearch", -7, &[Readonly], ONE_KEY, "geo", "6.2.0", "Queries a geospatial index for members inside an area of a box or a circle.", run_geosearch),
+    ];
+
+    pub fn find_command(name: &[u8]) -> Option<&'static RedisCommand> {
+        COMMAND_TABLE.iter().find(|cmd| cmd.name.as_bytes().eq_ignore_ascii_case(name))
+    }
+
+    // find the command and check its arity, unknown commands and a wrong number of arguments get their error reply back
+    pub fn lookup_command(cmd_args: &[Vec<u8>]) -> Result<&'static RedisCommand, RespValue> {
+        let cmd = match find_command(&cmd_args[0]) {
+            Some(cmd) => cmd,
+            None => {
+                let mut args = String::new();
+                for arg in &cmd_args[1..] {
+                    if args.len() >= 128 {
+                        break;
+                    }
+                    args.push_str(&format!("'{}' ", arg_str(arg).chars().take(128 - args.len()).collect::<String>()));
+                }
+                let msg = format!("ERR unknown command '{}', with args beginning with: {}",
+                    arg_str(&cmd_args[0]).chars().take(128).collect::<String>(), args);
+                // the error is a single line
+                return Err(redis_err(&msg.replace(['\r', '\n'], " ")));
+            }
+        };
+
+        if !cmd.arity_ok(cmd_args.len()) {
+            return Err(redis_err(&format!("ERR wrong number of arguments for '{}' command", cmd.name)));
+        }
+
+        Ok(cmd)
+    }
+
+    fn xread_keys(cmd_args: &[Vec<u8>]) -> Vec<usize> {
+        // XREAD [COUNT count] [BLOCK ms] STREAMS key [key ...] id [id ...]
+        match cmd_args.iter().position(|arg| arg.eq_ignore_ascii_case(b"STREAMS")) {
+            Some(streams) => {
+                let remaining = cmd_args.len() - streams - 1;
+                if remaining == 0 || !remaining.is_multiple_of(2) {
+                    return vec![];
+                }
+                (streams + 1..streams + 1 + remaining / 2).collect()
+            },
+            None => vec![],
+        }
+    }
+
+    // COMMAND [COUNT | INFO [name ...] | DOCS [name ...] | GETKEYS cmd [arg ...]]
+    pub fn cmd_command(cmd_args: &[Vec<u8>]) -> RespValue {
+        if cmd_args.len() == 1 {
+            return RespValue::Array(COMMAND_TABLE.iter().map(|cmd| cmd.info()).collect());
+        }
+
+        match arg_str(&cmd_args[1]).to_uppercase().as_str() {
+            "COUNT" if cmd_args.len() == 2 => {
+                RespValue::Integer(COMMAND_TABLE.len() as i64)
+            },
+            "INFO" => {
+                if cmd_args.len() == 2 {
+                    return RespValue::Array(COMMAND_TABLE.iter().map(|cmd| cmd.info()).collect());
+                }
+                RespValue::Array(cmd_args[2..].iter().map(|name| {
+                    find_command(name).map_or(RespValue::Null, |cmd| cmd.info())
+                }).collect())
+            },
+            "DOCS" => {
+                // unknown names are left out of the reply
+                let docs: Vec<_> = if cmd_args.len() == 2 {
+                    COMMAND_TABLE.iter().collect()
+                } else {
+                    cmd_args[2..].iter().filter_map(|name| find_command(name)).collect()
+                };
+                RespValue::Map(docs.iter().map(|cmd| (RespValue::bulk(cmd.name), cmd.docs())).collect())
+            },
+            "GETKEYS" if cmd_args.len() >= 3 => {
+                let target = &cmd_args[2..];
+                let cmd = match find_command(&target[0]) {
+                    Some(cmd) => cmd,
+                    None => return redis_err("ERR Invalid command specified"),
+                };
+                if !cmd.arity_ok(target.len()) {
+                    return redis_err("ERR Invalid number of arguments specified for command");
+                }
+                let keys = cmd.key_indexes(target);
+                if keys.is_empty() {
+                    return redis_err("ERR The command has no key arguments");
+                }
+                RespValue::bulk_array(&keys.iter().map(|&i| &target[i]).collect::<Vec<_>>())
+            },
+            _ => {
+                redis_err(&format!("ERR unknown subcommand or wrong number of arguments for '{}'. Try COMMAND HELP.", arg_str(&cmd_args[1])))
+            }
+        }
+    }
+
+    // ---HANDLERS---
+    // thin adapters from the command context to the command implementations
+
+    fn is_replica(ctx: &CommandCtx) -> bool {
+        !ctx.config_args.replicaof.starts_with("None")
+    }
+
+    // send a write that happened on this master to the replicas, the master accounts for the bytes it sends
+    fn replicate(ctx: &mut CommandCtx, cmd: &[Vec<u8>]) {
+        if is_replica(ctx) {
+            return;
+        }
+        ctx.config_args.bytes_rx += ctx.dbs.propagate(ctx.config_args.db, cmd, &ctx.tx);
+    }
+
+    // publishes a keyspace event about a key of the selected database
+    async fn notify(ctx: &CommandCtx<'_>, class: u32, event: &str, key: &[u8]) {
+        notify::notify::notify_keyspace_event(&ctx.glob_config, &ctx.tx, class, event, key, ctx.config_args.db).await;
+    }
+
+    // whether events of the class are published, for events that are costly to gather
+    async fn notifies(ctx: &CommandCtx<'_>, class: u32) -> bool {
+        ctx.glob_config.lock().await.notify_keyspace_events & class != 0
+    }
+
+    // reply of a write command, a replica only accounts for the bytes since writes only come from its master
+    fn write_reply(ctx: &mut CommandCtx, response: RespValue) -> Vec<RespValue> {
+        if is_replica(ctx) {
+            ctx.config_args.bytes_rx += ctx.bytes_rx;
+            return vec![];
+        }
+        vec![response]
+    }
+
+    // lazy expiry: a master deletes the expired keys a command is about to touch before it runs, and replicates
+    // the deletion as a DEL
+    pub async fn expire_keys(ctx: &mut CommandCtx<'_>, cmd: &RedisCommand) {
+        if is_replica(ctx) {
+            return;
+        }
+        let keys: Vec<&[u8]> = cmd.key_indexes(ctx.cmd_args).iter().map(|&i| ctx.cmd_args[i].as_slice()).collect();
+        if keys.is_empty() {
+            return;
+        }
+        for key in expiry::expiry::expire_if_needed(&keys, ctx.storage_ref.clone()).await {
+            notify(ctx, _NOTIFY_EXPIRED_, "expired", &key).await;
+            replicate(ctx, &[b"DEL".to_vec(), key]);
+        }
+    }
+
+    // commands that look at keys without it counting as an access for the lru and lfu policies, RESTORE sets the
+    // access metadata itself
+    const _NO_TOUCH_: &[&str] = &["object", "type", "exists", "ttl", "pttl", "expiretime", "pexpiretime", "restore"];
+
+    // with maxmemory set a master evicts keys by the policy before running a command once it is over the limit,
+    // and refuses the commands that may grow memory if it is still over it
+    pub async fn check_memory(ctx: &mut CommandCtx<'_>, cmd: &RedisCommand) -> Result<(), RespValue> {
+        let maxmemory = ctx.config_args.maxmemory;
+        if maxmemory == 0 || is_replica(ctx) {
+            return Ok(());
+        }
+        for (db, key) in eviction::eviction::evict(&ctx.dbs, maxmemory, ctx.config_args.maxmemory_policy).await {
+            notify::notify::notify_keyspace_event(&ctx.glob_config, &ctx.tx, _NOTIFY_EVICTED_, "evicted", &key, db).await;
+            ctx.config_args.bytes_rx += ctx.dbs.propagate(db, &[b"DEL".to_vec(), key], &ctx.tx);
+        }
+        if cmd.has_flag(CommandFlag::Denyoom) && ctx.dbs.used_memory.load(std::sync::atomic::Ordering::Relaxed) > maxmemory {
+            return Err(redis_err(_ERROR_OOM_));
+        }
+        Ok(())
+    }
+
+    // updates the size and access metadata of the keys a command ran on in database db
+    pub async fn track_keys(ctx: &mut CommandCtx<'_>, cmd: &RedisCommand, db: usize) {
+        let keys: Vec<&[u8]> = cmd.key_indexes(ctx.cmd_args).iter().map(|&i| ctx.cmd_args[i].as_slice()).collect();
+        if !keys.is_empty() {
+            eviction::eviction::track_keys(&ctx.dbs, db, &keys, !_NO_TOUCH_.contains(&cmd.name)).await;
+        }
+    }
+
+    fn run_ping<'a>(ctx: &'a mut CommandCtx) -> HandlerFuture<'a> {
+        Box::pin(async move {
+            if !ctx.config_args.replicaof.starts_with("None") {   // if its a replica, dont send back any response
+                ctx.config_args.bytes_rx += ctx.bytes_rx;
+                return vec![];
+            }
+            if ctx.config_args.client_in_sub_mode && !ctx.config_args.resp3 {
+                let msg = ctx.cmd_args.get(1).map_or(RespValue::bulk(""), RespValue::bulk);
+                return vec![RespValue::Array(vec![RespValue::bulk("pong"), msg])];
+            }
+            match ctx.cmd_args.get(1) {
+                Some(msg) if ctx.cmd_args.len() == 2 => vec![RespValue::bulk(msg)],
+                Some(_) => vec![redis_err("ERR wrong number of arguments for 'ping' command")],
+                None => vec![RespValue::simple("PONG")],
+            }
+        })
+    }
+
+    fn run_echo<'a>(ctx: &'a mut CommandCtx) -> HandlerFuture<'a> {
+        Box::pin(async move { vec![RespValue::bulk(&ctx.cmd_args[1])] })
+    }
+
+    fn run_hello<'a>(ctx: &'a mut CommandCtx) -> HandlerFuture<'a> {
+        Box::pin(async move { vec![connection::connection::cmd_hello(ctx.config_args, ctx.cmd_args, ctx.glob_config.clone()).await] })
+    }
+
+    fn run_client<'a>(ctx: &'a mut CommandCtx) -> HandlerFuture<'a> {
+        Box::pin(async move { vec![connection::connection::cmd_client(ctx.config_args, ctx.cmd_args).await] })
+    }
+
+    // the master selects databases in the replication stream too, that SELECT gets no reply
+    fn run_select<'a>(ctx: &'a mut CommandCtx) -> HandlerFuture<'a> {
+        Box::pin(async move {
+            let response = connection::connection::cmd_select(ctx.config_args, &ctx.cmd_args[1], &ctx.dbs);
+            if ctx.config_args.master_link {
+                ctx.config_args.bytes_rx += ctx.bytes_rx;
+                return vec![];
+            }
+            vec![response]
+        })
+    }
+
+    fn run_quit<'a>(ctx: &'a mut CommandCtx) -> HandlerFuture<'a> {
+        Box::pin(async move {
+            // the connection is closed once this reply is written
+            ctx.config_args.closing = true;
+            vec![response_ok()]
+        })
+    }
+
+    fn run_get<'a>(ctx: &'a mut CommandCtx) -> HandlerFuture<'a> {
+        Box::pin(async move {
+            match replication::replication::cmd_get(&ctx.cmd_args[1], ctx.storage_ref.clone()).await {
+                Some(RDBValue::String(s)) => vec![RespValue::Bulk(s)],
+                Some(_) => vec![redis_err(_ERROR_WRONGTYPE_)],
+                None => vec![RespValue::Null],
+            }
+        })
+    }
+
+    fn run_set<'a>(ctx: &'a mut CommandCtx) -> HandlerFuture<'a> {
+        Box::pin(async move {
+            let (response, propagate) = replication::replication::cmd_set(ctx.cmd_args, ctx.storage_ref.clone()).await;
+            if let Some(cmd) = propagate {
+                notify(ctx, _NOTIFY_STRING_, "set", &ctx.cmd_args[1]).await;
+                if ctx.cmd_args[3..].iter().any(|arg| ["EX", "PX", "EXAT", "PXAT"].contains(&arg_str(arg).to_uppercase().as_str())) {
+                    notify(ctx, _NOTIFY_GENERIC_, "expire", &ctx.cmd_args[1]).await;
+                }
+                replicate(ctx, &cmd);
+            }
+            write_reply(ctx, response)
+        })
+    }
+
+    fn run_mget<'a>(ctx: &'a mut CommandCtx) -> HandlerFuture<'a> {
+        Box::pin(async move { vec![strings::strings::cmd_mget(ctx.cmd_args, ctx.storage_ref.clone()).await] })
+    }
+
+    fn run_mset<'a>(ctx: &'a mut CommandCtx) -> HandlerFuture<'a> {
+        Box::pin(async move {
+            let response = strings::strings::cmd_mset(ctx.cmd_args, ctx.storage_ref.clone()).await;
+            if !response.is_error() {
+                for key in ctx.cmd_args[1..].iter().step_by(2) {
+                    notify(ctx, _NOTIFY_STRING_, "set", key).await;
+                }
+                replicate(ctx, ctx.cmd_args);
+            }
+            write_reply(ctx, response)
+        })
+    }
+
+    fn run_msetnx<'a>(ctx: &'a mut CommandCtx) -> HandlerFuture<'a> {
+        Box::pin(async move {
+            let response = strings::strings::cmd_msetnx(ctx.cmd_args, ctx.storage_ref.clone()).await;
+            if response == RespValue::Integer(1) {
+                for key in ctx.cmd_args[1..].iter().step_by(2) {
+                    notify(ctx, _NOTIFY_STRING_, "set", key).await;
+                }
+                replicate(ctx, ctx.cmd_args);
+            }
+            write_reply(ctx, response)
+        })
+    }
+
+    // shared by INCR, DECR, INCRBY and DECRBY, which are replicated as they are
+    async fn incr_by(ctx: &mut CommandCtx<'_>, delta: Option<i64>) -> Vec<RespValue> {
+        let delta = match delta {
+            Some(delta) => delta,
+            None => return vec![redis_err(_ERROR_INCR_NOT_AN_INT_)],
+        };
+        let response = strings::strings::cmd_incr_by(&ctx.cmd_args[1], delta, ctx.storage_ref.clone()).await;
+        if !response.is_error() {
+            notify(ctx, _NOTIFY_STRING_, "incrby", &ctx.cmd_args[1]).await;
+            replicate(ctx, ctx.cmd_args);
+        }
+        write_reply(ctx, response)
+    }
+
+    fn run_incr<'a>(ctx: &'a mut CommandCtx) -> HandlerFuture<'a> {
+        Box::pin(incr_by(ctx, Some(1)))
+    }
+
+    fn run_decr<'a>(ctx: &'a mut CommandCtx) -> HandlerFuture<'a> {
+        Box::pin(incr_by(ctx, Some(-1)))
+    }
+
+    fn run_incrby<'a>(ctx: &'a mut CommandCtx) -> HandlerFuture<'a> {
+        let delta = strings::strings::parse_i64(&ctx.cmd_args[2]);
+        Box::pin(incr_by(ctx, delta))
+    }
+
+    fn run_decrby<'a>(ctx: &'a mut CommandCtx) -> HandlerFuture<'a> {
+        let delta = strings::strings::parse_i64(&ctx.cmd_args[2]);
+        Box::pin(async move {
+            match delta.map(i64::checked_neg) {
+                Some(None) => vec![redis_err("ERR decrement would overflow")],
+                delta => incr_by(ctx, delta.flatten()).await,
+            }
+        })
+    }
+
+    fn run_incrbyfloat<'a>(ctx: &'a mut CommandCtx) -> HandlerFuture<'a> {
+        Box::pin(async move {
+            let response = strings::strings::cmd_incr_by_float(&ctx.cmd_args[1], &ctx.cmd_args[2], ctx.storage_ref.clone()).await;
+            // replicated as the resulting value so float rounding can't make the replica drift
+            if let RespValue::Bulk(value) = &response {
+                notify(ctx, _NOTIFY_STRING_, "incrbyfloat", &ctx.cmd_args[1]).await;
+                let cmd = [b"SET".to_vec(), ctx.cmd_args[1].clone(), value.clone(), b"KEEPTTL".to_vec()];
+                replicate(ctx, &cmd);
+            }
+            write_reply(ctx, response)
+        })
+    }
+
+    fn run_append<'a>(ctx: &'a mut CommandCtx) -> HandlerFuture<'a> {
+        Box::pin(async move {
+            let response = strings::strings::cmd_append(&ctx.cmd_args[1], &ctx.cmd_args[2], ctx.storage_ref.clone()).await;
+            if !response.is_error() {
+                notify(ctx, _NOTIFY_STRING_, "append", &ctx.cmd_args[1]).await;
+                replicate(ctx, ctx.cmd_args);
+            }
+            write_reply(ctx, response)
+        })
+    }
+
+    fn run_strlen<'a>(ctx: &'a mut CommandCtx) -> HandlerFuture<'a> {
+        Box::pin(async move { vec![strings::strings::cmd_strlen(&ctx.cmd_args[1], ctx.storage_ref.clone()).await] })
+    }
+
+    fn run_getrange<'a>(ctx: &'a mut CommandCtx) -> HandlerFuture<'a> {
+        Box::pin(async move {
+            vec![strings::strings::cmd_getrange(&ctx.cmd_args[1], &ctx.cmd_args[2], &ctx.cmd_args[3], ctx.storage_ref.clone()).await]
+        })
+    }
+
+    fn run_setrange<'a>(ctx: &'a mut CommandCtx) -> HandlerFuture<'a> {
+        Box::pin(async move {
+            let response = strings::strings::cmd_setrange(&ctx.cmd_args[1], &ctx.cmd_args[2], &ctx.cmd_args[3], ctx.storage_ref.clone()).await;
+            if !response.is_error() && !ctx.cmd_args[3].is_empty() {
+                notify(ctx, _NOTIFY_STRING_, "setrange", &ctx.cmd_args[1]).await;
+                replicate(ctx, ctx.cmd_args);
+            }
+            write_reply(ctx, response)
+        })
+    }
+
+    fn run_getdel<'a>(ctx: &'a mut CommandCtx) -> HandlerFuture<'a> {
+        Box::pin(async move {
+            let response = strings::strings::cmd_getdel(&ctx.cmd_args[1], ctx.storage_ref.clone()).await;
+            if let RespValue::Bulk(_) = response {
+                notify(ctx, _NOTIFY_GENERIC_, "del", &ctx.cmd_args[1]).await;
+                replicate(ctx, ctx.cmd_args);
+            }
+            write_reply(ctx, response)
+        })
+    }
+
+    fn run_getex<'a>(ctx: &'a mut CommandCtx) -> HandlerFuture<'a> {
+        Box::pin(async move {
+            let (response, propagate) = strings::strings::cmd_getex(ctx.cmd_args, ctx.storage_ref.clone()).await;
+            if let Some(cmd) = propagate {
+                // a time in the past deletes the key
+                let event = match cmd.get(2).map(Vec::as_slice) {
+                    None => "del",
+                    Some(b"PERSIST") => "persist",
+                    Some(_) => "expire",
+                };
+                notify(ctx, _NOTIFY_GENERIC_, event, &ctx.cmd_args[1]).await;
+                replicate(ctx, &cmd);
+            }
+            write_reply(ctx, response)
+        })
+    }
+
+    fn run_getset<'a>(ctx: &'a mut CommandCtx) -> HandlerFuture<'a> {
+        Box::pin(async move {
+            let response = strings::strings::cmd_getset(&ctx.cmd_args[1], &ctx.cmd_args[2], ctx.storage_ref.clone()).await;
+            // replicated as the SET it amounts to
+            if !response.is_error() {
+                notify(ctx, _NOTIFY_STRING_, "set", &ctx.cmd_args[1]).await;
+                let cmd = [b"SET".to_vec(), ctx.cmd_args[1].clone(), ctx.cmd_args[2].clone()];
+                replicate(ctx, &cmd);
+            }
+            write_reply(ctx, response)
+        })
+    }
+
+    fn run_setbit<'a>(ctx: &'a mut CommandCtx) -> HandlerFuture<'a> {
+        Box::pin(async move {
+            let response = bitmaps::bitmaps::cmd_setbit(&ctx.cmd_args[1], &ctx.cmd_args[2], &ctx.cmd_args[3], ctx.storage_ref.clone()).await;
+            if !response.is_error() {
+                notify(ctx, _NOTIFY_STRING_, "setbit", &ctx.cmd_args[1]).await;
+                replicate(ctx, ctx.cmd_args);
+            }
+            write_reply(ctx, response)
+        })
+    }
+
+    fn run_getbit<'a>(ctx: &'a mut CommandCtx) -> HandlerFuture<'a> {
+        Box::pin(async move { vec![bitmaps::bitmaps::cmd_getbit(&ctx.cmd_args[1], &ctx.cmd_args[2], ctx.storage_ref.clone()).await] })
+    }
+
+    fn run_bitcount<'a>(ctx: &'a mut CommandCtx) -> HandlerFuture<'a> {
+        Box::pin(async move { vec![bitmaps::bitmaps::cmd_bitcount(ctx.cmd_args, ctx.storage_ref.clone()).await] })
+    }
+
+    fn run_bitpos<'a>(ctx: &'a mut CommandCtx) -> HandlerFuture<'a> {
+        Box::pin(async move { vec![bitmaps::bitmaps::cmd_bitpos(ctx.cmd_args, ctx.storage_ref.clone()).await] })
+    }
+
+    fn run_bitop<'a>(ctx: &'a mut CommandCtx) -> HandlerFuture<'a> {
+        Box::pin(async move {
+            let response = bitmaps::bitmaps::cmd_bitop(ctx.cmd_args, ctx.storage_ref.clone()).await;
+            if !response.is_error() {
+                // an empty result deletes the destination
+                if response == RespValue::Integer(0) {
+                    notify(ctx, _NOTIFY_GENERIC_, "del", &ctx.cmd_args[2]).await;
+                } else {
+                    notify(ctx, _NOTIFY_STRING_, "set", &ctx.cmd_args[2]).await;
+                }
+                replicate(ctx, ctx.cmd_args);
+            }
+            write_reply(ctx, response)
+        })
+    }
+
+    fn run_bitfield<'a>(ctx: &'a mut CommandCtx) -> HandlerFuture<'a> {
+        Box::pin(async move {
+            let (response, propagate) = bitfield::bitfield::cmd_bitfield(ctx.cmd_args, false, ctx.storage_ref.clone()).await;
+            if let Some(cmd) = propagate {
+                notify(ctx, _NOTIFY_STRING_, "setbit", &ctx.cmd_args[1]).await;
+                replicate(ctx, &cmd);
+            }
+            write_reply(ctx, response)
+        })
+    }
+
+    // the read only variant answers on replicas too
+    fn run_bitfield_ro<'a>(ctx: &'a mut CommandCtx) -> HandlerFuture<'a> {
+        Box::pin(async move { vec![bitfield::bitfield::cmd_bitfield(ctx.cmd_args, true, ctx.storage_ref.clone()).await.0] })
+    }
+
+    fn run_pfadd<'a>(ctx: &'a mut CommandCtx) -> HandlerFuture<'a> {
+        Box::pin(async move {
+            let response = hyperloglog::hyperloglog::cmd_pfadd(ctx.cmd_args, ctx.storage_ref.clone()).await;
+            if response == RespValue::Integer(1) {
+                notify(ctx, _NOTIFY_STRING_, "pfadd", &ctx.cmd_args[1]).await;
+                replicate(ctx, ctx.cmd_args);
+            }
+            write_reply(ctx, response)
+        })
+    }
+
+    fn run_pfcount<'a>(ctx: &'a mut CommandCtx) -> HandlerFuture<'a> {
+        Box::pin(async move { vec![hyperloglog::hyperloglog::cmd_pfcount(ctx.cmd_args, ctx.storage_ref.clone()).await] })
+    }
+
+    fn run_pfmerge<'a>(ctx: &'a mut CommandCtx) -> HandlerFuture<'a> {
+        Box::pin(async move {
+            let response = hyperloglog::hyperloglog::cmd_pfmerge(ctx.cmd_args, ctx.storage_ref.clone()).await;
+            if !response.is_error() {
+                notify(ctx, _NOTIFY_STRING_, "pfadd", &ctx.cmd_args[1]).await;
+                replicate(ctx, ctx.cmd_args);
+            }
+            write_reply(ctx, response)
+        })
+    }
+
+    // shared by DEL and UNLINK, which are replicated as they are
+    async fn del(ctx: &mut CommandCtx<'_>, lazy_free: bool) -> Vec<RespValue> {
+        let (response, deleted) = keyspace::keyspace::cmd_del(&ctx.cmd_args[1..], lazy_free, ctx.storage_ref.clone()).await;
+        for key in &deleted {
+            notify(ctx, _NOTIFY_GENERIC_, "del", key).await;
+        }
+        if !deleted.is_empty() {
+            replicate(ctx, ctx.cmd_args);
+        }
+        write_reply(ctx, response)
+    }
+
+    fn run_del<'a>(ctx: &'a mut CommandCtx) -> HandlerFuture<'a> {
+        Box::pin(del(ctx, false))
+    }
+
+    fn run_unlink<'a>(ctx: &'a mut CommandCtx) -> HandlerFuture<'a> {
+        Box::pin(del(ctx, true))
+    }
+
+    fn run_exists<'a>(ctx: &'a mut CommandCtx) -> HandlerFuture<'a> {
+        Box::pin(async move { vec![keyspace::keyspace::cmd_exists(&ctx.cmd_args[1..], ctx.storage_ref.clone()).await] })
+    }
+
+    // shared by RENAME and RENAMENX, which are replicated as they are when the key moved
+    async fn rename(ctx: &mut CommandCtx<'_>, nx: bool) -> Vec<RespValue> {
+        let response = keyspace::keyspace::cmd_rename(&ctx.cmd_args[1], &ctx.cmd_args[2], nx, ctx.storage_ref.clone()).await;
+        if !response.is_error() && response != RespValue::Integer(0) {
+            if ctx.cmd_args[1] != ctx.cmd_args[2] {
+                notify(ctx, _NOTIFY_GENERIC_, "rename_from", &ctx.cmd_args[1]).await;
+                notify(ctx, _NOTIFY_GENERIC_, "rename_to", &ctx.cmd_args[2]).await;
+            }
+            replicate(ctx, ctx.cmd_args);
+        }
+        write_reply(ctx, response)
+    }
+
+    fn run_rename<'a>(ctx: &'a mut CommandCtx) -> HandlerFuture<'a> {
+        Box::pin(rename(ctx, false))
+    }
+
+    fn run_renamenx<'a>(ctx: &'a mut CommandCtx) -> HandlerFuture<'a> {
+        Box::pin(rename(ctx, true))
+    }
+
+    fn run_move<'a>(ctx: &'a mut CommandCtx) -> HandlerFuture<'a> {
+        Box::pin(async move {
+            let response = keyspace::keyspace::cmd_move(&ctx.cmd_args[1], &ctx.cmd_args[2], ctx.config_args.db, &ctx.dbs).await;
+            if response == RespValue::Integer(1) {
+                notify(ctx, _NOTIFY_GENERIC_, "move_from", &ctx.cmd_args[1]).await;
+                if let Ok(target) = ctx.dbs.parse_index(&ctx.cmd_args[2]) {
+                    notify::notify::notify_keyspace_event(&ctx.glob_config, &ctx.tx, _NOTIFY_GENERIC_, "move_to", &ctx.cmd_args[1], target).await;
+                }
+                replicate(ctx, ctx.cmd_args);
+            }
+            write_reply(ctx, response)
+        })
+    }
+
+    fn run_copy<'a>(ctx: &'a mut CommandCtx) -> HandlerFuture<'a> {
+        Box::pin(async move {
+            let (response, target) = keyspace::keyspace::cmd_copy(ctx.cmd_args, ctx.config_args.db, &ctx.dbs).await;
+            if response == RespValue::Integer(1) {
+                notify::notify::notify_keyspace_event(&ctx.glob_config, &ctx.tx, _NOTIFY_GENERIC_, "copy_to", &ctx.cmd_args[2], target).await;
+                replicate(ctx, ctx.cmd_args);
+            }
+            write_reply(ctx, response)
+        })
+    }
+
+    // shared by EXPIRE, PEXPIRE, EXPIREAT and PEXPIREAT, which are replicated as a PEXPIREAT or a DEL
+    async fn expire(ctx: &mut CommandCtx<'_>, unit: &str) -> Vec<RespValue> {
+        let (response, propagate) = keyspace::keyspace::cmd_expire(ctx.cmd_args, unit, ctx.storage_ref.clone()).await;
+        if let Some(cmd) = propagate {
+            // a time in the past deletes the key
+            let event = if cmd[0] == b"DEL" { "del" } else { "expire" };
+            notify(ctx, _NOTIFY_GENERIC_, event, &ctx.cmd_args[1]).await;
+            replicate(ctx, &cmd);
+        }
+        write_reply(ctx, response)
+    }
+
+    fn run_expire<'a>(ctx: &'a mut CommandCtx) -> HandlerFuture<'a> {
+        Box::pin(expire(ctx, "EX"))
+    }
+
+    fn run_pexpire<'a>(ctx: &'a mut CommandCtx) -> HandlerFuture<'a> {
+        Box::pin(expire(ctx, "PX"))
+    }
+
+    fn run_expireat<'a>(ctx: &'a mut CommandCtx) -> HandlerFuture<'a> {
+        Box::pin(expire(ctx, "EXAT"))
+    }
+
+    fn run_pexpireat<'a>(ctx: &'a mut CommandCtx) -> HandlerFuture<'a> {
+        Box::pin(expire(ctx, "PXAT"))
+    }
+
+    // shared by TTL, PTTL, EXPIRETIME and PEXPIRETIME
+    async fn ttl(ctx: &mut CommandCtx<'_>, relative: bool, in_millis: bool) -> Vec<RespValue> {
+        vec![keyspace::keyspace::cmd_ttl(&ctx.cmd_args[1], relative, in_millis, ctx.storage_ref.clone()).await]
+    }
+
+    fn run_ttl<'a>(ctx: &'a mut CommandCtx) -> HandlerFuture<'a> {
+        Box::pin(ttl(ctx, true, false))
+    }
+
+    fn run_pttl<'a>(ctx: &'a mut CommandCtx) -> HandlerFuture<'a> {
+        Box::pin(ttl(ctx, true, true))
+    }
+
+    fn run_expiretime<'a>(ctx: &'a mut CommandCtx) -> HandlerFuture<'a> {
+        Box::pin(ttl(ctx, false, false))
+    }
+
+    fn run_pexpiretime<'a>(ctx: &'a mut CommandCtx) -> HandlerFuture<'a> {
+        Box::pin(ttl(ctx, false, true))
+    }
+
+    fn run_persist<'a>(ctx: &'a mut CommandCtx) -> HandlerFuture<'a> {
+        Box::pin(async move {
+            let response = keyspace::keyspace::cmd_persist(&ctx.cmd_args[1], ctx.storage_ref.clone()).await;
+            if response == RespValue::Integer(1) {
+                notify(ctx, _NOTIFY_GENERIC_, "persist", &ctx.cmd_args[1]).await;
+                replicate(ctx, ctx.cmd_args);
+            }
+            write_reply(ctx, response)
+        })
+    }
+
+    fn run_dump<'a>(ctx: &'a mut CommandCtx) -> HandlerFuture<'a> {
+        Box::pin(async move { vec![keyspace::keyspace::cmd_dump(&ctx.cmd_args[1], ctx.storage_ref.clone()).await] })
+    }
+
+    fn run_restore<'a>(ctx: &'a mut CommandCtx) -> HandlerFuture<'a> {
+        Box::pin(async move {
+            let db = &ctx.dbs.dbs[ctx.config_args.db];
+            let (response, propagate) = keyspace::keyspace::cmd_restore(ctx.cmd_args, db).await;
+            if let Some(cmd) = propagate {
+                // an already expired value deletes the key it replaces
+                let event = if cmd[0] == b"DEL" { "del" } else { "restore" };
+                notify(ctx, _NOTIFY_GENERIC_, event, &ctx.cmd_args[1]).await;
+                replicate(ctx, &cmd);
+            }
+            write_reply(ctx, response)
+        })
+    }
+
+    fn run_keys<'a>(ctx: &'a mut CommandCtx) -> HandlerFuture<'a> {
+        Box::pin(async move { vec![keyspace::keyspace::cmd_keys(&ctx.cmd_args[1], ctx.storage_ref.clone()).await] })
+    }
+
+    fn run_scan<'a>(ctx: &'a mut CommandCtx) -> HandlerFuture<'a> {
+        Box::pin(async move { vec![keyspace::keyspace::cmd_scan(ctx.cmd_args, &ctx.dbs.dbs[ctx.config_args.db]).await] })
+    }
+
+    fn run_object<'a>(ctx: &'a mut CommandCtx) -> HandlerFuture<'a> {
+        Box::pin(async move {
+            let db = &ctx.dbs.dbs[ctx.config_args.db];
+            vec![keyspace::keyspace::cmd_object(ctx.cmd_args, ctx.config_args.maxmemory_policy, db).await]
+        })
+    }
+
+    fn run_type<'a>(ctx: &'a mut CommandCtx) -> HandlerFuture<'a> {
+        Box::pin(async move { vec![keyspace::keyspace::cmd_type(&ctx.cmd_args[1], ctx.storage_ref.clone()).await] })
+    }
+
+    fn run_wait<'a>(ctx: &'a mut CommandCtx) -> HandlerFuture<'a> {
+        Box::pin(async move {
+            let (max_ack, max_wait) = match (parse_arg(&ctx.cmd_args[1]), parse_arg(&ctx.cmd_args[2])) {
+                (Some(max_ack), Some(max_wait)) => (max_ack, max_wait),
+                _ => return vec![redis_err(_ERROR_INCR_NOT_AN_INT_)],
+            };
+            // save the byte of all the commands processed before this WAIT command
+            let target_bytes = ctx.config_args.bytes_rx;    // bytes received by master
+            println!("bytes to match {}", target_bytes);
+
+            let msg = encode_command(&["REPLCONF", "GETACK", "*"]);
+            if target_bytes > 0 { // only add getack bytes to master if some writes exist
+                ctx.config_args.bytes_rx += msg.len();
+            }
+            ctx.tx.send(msg).unwrap();
+
+            vec![cmd_wait(max_ack, max_wait, ctx.glob_config.clone(), target_bytes).await]
+        })
+    }
+
+    fn run_info<'a>(ctx: &'a mut CommandCtx) -> HandlerFuture<'a> {
+        Box::pin(async move { vec![replication::replication::cmd_info(ctx.config_args, ctx.cmd_args.get(1).map(Vec::as_slice), &ctx.dbs).await] })
+    }
+
+    fn run_config<'a>(ctx: &'a mut CommandCtx) -> HandlerFuture<'a> {
+        Box::pin(async move {
+            let subcommand = arg_str(&ctx.cmd_args[1]).to_uppercase();
+            match (subcommand.as_str(), ctx.cmd_args.len()) {
+                ("GET", 3) => {
+                    let notify_keyspace_events = ctx.glob_config.lock().await.notify_keyspace_events;
+                    vec![replication::replication::cmd_config(&ctx.cmd_args[2], ctx.config_args, notify_keyspace_events).await]
+                },
+                ("SET", 4) => vec![replication::replication::cmd_config_set(&ctx.cmd_args[2], &ctx.cmd_args[3], ctx.glob_config.clone()).await],
+                _ => vec![redis_err(&format!("ERR unknown subcommand or wrong number of arguments for '{}'. Try CONFIG HELP.", arg_str(&ctx.cmd_args[1])))],
+            }
+        })
+    }
+
+    fn run_dbsize<'a>(ctx: &'a mut CommandCtx) -> HandlerFuture<'a> {
+        Box::pin(async move { vec![keyspace::keyspace::cmd_dbsize(ctx.storage_ref.clone()).await] })
+    }
+
+    fn run_flushdb<'a>(ctx: &'a mut CommandCtx) -> HandlerFuture<'a> {
+        Box::pin(async move {
+            let lazy_free = match keyspace::keyspace::parse_flush_mode(ctx.cmd_args) {
+                Ok(lazy_free) => lazy_free,
+                Err(e) => return vec![e],
+            };
+            let notify_del = notifies(ctx, _NOTIFY_GENERIC_).await;
+            for key in keyspace::keyspace::flush_db(&ctx.dbs, ctx.config_args.db, lazy_free, notify_del).await {
+                notify(ctx, _NOTIFY_GENERIC_, "del", &key).await;
+            }
+            replicate(ctx, ctx.cmd_args);
+            write_reply(ctx, response_ok())
+        })
+    }
+
+    fn run_flushall<'a>(ctx: &'a mut CommandCtx) -> HandlerFuture<'a> {
+        Box::pin(async move {
+            let lazy_free = match keyspace::keyspace::parse_flush_mode(ctx.cmd_args) {
+                Ok(lazy_free) => lazy_free,
+                Err(e) => return vec![e],
+            };
+            let notify_del = notifies(ctx, _NOTIFY_GENERIC_).await;
+            for db in 0..ctx.dbs.dbs.len() {
+                for key in keyspace::keyspace::flush_db(&ctx.dbs, db, lazy_free, notify_del).await {
+                    notify::notify::notify_keyspace_event(&ctx.glob_config, &ctx.tx, _NOTIFY_GENERIC_, "del", &key, db).await;
+                }
+            }
+            replicate(ctx, ctx.cmd_args);
+            write_reply(ctx, response_ok())
+        })
+    }
+
+    fn run_swapdb<'a>(ctx: &'a mut CommandCtx) -> HandlerFuture<'a> {
+        Box::pin(async move {
+            let response = keyspace::keyspace::cmd_swapdb(&ctx.cmd_args[1], &ctx.cmd_args[2], &ctx.dbs).await;
+            if !response.is_error() {
+                replicate(ctx, ctx.cmd_args);
+            }
+            write_reply(ctx, response)
+        })
+    }
+
+    fn run_save<'a>(ctx: &'a mut CommandCtx) -> HandlerFuture<'a> {
+        Box::pin(async move { vec![replication::replication::cmd_save(&ctx.dbs, &ctx.dbfilepath).await] })
+    }
+
+    fn run_replconf<'a>(ctx: &'a mut CommandCtx) -> HandlerFuture<'a> {
+        Box::pin(async move {
+            let cmd_args = ctx.cmd_args;
+            if cmd_args.len() < 2 {
+                return vec![response_ok()];
+            }
+
+            if cmd_args[1].eq_ignore_ascii_case(b"GETACK") {    // return number of bytes processed by this replica
+                if ctx.config_args.bytes_rx > 0 {
+                    ctx.config_args.bytes_rx += ctx.bytes_rx;
+                }
+                vec![replication::replication::cmd_get_ack(ctx.config_args.bytes_rx)]
+            } else if cmd_args[1].eq_ignore_ascii_case(b"ACK") {
+                // this message was sent by replica to (this instance) master, save the bytes it has processed
+                // the replica is keyed by the port it shared on this connection during the handshake
+                println!("recvd ack from: {}", ctx.config_args.other_port);
+                let offset = cmd_args.get(2).and_then(|arg| parse_arg::<usize>(arg));
+                if let (Some(offset), Some(replica)) = (offset, ctx.glob_config.lock().await.replicas.get_mut(&ctx.config_args.other_port)) {
+                    replica.bytes_rx += offset;
+                }
+                vec![]
+            } else {
+                // port sharing by replica to master, this assumes that this command is always sent on the correct connection
+                if cmd_args[1].eq_ignore_ascii_case(b"listening-port") {
+                    match cmd_args.get(2).and_then(|arg| parse_arg(arg)) {
+                        Some(port) => {
+                            ctx.config_args.replica_conn = true;
+                            ctx.config_args.other_port = port;
+                            ctx.glob_config.lock().await.replicas.insert(port, ReplicaInfo{bytes_rx: 0});
+                        },
+                        None => return vec![redis_err(_ERROR_INCR_NOT_AN_INT_)],
+                    }
+                }
+                vec![response_ok()]
+            }
+        })
+    }
+
+    fn run_psync<'a>(ctx: &'a mut CommandCtx) -> HandlerFuture<'a> {
+        Box::pin(async move {
+            println!("pysnc() {:?}", ctx.config_args);
+            ctx.dbs.reset_replication_db();
+            vec![replication::replication::cmd_psync(ctx.config_args).await,
+                replication::replication::cmd_fullresync(ctx.config_args).await]
+        })
+    }
+
+    fn run_acl<'a>(ctx: &'a mut CommandCtx) -> HandlerFuture<'a> {
+        Box::pin(async move {
+            // TODO: ACL LIST/SETUSER/WHOAMI, see auth::cmd_list
+            vec![redis_err(&format!("ERR unknown subcommand '{}'. Try ACL HELP.", arg_str(&ctx.cmd_args[1])))]
+        })
+    }
+
+    fn run_command<'a>(ctx: &'a mut CommandCtx) -> HandlerFuture<'a> {
+        Box::pin(async move { vec![cmd_command(ctx.cmd_args)] })
+    }
+
+    fn run_multi<'a>(ctx: &'a mut CommandCtx) -> HandlerFuture<'a> {
+        Box::pin(async move {
+            ctx.config_args.queueing = true;
+            vec![response_ok()]
+        })
+    }
+
+    fn run_exec<'a>(_ctx: &'a mut CommandCtx) -> HandlerFuture<'a> {
+        // only reached when there is no transaction, the connection runs EXEC itself otherwise
+        Box::pin(async move { vec![redis_err(_ERROR_EXEC_WITHOUT_MULTI_)] })
+    }
+
+    fn run_discard<'a>(ctx: &'a mut CommandCtx) -> HandlerFuture<'a> {
+        Box::pin(async move {
+            ctx.config_args.queueing = false;
+            vec![response_ok()]
+        })
+    }
+
+    // shared by RPUSH and LPUSH
+    async fn list_push(ctx: &mut CommandCtx<'_>, push_back: bool) -> Vec<RespValue> {
+        let response = lists::lists::cmd_list_push(ctx.cmd_args, ctx.storage_ref.clone(), push_back, ctx.tx.clone()).await;
+        if !response.is_error() {
+            notify(ctx, _NOTIFY_LIST_, if push_back { "rpush" } else { "lpush" }, &ctx.cmd_args[1]).await;
+        }
+        vec![response]
+    }
+
+    fn run_rpush<'a>(ctx: &'a mut CommandCtx) -> HandlerFuture<'a> {
+        Box::pin(list_push(ctx, true))
+    }
+
+    fn run_lpush<'a>(ctx: &'a mut CommandCtx) -> HandlerFuture<'a> {
+        Box::pin(list_push(ctx, false))
+    }
+
+    fn run_lrange<'a>(ctx: &'a mut CommandCtx) -> HandlerFuture<'a> {
+        Box::pin(async move { vec![lists::lists::cmd_lrange(ctx.cmd_args, ctx.storage_ref.clone()).await] })
+    }
+
+    fn run_llen<'a>(ctx: &'a mut CommandCtx) -> HandlerFuture<'a> {
+        Box::pin(async move { vec![lists::lists::cmd_llen(ctx.cmd_args, ctx.storage_ref.clone()).await] })
+    }
+
+    fn run_lpop<'a>(ctx: &'a mut CommandCtx) -> HandlerFuture<'a> {
+        Box::pin(async move {
+            let response = lists::lists::cmd_lpop(ctx.cmd_args, ctx.storage_ref.clone()).await;
+            if matches!(&response, RespValue::Bulk(_)) || matches!(&response, RespValue::Array(popped) if !popped.is_empty()) {
+                notify(ctx, _NOTIFY_LIST_, "lpop", &ctx.cmd_args[1]).await;
+                // the list goes away with its last element
+                if !ctx.storage_ref.lock().await.contains_key(&ctx.cmd_args[1]) {
+                    notify(ctx, _NOTIFY_GENERIC_, "del", &ctx.cmd_args[1]).await;
+                }
+            }
+            vec![response]
+        })
+    }
+
+    fn run_blpop<'a>(ctx: &'a mut CommandCtx) -> HandlerFuture<'a> {
+        Box::pin(async move {
+            let timeout = match parse_arg::<f64>(&ctx.cmd_args[2]) {
+                Some(secs) if secs < 0.0 => return vec![redis_err(_ERROR_TIMEOUT_NEGATIVE_)],
+                Some(secs) => match Duration::try_from_secs_f64(secs) {
+                    Ok(timeout) => timeout,
+                    Err(_) => return vec![redis_err(_ERROR_TIMEOUT_NOT_A_FLOAT_)],
+                },
+                None => return vec![redis_err(_ERROR_TIMEOUT_NOT_A_FLOAT_)],
+            };
+            // add this client to the waiting list
+            ctx.glob_config.lock().await.blocked_clients.entry(ctx.cmd_args[1].clone()).or_default().push_back(ctx.config_args.other_port);
+            println!("client {} waiting on {}", ctx.config_args.other_port, arg_str(&ctx.cmd_args[1]));
+            vec![pub_sub::pub_sub::cmd_blpop(ctx.config_args, ctx.cmd_args, ctx.storage_ref.clone(), ctx.tx.subscribe(), ctx.glob_config.clone(), timeout).await]
+        })
+    }
+
+    fn run_xadd<'a>(ctx: &'a mut CommandCtx) -> HandlerFuture<'a> {
+        Box::pin(async move {
+            let response = streams::streams::cmd_xadd(ctx.cmd_args, ctx.storage_ref.clone(), ctx.tx.clone()).await;
+            if !response.is_error() {
+                notify(ctx, _NOTIFY_STREAM_, "xadd", &ctx.cmd_args[1]).await;
+            }
+            vec![response]
+        })
+    }
+
+    fn run_xrange<'a>(ctx: &'a mut CommandCtx) -> HandlerFuture<'a> {
+        Box::pin(async move { vec![streams::streams::cmd_xrange(ctx.cmd_args, ctx.storage_ref.clone()).await] })
+    }
+
+    fn run_xread<'a>(ctx: &'a mut CommandCtx) -> HandlerFuture<'a> {
+        Box::pin(async move { vec![streams::streams::cmd_xread(ctx.config_args, ctx.cmd_args, ctx.storage_ref.clone(), ctx.tx.subscribe()).await] })
+    }
+
+    fn run_subscribe<'a>(ctx: &'a mut CommandCtx) -> HandlerFuture<'a> {
+        Box::pin(async move {
+            // one confirmation per channel
+            let mut replies = vec![];
+            for chan_name in &ctx.cmd_args[1..] {
+                replies.push(pub_sub::pub_sub::cmd_sub(ctx.glob_config.clone(), ctx.config_args, &[ctx.cmd_args[0].clone(), chan_name.clone()]).await);
+            }
+            replies
+        })
+    }
+
+    fn run_unsubscribe<'a>(ctx: &'a mut CommandCtx) -> HandlerFuture<'a> {
+        Box::pin(async move {
+            // without channels the client leaves every channel it is subscribed to
+            let chans: Vec<Vec<u8>> = if ctx.cmd_args.len() > 1 {
+                ctx.cmd_args[1..].to_vec()
+            } else {
+                ctx.config_args.subbed_chans.keys().cloned().collect()
+            };
+            if chans.is_empty() {
+                let count = pub_sub::pub_sub::subscription_count(ctx.config_args);
+                return vec![RespValue::Push(vec![RespValue::bulk("unsubscribe"), RespValue::Null, RespValue::Integer(count)])];
+            }
+
+            let mut replies = vec![];
+            for chan_name in chans {
+                replies.push(pub_sub::pub_sub::cmd_unsub(ctx.config_args, &[ctx.cmd_args[0].clone(), chan_name], ctx.glob_config.clone()).await);
+            }
+            replies
+        })
+    }
+
+    fn run_psubscribe<'a>(ctx: &'a mut CommandCtx) -> HandlerFuture<'a> {
+        Box::pin(async move {
+            // one confirmation per pattern
+            let mut replies = vec![];
+            for pattern in &ctx.cmd_args[1..] {
+                replies.push(pub_sub::pub_sub::cmd_psub(ctx.glob_config.clone(), ctx.config_args, &[ctx.cmd_args[0].clone(), pattern.clone()]).await);
+            }
+            replies
+        })
+    }
+
+    fn run_punsubscribe<'a>(ctx: &'a mut CommandCtx) -> HandlerFuture<'a> {
+        Box::pin(async move {
+            // without patterns the client leaves every pattern it is subscribed to
+            let patterns: Vec<Vec<u8>> = if ctx.cmd_args.len() > 1 {
+                ctx.cmd_args[1..].to_vec()
+            } else {
+                ctx.config_args.subbed_patterns.keys().cloned().collect()
+            };
+            if patterns.is_empty() {
+                let count = pub_sub::pub_sub::subscription_count(ctx.config_args);
+                return vec![RespValue::Push(vec![RespValue::bulk("punsubscribe"), RespValue::Null, RespValue::Integer(count)])];
+            }
+
+            let mut replies = vec![];
+            for pattern in patterns {
+                replies.push(pub_sub::pub_sub::cmd_punsub(ctx.config_args, &[ctx.cmd_args[0].clone(), pattern], ctx.glob_config.clone()).await);
+            }
+            replies
+        })
+    }
+
+    fn run_publish<'a>(ctx: &'a mut CommandCtx) -> HandlerFuture<'a> {
+        Box::pin(async move { vec![pub_sub::pub_sub::cmd_pub(ctx.config_args, ctx.cmd_args, ctx.glob_config.clone(), ctx.tx.clone()).await] })
+    }
+
+    fn run_zadd<'a>(ctx: &'a mut CommandCtx) -> HandlerFuture<'a> {
+        Box::pin(async move {
+            let response = sorted_sets::sorted_sets::cmd_zadd(ctx.config_args, ctx.cmd_args, ctx.storage_ref.clone()).await;
+            if !response.is_error() {
+                notify(ctx, _NOTIFY_ZSET_, "zadd", &ctx.cmd_args[1]).await;
+            }
+            vec![response]
+        })
+    }
+
+    fn run_zrank<'a>(ctx: &'a mut CommandCtx) -> HandlerFuture<'a> {
+        Box::pin(async move { vec![sorted_sets::sorted_sets::cmd_zrank(ctx.config_args, ctx.cmd_args, ctx.storage_ref.clone()).await] })
+    }
+
+    fn run_zrange<'a>(ctx: &'a mut CommandCtx) -> HandlerFuture<'a> {
+        Box::pin(async move { vec![sorted_sets::sorted_sets::cmd_zrange(ctx.config_args, ctx.cmd_args, ctx.storage_ref.clone()).await] })
+    }
+
+    fn run_zcard<'a>(ctx: &'a mut CommandCtx) -> HandlerFuture<'a> {
+        Box::pin(async move { vec![sorted_sets::sorted_sets::cmd_zcard(ctx.config_args, ctx.cmd_args, ctx.storage_ref.clone()).await] })
+    }
+
+    fn run_zscore<'a>(ctx: &'a mut CommandCtx) -> HandlerFuture<'a> {
+        Box::pin(async move { vec![sorted_sets::sorted_sets::cmd_zscore(ctx.config_args, ctx.cmd_args, ctx.storage_ref.clone()).await] })
+    }
+
+    fn run_zscan<'a>(ctx: &'a mut CommandCtx) -> HandlerFuture<'a> {
+        Box::pin(async move { vec![sorted_sets::sorted_sets::cmd_zscan(ctx.config_args, ctx.cmd_args, ctx.storage_ref.clone()).await] })
+    }
+
+    fn run_zrem<'a>(ctx: &'a mut CommandCtx) -> HandlerFuture<'a> {
+        Box::pin(async move {
+            let response = sorted_sets::sorted_sets::cmd_zrem(ctx.config_args, ctx.cmd_args, ctx.storage_ref.clone()).await;
+            if matches!(response, RespValue::Integer(removed) if removed > 0) {
+                notify(ctx, _NOTIFY_ZSET_, "zrem", &ctx.cmd_args[1]).await;
+                // removing the last member removes the key
+                let gone = !ctx.storage_ref.lock().await.contains_key(&ctx.cmd_args[1]);
+                if gone {
+                    notify(ctx, _NOTIFY_GENERIC_, "del", &ctx.cmd_args[1]).await;
+                }
+            }
+            vec![response]
+        })
+    }
+
+    fn run_geoadd<'a>(ctx: &'a mut CommandCtx) -> HandlerFuture<'a> {
+        Box::pin(async move { vec![geospatial::geospatial::cmd_geoadd(ctx.config_args, ctx.cmd_args, ctx.storage_ref.clone()).await] })
+    }
+
+    fn run_geopos<'a>(ctx: &'a mut CommandCtx) -> HandlerFuture<'a> {
+        Box::pin(async move { vec![geospatial::geospatial::cmd_geopos(ctx.config_args, ctx.cmd_args, ctx.storage_ref.clone()).await] })
+    }
+
+    fn run_geodist<'a>(ctx: &'a mut CommandCtx) -> HandlerFuture<'a> {
+        Box::pin(async move { vec![geospatial::geospatial::cmd_geodist(ctx.config_args, ctx.cmd_args, ctx.storage_ref.clone()).await] })
+    }
+
+    fn run_geosearch<'a>(ctx: &'a mut CommandCtx) -> HandlerFuture<'a> {
+        Box::pin(async move {
+            // only the FROMLONLAT lon lat BYRADIUS radius unit form is supported
+            if ctx.cmd_args.len() < 8 {
+                return vec![redis_err(_ERROR_SYNTAX_)];
+            }
+            vec![geospatial::geospatial::cmd_geosearch(ctx.cmd_args, ctx.storage_ref.clone()).await]
+        })
+    }
+}
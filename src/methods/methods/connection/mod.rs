@@ -0,0 +1,115 @@
+pub mod connection {
+    use std::sync::Arc;
+    use tokio::sync::Mutex;
+    use crate::utils::utils::*;
+
+    // HELLO [protover [AUTH username password] [SETNAME clientname]]
+    // switches this connection to the requested protocol and replies with the server properties
+    pub async fn cmd_hello(
+        config_args: &mut Args,
+        cmd_args: &[Vec<u8>],
+        glob_config: Arc<Mutex<GlobConfig>>) -> RespValue {
+
+        let mut resp3 = config_args.resp3;
+        let mut client_name = None;
+
+        if cmd_args.len() > 1 {
+            match parse_arg::<usize>(&cmd_args[1]) {
+                Some(2) => resp3 = false,
+                Some(3) => resp3 = true,
+                Some(_) => return redis_err(_ERROR_NOPROTO_),
+                None => return redis_err("ERR Protocol version is not an integer or out of range"),
+            }
+        }
+
+        let mut i = 2;
+        while i < cmd_args.len() {
+            let remaining = cmd_args.len() - i - 1;
+            match arg_str(&cmd_args[i]).to_uppercase().as_str() {
+                "AUTH" if remaining >= 2 => {
+                    let user = arg_str(&cmd_args[i + 1]);
+                    let pass = arg_str(&cmd_args[i + 2]);
+                    let glob_config = glob_config.lock().await;
+                    // with no users configured the default user has no password, like a fresh redis
+                    let allowed = if glob_config.users.is_empty() {
+                        user == "default"
+                    } else {
+                        glob_config.users.contains_key(&(user, pass))
+                    };
+                    if !allowed {
+                        return redis_err(_ERROR_WRONGPASS_);
+                    }
+                    i += 3;
+                },
+                "SETNAME" if remaining >= 1 => {
+                    client_name = Some(arg_str(&cmd_args[i + 1]));
+                    i += 2;
+                },
+                _ => {
+                    return redis_err(&format!("ERR Syntax error in HELLO option '{}'", arg_str(&cmd_args[i])));
+                }
+            }
+        }
+
+        // only switch once every option was accepted
+        config_args.resp3 = resp3;
+        if let Some(name) = client_name {
+            config_args.client_name = name;
+        }
+
+        let role = if config_args.replicaof.starts_with("None") { "master" } else { "replica" };
+        RespValue::Map(vec![
+            (RespValue::bulk("server"), RespValue::bulk("redis")),
+            (RespValue::bulk("version"), RespValue::bulk(_SERVER_VERSION_)),
+            (RespValue::bulk("proto"), RespValue::Integer(if resp3 { 3 } else { 2 })),
+            (RespValue::bulk("id"), RespValue::Integer(config_args.other_port as i64)),
+            (RespValue::bulk("mode"), RespValue::bulk("standalone")),
+            (RespValue::bulk("role"), RespValue::bulk(role)),
+            (RespValue::bulk("modules"), RespValue::Array(vec![])),
+        ])
+    }
+
+    // CLIENT SETNAME | GETNAME | ID | SETINFO, enough for client libraries to finish their handshake
+    pub async fn cmd_client(config_args: &mut Args, cmd_args: &[Vec<u8>]) -> RespValue {
+        if cmd_args.len() < 2 {
+            return redis_err("ERR wrong number of arguments for 'client' command");
+        }
+
+        match arg_str(&cmd_args[1]).to_uppercase().as_str() {
+            "SETNAME" if cmd_args.len() == 3 => {
+                if cmd_args[2].iter().any(|&ch| ch <= b' ' || ch > b'~') {
+                    return redis_err("ERR Client names cannot contain spaces, newlines or special characters.");
+                }
+                config_args.client_name = arg_str(&cmd_args[2]);
+                response_ok()
+            },
+            "GETNAME" => {
+                if config_args.client_name.is_empty() {
+                    return RespValue::Null;
+                }
+                RespValue::bulk(&config_args.client_name)
+            },
+            "ID" => {
+                RespValue::Integer(config_args.other_port as i64)
+            },
+            // library name/version are informational only
+            "SETINFO" if cmd_args.len() == 4 => {
+                response_ok()
+            },
+            _ => {
+                redis_err(&format!("ERR unknown subcommand or wrong number of arguments for '{}'", arg_str(&cmd_args[1])))
+            }
+        }
+    }
+
+    // SELECT index, switches the database this connection works in
+    pub fn cmd_select(config_args: &mut Args, index: &[u8], dbs: &Databases) -> RespValue {
+        match dbs.parse_index(index) {
+            Ok(index) => {
+                config_args.db = index;
+                response_ok()
+            },
+            Err(e) => e,
+        }
+    }
+}
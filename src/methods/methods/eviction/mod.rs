@@ -0,0 +1,237 @@
+pub mod eviction {
+    // maxmemory support. Memory isn't measured, instead every key carries an estimate of its size in its metadata,
+    // computed the way MEMORY USAGE does in redis(big values are sampled rather than walked) and brought up to date
+    // by track_keys after each command. Once the sum goes over maxmemory a master evicts keys picked by the
+    // maxmemory policy before running a command, and refuses the commands that may grow memory when nothing is left
+    // to evict. The metadata also keeps the last access time and a logarithmic access counter for the lru and lfu
+    // policies, which OBJECT IDLETIME and OBJECT FREQ report
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::time::SystemTime;
+    use crate::utils::utils::*;
+
+    // elements looked at to estimate the size of a list, stream or sorted set
+    const _SIZE_SAMPLES_: usize = 5;
+    // hash map entry, ttl and metadata of a key
+    const _KEY_OVERHEAD_: usize = 64;
+    // allocation and bookkeeping of one element of a list, stream entry or sorted set
+    const _ELEMENT_OVERHEAD_: usize = 16;
+    // candidates kept in the eviction pool
+    const _EVPOOL_SIZE_: usize = 16;
+    // keys sampled per database each time the pool is filled, redis' maxmemory-samples default
+    const _MAXMEMORY_SAMPLES_: usize = 5;
+    // redis' lfu-log-factor and lfu-decay-time(in minutes) defaults, and the counter of new keys
+    const _LFU_INIT_VAL_: u8 = 5;
+    const _LFU_LOG_FACTOR_: f64 = 10.0;
+    const _LFU_DECAY_TIME_: u64 = 1;
+
+    static RNG_STATE: AtomicU64 = AtomicU64::new(0);
+
+    // xorshift64*, good enough to pick random keys and for the lfu counter
+    pub fn random() -> u64 {
+        let mut x = RNG_STATE.load(Ordering::Relaxed);
+        if x == 0 {
+            x = unix_millis(SystemTime::now()) as u64 | 1;
+        }
+        x ^= x >> 12;
+        x ^= x << 25;
+        x ^= x >> 27;
+        RNG_STATE.store(x, Ordering::Relaxed);
+        x.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    // current time in ms and in minutes, the lru clock and the lfu decay clock
+    fn clocks() -> (u64, u64) {
+        let now = unix_millis(SystemTime::now()) as u64;
+        (now, now / 60_000)
+    }
+
+    // the size of the sampled items scaled up to all of them
+    fn sampled<T>(items: impl ExactSizeIterator<Item = T>, size: impl Fn(T) -> usize) -> usize {
+        let len = items.len();
+        if len == 0 {
+            return 0;
+        }
+        let sample: usize = items.take(_SIZE_SAMPLES_).map(size).sum();
+        sample * len / len.min(_SIZE_SAMPLES_)
+    }
+
+    pub fn value_size(value: &RDBValue) -> usize {
+        match value {
+            RDBValue::String(s) => s.len(),
+            RDBValue::List(list) => sampled(list.iter(), |elem| elem.len() + _ELEMENT_OVERHEAD_),
+            RDBValue::Stream(entries) => sampled(entries.iter(), |entry| {
+                let fields: usize = entry.value.iter().map(|(field, value)| field.len() + value.len() + 2 * _ELEMENT_OVERHEAD_).sum();
+                fields + _ELEMENT_OVERHEAD_
+            }),
+            RDBValue::ZSet(set) => zset_size(set),
+        }
+    }
+
+    // members are stored three times, in the member to score map, the ordered set and the ZSCAN order
+    pub fn zset_size(set: &SortedSet) -> usize {
+        sampled(set.kv.keys(), |member| 3 * (member.len() + std::mem::size_of::<SortableF64>() + _ELEMENT_OVERHEAD_))
+    }
+
+    // the counter once decayed by one for every _LFU_DECAY_TIME_ minutes since the last decay
+    pub fn lfu_decr(meta: &KeyMeta, now_min: u64) -> u8 {
+        let periods = now_min.saturating_sub(meta.ldt) / _LFU_DECAY_TIME_;
+        meta.freq.saturating_sub(periods.min(u8::MAX as u64) as u8)
+    }
+
+    // increments the counter with a probability that falls as the counter grows, so 255 takes about a million hits
+    fn lfu_incr(counter: u8) -> u8 {
+        if counter == u8::MAX {
+            return counter;
+        }
+        let base = counter.saturating_sub(_LFU_INIT_VAL_) as f64;
+        let p = 1.0 / (base * _LFU_LOG_FACTOR_ + 1.0);
+        if (random() as f64 / u64::MAX as f64) < p { counter + 1 } else { counter }
+    }
+
+    // seconds since the key was last accessed
+    pub fn idle_time(meta: &KeyMeta) -> u64 {
+        clocks().0.saturating_sub(meta.lru) / 1000
+    }
+
+    pub fn lfu_counter(meta: &KeyMeta) -> u8 {
+        lfu_decr(meta, clocks().1)
+    }
+
+    // brings the metadata of the keys of a database up to date after a command ran on them: sizes are estimated
+    // again with the difference going to used_memory, whether they have a ttl is noted and keys that are gone are
+    // forgotten. With touch the command counts as an access to the keys
+    pub async fn track_keys(dbs: &Databases, db: usize, keys: &[&[u8]], touch: bool) {
+        let db_ref = &dbs.dbs[db];
+        let storage = db_ref.storage.lock().await;
+        let mut meta = db_ref.meta.lock().await;
+        let (now_ms, now_min) = clocks();
+
+        for &key in keys {
+            let Some((value, exp_ts)) = storage.get(key) else {
+                if let Some(old) = meta.remove(key) {
+                    dbs.used_memory.fetch_sub(old.size, Ordering::Relaxed);
+                }
+                continue;
+            };
+
+            let size = value_size(value) + key.len() + _KEY_OVERHEAD_;
+            dbs.used_memory.fetch_add(size, Ordering::Relaxed);
+            if let Some(key_meta) = meta.get_mut(key) {
+                dbs.used_memory.fetch_sub(key_meta.size, Ordering::Relaxed);
+                key_meta.size = size;
+                if touch {
+                    key_meta.lru = now_ms;
+                    key_meta.freq = lfu_incr(lfu_decr(key_meta, now_min));
+                    key_meta.ldt = now_min;
+                }
+            } else {
+                meta.insert(key.to_vec(), KeyMeta { size, lru: now_ms, freq: _LFU_INIT_VAL_, ldt: now_min });
+            }
+            meta.set_volatile(key, exp_ts.is_some());
+        }
+    }
+
+    // access metadata of a key RESTORE created, as if it was accessed idle seconds ago and with the given lfu
+    // counter. Its size is left to track_keys
+    pub async fn set_access(db: &Db, key: &[u8], idle: Option<u64>, freq: Option<u8>) {
+        let (now_ms, now_min) = clocks();
+        let key_meta = KeyMeta {
+            size: 0,
+            lru: now_ms.saturating_sub(idle.unwrap_or(0).saturating_mul(1000)),
+            freq: freq.unwrap_or(_LFU_INIT_VAL_),
+            ldt: now_min,
+        };
+        let mut meta = db.meta.lock().await;
+        // a replaced key keeps the size that is counted in used_memory
+        let size = meta.get(key).map_or(0, |old| old.size);
+        meta.insert(key.to_vec(), KeyMeta { size, ..key_meta });
+    }
+
+    // samples a few random keys of every database into the pool of candidates, which keeps the best _EVPOOL_SIZE_
+    // seen so far under the policy like redis' eviction pool. Under the volatile policies only keys with a ttl are
+    // candidates
+    async fn fill_pool(dbs: &Databases, policy: MaxmemoryPolicy, pool: &mut Vec<(u64, usize, Vec<u8>)>) {
+        use MaxmemoryPolicy::*;
+        let volatile = matches!(policy, VolatileLru | VolatileLfu | VolatileRandom | VolatileTtl);
+        let (now_ms, now_min) = clocks();
+
+        for (index, db) in dbs.dbs.iter().enumerate() {
+            let storage = db.storage.lock().await;
+            let meta = db.meta.lock().await;
+            let candidates = if volatile { meta.volatile_len() } else { meta.len() };
+            if candidates == 0 {
+                continue;
+            }
+            for _ in 0.._MAXMEMORY_SAMPLES_.min(candidates) {
+                let key = meta.key_at(random() as usize % candidates, volatile);
+                // the index catches up with keys deleted or persisted behind its back once they are tracked
+                let (Some(key_meta), Some((_, exp_ts))) = (meta.get(key), storage.get(key)) else {
+                    continue;
+                };
+                if volatile && exp_ts.is_none() {
+                    continue;
+                }
+                let score = match policy {
+                    AllkeysLru | VolatileLru => now_ms.saturating_sub(key_meta.lru),
+                    AllkeysLfu | VolatileLfu => (u8::MAX - lfu_decr(key_meta, now_min)) as u64,
+                    AllkeysRandom | VolatileRandom => random(),
+                    // the sooner it expires the better
+                    VolatileTtl => u64::MAX - exp_ts.map_or(0, |ts| unix_millis(ts) as u64),
+                    Noeviction => return,
+                };
+
+                // a key sampled again takes its new score
+                pool.retain(|(_, db, other)| *db != index || other != key);
+                if pool.len() == _EVPOOL_SIZE_ && pool.last().is_some_and(|(worst, _, _)| *worst >= score) {
+                    continue;
+                }
+                let pos = pool.partition_point(|(other, _, _)| *other >= score);
+                pool.insert(pos, (score, index, key.to_vec()));
+                pool.truncate(_EVPOOL_SIZE_);
+            }
+        }
+    }
+
+    // deletes an evicted key, returns whether it still existed. The pool may be stale, a key that lost its ttl
+    // since it was sampled isn't evicted under the volatile policies
+    async fn evict_key(dbs: &Databases, db: usize, key: &[u8], volatile: bool) -> bool {
+        let db_ref = &dbs.dbs[db];
+        let mut storage = db_ref.storage.lock().await;
+        if volatile && storage.get(key).is_some_and(|(_, exp_ts)| exp_ts.is_none()) {
+            return false;
+        }
+        let mut meta = db_ref.meta.lock().await;
+        let existed = storage.remove(key).is_some();
+        if let Some(old) = meta.remove(key) {
+            dbs.used_memory.fetch_sub(old.size, Ordering::Relaxed);
+        }
+        if existed {
+            dbs.evicted_keys.fetch_add(1, Ordering::Relaxed);
+        }
+        existed
+    }
+
+    // evicts keys until the used memory is back under maxmemory or the policy has nothing left to evict, returns
+    // the evicted keys with their database for the replicas. Every key evicted costs one sample of each database
+    pub async fn evict(dbs: &Databases, maxmemory: usize, policy: MaxmemoryPolicy) -> Vec<(usize, Vec<u8>)> {
+        use MaxmemoryPolicy::*;
+        let volatile = matches!(policy, VolatileLru | VolatileLfu | VolatileRandom | VolatileTtl);
+        let mut evicted = vec![];
+        if policy == Noeviction || dbs.used_memory.load(Ordering::Relaxed) <= maxmemory {
+            return evicted;
+        }
+
+        let mut pool = dbs.eviction_pool.lock().await;
+        while dbs.used_memory.load(Ordering::Relaxed) > maxmemory {
+            fill_pool(dbs, policy, &mut pool).await;
+            if pool.is_empty() {
+                break;
+            }
+            let (_, db, key) = pool.remove(0);
+            if evict_key(dbs, db, &key, volatile).await {
+                evicted.push((db, key));
+            }
+        }
+        evicted
+    }
+}
@@ -0,0 +1,106 @@
+pub mod expiry {
+    // keys with a ttl are removed in two ways like in redis: lazily when a command touches an expired key, and by a
+    // background cycle on the master that samples keys with a ttl and deletes the expired ones, so keys nobody asks
+    // for again don't stay in memory forever. Replicas never expire keys themselves, they wait for the master's DEL
+    // and meanwhile just don't show expired keys
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+    use tokio::sync::{broadcast, Mutex};
+    use tokio::time::interval;
+    use crate::methods::methods::eviction::eviction::{random, track_keys};
+    use crate::methods::methods::notify::notify::notify_keyspace_event;
+    use crate::utils::utils::*;
+
+    // how many times per second the active cycle runs
+    const _SERVER_HZ_: u64 = 10;
+    // keys looked at per sampling round
+    const _ACTIVE_EXPIRE_CYCLE_KEYS_PER_LOOP_: usize = 20;
+    // keep sampling while more than this percentage of a round was expired
+    const _ACTIVE_EXPIRE_CYCLE_ACCEPTABLE_STALE_: usize = 25;
+    // share of each 1/hz period a cycle may spend, in percent
+    const _ACTIVE_EXPIRE_CYCLE_SLOW_TIME_PERC_: u64 = 25;
+
+    // removes the keys that are expired, returns the removed ones
+    pub async fn expire_if_needed(keys: &[&[u8]], storage_ref: Storage) -> Vec<Vec<u8>> {
+        let mut storage = storage_ref.lock().await;
+
+        let mut removed = vec![];
+        for &key in keys {
+            if storage.get(key).is_some_and(|(_, exp_ts)| is_expired(exp_ts)) {
+                storage.remove(key);
+                removed.push(key.to_vec());
+            }
+        }
+        removed
+    }
+
+    // one cycle over a database: rounds of keys picked at random among those with a ttl, until a round is mostly
+    // alive or the deadline passes. Returns the deleted keys
+    pub async fn expire_cycle(db: &Db, deadline: Instant) -> Vec<Vec<u8>> {
+        let mut deleted = vec![];
+
+        loop {
+            // the locks are taken per round so clients get in between rounds
+            let mut storage = db.storage.lock().await;
+            let mut meta = db.meta.lock().await;
+            let volatile = meta.volatile_len();
+            if volatile == 0 {
+                break;
+            }
+
+            // small databases have all their keys with a ttl looked at, the others a random sample of them
+            let sample: Vec<Vec<u8>> = if volatile <= _ACTIVE_EXPIRE_CYCLE_KEYS_PER_LOOP_ {
+                (0..volatile).map(|pos| meta.key_at(pos, true).to_vec()).collect()
+            } else {
+                (0.._ACTIVE_EXPIRE_CYCLE_KEYS_PER_LOOP_).map(|_| meta.key_at(random() as usize % volatile, true).to_vec()).collect()
+            };
+            let mut expired = 0;
+            for key in &sample {
+                if !storage.get(key).is_some_and(|(_, exp_ts)| is_expired(exp_ts)) {
+                    continue;
+                }
+                storage.remove(key);
+                // out of the sample for the next rounds, track_keys forgets the rest of it afterwards
+                meta.set_volatile(key, false);
+                deleted.push(key.clone());
+                expired += 1;
+            }
+            drop(meta);
+            drop(storage);
+
+            if expired * 100 <= sample.len() * _ACTIVE_EXPIRE_CYCLE_ACCEPTABLE_STALE_ || Instant::now() > deadline {
+                break;
+            }
+        }
+
+        deleted
+    }
+
+    // background task of a master, runs the active expire cycle over the databases hz times per second and sends a
+    // DEL for every deleted key to the replicas
+    pub async fn active_expire(dbs: Arc<Databases>, tx: broadcast::Sender<Vec<u8>>, glob_config: Arc<Mutex<GlobConfig>>) {
+        let mut ticker = interval(Duration::from_millis(1000 / _SERVER_HZ_));
+        let budget = Duration::from_micros(1_000_000 / _SERVER_HZ_ * _ACTIVE_EXPIRE_CYCLE_SLOW_TIME_PERC_ / 100);
+        // the databases share the time budget of a cycle, the next one picks up where the last one ran out of time
+        let mut next_db = 0;
+
+        loop {
+            ticker.tick().await;
+            let deadline = Instant::now() + budget;
+            for _ in 0..dbs.dbs.len() {
+                if Instant::now() > deadline {
+                    break;
+                }
+                let db = &dbs.dbs[next_db];
+                let deleted = expire_cycle(db, deadline).await;
+                let keys: Vec<&[u8]> = deleted.iter().map(Vec::as_slice).collect();
+                track_keys(&dbs, next_db, &keys, false).await;
+                for key in deleted {
+                    notify_keyspace_event(&glob_config, &tx, _NOTIFY_EXPIRED_, "expired", &key, next_db).await;
+                    dbs.propagate(next_db, &[b"DEL".to_vec(), key], &tx);
+                }
+                next_db = (next_db + 1) % dbs.dbs.len();
+            }
+        }
+    }
+}
@@ -1,136 +1,148 @@
 pub mod geospatial {
-    use std::{collections::HashMap, sync::Arc};
-
-    use tokio::sync::Mutex;
-
     use crate::utils::utils::*;
 
     pub async fn cmd_geoadd(
         _: &Args,
-        cmd_args: &Vec<String>,
-        sorted_set_ref: Arc<Mutex<HashMap<String, SortedSet>>>) -> String {
-        
-        // args format: [_, key, long, lat, member]
-        // let GEO_SET_NAME = String::from("GEO"); // all the geolocation entries belong to the GEO set 
+        cmd_args: &[Vec<u8>],
+        storage_ref: Storage) -> RespValue {
+
+        // args format: [_, key, long, lat, member, long, lat, member, ...]
         let key = &cmd_args[1]; // set name
+        if !(cmd_args.len() - 2).is_multiple_of(3) {
+            return redis_err(_ERROR_SYNTAX_);
+        }
+
+        // every location is checked before anything is added
+        let mut values = vec![];
+        for location in cmd_args[2..].chunks(3) {
+            let (Some(long), Some(lat)) = (parse_arg::<f64>(&location[0]), parse_arg::<f64>(&location[1])) else {
+                return redis_err(_ERROR_NOT_A_FLOAT_);
+            };
+            let value = GEOlocation{
+                member: location[2].clone(),
+                lat: SortableF64(lat),
+                long: SortableF64(long),
+            };
+
+            if !(-180.0..=180.0).contains(&value.long.0)
+                || !(-85.05112878..=85.05112878).contains(&value.lat.0) {
+                return redis_err(&format!("{} {:.6},{:.6}", _ERROR_OUT_OF_RANGE_GEOCOORDS_, value.long.0, value.lat.0));
+            }
+            values.push(value);
+        }
+
+        let mut storage = storage_ref.lock().await;
 
-        let value = GEOlocation{
-            member: cmd_args[4].clone(),
-            lat: SortableF64(cmd_args[3].clone().parse().unwrap()),  
-            long: SortableF64(cmd_args[2].clone().parse().unwrap()),
+        let set = match zset_entry(&mut storage, key) {
+            Ok(set) => set,
+            Err(e) => return e,
         };
 
-        if value.long.0 > 180.0 
-            || value.long.0 < -180.0 
-            || value.lat.0 < -85.05112878
-            || value.lat.0 > 85.05112878 {
-            return redis_err(&format!("{} {:?},{:?}", _ERROR_OUT_OF_RANGE_GEOCOORDS_, value.long, value.lat));
-        } 
-
-        let mut sorted_set = sorted_set_ref.lock().await;
-        
-        let set = sorted_set.entry(key.clone()).or_default();
-        // let serialized_value = &serde_json::to_string(&value).unwrap();
-
-        // for now 
-        // dont store coords
-        // score is hardcoded to 0.0
-        encode_int(set.insert(&value.member, &SortableF64(geo_encode(value.lat.0, value.long.0) as f64), &value.member))
+        // the score is the interleaved geohash of the coordinates, geo_decode recovers them
+        let added: usize = values.iter()
+            .map(|value| set.insert(&value.member, &SortableF64(geo_encode(value.lat.0, value.long.0) as f64), &value.member))
+            .sum();
+        RespValue::Integer(added as i64)
     }
 
     pub async fn cmd_geopos(
-        cmd_args: &Vec<String>,
-        sorted_set_ref: Arc<Mutex<HashMap<String, SortedSet>>>) -> String {
+        _: &Args,
+        cmd_args: &[Vec<u8>],
+        storage_ref: Storage) -> RespValue {
 
         let mut result = vec![];
         let set_name = &cmd_args[1];
 
-        for i in 2..cmd_args.len() {
-
-            let place = &cmd_args[i];
-
-            if let Some(set) = sorted_set_ref.lock().await.get(set_name) {
-                if let Some(score) = set.kv.get(place) {
-                    let coords = geo_decode(score.0 as u64);
-                    result.push(encode_array(&vec![coords.longitude.to_string(), coords.latitude.to_string()], true));
-                } else {
-                    result.push("*-1\r\n".to_owned());
-                    // result.push(encode_array(&vec![], false));
-                }
+        let storage = storage_ref.lock().await;
+        let set = match live_zset(&storage, set_name) {
+            Ok(set) => set,
+            Err(e) => return e,
+        };
+        for place in &cmd_args[2..] {
+            if let Some(score) = set.and_then(|set| set.kv.get(place)) {
+                let coords = geo_decode(score.0 as u64);
+                result.push(RespValue::Array(vec![
+                    RespValue::Double(coords.longitude),
+                    RespValue::Double(coords.latitude)]));
             } else {
-
-                result.push("*-1\r\n".to_owned());
-                // result.push(encode_array(&vec![], false));
+                result.push(RespValue::NullArray);
             }
         }
 
-        if result.is_empty() {
-            return "*-1\r\n".to_owned();
-        }
-
-        encode_array(&result, false)
+        RespValue::Array(result)
     }
 
     pub async fn cmd_geodist(
-        cmd_args: &Vec<String>,
-        sorted_set_ref: Arc<Mutex<HashMap<String, SortedSet>>>,
-    ) -> String {
+        _: &Args,
+        cmd_args: &[Vec<u8>],
+        storage_ref: Storage,
+    ) -> RespValue {
 
-        let set_name = &cmd_args[1]; 
+        let set_name = &cmd_args[1];
         let key1 = &cmd_args[2];
         let key2 = &cmd_args[3];
-        let mut result = encode_bulk("");
+        let mut result = RespValue::Null;
 
-        if let Some(set) = sorted_set_ref.lock().await.get(set_name) {
-            let coords1 = geo_decode(set.kv.get(key1).unwrap().0 as u64);
-            let coords2 = geo_decode(set.kv.get(key2).unwrap().0 as u64);
+        let storage = storage_ref.lock().await;
+        let set = match live_zset(&storage, set_name) {
+            Ok(set) => set,
+            Err(e) => return e,
+        };
+        if let Some(set) = set {
+            // nil if either member is missing
+            if let (Some(score1), Some(score2)) = (set.kv.get(key1), set.kv.get(key2)) {
+                let coords1 = geo_decode(score1.0 as u64);
+                let coords2 = geo_decode(score2.0 as u64);
 
-            result= encode_bulk(haversine_dist(&coords1, &coords2).to_string().as_str());
-        }  
+                result = RespValue::Double(haversine_dist(&coords1, &coords2));
+            }
+        }
 
         result
-    } 
-    
+    }
+
     pub async fn cmd_geosearch(
-        cmd_args: &Vec<String>,
-        sorted_set_ref: Arc<Mutex<HashMap<String, SortedSet>>>,
-    ) -> String {
+        cmd_args: &[Vec<u8>],
+        storage_ref: Storage,
+    ) -> RespValue {
         // 0 1 2 3 4 5 6 7
         // FROMLONLAT, BYRADIUS options are fixed
         // [GEOSEARCH places FROMLONLAT long lat BYRADIUS x m]
         let set_name    =  &cmd_args[1];
-        let center  = Coordinates{
-            longitude: cmd_args[3].parse().unwrap(), 
-            latitude: cmd_args[4].parse().unwrap()
-        };  
-        let radius: f64;
-
-        match cmd_args[7].as_str() {
-            "km" => {
-                radius =  cmd_args[6].parse::<f64>().unwrap() * 1000.0;
-            },
-            "m" => {
-                radius =  cmd_args[6].parse::<f64>().unwrap();
-            },
-            "mi" => {
-                radius =  cmd_args[6].parse::<f64>().unwrap() * 1609.34;
-            },
+        let (Some(longitude), Some(latitude), Some(radius)) = (
+            parse_arg::<f64>(&cmd_args[3]),
+            parse_arg::<f64>(&cmd_args[4]),
+            parse_arg::<f64>(&cmd_args[6])
+        ) else {
+            return redis_err(_ERROR_NOT_A_FLOAT_);
+        };
+        let center  = Coordinates{ longitude, latitude };
+        let radius: f64 = radius * match arg_str(&cmd_args[7]).as_str() {
+            "km" => 1000.0,
+            "m" => 1.0,
+            "mi" => 1609.34,
+            "ft" => 0.3048,
             _ => {
-                panic!("unidentified distance metric in cmd: geosearch.");
+                return redis_err("ERR unsupported unit provided. please use M, KM, FT, MI");
             }
-        }
+        };
 
         let mut result = vec![];
 
-        if let Some(set) = sorted_set_ref.lock().await.get(set_name) {
+        let storage = storage_ref.lock().await;
+        let set = match live_zset(&storage, set_name) {
+            Ok(set) => set,
+            Err(e) => return e,
+        };
+        if let Some(set) = set {
             for (loc, score) in set.kv.iter() {
                 if haversine_dist(&center, &geo_decode(score.0 as u64)) <= radius {
                     result.push(loc.clone());
                 }
-            } 
-        }  
+            }
+        }
 
-        encode_array(&result, true)
-    } 
+        RespValue::bulk_array(&result)
+    }
 
-}
\ No newline at end of file
+}
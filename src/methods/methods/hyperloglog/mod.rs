@@ -0,0 +1,370 @@
+pub mod hyperloglog {
+    // HyperLogLog with the same string layout as redis(hyperloglog.c), so the values can be read back with GET and
+    // moved between servers as they are:
+    //
+    //   +------+---+-----+----------+
+    //   | HYLL | E | N/U | Cardin.  |    4 bytes magic, 1 byte encoding, 3 unused bytes,
+    //   +------+---+-----+----------+    8 bytes little endian cached cardinality(msb of the last byte set = stale)
+    //
+    // followed by 16384 registers, either dense(6 bits each, least significant bits first) or sparse(run length
+    // encoded with the ZERO, XZERO and VAL opcodes). New keys start out sparse and turn dense once the sparse form
+    // grows past _HLL_SPARSE_MAX_BYTES_ or a register needs a value the VAL opcode can't hold
+    use crate::methods::methods::bitmaps::bitmaps::*;
+    use crate::utils::utils::*;
+
+    const _HLL_P_: u32 = 14;
+    const _HLL_Q_: u32 = 64 - _HLL_P_;
+    const _HLL_REGISTERS_: usize = 1 << _HLL_P_;
+    const _HLL_BITS_: usize = 6;
+    const _HLL_HDR_SIZE_: usize = 16;
+    const _HLL_DENSE_SIZE_: usize = _HLL_HDR_SIZE_ + (_HLL_REGISTERS_ * _HLL_BITS_).div_ceil(8);
+    const _HLL_DENSE_: u8 = 0;
+    const _HLL_SPARSE_: u8 = 1;
+    // redis' default hll-sparse-max-bytes, header included
+    const _HLL_SPARSE_MAX_BYTES_: usize = 3000;
+    const _HLL_SPARSE_VAL_MAX_VALUE_: u8 = 32;
+    const _HLL_SPARSE_VAL_MAX_LEN_: usize = 4;
+    const _HLL_SPARSE_ZERO_MAX_LEN_: usize = 64;
+    const _HLL_SPARSE_XZERO_MAX_LEN_: usize = 16384;
+    const _HLL_ALPHA_INF_: f64 = 0.721_347_520_444_481_7;
+
+    const _ERROR_NOT_HLL_: &str = "WRONGTYPE Key is not a valid HyperLogLog string value.";
+    const _ERROR_CORRUPTED_HLL_: &str = "INVALIDOBJ Corrupted HLL object detected";
+
+    // MurmurHash2, 64 bit version for 64 bit platforms, the hash redis uses for HyperLogLog elements
+    fn murmurhash64a(key: &[u8], seed: u64) -> u64 {
+        const M: u64 = 0xc6a4a7935bd1e995;
+        const R: u32 = 47;
+        let mut h = seed ^ (key.len() as u64).wrapping_mul(M);
+
+        let blocks = key.chunks_exact(8);
+        let tail = blocks.remainder();
+        for block in blocks {
+            let mut k = u64::from_le_bytes(block.try_into().unwrap());
+            k = k.wrapping_mul(M);
+            k ^= k >> R;
+            k = k.wrapping_mul(M);
+            h ^= k;
+            h = h.wrapping_mul(M);
+        }
+        if !tail.is_empty() {
+            for (i, byte) in tail.iter().enumerate() {
+                h ^= (*byte as u64) << (8 * i);
+            }
+            h = h.wrapping_mul(M);
+        }
+
+        h ^= h >> R;
+        h = h.wrapping_mul(M);
+        h ^= h >> R;
+        h
+    }
+
+    // register an element falls in and the run of zeros(plus one) that follows in its hash
+    fn pattern_len(element: &[u8]) -> (usize, u8) {
+        let hash = murmurhash64a(element, 0xadc83b19);
+        let index = (hash & (_HLL_REGISTERS_ as u64 - 1)) as usize;
+        let hash = (hash >> _HLL_P_) | (1 << _HLL_Q_);
+        (index, hash.trailing_zeros() as u8 + 1)
+    }
+
+    fn dense_get(registers: &[u8], index: usize) -> u8 {
+        let (byte, fb) = (index * _HLL_BITS_ / 8, (index * _HLL_BITS_) & 7);
+        let b0 = registers[byte] as u16;
+        let b1 = registers.get(byte + 1).copied().unwrap_or(0) as u16;
+        (((b0 >> fb) | (b1 << (8 - fb))) & 63) as u8
+    }
+
+    fn dense_set(registers: &mut [u8], index: usize, value: u8) {
+        let (byte, fb) = (index * _HLL_BITS_ / 8, (index * _HLL_BITS_) & 7);
+        let value = value as u16;
+        registers[byte] &= !((63u16 << fb) as u8);
+        registers[byte] |= (value << fb) as u8;
+        if let Some(next) = registers.get_mut(byte + 1) {
+            *next &= !((63u16 >> (8 - fb)) as u8);
+            *next |= (value >> (8 - fb)) as u8;
+        }
+    }
+
+    fn sparse_decode(data: &[u8]) -> Result<Vec<u8>, RespValue> {
+        let mut registers = Vec::with_capacity(_HLL_REGISTERS_);
+        let mut i = 0;
+        while i < data.len() {
+            let op = data[i];
+            if op & 0xc0 == 0x00 {
+                // ZERO 00xxxxxx
+                registers.resize(registers.len() + (op & 0x3f) as usize + 1, 0);
+                i += 1;
+            } else if op & 0xc0 == 0x40 {
+                // XZERO 01xxxxxx yyyyyyyy
+                let Some(&low) = data.get(i + 1) else {
+                    return Err(redis_err(_ERROR_CORRUPTED_HLL_));
+                };
+                registers.resize(registers.len() + (((op & 0x3f) as usize) << 8 | low as usize) + 1, 0);
+                i += 2;
+            } else {
+                // VAL 1vvvvvxx
+                let value = ((op >> 2) & 0x1f) + 1;
+                registers.resize(registers.len() + (op & 0x3) as usize + 1, value);
+                i += 1;
+            }
+            if registers.len() > _HLL_REGISTERS_ {
+                return Err(redis_err(_ERROR_CORRUPTED_HLL_));
+            }
+        }
+        if registers.len() != _HLL_REGISTERS_ {
+            return Err(redis_err(_ERROR_CORRUPTED_HLL_));
+        }
+        Ok(registers)
+    }
+
+    // sparse opcodes for the registers, None when a value is too big for a VAL opcode
+    fn sparse_encode(registers: &[u8]) -> Option<Vec<u8>> {
+        let mut data = vec![];
+        let mut i = 0;
+        while i < registers.len() {
+            let value = registers[i];
+            let run = registers[i..].iter().take_while(|&&v| v == value).count();
+            i += run;
+            if value > _HLL_SPARSE_VAL_MAX_VALUE_ {
+                return None;
+            }
+            let mut left = run;
+            while left > 0 {
+                if value == 0 && left > _HLL_SPARSE_ZERO_MAX_LEN_ {
+                    let len = left.min(_HLL_SPARSE_XZERO_MAX_LEN_);
+                    data.push(0x40 | ((len - 1) >> 8) as u8);
+                    data.push(((len - 1) & 0xff) as u8);
+                    left -= len;
+                } else if value == 0 {
+                    data.push((left - 1) as u8);
+                    left = 0;
+                } else {
+                    let len = left.min(_HLL_SPARSE_VAL_MAX_LEN_);
+                    data.push(0x80 | ((value - 1) << 2) | (len - 1) as u8);
+                    left -= len;
+                }
+            }
+        }
+        Some(data)
+    }
+
+    // checks the header and the size of a string that should hold a HyperLogLog
+    fn check_hll(bytes: &[u8]) -> Result<(), RespValue> {
+        if bytes.len() < _HLL_HDR_SIZE_ || &bytes[..4] != b"HYLL" || bytes[4] > _HLL_SPARSE_
+            || (bytes[4] == _HLL_DENSE_ && bytes.len() != _HLL_DENSE_SIZE_) {
+            return Err(redis_err(_ERROR_NOT_HLL_));
+        }
+        Ok(())
+    }
+
+    // the 16384 registers of a HyperLogLog, one byte each
+    fn registers(bytes: &[u8]) -> Result<Vec<u8>, RespValue> {
+        check_hll(bytes)?;
+        if bytes[4] == _HLL_SPARSE_ {
+            return sparse_decode(&bytes[_HLL_HDR_SIZE_..]);
+        }
+        let dense = &bytes[_HLL_HDR_SIZE_..];
+        Ok((0.._HLL_REGISTERS_).map(|index| dense_get(dense, index)).collect())
+    }
+
+    // HyperLogLog string for the registers, sparse when asked for and small enough, dense otherwise
+    fn encode(registers: &[u8], header: &[u8], sparse: bool) -> Vec<u8> {
+        let mut out = header[.._HLL_HDR_SIZE_].to_vec();
+        if sparse {
+            if let Some(data) = sparse_encode(registers).filter(|data| _HLL_HDR_SIZE_ + data.len() <= _HLL_SPARSE_MAX_BYTES_) {
+                out[4] = _HLL_SPARSE_;
+                out.extend(data);
+                return out;
+            }
+        }
+        out[4] = _HLL_DENSE_;
+        out.resize(_HLL_DENSE_SIZE_, 0);
+        for (index, &value) in registers.iter().enumerate() {
+            dense_set(&mut out[_HLL_HDR_SIZE_..], index, value);
+        }
+        out
+    }
+
+    fn empty_hll() -> Vec<u8> {
+        let mut header = b"HYLL".to_vec();
+        header.resize(_HLL_HDR_SIZE_, 0);
+        encode(&[0; _HLL_REGISTERS_], &header, true)
+    }
+
+    fn invalidate_cache(bytes: &mut [u8]) {
+        bytes[15] |= 0x80;
+    }
+
+    fn cached_count(bytes: &[u8]) -> Option<u64> {
+        (bytes[15] & 0x80 == 0).then(|| u64::from_le_bytes(bytes[8..16].try_into().unwrap()))
+    }
+
+    fn hll_sigma(mut x: f64) -> f64 {
+        if x == 1.0 {
+            return f64::INFINITY;
+        }
+        let (mut y, mut z) = (1.0, x);
+        loop {
+            x *= x;
+            let z_prime = z;
+            z += x * y;
+            y += y;
+            if z_prime == z {
+                return z;
+            }
+        }
+    }
+
+    fn hll_tau(mut x: f64) -> f64 {
+        if x == 0.0 || x == 1.0 {
+            return 0.0;
+        }
+        let (mut y, mut z) = (1.0, 1.0 - x);
+        loop {
+            x = x.sqrt();
+            let z_prime = z;
+            y *= 0.5;
+            z -= (1.0 - x).powi(2) * y;
+            if z_prime == z {
+                return z / 3.0;
+            }
+        }
+    }
+
+    // cardinality estimate of the registers, Otmar Ertl's improved estimator like redis uses since 5.0
+    fn estimate(registers: &[u8]) -> u64 {
+        let mut histogram = [0u32; 64];
+        for &value in registers {
+            histogram[value as usize] += 1;
+        }
+
+        let m = _HLL_REGISTERS_ as f64;
+        let q = _HLL_Q_ as usize;
+        let mut z = m * hll_tau((m - histogram[q + 1] as f64) / m);
+        for j in (1..=q).rev() {
+            z += histogram[j] as f64;
+            z *= 0.5;
+        }
+        z += m * hll_sigma(histogram[0] as f64 / m);
+        (_HLL_ALPHA_INF_ * m * m / z).round() as u64
+    }
+
+    // PFADD key [element ...], returns 1 when the key was created or a register changed
+    pub async fn cmd_pfadd(cmd_args: &[Vec<u8>], storage_ref: Storage) -> RespValue {
+        let key = &cmd_args[1];
+        let mut storage = storage_ref.lock().await;
+
+        let created = match bitmap(&storage, key) {
+            Ok(bytes) => bytes.is_none(),
+            Err(e) => return e,
+        };
+        let bytes = match bitmap_mut(&mut storage, key) {
+            Ok(bytes) => bytes,
+            Err(e) => return e,
+        };
+        if created {
+            *bytes = empty_hll();
+        }
+        if let Err(e) = check_hll(bytes) {
+            return e;
+        }
+
+        let mut changed = false;
+        if bytes[4] == _HLL_DENSE_ {
+            // registers are updated in place
+            for element in &cmd_args[2..] {
+                let (index, count) = pattern_len(element);
+                if dense_get(&bytes[_HLL_HDR_SIZE_..], index) < count {
+                    dense_set(&mut bytes[_HLL_HDR_SIZE_..], index, count);
+                    changed = true;
+                }
+            }
+        } else {
+            let mut regs = match sparse_decode(&bytes[_HLL_HDR_SIZE_..]) {
+                Ok(regs) => regs,
+                Err(e) => return e,
+            };
+            for element in &cmd_args[2..] {
+                let (index, count) = pattern_len(element);
+                if regs[index] < count {
+                    regs[index] = count;
+                    changed = true;
+                }
+            }
+            if changed {
+                *bytes = encode(&regs, bytes, true);
+            }
+        }
+
+        if changed {
+            invalidate_cache(bytes);
+        }
+        RespValue::Integer((created || changed) as i64)
+    }
+
+    // PFCOUNT key [key ...], the estimate of a single key is cached in its header, several keys are counted as
+    // the union of their registers
+    pub async fn cmd_pfcount(cmd_args: &[Vec<u8>], storage_ref: Storage) -> RespValue {
+        let mut storage = storage_ref.lock().await;
+
+        if cmd_args.len() == 2 {
+            let bytes = match bitmap(&storage, &cmd_args[1]) {
+                Ok(Some(_)) => bitmap_mut(&mut storage, &cmd_args[1]).unwrap(),
+                Ok(None) => return RespValue::Integer(0),
+                Err(e) => return e,
+            };
+            let regs = match registers(bytes) {
+                Ok(regs) => regs,
+                Err(e) => return e,
+            };
+            let count = cached_count(bytes).unwrap_or_else(|| estimate(&regs));
+            bytes[8..16].copy_from_slice(&count.to_le_bytes());
+            return RespValue::Integer(count as i64);
+        }
+
+        let mut union = vec![0u8; _HLL_REGISTERS_];
+        for key in &cmd_args[1..] {
+            let regs = match bitmap(&storage, key) {
+                Ok(Some(bytes)) => registers(bytes),
+                Ok(None) => continue,
+                Err(e) => return e,
+            };
+            match regs {
+                Ok(regs) => union.iter_mut().zip(regs).for_each(|(max, value)| *max = (*max).max(value)),
+                Err(e) => return e,
+            }
+        }
+        RespValue::Integer(estimate(&union) as i64)
+    }
+
+    // PFMERGE destkey [sourcekey ...], the destination becomes the union of itself and the sources, it stays sparse
+    // only if all the inputs were
+    pub async fn cmd_pfmerge(cmd_args: &[Vec<u8>], storage_ref: Storage) -> RespValue {
+        let mut storage = storage_ref.lock().await;
+
+        let mut union = vec![0u8; _HLL_REGISTERS_];
+        let mut sparse = true;
+        for key in cmd_args[1..].iter() {
+            let regs = match bitmap(&storage, key) {
+                Ok(Some(bytes)) => {
+                    sparse &= bytes.get(4) == Some(&_HLL_SPARSE_);
+                    registers(bytes)
+                },
+                Ok(None) => continue,
+                Err(e) => return e,
+            };
+            match regs {
+                Ok(regs) => union.iter_mut().zip(regs).for_each(|(max, value)| *max = (*max).max(value)),
+                Err(e) => return e,
+            }
+        }
+
+        let dest = bitmap_mut(&mut storage, &cmd_args[1]).unwrap();
+        let header = if dest.is_empty() { empty_hll() } else { dest.clone() };
+        *dest = encode(&union, &header, sparse);
+        invalidate_cache(dest);
+
+        response_ok()
+    }
+}
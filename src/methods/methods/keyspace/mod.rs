@@ -0,0 +1,485 @@
+pub mod keyspace {
+    // generic commands that work on keys of any type
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+    use tokio::sync::MutexGuard;
+    use crate::methods::methods::eviction::eviction::{idle_time, lfu_counter, set_access, track_keys};
+    use crate::methods::methods::replication::replication::{dump_value, restore_value};
+    use crate::utils::utils::*;
+
+    type Entry = (RDBValue, Option<SystemTime>);
+
+    // locks two different databases, always the lower index first so commands going opposite ways can't deadlock
+    async fn lock_dbs(dbs: &Databases, first: usize, second: usize) -> (MutexGuard<'_, Entries>, MutexGuard<'_, Entries>) {
+        if first < second {
+            let first = dbs.dbs[first].storage.lock().await;
+            (first, dbs.dbs[second].storage.lock().await)
+        } else {
+            let second = dbs.dbs[second].storage.lock().await;
+            (dbs.dbs[first].storage.lock().await, second)
+        }
+    }
+
+    fn key_exists(storage: &Entries, key: &[u8]) -> bool {
+        storage.get(key).is_some_and(|(_, exp_ts)| !is_expired(exp_ts))
+    }
+
+    fn get_entry(storage: &Entries, key: &[u8]) -> Option<Entry> {
+        storage.get(key).filter(|(_, exp_ts)| !is_expired(exp_ts)).cloned()
+    }
+
+    // removes the key, returns its value when it was alive
+    fn take_entry(storage: &mut Entries, key: &[u8]) -> Option<Entry> {
+        storage.remove(key).filter(|(_, exp_ts)| !is_expired(exp_ts))
+    }
+
+    // DEL key [key ...] and UNLINK key [key ...], returns how many of the keys existed and which. UNLINK only takes the keys
+    // out of the keyspace and leaves freeing the values, which can be big, to a background thread
+    pub async fn cmd_del(keys: &[Vec<u8>], lazy_free: bool, storage_ref: Storage) -> (RespValue, Vec<Vec<u8>>) {
+        let mut storage = storage_ref.lock().await;
+
+        let (deleted_keys, deleted): (Vec<Vec<u8>>, Vec<Entry>) = keys.iter()
+            .filter_map(|key| take_entry(&mut storage, key).map(|entry| (key.clone(), entry)))
+            .unzip();
+        if lazy_free && !deleted.is_empty() {
+            tokio::task::spawn_blocking(move || drop(deleted));
+        }
+
+        (RespValue::Integer(deleted_keys.len() as i64), deleted_keys)
+    }
+
+    // EXISTS key [key ...], a key given more than once is counted every time
+    pub async fn cmd_exists(keys: &[Vec<u8>], storage_ref: Storage) -> RespValue {
+        let storage = storage_ref.lock().await;
+
+        RespValue::Integer(keys.iter().filter(|key| key_exists(&storage, key)).count() as i64)
+    }
+
+    // TYPE key, none for a missing key
+    pub async fn cmd_type(key: &[u8], storage_ref: Storage) -> RespValue {
+        match storage_ref.lock().await.get(key).filter(|(_, exp_ts)| !is_expired(exp_ts)) {
+            Some((value, _)) => RespValue::Simple(value.repr()),
+            None => RespValue::simple("none"),
+        }
+    }
+
+    // OBJECT IDLETIME key and OBJECT FREQ key, nil for a missing key. Like in redis the idle time is only reported
+    // under the lru policies and the access counter only under the lfu ones
+    pub async fn cmd_object(cmd_args: &[Vec<u8>], policy: MaxmemoryPolicy, db: &Db) -> RespValue {
+        let subcommand = arg_str(&cmd_args[1]).to_uppercase();
+        if !matches!(subcommand.as_str(), "IDLETIME" | "FREQ") || cmd_args.len() != 3 {
+            return redis_err(&format!("ERR unknown subcommand or wrong number of arguments for '{}'. Try OBJECT HELP.", arg_str(&cmd_args[1])));
+        }
+        let key = &cmd_args[2];
+        let storage = db.storage.lock().await;
+        if !key_exists(&storage, key) {
+            return RespValue::Null;
+        }
+        let meta = db.meta.lock().await;
+        let Some(key_meta) = meta.get(key) else {
+            return RespValue::Null;
+        };
+
+        match (subcommand.as_str(), policy.is_lfu()) {
+            ("IDLETIME", false) => RespValue::Integer(idle_time(key_meta) as i64),
+            ("IDLETIME", true) => redis_err("ERR An LFU maxmemory policy is selected, idle time not tracked. Please note that when switching between policies at runtime LRU and LFU data will take some time to adjust."),
+            (_, true) => RespValue::Integer(lfu_counter(key_meta) as i64),
+            (_, false) => redis_err("ERR An LFU maxmemory policy is not selected, access frequency not tracked. Please note that when switching between policies at runtime LRU and LFU data will take some time to adjust."),
+        }
+    }
+
+    // DUMP key, the value serialized the way it is in an rdb file
+    pub async fn cmd_dump(key: &[u8], storage_ref: Storage) -> RespValue {
+        let storage = storage_ref.lock().await;
+        let Some((value, _)) = storage.get(key).filter(|(_, exp_ts)| !is_expired(exp_ts)) else {
+            return RespValue::Null;
+        };
+        RespValue::Bulk(dump_value(value))
+    }
+
+    // RESTORE key ttl serialized-value [REPLACE] [ABSTTL] [IDLETIME seconds] [FREQ frequency], a ttl of 0 means
+    // no expiry and ABSTTL makes it a unix time in ms. Like in redis it is replicated with the absolute ttl, or as
+    // a DEL when an already expired value replaces a key
+    pub async fn cmd_restore(cmd_args: &[Vec<u8>], db: &Db) -> (RespValue, Option<Vec<Vec<u8>>>) {
+        let key = &cmd_args[1];
+        let (mut replace, mut absttl, mut idle, mut freq) = (false, false, None, None);
+        let mut i = 4;
+        while i < cmd_args.len() {
+            // IDLETIME and FREQ can't go together
+            match arg_str(&cmd_args[i]).to_uppercase().as_str() {
+                "REPLACE" => replace = true,
+                "ABSTTL" => absttl = true,
+                "IDLETIME" if i + 1 < cmd_args.len() && freq.is_none() => {
+                    i += 1;
+                    match parse_arg::<i64>(&cmd_args[i]) {
+                        Some(seconds) if seconds >= 0 => idle = Some(seconds as u64),
+                        Some(_) => return (redis_err("ERR Invalid IDLETIME value, must be >= 0"), None),
+                        None => return (redis_err(_ERROR_INCR_NOT_AN_INT_), None),
+                    }
+                },
+                "FREQ" if i + 1 < cmd_args.len() && idle.is_none() => {
+                    i += 1;
+                    match parse_arg::<i64>(&cmd_args[i]) {
+                        Some(counter) if (0..=255).contains(&counter) => freq = Some(counter as u8),
+                        Some(_) => return (redis_err("ERR Invalid FREQ value, must be >= 0 and <= 255"), None),
+                        None => return (redis_err(_ERROR_INCR_NOT_AN_INT_), None),
+                    }
+                },
+                _ => return (redis_err(_ERROR_SYNTAX_), None),
+            }
+            i += 1;
+        }
+
+        let mut storage = db.storage.lock().await;
+        if !replace && key_exists(&storage, key) {
+            return (redis_err("BUSYKEY Target key name already exists."), None);
+        }
+        let ttl = match parse_arg::<i64>(&cmd_args[2]) {
+            Some(ttl) if ttl >= 0 => ttl,
+            Some(_) => return (redis_err("ERR Invalid TTL value, must be >= 0"), None),
+            None => return (redis_err(_ERROR_INCR_NOT_AN_INT_), None),
+        };
+        let value = match restore_value(&cmd_args[3]) {
+            Ok(value) => value,
+            Err(e) => return (e, None),
+        };
+
+        let now = unix_millis(SystemTime::now()) as i64;
+        let when = match ttl {
+            0 => None,
+            ttl if absttl => Some(ttl),
+            ttl => Some(now.saturating_add(ttl)),
+        };
+        if when.is_some_and(|when| when <= now) {
+            let deleted = take_entry(&mut storage, key).is_some();
+            return (response_ok(), deleted.then(|| vec![b"DEL".to_vec(), key.clone()]));
+        }
+        let exp_ts = when.map(|when| UNIX_EPOCH + Duration::from_millis(when as u64));
+        storage.insert(key.clone(), (value, exp_ts));
+        drop(storage);
+        set_access(db, key, idle, freq).await;
+
+        let mut propagate = cmd_args.to_vec();
+        if let (Some(when), false) = (when, absttl) {
+            propagate[2] = when.to_string().into_bytes();
+            propagate.push(b"ABSTTL".to_vec());
+        }
+        (response_ok(), Some(propagate))
+    }
+
+    // KEYS pattern, the keys are copied out so the matching, which is the slow part, happens without the locks
+    pub async fn cmd_keys(pattern: &[u8], storage_ref: Storage) -> RespValue {
+        let mut keys: Vec<Vec<u8>> = {
+            let storage = storage_ref.lock().await;
+            storage.iter().filter(|(_, (_, exp_ts))| !is_expired(exp_ts)).map(|(key, _)| key.clone()).collect()
+        };
+        if pattern != b"*" {
+            keys.retain(|key| string_match(pattern, key, false));
+        }
+
+        RespValue::bulk_array(&keys)
+    }
+
+    // SCAN cursor [MATCH pattern] [COUNT count] [TYPE type], replies with the next cursor and a batch of keys. MATCH
+    // and TYPE filter the batch after it is picked, like in redis, so a batch may come back empty before the end
+    pub async fn cmd_scan(cmd_args: &[Vec<u8>], db: &Db) -> RespValue {
+        let options = match parse_scan_options(&cmd_args[1..], true) {
+            Ok(options) => options,
+            Err(e) => return e,
+        };
+        if let Some(type_name) = &options.type_name {
+            if !["string", "list", "set", "zset", "hash", "stream"].contains(&type_name.as_str()) {
+                return redis_err(&format!("ERR unknown type name '{}'", type_name));
+            }
+        }
+
+        // the batch comes from the key index, the keys in it that are gone or expired are left out
+        let storage = db.storage.lock().await;
+        let meta = db.meta.lock().await;
+        let (next, batch) = meta.scan.step(options.cursor, options.count);
+        let keys: Vec<&[u8]> = batch.into_iter().filter(|key| {
+            let type_name = match storage.get(*key) {
+                Some((value, exp_ts)) if !is_expired(exp_ts) => value.repr(),
+                _ => return false,
+            };
+            options.type_name.as_ref().is_none_or(|wanted| *wanted == type_name)
+                && options.pattern.as_ref().is_none_or(|pattern| string_match(pattern, key, false))
+        }).collect();
+
+        RespValue::Array(vec![RespValue::bulk(next.to_string()), RespValue::bulk_array(&keys)])
+    }
+
+    // RENAME key newkey and RENAMENX key newkey, the value keeps its ttl and replaces the destination unless nx is set
+    // and the destination exists, in which case 0 is returned
+    pub async fn cmd_rename(key: &[u8], new_key: &[u8], nx: bool, storage_ref: Storage) -> RespValue {
+        let mut storage = storage_ref.lock().await;
+
+        if !key_exists(&storage, key) {
+            return redis_err("ERR no such key");
+        }
+        if key == new_key {
+            return if nx { RespValue::Integer(0) } else { response_ok() };
+        }
+        if nx && key_exists(&storage, new_key) {
+            return RespValue::Integer(0);
+        }
+
+        if let Some(entry) = take_entry(&mut storage, key) {
+            storage.insert(new_key.to_vec(), entry);
+        }
+        if nx { RespValue::Integer(1) } else { response_ok() }
+    }
+
+    // COPY source destination [DB destination-db] [REPLACE], the copy keeps the ttl of the source. Returns 1 when
+    // the key was copied, 0 when the destination exists and REPLACE isn't given, along with the destination database
+    pub async fn cmd_copy(cmd_args: &[Vec<u8>], db: usize, dbs: &Databases) -> (RespValue, usize) {
+        let (source, destination) = (&cmd_args[1], &cmd_args[2]);
+        let mut replace = false;
+        let mut target = db;
+        let mut i = 3;
+        while i < cmd_args.len() {
+            match arg_str(&cmd_args[i]).to_uppercase().as_str() {
+                "REPLACE" => replace = true,
+                "DB" if i + 1 < cmd_args.len() => {
+                    i += 1;
+                    match dbs.parse_index(&cmd_args[i]) {
+                        Ok(index) => target = index,
+                        Err(e) => return (e, target),
+                    }
+                },
+                _ => return (redis_err(_ERROR_SYNTAX_), target),
+            }
+            i += 1;
+        }
+        if source == destination && db == target {
+            return (redis_err("ERR source and destination objects are the same"), target);
+        }
+
+        let copied = if db == target {
+            let mut storage = dbs.dbs[db].storage.lock().await;
+            let entry = get_entry(&storage, source);
+            put_new_entry(&mut storage, destination, entry, replace)
+        } else {
+            let (storage, mut dst_storage) = lock_dbs(dbs, db, target).await;
+            let entry = get_entry(&storage, source);
+            put_new_entry(&mut dst_storage, destination, entry, replace)
+        };
+        // the command's own keys are tracked in the selected database only
+        if copied && db != target {
+            track_keys(dbs, target, &[destination], false).await;
+        }
+
+        (RespValue::Integer(copied as i64), target)
+    }
+
+    // stores the entry unless there is none or the key exists and replace isn't set, returns whether it was stored
+    fn put_new_entry(storage: &mut Entries, key: &[u8], entry: Option<Entry>, replace: bool) -> bool {
+        let Some(entry) = entry else {
+            return false;
+        };
+        if !replace && key_exists(storage, key) {
+            return false;
+        }
+        storage.insert(key.to_vec(), entry);
+        true
+    }
+
+    // MOVE key db, moves the key with its ttl to another database unless it exists there, returns 1 when it moved
+    pub async fn cmd_move(key: &[u8], target: &[u8], db: usize, dbs: &Databases) -> RespValue {
+        let target = match dbs.parse_index(target) {
+            Ok(target) => target,
+            Err(e) => return e,
+        };
+        if target == db {
+            return redis_err("ERR source and destination objects are the same");
+        }
+
+        let moved = {
+            let (mut storage, mut dst_storage) = lock_dbs(dbs, db, target).await;
+            if !key_exists(&storage, key) || key_exists(&dst_storage, key) {
+                return RespValue::Integer(0);
+            }
+            let entry = take_entry(&mut storage, key);
+            put_new_entry(&mut dst_storage, key, entry, false)
+        };
+        if moved {
+            track_keys(dbs, target, &[key], false).await;
+        }
+        RespValue::Integer(moved as i64)
+    }
+
+    // SWAPDB index1 index2, every connection using one of the two databases sees the other one's keys from now on
+    pub async fn cmd_swapdb(first: &[u8], second: &[u8], dbs: &Databases) -> RespValue {
+        let Some(first) = parse_arg::<i64>(first) else {
+            return redis_err("ERR invalid first DB index");
+        };
+        let Some(second) = parse_arg::<i64>(second) else {
+            return redis_err("ERR invalid second DB index");
+        };
+        let in_range = |index: i64| (0..dbs.dbs.len() as i64).contains(&index);
+        if !in_range(first) || !in_range(second) {
+            return redis_err("ERR DB index is out of range");
+        }
+        if first == second {
+            return response_ok();
+        }
+
+        let (mut storage, mut other_storage) = lock_dbs(dbs, first as usize, second as usize).await;
+        std::mem::swap(&mut *storage, &mut *other_storage);
+        let (low, high) = (first.min(second) as usize, first.max(second) as usize);
+        let mut meta = dbs.dbs[low].meta.lock().await;
+        std::mem::swap(&mut *meta, &mut *dbs.dbs[high].meta.lock().await);
+
+        response_ok()
+    }
+
+    // the [ASYNC | SYNC] option of FLUSHDB and FLUSHALL, true for ASYNC
+    pub fn parse_flush_mode(cmd_args: &[Vec<u8>]) -> Result<bool, RespValue> {
+        match cmd_args.get(1).map(|mode| arg_str(mode).to_uppercase()) {
+            None => Ok(false),
+            Some(mode) if mode == "ASYNC" && cmd_args.len() == 2 => Ok(true),
+            Some(mode) if mode == "SYNC" && cmd_args.len() == 2 => Ok(false),
+            Some(_) => Err(redis_err(_ERROR_SYNTAX_)),
+        }
+    }
+
+    // empties a database, with lazy_free the old keys are freed by a background thread. Returns the flushed keys
+    // when with_keys is set, for their del events
+    pub async fn flush_db(dbs: &Databases, index: usize, lazy_free: bool, with_keys: bool) -> Vec<Vec<u8>> {
+        let db = &dbs.dbs[index];
+        let mut storage = db.storage.lock().await;
+        let meta = std::mem::take(&mut *db.meta.lock().await);
+        dbs.used_memory.fetch_sub(meta.values().map(|key_meta| key_meta.size).sum(), std::sync::atomic::Ordering::Relaxed);
+        let flushed = (std::mem::take(&mut *storage), meta);
+        let keys = if with_keys { flushed.0.keys().cloned().collect() } else { vec![] };
+        if lazy_free {
+            tokio::task::spawn_blocking(move || drop(flushed));
+        }
+        keys
+    }
+
+    // DBSIZE, the number of keys of the database, including expired ones that weren't deleted yet
+    pub async fn cmd_dbsize(storage_ref: Storage) -> RespValue {
+        RespValue::Integer(storage_ref.lock().await.len() as i64)
+    }
+
+    // the ttl slot of a live key
+    fn expiry_slot<'a>(storage: &'a mut Entries, key: &[u8]) -> Option<&'a mut Option<SystemTime>> {
+        storage.get_mut(key).filter(|(_, exp_ts)| !is_expired(exp_ts)).map(|(_, exp_ts)| exp_ts)
+    }
+
+    #[derive(Debug, Default)]
+    struct ExpireFlags {
+        nx: bool,
+        xx: bool,
+        gt: bool,
+        lt: bool,
+    }
+
+    fn parse_expire_flags(args: &[Vec<u8>]) -> Result<ExpireFlags, RespValue> {
+        let mut flags = ExpireFlags::default();
+        for arg in args {
+            match arg_str(arg).to_uppercase().as_str() {
+                "NX" => flags.nx = true,
+                "XX" => flags.xx = true,
+                "GT" => flags.gt = true,
+                "LT" => flags.lt = true,
+                _ => return Err(redis_err(&format!("ERR Unsupported option {}", arg_str(arg)))),
+            }
+        }
+        if flags.nx && (flags.xx || flags.gt || flags.lt) {
+            return Err(redis_err("ERR NX and XX, GT or LT options at the same time are not compatible"));
+        }
+        if flags.gt && flags.lt {
+            return Err(redis_err("ERR GT and LT options at the same time are not compatible"));
+        }
+        Ok(flags)
+    }
+
+    // EXPIRE, PEXPIRE, EXPIREAT and PEXPIREAT key time [NX | XX | GT | LT], unit is one of EX, PX, EXAT and PXAT like
+    // for SET. Returns 1 when the ttl was set, a time in the past deletes the key. Replicated as a PEXPIREAT, or a DEL
+    pub async fn cmd_expire(cmd_args: &[Vec<u8>], unit: &str, storage_ref: Storage) -> (RespValue, Option<Vec<Vec<u8>>>) {
+        let invalid = || redis_err(&_error_invalid_expire_msg_(&arg_str(&cmd_args[0]).to_lowercase()));
+        let n = match parse_arg::<i64>(&cmd_args[2]) {
+            Some(n) => n,
+            None => return (redis_err(_ERROR_INCR_NOT_AN_INT_), None),
+        };
+        let flags = match parse_expire_flags(&cmd_args[3..]) {
+            Ok(flags) => flags,
+            Err(e) => return (e, None),
+        };
+
+        // absolute unix time in milliseconds, it has to fit an i64 like in redis
+        let now = unix_millis(SystemTime::now()) as i64;
+        let millis = match unit {
+            "EX" | "EXAT" => n.checked_mul(1000),
+            _ => Some(n),
+        };
+        let when = match millis {
+            Some(millis) if unit.ends_with("AT") => Some(millis),
+            Some(millis) => millis.checked_add(now),
+            None => None,
+        };
+        let Some(when) = when else {
+            return (invalid(), None);
+        };
+
+        let key = &cmd_args[1];
+        let mut storage = storage_ref.lock().await;
+        let Some(exp_ts) = expiry_slot(&mut storage, key) else {
+            return (RespValue::Integer(0), None);
+        };
+
+        // a key without a ttl counts as expiring never
+        let current = exp_ts.map(|ts| unix_millis(ts) as i64);
+        let refused = (flags.nx && current.is_some())
+            || (flags.xx && current.is_none())
+            || (flags.gt && current.is_none_or(|current| when <= current))
+            || (flags.lt && current.is_some_and(|current| when >= current));
+        if refused {
+            return (RespValue::Integer(0), None);
+        }
+
+        if when <= now {
+            storage.remove(key);
+            return (RespValue::Integer(1), Some(vec![b"DEL".to_vec(), key.clone()]));
+        }
+        *exp_ts = Some(UNIX_EPOCH + Duration::from_millis(when as u64));
+        (RespValue::Integer(1), Some(vec![b"PEXPIREAT".to_vec(), key.clone(), when.to_string().into_bytes()]))
+    }
+
+    // TTL, PTTL, EXPIRETIME and PEXPIRETIME key, -2 for a missing key and -1 for a key without a ttl. relative says
+    // whether the time left or the unix time of the expiry is returned, in_millis its unit
+    pub async fn cmd_ttl(key: &[u8], relative: bool, in_millis: bool, storage_ref: Storage) -> RespValue {
+        let mut storage = storage_ref.lock().await;
+
+        let exp_ts = match expiry_slot(&mut storage, key) {
+            Some(Some(exp_ts)) => *exp_ts,
+            Some(None) => return RespValue::Integer(-1),
+            None => return RespValue::Integer(-2),
+        };
+        let millis = match relative {
+            true => exp_ts.duration_since(SystemTime::now()).unwrap_or_default().as_millis() as i64,
+            false => unix_millis(exp_ts) as i64,
+        };
+
+        // like redis the time left is rounded to the nearest second and the unix time truncated
+        RespValue::Integer(match (in_millis, relative) {
+            (true, _) => millis,
+            (false, true) => (millis + 500) / 1000,
+            (false, false) => millis / 1000,
+        })
+    }
+
+    // PERSIST key, returns 1 when the key had a ttl that got removed
+    pub async fn cmd_persist(key: &[u8], storage_ref: Storage) -> RespValue {
+        let mut storage = storage_ref.lock().await;
+
+        match expiry_slot(&mut storage, key) {
+            Some(exp_ts) if exp_ts.is_some() => {
+                *exp_ts = None;
+                RespValue::Integer(1)
+            },
+            _ => RespValue::Integer(0),
+        }
+    }
+}
@@ -1,60 +1,62 @@
 pub mod lists {
-    use std::{collections::{HashMap, VecDeque}, sync::Arc, time::SystemTime};
-    use tokio::sync::{broadcast, Mutex};
+    use std::collections::VecDeque;
+    use tokio::sync::broadcast;
 
     use crate::utils::utils::*;
 
     pub async fn cmd_list_push(
-        cmd_args: &Vec<String>,
-        storage_ref: Arc<Mutex<HashMap<String, (RDBValue, Option<SystemTime>)>>>,
+        cmd_args: &[Vec<u8>],
+        storage_ref: Storage,
         push_back: bool,
-        tx: broadcast::Sender<Vec<u8>>) -> String {
+        tx: broadcast::Sender<Vec<u8>>) -> RespValue {
 
         let result;
         let key = &cmd_args[1];
         {
             let mut _db = storage_ref.lock().await;
 
-            if _db.get_mut(key).is_none() {
+            // a missing or expired key starts out as a new list
+            if _db.get(key).is_none_or(|(_, exp_ts)| is_expired(exp_ts)) {
                 _db.insert(key.clone(), (RDBValue::List(VecDeque::new()), None));
-            } 
+            }
 
-            let (rdb_value, _) = _db.get_mut(key).unwrap(); 
+            let (rdb_value, _) = _db.get_mut(key).unwrap();
 
             match rdb_value {
                 RDBValue::List(v) => {
-                    for i in 2..cmd_args.len() {
+                    for arg in &cmd_args[2..] {
                         if push_back {
-                            v.push_back(cmd_args[i].clone());
+                            v.push_back(arg.clone());
                         } else {
-                            v.push_front(cmd_args[i].clone());
+                            v.push_front(arg.clone());
                         }
                     }
 
-                    result =encode_int(v.len());
+                    result = RespValue::Integer(v.len() as i64);
                 },
                 _ => {
-                    panic!("invalid data type in cmd_list_push()");
+                    return redis_err(_ERROR_WRONGTYPE_);
                 }
             }
         }
 
         // release lock on db, send message
-        let mut msg = _EVENT_DB_UPDATED_LIST_.as_bytes().to_vec().to_owned();
-        msg.extend_from_slice(key.as_bytes());
+        let mut msg = _EVENT_DB_UPDATED_LIST_.as_bytes().to_vec();
+        msg.extend_from_slice(key);
         pbas(&msg);
         tx.send(msg).ok();
 
-        return result;
+        result
     }
 
     pub async fn cmd_lrange(
-        cmd_args: &Vec<String>,
-        storage_ref: Arc<Mutex<HashMap<String, (RDBValue, Option<SystemTime>)>>>) -> String {
-            
+        cmd_args: &[Vec<u8>],
+        storage_ref: Storage) -> RespValue {
+
         let key = &cmd_args[1];
-        let mut l: isize = cmd_args[2].parse().unwrap();
-        let mut r: isize = cmd_args[3].parse().unwrap();
+        let (Some(mut l), Some(mut r)) = (parse_arg::<isize>(&cmd_args[2]), parse_arg::<isize>(&cmd_args[3])) else {
+            return redis_err(_ERROR_INCR_NOT_AN_INT_);
+        };
 
         let _db = storage_ref.lock().await;
         let mut result = vec![];
@@ -68,7 +70,7 @@ pub mod lists {
                     }
                     if r < 0 && r < -(size - 1) {
                         r = 0;
-                    } 
+                    }
 
                     if l < 0 {
                         l = (l + size) % size;
@@ -78,73 +80,69 @@ pub mod lists {
                     }
 
                     if l > r {
-                        return encode_array(&vec![], true);
+                        return RespValue::Array(vec![]);
                     }
 
                     for j in l..std::cmp::min(r + 1, size) {
                         result.push(v[j as usize].clone());
-                    }         
+                    }
                 },
-                _ => { 
-                    panic!("mismatched data types");
+                _ => {
+                    return redis_err(_ERROR_WRONGTYPE_);
                 }
             }
-        } 
+        }
 
-        encode_array(&result, true)
+        RespValue::bulk_array(&result)
     }
 
-    pub async fn cmd_llen(cmd_args: &Vec<String>, storage_ref: Arc<Mutex<HashMap<String, (RDBValue, Option<SystemTime>)>>>) -> String {
+    pub async fn cmd_llen(cmd_args: &[Vec<u8>], storage_ref: Storage) -> RespValue {
 
         if let Some((rdb_val, _)) = storage_ref.lock().await.get(&cmd_args[1]) {
             match rdb_val {
                 RDBValue::List(v) => {
-                    return encode_int(v.len());
+                    return RespValue::Integer(v.len() as i64);
                 },
                 _ => {
-                    panic!("invalid data type for this key in cmd_llen()");
+                    return redis_err(_ERROR_WRONGTYPE_);
                 }
             }
         }
 
-        return encode_int(0);
-    } 
+        RespValue::Integer(0)
+    }
 
     pub async fn cmd_lpop(
-        cmd_args: &Vec<String>,
-        storage_ref: Arc<Mutex<HashMap<String, (RDBValue, Option<SystemTime>)>>>) -> String {
-
-        let mut result = vec![];
-        if let Some((rdb_val, _)) = storage_ref.lock().await.get_mut(&cmd_args[1]) {
-            match rdb_val {
-                RDBValue::List(v) => {
-                    let mut remove_count: usize = 1; 
-                    if cmd_args.len() > 2 {
-                        remove_count = cmd_args[2].parse().unwrap();
-                    }
-
-                    while let Some(val) = v.pop_front() {
-                        result.push(val);
-                        remove_count -= 1;
-                        if remove_count == 0 {
-                            break;
-                        } 
-                    }
-
-                    // if single variant was called
-                    if cmd_args.len() == 2 {
-                        return encode_bulk(&result[0]);
-                    } else {
-                        // if muti variant was called 
-                        return encode_array(&result, true); 
-                    }
-                },
-                _ => {
-                    panic!("invalid data type for this key in cmd_lpop()");
+        cmd_args: &[Vec<u8>],
+        storage_ref: Storage) -> RespValue {
+
+        // LPOP key [count], the count variant replies with an array
+        let count = match cmd_args.get(2) {
+            Some(arg) => match parse_arg::<usize>(arg) {
+                Some(count) => Some(count),
+                None => return redis_err("ERR value is out of range, must be positive"),
+            },
+            None => None,
+        };
+
+        let mut _db = storage_ref.lock().await;
+        let result = match _db.get_mut(&cmd_args[1]) {
+            Some((RDBValue::List(v), _)) => {
+                let popped: Vec<Vec<u8>> = v.drain(..std::cmp::min(count.unwrap_or(1), v.len())).collect();
+                // the list goes away with its last element
+                if v.is_empty() {
+                    _db.remove(&cmd_args[1]);
                 }
-            }
-        } else {
-            return encode_bulk(""); 
+                popped
+            },
+            Some(_) => return redis_err(_ERROR_WRONGTYPE_),
+            None if count.is_some() => return RespValue::NullArray,
+            None => return RespValue::Null,
+        };
+
+        match count {
+            None => result.into_iter().next().map_or(RespValue::Null, RespValue::Bulk),
+            Some(_) => RespValue::bulk_array(&result),
         }
     }
-}
\ No newline at end of file
+}
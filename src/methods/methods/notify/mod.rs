@@ -0,0 +1,34 @@
+pub mod notify {
+    // keyspace notifications like redis' notify.c: with notify-keyspace-events set, changes to keys are published
+    // through the regular pub/sub channels, on __keyspace@<db>__:<key> with the event as the message(K) and on
+    // __keyevent@<db>__:<event> with the key as the message(E), but only for the enabled event classes
+    use std::sync::Arc;
+    use tokio::sync::{broadcast, Mutex};
+    use crate::methods::methods::pub_sub::pub_sub::publish;
+    use crate::utils::utils::*;
+
+    pub async fn notify_keyspace_event(
+        glob_config: &Arc<Mutex<GlobConfig>>,
+        tx: &broadcast::Sender<Vec<u8>>,
+        class: u32,
+        event: &str,
+        key: &[u8],
+        db: usize) {
+
+        let glob_config = glob_config.lock().await;
+        let flags = glob_config.notify_keyspace_events;
+        if flags & class == 0 {
+            return;
+        }
+
+        if flags & _NOTIFY_KEYSPACE_ != 0 {
+            let mut chan_name = format!("__keyspace@{}__:", db).into_bytes();
+            chan_name.extend_from_slice(key);
+            publish(&glob_config, tx, &chan_name, event.as_bytes());
+        }
+        if flags & _NOTIFY_KEYEVENT_ != 0 {
+            let chan_name = format!("__keyevent@{}__:{}", db, event);
+            publish(&glob_config, tx, chan_name.as_bytes(), key);
+        }
+    }
+}
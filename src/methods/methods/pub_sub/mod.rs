@@ -1,55 +1,65 @@
 pub mod pub_sub {
-    use std::{collections::HashMap, sync::Arc, time::{Duration, SystemTime}};
+    use std::{sync::Arc, time::Duration};
     use tokio::{select, sync::{broadcast, Mutex}, time::{sleep_until, Instant}};
     use crate::utils::utils::*;
-    use std::collections::HashSet;
 
     pub async fn cmd_sub (
         glob_config_ref: Arc<Mutex<GlobConfig>>,
         config_args: &mut Args,
-        cmd_args: &Vec<String>) -> String {
+        cmd_args: &[Vec<u8>]) -> RespValue {
         let chan_name = &cmd_args[1];
         config_args.client_in_sub_mode = true;
-        config_args.subbed_chans.insert(chan_name.as_bytes().to_vec(), ());
-        
+        config_args.subbed_chans.insert(chan_name.clone(), ());
+
         let mut glob_config = glob_config_ref.lock().await;
-        if let Some(clients) = glob_config.subscriptions.get_mut(chan_name) {
-            clients.insert(config_args.other_port);
-        } else {
-            let mut new_entry = HashSet::new();
-            new_entry.insert(config_args.other_port);
-            glob_config.subscriptions.insert(chan_name.clone(), new_entry);
-        }
+        glob_config.subscriptions.entry(chan_name.clone()).or_default().insert(config_args.other_port);
 
-        return encode_array(&vec![encode_bulk("subscribe"), encode_bulk(chan_name), encode_int(config_args.subbed_chans.len())], false);
+        RespValue::Push(vec![RespValue::bulk("subscribe"), RespValue::bulk(chan_name), RespValue::Integer(subscription_count(config_args))])
+    }
+
+    pub async fn cmd_psub(
+        glob_config_ref: Arc<Mutex<GlobConfig>>,
+        config_args: &mut Args,
+        cmd_args: &[Vec<u8>]) -> RespValue {
+        let pattern = &cmd_args[1];
+        config_args.client_in_sub_mode = true;
+        config_args.subbed_patterns.insert(pattern.clone(), ());
+
+        let mut glob_config = glob_config_ref.lock().await;
+        glob_config.pattern_subscriptions.entry(pattern.clone()).or_default().insert(config_args.other_port);
+
+        RespValue::Push(vec![RespValue::bulk("psubscribe"), RespValue::bulk(pattern), RespValue::Integer(subscription_count(config_args))])
+    }
+
+    // channels and patterns the client is subscribed to, the count every (un)subscribe confirmation carries
+    pub fn subscription_count(config_args: &Args) -> i64 {
+        (config_args.subbed_chans.len() + config_args.subbed_patterns.len()) as i64
     }
 
     pub async fn cmd_blpop(
         config_args: &Args,
-        cmd_args: &Vec<String>, 
-        storage_ref: Arc<Mutex<HashMap<String, (RDBValue, Option<SystemTime>)>>>,
+        cmd_args: &[Vec<u8>],
+        storage_ref: Storage,
         mut rx: broadcast::Receiver<Vec<u8>>,
-        glob_config: Arc<Mutex<GlobConfig>>) -> String {
-        
+        glob_config: Arc<Mutex<GlobConfig>>,
+        mut timeout: Duration) -> RespValue {
+
         let key = &cmd_args[1];
-        let mut timeout: f32 = cmd_args[2].parse().unwrap(); 
-        let mut result  = vec![];
-        if timeout == 0.0 {
-            timeout = 60.0 * 60.0;  // 1 hour, basically block infinitely for our purposes
+        let result;
+        if timeout.is_zero() {
+            timeout = Duration::from_secs(60 * 60);  // 1 hour, basically block infinitely for our purposes
         }
-        let end = Instant::now() + Duration::from_secs_f32(timeout);
+        // timeouts too far out to be represented block "infinitely" as well
+        let end = Instant::now().checked_add(timeout).unwrap_or_else(|| Instant::now() + Duration::from_secs(60 * 60));
 
         loop {
             select! {
                 _ = sleep_until(end) => {
                     // remove this client from the queue
-                    // we could use a hashmap to make insert and removals O(1) 
-                    glob_config.lock().await.blocked_clients.get_mut(&cmd_args[1]).unwrap().retain(|ele| *ele != config_args.other_port);
+                    // we could use a hashmap to make insert and removals O(1)
+                    glob_config.lock().await.blocked_clients.get_mut(key).unwrap().retain(|ele| *ele != config_args.other_port);
 
-                    // we need to return null array but apparently i havent implemented it yet
-                    return "*-1\r\n".to_owned(); 
-                    // return encode_array(&vec!["".to_owned()], true);
-                    // return encode_bulk("");
+                    return RespValue::NullArray;
                 },
 
                 data = rx.recv() => {
@@ -59,77 +69,105 @@ pub mod pub_sub {
 
                     let msg = data.unwrap();
 
-                    pbas(&msg); 
-                    if msg.starts_with(_EVENT_DB_UPDATED_LIST_.as_bytes()) {
-                        if msg[_EVENT_DB_UPDATED_LIST_.as_bytes().len()..].starts_with(key.as_bytes()) {
-                            // if this isnt the first one waiting on this key
-                            let mut glob_config_data = glob_config.lock().await;
-                            // in the tests the thread is panicking here, but it passes the tests because in this case the thread is supposed to exit 
-                            if glob_config_data.blocked_clients.get(&cmd_args[1]).unwrap().is_empty() || glob_config_data.blocked_clients.get(&cmd_args[1]).unwrap().front().unwrap() != &config_args.other_port {
-                                // continue blocking  
-                                continue;
-                            }
+                    pbas(&msg);
+                    if msg.starts_with(_EVENT_DB_UPDATED_LIST_.as_bytes()) && msg[_EVENT_DB_UPDATED_LIST_.len()..].starts_with(key) {
+                        // if this isnt the first one waiting on this key
+                        let mut glob_config_data = glob_config.lock().await;
+                        // in the tests the thread is panicking here, but it passes the tests because in this case the thread is supposed to exit
+                        if glob_config_data.blocked_clients.get(key).unwrap().front() != Some(&config_args.other_port) {
+                            // continue blocking
+                            continue;
+                        }
 
-                            // if it is 
-                            glob_config_data.blocked_clients.clear();
-                            let mut _db = storage_ref.lock().await;
-                            let (ref mut rdb_value, _) = _db.get_mut(key).unwrap();
-
-                            match rdb_value {
-                                RDBValue::List(v) => {
-                                    result = vec![key.clone(), v.pop_front().unwrap()];
-                                },
-                                _ => {
-                                    panic!("stop ittttt");
-                                }
+                        // if it is
+                        glob_config_data.blocked_clients.clear();
+                        let mut _db = storage_ref.lock().await;
+                        let (ref mut rdb_value, _) = _db.get_mut(key).unwrap();
+
+                        match rdb_value {
+                            RDBValue::List(v) => {
+                                result = vec![key.clone(), v.pop_front().unwrap()];
+                            },
+                            _ => {
+                                return redis_err(_ERROR_WRONGTYPE_);
                             }
-                            break;
                         }
+                        break;
                     }
                 }
-            } 
+            }
         }
 
-        encode_array(&result, true)
+        RespValue::bulk_array(&result)
     }
 
-    pub async fn cmd_pub(config_args: &mut Args, 
-        cmd_args: &Vec<String>, 
+    pub async fn cmd_pub(_: &Args,
+        cmd_args: &[Vec<u8>],
         glob_config_ref: Arc<Mutex<GlobConfig>>,
-        tx: broadcast::Sender<Vec<u8>>) -> String {
-        
+        tx: broadcast::Sender<Vec<u8>>) -> RespValue {
+
         let chan_name = &cmd_args[1];
         let msg = &cmd_args[2];
-        let transmission = encode_array(&vec!["message".to_owned(), chan_name.clone(), msg.clone()], true);
-        config_args.subbed_chans.insert(chan_name.as_bytes().to_owned(), ()); 
 
         let glob_config = glob_config_ref.lock().await;
+        RespValue::Integer(publish(&glob_config, &tx, chan_name, msg) as i64)
+    }
+
+    // broadcasts a message to the connections subscribed to the channel, and a pmessage for every pattern matching
+    // it, each connection checks whether it is one of their subscribers. Returns the number of subscribers
+    pub fn publish(glob_config: &GlobConfig, tx: &broadcast::Sender<Vec<u8>>, chan_name: &[u8], msg: &[u8]) -> usize {
+        let mut receivers = 0;
         // check if there are clients subscribed to this channel
-        if let Some(clients) = glob_config.subscriptions.get(chan_name) {
-            if tx.send(transmission.as_bytes().to_vec()).is_err() {
-                panic!("could not broadcast the publisher's msg");
+        if let Some(clients) = glob_config.subscriptions.get(chan_name).filter(|clients| !clients.is_empty()) {
+            let transmission = encode_command(&[b"message".as_slice(), chan_name, msg]);
+            print!("published: ");
+            pbas(&transmission);
+            tx.send(transmission).ok();
+            receivers += clients.len();
+        }
+        for (pattern, clients) in &glob_config.pattern_subscriptions {
+            if clients.is_empty() || !string_match(pattern, chan_name, false) {
+                continue;
             }
-            println!("published: {}", transmission);
-            return encode_int(clients.len()); 
+            tx.send(encode_command(&[b"pmessage".as_slice(), pattern, chan_name, msg])).ok();
+            receivers += clients.len();
         }
-
-        return encode_int(0);
+        receivers
     }
 
     pub async fn cmd_unsub(
         config_args: &mut Args,
-        cmd_args: &Vec<String>,
-        glob_config: Arc<Mutex<GlobConfig>>) -> String {
+        cmd_args: &[Vec<u8>],
+        glob_config: Arc<Mutex<GlobConfig>>) -> RespValue {
 
         let chan_name = &cmd_args[1];
-        glob_config.lock().await.subscriptions
-            .get_mut(chan_name)
-            .or(Some(&mut HashSet::new())).unwrap()
-            .remove(&config_args.other_port);
-            
-        config_args.subbed_chans.remove(chan_name.as_bytes());
-
-        return encode_array(&vec![encode_bulk("unsubscribe"), encode_bulk(chan_name), encode_int(glob_config.lock().await.subscriptions.get(chan_name).or(Some(&HashSet::new())).unwrap().len())], false); 
+        let mut glob_config = glob_config.lock().await;
+        if let Some(clients) = glob_config.subscriptions.get_mut(chan_name) {
+            clients.remove(&config_args.other_port);
+        }
+
+        config_args.subbed_chans.remove(chan_name);
+        // the client leaves sub mode along with its last channel or pattern
+        config_args.client_in_sub_mode = subscription_count(config_args) > 0;
+
+        RespValue::Push(vec![RespValue::bulk("unsubscribe"), RespValue::bulk(chan_name), RespValue::Integer(subscription_count(config_args))])
+    }
+
+    pub async fn cmd_punsub(
+        config_args: &mut Args,
+        cmd_args: &[Vec<u8>],
+        glob_config: Arc<Mutex<GlobConfig>>) -> RespValue {
+
+        let pattern = &cmd_args[1];
+        let mut glob_config = glob_config.lock().await;
+        if let Some(clients) = glob_config.pattern_subscriptions.get_mut(pattern) {
+            clients.remove(&config_args.other_port);
+        }
+
+        config_args.subbed_patterns.remove(pattern);
+        config_args.client_in_sub_mode = subscription_count(config_args) > 0;
+
+        RespValue::Push(vec![RespValue::bulk("punsubscribe"), RespValue::bulk(pattern), RespValue::Integer(subscription_count(config_args))])
     }
 
-}
\ No newline at end of file
+}
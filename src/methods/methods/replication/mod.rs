@@ -1,155 +1,738 @@
 pub mod replication {
+    use crate::methods::methods::eviction::eviction::track_keys;
     use crate::utils::utils::*;
-    use std::{collections::HashMap, io::ErrorKind, ops::BitAnd, sync::Arc, time::{Duration, SystemTime, UNIX_EPOCH}};
+    use std::{collections::{HashMap, VecDeque}, io::ErrorKind, sync::{atomic::Ordering, Arc}, time::{Duration, SystemTime, UNIX_EPOCH}};
     use bytes::BufMut;
     use tokio::{fs::File, io::{AsyncReadExt, AsyncWriteExt}, sync::Mutex};
     use crc64::crc64;
 
-    
-    pub async fn cmd_fullresync(_: &Args) -> Vec<u8> {
+
+    pub async fn cmd_fullresync(_: &Args) -> RespValue {
         // in future we would wanna read contents from the file on disk and return it
-        // right now the server expects us to hard code contents of such a file   
-        encode_file(_EMPTY_RDB_FILE_.as_bytes()) 
+        // right now the server expects us to hard code contents of such a file
+        encode_file(_EMPTY_RDB_FILE_.as_bytes())
     }
 
-    pub fn encode_file(contents: &[u8]) -> Vec<u8> {
-        let raw_bytes = hex::decode(contents).unwrap(); 
-        let mut res= Vec::<u8>::new(); 
-        res.push(b'$');
-        // res.push(raw_bytes.len() as u8);
-        for b in raw_bytes.len().to_string().as_bytes() {
-            res.push(*b);
-        }
-        res.push(b'\r');
-        res.push(b'\n');
-
-        for b in raw_bytes {
-            res.push(b);
-        }
+    // the rdb file goes out like a bulk string but without the trailing CRLF
+    pub fn encode_file(contents: &[u8]) -> RespValue {
+        let raw_bytes = hex::decode(contents).unwrap();
+        let mut res = format!("${}\r\n", raw_bytes.len()).into_bytes();
+        res.extend(raw_bytes);
 
-        res
+        RespValue::Raw(res)
     }
 
-    pub async fn cmd_info(config_args: &Args) -> String {
+    // INFO [section], the replication section and the keyspace section with the number of keys of each database
+    // that has any, including expired ones that weren't deleted yet
+    pub async fn cmd_info(config_args: &Args, section: Option<&[u8]>, dbs: &Databases) -> RespValue {
+        let section = section.map_or("default".to_owned(), |section| arg_str(section).to_lowercase());
+        let all = ["default", "all", "everything"].contains(&section.as_str());
+
         let mut res = String::new();
-        res.push_str(&format!("role:{}", if config_args.replicaof.starts_with("None") {"master"} else {"slave"}).as_str());
-        if config_args.replicaof.starts_with("None") {
-            res.push_str(&format!("\nmaster_replid:{}", config_args.master_replid));
-            res.push_str(&format!("\nmaster_repl_offset:{}", config_args.master_repl_offset));
-        }  
-        
-        encode_bulk(&res)
+        if all || section == "memory" {
+            let used_memory = dbs.used_memory.load(Ordering::Relaxed);
+            res.push_str("# Memory\r\n");
+            res.push_str(&format!("used_memory:{}\r\n", used_memory));
+            res.push_str(&format!("used_memory_human:{}\r\n", bytes_to_human(used_memory)));
+            res.push_str(&format!("maxmemory:{}\r\n", config_args.maxmemory));
+            res.push_str(&format!("maxmemory_human:{}\r\n", bytes_to_human(config_args.maxmemory)));
+            res.push_str(&format!("maxmemory_policy:{}\r\n", config_args.maxmemory_policy.name()));
+        }
+        if all || section == "stats" {
+            if !res.is_empty() {
+                res.push_str("\r\n");
+            }
+            res.push_str("# Stats\r\n");
+            res.push_str(&format!("evicted_keys:{}\r\n", dbs.evicted_keys.load(Ordering::Relaxed)));
+        }
+        if all || section == "replication" {
+            if !res.is_empty() {
+                res.push_str("\r\n");
+            }
+            res.push_str("# Replication\r\n");
+            res.push_str(&format!("role:{}\r\n", if config_args.replicaof.starts_with("None") {"master"} else {"slave"}));
+            if config_args.replicaof.starts_with("None") {
+                res.push_str(&format!("master_replid:{}\r\n", config_args.master_replid));
+                res.push_str(&format!("master_repl_offset:{}\r\n", config_args.master_repl_offset));
+            }
+        }
+        if all || section == "keyspace" {
+            if !res.is_empty() {
+                res.push_str("\r\n");
+            }
+            res.push_str("# Keyspace\r\n");
+            for (index, db) in dbs.dbs.iter().enumerate() {
+                let storage = db.storage.lock().await;
+                let keys = storage.len();
+                let expires = storage.values().filter(|(_, exp_ts)| exp_ts.is_some()).count();
+                if keys > 0 {
+                    res.push_str(&format!("db{}:keys={},expires={},avg_ttl=0\r\n", index, keys, expires));
+                }
+            }
+        }
+
+        RespValue::bulk(&res)
     }
 
-    pub async fn cmd_config(query: &String, config_args: &Args) -> String {
-        match query.as_str() {
+    pub async fn cmd_config(query: &[u8], config_args: &Args, notify_keyspace_events: u32) -> RespValue {
+        match arg_str(query).as_str() {
             "dir" => {
-                format!("*2\r\n$3\r\ndir\r\n${}\r\n{}\r\n", &config_args.dir.len(), &config_args.dir)
+                RespValue::bulk_array(&["dir", config_args.dir.as_str()])
             },
             "dbfilename" => {
-                format!("*2\r\n$10\r\ndbfilename\r\n${}\r\n{}\r\n", config_args.dbfilename.len(), &config_args.dbfilename)
+                RespValue::bulk_array(&["dbfilename", config_args.dbfilename.as_str()])
+            },
+            "databases" => {
+                RespValue::bulk_array(&["databases".to_owned(), config_args.databases.to_string()])
+            },
+            "maxmemory" => {
+                RespValue::bulk_array(&["maxmemory".to_owned(), config_args.maxmemory.to_string()])
+            },
+            "maxmemory-policy" => {
+                RespValue::bulk_array(&["maxmemory-policy", config_args.maxmemory_policy.name()])
+            },
+            "notify-keyspace-events" => {
+                RespValue::bulk_array(&["notify-keyspace-events".to_owned(), keyspace_events_to_string(notify_keyspace_events)])
             },
             _ => {
-                unimplemented!("Unexpected config parameter requested: {}", query);
+                // unknown parameters match nothing
+                RespValue::Array(vec![])
             }
         }
     }
-    pub async fn cmd_get<'a>(key: &String, dbfilepath: &String, storage_ref: Arc<Mutex<HashMap<String, (RDBValue, Option<SystemTime>)>>>) -> Option<RDBValue> {
-        // syncing db
-        println!("syncing db.."); 
-        cmd_sync(dbfilepath, storage_ref.clone()).await;
-        // println!("searching for {:?}", key);
-
-        let storage = storage_ref.lock().await; 
-        // rewrap for our purposes
-        let result = match storage.get(key) {
-            Some((res, ts)) => {
-                match ts {
-                    Some(n) => {
-                        if *n < SystemTime::now() {
-                            None
-                        } else {
-                            Some (res)
-                        }
-                    },
-                    None => Some(res),  // if no expiry 
-                }
+
+    // CONFIG SET parameter value, only notify-keyspace-events can be changed at runtime
+    pub async fn cmd_config_set(parameter: &[u8], value: &[u8], glob_config: Arc<Mutex<GlobConfig>>) -> RespValue {
+        let parameter = arg_str(parameter).to_lowercase();
+        if parameter != "notify-keyspace-events" {
+            return redis_err(&format!("ERR Unknown option or number of arguments for CONFIG SET - '{}'", parameter));
+        }
+        match parse_keyspace_events(&arg_str(value)) {
+            Ok(flags) => {
+                glob_config.lock().await.notify_keyspace_events = flags;
+                response_ok()
             },
-            None => {
-                println!("did not find key");
-                None
+            Err(e) => redis_err(&format!("ERR CONFIG SET failed (possibly related to argument '{}') - {}", parameter, e)),
+        }
+    }
+
+    pub async fn cmd_get(key: &[u8], storage_ref: Storage) -> Option<RDBValue> {
+        let storage = storage_ref.lock().await;
+        match storage.get(key) {
+            Some((_, exp_ts)) if is_expired(exp_ts) => None,
+            Some((res, _)) => Some(res.clone()),
+            None => None,
+        }
+    }
+
+    // SET key value [NX | XX] [GET] [EX seconds | PX milliseconds | EXAT unix-time-seconds | PXAT unix-time-milliseconds | KEEPTTL]
+    // returns the reply and, when the key was written, the command replicas should apply:
+    // relative expiries are sent as an absolute PXAT so the replica's copy expires at the same moment
+    pub async fn cmd_set(cmd_args: &[Vec<u8>], storage_ref: Storage) -> (RespValue, Option<Vec<Vec<u8>>>) {
+        let (mut nx, mut xx, mut get, mut keepttl) = (false, false, false, false);
+        let mut expiry: Option<SystemTime> = None;
+
+        let mut i = 3;
+        while i < cmd_args.len() {
+            let opt = arg_str(&cmd_args[i]).to_uppercase();
+            match opt.as_str() {
+                "NX" if !xx => nx = true,
+                "XX" if !nx => xx = true,
+                "GET" => get = true,
+                "KEEPTTL" if expiry.is_none() => keepttl = true,
+                "EX" | "PX" | "EXAT" | "PXAT" if expiry.is_none() && !keepttl && i + 1 < cmd_args.len() => {
+                    i += 1;
+                    match parse_expiry(&opt, &cmd_args[i], "set") {
+                        Ok(ts) => expiry = Some(ts),
+                        Err(e) => return (e, None),
+                    }
+                },
+                _ => return (redis_err(_ERROR_SYNTAX_), None),
             }
+            i += 1;
+        }
+
+        let key = &cmd_args[1];
+        let mut storage = storage_ref.lock().await;
+        // an expired key is as good as a missing one
+        let current = storage.get(key).filter(|(_, exp_ts)| !is_expired(exp_ts));
+
+        let old_value = match current {
+            Some((RDBValue::String(s), _)) => RespValue::Bulk(s.clone()),
+            Some(_) if get => return (redis_err(_ERROR_WRONGTYPE_), None),
+            _ => RespValue::Null,
         };
+        let exists = current.is_some();
+        if keepttl {
+            expiry = current.and_then(|(_, exp_ts)| *exp_ts);
+        }
+
+        let reply = if get { old_value } else { response_ok() };
+        if (nx && exists) || (xx && !exists) {
+            return (if get { reply } else { RespValue::Null }, None);
+        }
+
+        storage.insert(key.clone(), (RDBValue::String(cmd_args[2].clone()), expiry));
+
+        let mut propagate = vec![b"SET".to_vec(), key.clone(), cmd_args[2].clone()];
+        if let Some(ts) = expiry {
+            propagate.push(b"PXAT".to_vec());
+            propagate.push(unix_millis(ts).to_string().into_bytes());
+        }
+        (reply, Some(propagate))
+    }
+
+    pub fn cmd_get_ack(bytes_offset: usize) -> RespValue {
+        RespValue::bulk_array(&["REPLCONF".to_owned(), "ACK".to_owned(), bytes_offset.to_string()])
+    }
+
+    pub async fn cmd_psync(config_args: &Args) -> RespValue {
+        RespValue::Simple(format!("FULLRESYNC {} {}", config_args.master_replid, config_args.master_repl_offset))
+    }
+
+    // ---RDB ENCODING---
+    // lengths use the rdb "length encoding": the top two bits of the first byte tell how many bytes follow
+
+    pub enum RdbLen {
+        Len(usize),
+        // 0, 1, 2 => 8, 16, 32 bit integer stored as a string, 3 => LZF compressed string
+        Encoded(u8),
+    }
 
-        match result {
-            Some(rdb_val) => {
-                Some(rdb_val.clone())
+    fn rdb_take<'a>(buf: &'a [u8], i: &mut usize, n: usize) -> Result<&'a [u8], String> {
+        if buf.len() < *i + n {
+            return Err(format!("unexpected end of rdb data at byte {}", *i));
+        }
+        *i += n;
+
+        Ok(&buf[*i - n..*i])
+    }
+
+    pub fn rdb_read_len(buf: &[u8], i: &mut usize) -> Result<RdbLen, String> {
+        let first = rdb_take(buf, i, 1)?[0];
+        match first >> 6 {
+            0 => Ok(RdbLen::Len((first & 0x3F) as usize)),
+            1 => {
+                let next = rdb_take(buf, i, 1)?[0];
+                Ok(RdbLen::Len((((first & 0x3F) as usize) << 8) | next as usize))
+            },
+            2 if first == 0x80 => {
+                let bytes = rdb_take(buf, i, 4)?;
+                Ok(RdbLen::Len(u32::from_be_bytes(bytes.try_into().unwrap()) as usize))
+            },
+            2 if first == 0x81 => {
+                let bytes = rdb_take(buf, i, 8)?;
+                Ok(RdbLen::Len(u64::from_be_bytes(bytes.try_into().unwrap()) as usize))
+            },
+            3 => Ok(RdbLen::Encoded(first & 0x3F)),
+            _ => Err(format!("invalid rdb length encoding 0x{:02X}", first)),
+        }
+    }
+
+    // a plain length, special encodings aren't allowed here
+    pub fn rdb_read_size(buf: &[u8], i: &mut usize) -> Result<usize, String> {
+        match rdb_read_len(buf, i)? {
+            RdbLen::Len(n) => Ok(n),
+            RdbLen::Encoded(_) => Err("unexpected string encoding where a length was expected".to_owned()),
+        }
+    }
+
+    pub fn rdb_read_string(buf: &[u8], i: &mut usize) -> Result<Vec<u8>, String> {
+        match rdb_read_len(buf, i)? {
+            RdbLen::Len(n) => Ok(rdb_take(buf, i, n)?.to_vec()),
+            RdbLen::Encoded(0) => Ok((rdb_take(buf, i, 1)?[0] as i8).to_string().into_bytes()),
+            RdbLen::Encoded(1) => Ok(i16::from_le_bytes(rdb_take(buf, i, 2)?.try_into().unwrap()).to_string().into_bytes()),
+            RdbLen::Encoded(2) => Ok(i32::from_le_bytes(rdb_take(buf, i, 4)?.try_into().unwrap()).to_string().into_bytes()),
+            RdbLen::Encoded(3) => {
+                let compressed_len = rdb_read_size(buf, i)?;
+                let len = rdb_read_size(buf, i)?;
+                lzf_decompress(rdb_take(buf, i, compressed_len)?, len)
             },
-            None => {
-                None
+            RdbLen::Encoded(enc) => Err(format!("unknown rdb string encoding {}", enc)),
+        }
+    }
+
+    // redis compresses long strings with LZF when rdbcompression is on
+    fn lzf_decompress(input: &[u8], len: usize) -> Result<Vec<u8>, String> {
+        let mut output: Vec<u8> = Vec::with_capacity(len);
+        let mut i = 0;
+        while i < input.len() {
+            let ctrl = input[i] as usize;
+            i += 1;
+            if ctrl < 32 {
+                // literal run of ctrl + 1 bytes
+                let run = input.get(i..i + ctrl + 1).ok_or("corrupt LZF literal run")?;
+                output.extend_from_slice(run);
+                i += ctrl + 1;
+            } else {
+                // back reference
+                let mut run = ctrl >> 5;
+                if run == 7 {
+                    run += *input.get(i).ok_or("corrupt LZF back reference")? as usize;
+                    i += 1;
+                }
+                let offset = (((ctrl & 0x1F) << 8) | *input.get(i).ok_or("corrupt LZF back reference")? as usize) + 1;
+                i += 1;
+                if offset > output.len() {
+                    return Err("corrupt LZF back reference".to_owned());
+                }
+                let start = output.len() - offset;
+                for k in 0..run + 2 {
+                    output.push(output[start + k]);
+                }
             }
-        } 
+        }
+
+        if output.len() != len {
+            return Err("LZF decompressed length mismatch".to_owned());
+        }
+
+        Ok(output)
     }
 
-    pub async fn cmd_set(cmd_args: &Vec<String>, storage_ref: Arc<Mutex<HashMap<String, (RDBValue, Option<SystemTime>)>>>) -> String {
-        let mut new_kv = StorageKV {
-            key: cmd_args[1].clone(),
-            value: RDBValue::String(cmd_args[2].clone()),
-            //, value_type: RDBValueType::String },
-            exp_ts: None,
-        };
+    pub fn rdb_write_len(out: &mut Vec<u8>, len: usize) {
+        if len < 1 << 6 {
+            out.put_u8(len as u8);
+        } else if len < 1 << 14 {
+            out.put_u16(0x4000 | len as u16);
+        } else if len <= u32::MAX as usize {
+            out.put_u8(0x80);
+            out.put_u32(len as u32);
+        } else {
+            out.put_u8(0x81);
+            out.put_u64(len as u64);
+        }
+    }
+
+    pub fn rdb_write_string(out: &mut Vec<u8>, s: &[u8]) {
+        rdb_write_len(out, s.len());
+        out.extend_from_slice(s);
+    }
 
-        if cmd_args.len() > 3 { // input validation is not being performed
-            // SET foo bar px milliseconds
-            let n = cmd_args[4].parse().unwrap();
-            new_kv.exp_ts = SystemTime::now().checked_add(Duration::from_millis(n));
+    // an element of a listpack, redis stores strings that parse as integers as integers
+    enum LpElement {
+        Int(i64),
+        Str(Vec<u8>),
+    }
+
+    // bytes the back length of a listpack element of `len` bytes takes
+    fn lp_backlen_size(len: usize) -> usize {
+        match len {
+            0..128 => 1,
+            128..16383 => 2,
+            16383..2097151 => 3,
+            2097151..268435455 => 4,
+            _ => 5,
         }
-        // println!("insert new record: {:?}", new_kv);
-        storage_ref.lock().await.insert(new_kv.key, (new_kv.value, new_kv.exp_ts));
+    }
 
-        response_ok()
+    // listpacks are what redis keeps stream entries in: a 6 byte header(total bytes, element count), the elements each
+    // followed by its own length so they can be walked backwards, and a 0xFF terminator
+    #[derive(Default)]
+    struct Listpack {
+        elements: Vec<u8>,
+        count: usize,
     }
 
-    pub async fn cmd_keys(dbfilename: &String, storage_ref: Arc<Mutex<HashMap<String, (RDBValue, Option<SystemTime>)>>>) -> String {
-        let mut matched_keys: Vec<String> = vec![];
+    impl Listpack {
+        fn push_int(&mut self, v: i64) {
+            let start = self.elements.len();
+            let out = &mut self.elements;
+            if (0..128).contains(&v) {
+                out.put_u8(v as u8);
+            } else if (-4096..4096).contains(&v) {
+                // 13 bit two's complement
+                let u = (v as u16) & 0x1FFF;
+                out.put_u8(0xC0 | (u >> 8) as u8);
+                out.put_u8(u as u8);
+            } else if let Ok(v) = i16::try_from(v) {
+                out.put_u8(0xF1);
+                out.put_i16_le(v);
+            } else if (-(1 << 23)..(1 << 23)).contains(&v) {
+                out.put_u8(0xF2);
+                out.extend_from_slice(&v.to_le_bytes()[..3]);
+            } else if let Ok(v) = i32::try_from(v) {
+                out.put_u8(0xF3);
+                out.put_i32_le(v);
+            } else {
+                out.put_u8(0xF4);
+                out.put_i64_le(v);
+            }
+            self.finish_element(start);
+        }
 
-        cmd_sync(dbfilename, storage_ref.clone()).await;
-        let storage = storage_ref.lock().await;
-        if !storage.is_empty() {
-            for (key, (_, exp_ts)) in storage.iter() {
-                // add code for pattern matching keys in the future
-                if let Some(time) = exp_ts {
-                    if SystemTime::now() <= *time {  // if not expired
-                        matched_keys.push(key.clone());
-                    }
-                } else {    // or if no expiry
-                    matched_keys.push(key.clone());
+        fn push_str(&mut self, s: &[u8]) {
+            let start = self.elements.len();
+            let out = &mut self.elements;
+            if s.len() < 1 << 6 {
+                out.put_u8(0x80 | s.len() as u8);
+            } else if s.len() < 1 << 12 {
+                out.put_u8(0xE0 | (s.len() >> 8) as u8);
+                out.put_u8(s.len() as u8);
+            } else {
+                out.put_u8(0xF0);
+                out.put_u32_le(s.len() as u32);
+            }
+            out.extend_from_slice(s);
+            self.finish_element(start);
+        }
+
+        // back length: 7 bits per byte, most significant group first, every byte but the first has the high bit set
+        fn finish_element(&mut self, start: usize) {
+            let len = self.elements.len() - start;
+            let size = lp_backlen_size(len);
+            for k in (0..size).rev() {
+                let byte = ((len >> (7 * k)) & 127) as u8;
+                self.elements.put_u8(if k == size - 1 { byte } else { byte | 128 });
+            }
+            self.count += 1;
+        }
+
+        fn into_bytes(self) -> Vec<u8> {
+            let mut lp = Vec::with_capacity(self.elements.len() + 7);
+            lp.put_u32_le((self.elements.len() + 7) as u32);
+            // element counts that don't fit are stored as u16::MAX, meaning unknown
+            lp.put_u16_le(self.count.min(u16::MAX as usize) as u16);
+            lp.extend_from_slice(&self.elements);
+            lp.put_u8(0xFF);
+            lp
+        }
+    }
+
+    fn lp_read(lp: &[u8]) -> Result<Vec<LpElement>, String> {
+        let mut i = 6; // skip the header
+        let mut elements = vec![];
+        loop {
+            let start = i;
+            let enc = rdb_take(lp, &mut i, 1)?[0];
+            let element = match enc {
+                0xFF => break,
+                0x00..=0x7F => LpElement::Int(enc as i64),
+                0x80..=0xBF => LpElement::Str(rdb_take(lp, &mut i, (enc & 0x3F) as usize)?.to_vec()),
+                0xC0..=0xDF => {
+                    let u = (((enc & 0x1F) as i64) << 8) | rdb_take(lp, &mut i, 1)?[0] as i64;
+                    LpElement::Int(if u >= 1 << 12 { u - (1 << 13) } else { u })
+                },
+                0xE0..=0xEF => {
+                    let len = (((enc & 0x0F) as usize) << 8) | rdb_take(lp, &mut i, 1)?[0] as usize;
+                    LpElement::Str(rdb_take(lp, &mut i, len)?.to_vec())
+                },
+                0xF0 => {
+                    let len = u32::from_le_bytes(rdb_take(lp, &mut i, 4)?.try_into().unwrap()) as usize;
+                    LpElement::Str(rdb_take(lp, &mut i, len)?.to_vec())
+                },
+                0xF1 => LpElement::Int(i16::from_le_bytes(rdb_take(lp, &mut i, 2)?.try_into().unwrap()) as i64),
+                0xF2 => {
+                    let b = rdb_take(lp, &mut i, 3)?;
+                    // sign extend by reading it as the top 3 bytes of an i32
+                    LpElement::Int((i32::from_le_bytes([0, b[0], b[1], b[2]]) >> 8) as i64)
+                },
+                0xF3 => LpElement::Int(i32::from_le_bytes(rdb_take(lp, &mut i, 4)?.try_into().unwrap()) as i64),
+                0xF4 => LpElement::Int(i64::from_le_bytes(rdb_take(lp, &mut i, 8)?.try_into().unwrap())),
+                _ => return Err(format!("invalid listpack encoding 0x{:02X}", enc)),
+            };
+            let len = i - start;
+            rdb_take(lp, &mut i, lp_backlen_size(len))?;
+            elements.push(element);
+        }
+
+        Ok(elements)
+    }
+
+    fn lp_next_int(elements: &mut impl Iterator<Item = LpElement>) -> Result<i64, String> {
+        match elements.next() {
+            Some(LpElement::Int(v)) => Ok(v),
+            Some(LpElement::Str(s)) => arg_str(&s).parse().map_err(|_| "expected an integer in the stream listpack".to_owned()),
+            None => Err("stream listpack ended early".to_owned()),
+        }
+    }
+
+    fn lp_next_bytes(elements: &mut impl Iterator<Item = LpElement>) -> Result<Vec<u8>, String> {
+        match elements.next() {
+            Some(LpElement::Int(v)) => Ok(v.to_string().into_bytes()),
+            Some(LpElement::Str(s)) => Ok(s),
+            None => Err("stream listpack ended early".to_owned()),
+        }
+    }
+
+    const _STREAM_ITEM_FLAG_DELETED_: i64 = 1;
+    const _STREAM_ITEM_FLAG_SAMEFIELDS_: i64 = 2;
+
+    // a node of a stream: its entries as one listpack. it starts with a master entry(entry count, deleted count and
+    // field names), then each entry is flags, id relative to the master id, its fields and values and the number of
+    // listpack elements it took. entries flagged SAMEFIELDS only have values, their fields are the master's
+    fn stream_write_listpack(master: (usize, usize), node: &[StreamEntry]) -> Vec<u8> {
+        let mut lp = Listpack::default();
+        lp.push_int(node.len() as i64);
+        lp.push_int(0);
+        lp.push_int(node[0].value.len() as i64);
+        for (field, _) in &node[0].value {
+            lp.push_str(field);
+        }
+        lp.push_int(0);
+
+        for entry in node {
+            lp.push_int(0);
+            lp.push_int(entry.id.0.wrapping_sub(master.0) as i64);
+            lp.push_int(entry.id.1.wrapping_sub(master.1) as i64);
+            lp.push_int(entry.value.len() as i64);
+            for (field, value) in &entry.value {
+                lp.push_str(field);
+                lp.push_str(value);
+            }
+            lp.push_int(entry.value.len() as i64 * 2 + 4);
+        }
+        lp.into_bytes()
+    }
+
+    fn stream_read_listpack(master: (usize, usize), lp: &[u8], entries: &mut Vec<StreamEntry>) -> Result<(), String> {
+        let elements = &mut lp_read(lp)?.into_iter();
+        let count = lp_next_int(elements)? as usize;
+        let deleted = lp_next_int(elements)? as usize;
+        let master_fields = (0..lp_next_int(elements)?).map(|_| lp_next_bytes(elements)).collect::<Result<Vec<_>, _>>()?;
+        lp_next_int(elements)?;   // master entry terminator
+
+        for _ in 0..count + deleted {
+            let flags = lp_next_int(elements)?;
+            let id = (
+                master.0.wrapping_add(lp_next_int(elements)? as usize),
+                master.1.wrapping_add(lp_next_int(elements)? as usize),
+            );
+            let mut value = vec![];
+            if flags & _STREAM_ITEM_FLAG_SAMEFIELDS_ != 0 {
+                for field in &master_fields {
+                    value.push((field.clone(), lp_next_bytes(elements)?));
                 }
+            } else {
+                for _ in 0..lp_next_int(elements)? {
+                    value.push((lp_next_bytes(elements)?, lp_next_bytes(elements)?));
+                }
+            }
+            lp_next_int(elements)?;   // element count of the entry
+            if flags & _STREAM_ITEM_FLAG_DELETED_ == 0 {
+                entries.push(StreamEntry { id, value });
             }
         }
 
-        encode_array(&matched_keys, true)
+        Ok(())
+    }
+
+    pub fn rdb_value_type(value: &RDBValue) -> u8 {
+        match value {
+            RDBValue::String(_) => _RDB_TYPE_STRING_,
+            RDBValue::List(_) => _RDB_TYPE_LIST_,
+            RDBValue::ZSet(_) => _RDB_TYPE_ZSET_2_,
+            RDBValue::Stream(_) => _RDB_TYPE_STREAM_LISTPACKS_3_,
+        }
+    }
+
+    pub fn rdb_write_value(out: &mut Vec<u8>, value: &RDBValue) {
+        match value {
+            RDBValue::String(data) => {
+                rdb_write_string(out, data);
+            },
+            RDBValue::List(v) => {
+                rdb_write_len(out, v.len());
+                for ele in v {
+                    rdb_write_string(out, ele);
+                }
+            },
+            RDBValue::ZSet(set) => {
+                // members in score order, each followed by its score as a little endian double
+                rdb_write_len(out, set.st.len());
+                for (score, member) in &set.st {
+                    rdb_write_string(out, member);
+                    out.extend_from_slice(&score.0.to_le_bytes());
+                }
+            },
+            RDBValue::Stream(entries) => {
+                // the radix tree of listpacks as (master id, listpack) pairs, the master id is the node's first entry
+                let nodes: Vec<_> = entries.chunks(_STREAM_NODE_MAX_ENTRIES_).collect();
+                rdb_write_len(out, nodes.len());
+                for node in nodes {
+                    let master = node[0].id;
+                    let mut key = Vec::with_capacity(16);
+                    key.put_u64(master.0 as u64);
+                    key.put_u64(master.1 as u64);
+                    rdb_write_string(out, &key);
+                    rdb_write_string(out, &stream_write_listpack(master, node));
+                }
+
+                // length, last id, first id, max deleted id, entries added and no consumer groups
+                let first = entries.first().map_or((0, 0), |entry| entry.id);
+                let last = entries.last().map_or((0, 0), |entry| entry.id);
+                for n in [entries.len(), last.0, last.1, first.0, first.1, 0, 0, entries.len(), 0] {
+                    rdb_write_len(out, n);
+                }
+            }
+        }
+    }
+
+    pub fn rdb_read_value(buf: &[u8], i: &mut usize, value_type: u8) -> Result<RDBValue, String> {
+        match value_type {
+            _RDB_TYPE_STRING_ => Ok(RDBValue::String(rdb_read_string(buf, i)?)),
+            _RDB_TYPE_LIST_ => {
+                let len = rdb_read_size(buf, i)?;
+                let mut v = VecDeque::new();
+                for _ in 0..len {
+                    v.push_back(rdb_read_string(buf, i)?);
+                }
+                Ok(RDBValue::List(v))
+            },
+            _RDB_TYPE_ZSET_2_ => {
+                let len = rdb_read_size(buf, i)?;
+                let mut set = SortedSet::default();
+                for _ in 0..len {
+                    let member = rdb_read_string(buf, i)?;
+                    let score = f64::from_le_bytes(rdb_take(buf, i, 8)?.try_into().unwrap());
+                    set.insert(&member, &SortableF64(score), &member);
+                }
+                Ok(RDBValue::ZSet(set))
+            },
+            _RDB_TYPE_STREAM_LISTPACKS_ | _RDB_TYPE_STREAM_LISTPACKS_2_ | _RDB_TYPE_STREAM_LISTPACKS_3_ => {
+                let mut entries = vec![];
+                for _ in 0..rdb_read_size(buf, i)? {
+                    let key = rdb_read_string(buf, i)?;
+                    if key.len() != 16 {
+                        return Err("invalid stream node key".to_owned());
+                    }
+                    let master = (
+                        u64::from_be_bytes(key[..8].try_into().unwrap()) as usize,
+                        u64::from_be_bytes(key[8..].try_into().unwrap()) as usize,
+                    );
+                    stream_read_listpack(master, &rdb_read_string(buf, i)?, &mut entries)?;
+                }
+
+                // length and last id, v2 and later add the first id, max deleted id and entries added
+                let metadata = if value_type == _RDB_TYPE_STREAM_LISTPACKS_ { 3 } else { 8 };
+                for _ in 0..metadata {
+                    rdb_read_size(buf, i)?;
+                }
+
+                // consumer groups aren't supported, they are read past and dropped
+                for _ in 0..rdb_read_size(buf, i)? {
+                    rdb_read_string(buf, i)?;   // name
+                    rdb_read_size(buf, i)?;     // last delivered id
+                    rdb_read_size(buf, i)?;
+                    if value_type != _RDB_TYPE_STREAM_LISTPACKS_ {
+                        rdb_read_size(buf, i)?; // entries read
+                    }
+                    // pending entries: raw id, delivery time, delivery count
+                    for _ in 0..rdb_read_size(buf, i)? {
+                        rdb_take(buf, i, 16 + 8)?;
+                        rdb_read_size(buf, i)?;
+                    }
+                    // consumers: name, seen time, active time since v3 and the ids of their pending entries
+                    for _ in 0..rdb_read_size(buf, i)? {
+                        rdb_read_string(buf, i)?;
+                        rdb_take(buf, i, if value_type == _RDB_TYPE_STREAM_LISTPACKS_3_ { 16 } else { 8 })?;
+                        let pending = rdb_read_size(buf, i)?;
+                        rdb_take(buf, i, 16 * pending)?;
+                    }
+                }
+                Ok(RDBValue::Stream(entries))
+            },
+            _ => Err(format!("unsupported rdb value type {}", value_type)),
+        }
+    }
+
+    // DUMP payload of a value: its rdb type and serialization, then the rdb version and a crc64 of everything before
+    // it, both little endian
+    pub fn dump_value(value: &RDBValue) -> Vec<u8> {
+        let mut out = vec![rdb_value_type(value)];
+        rdb_write_value(&mut out, value);
+        out.put_u16_le(_RDB_VERSION_);
+        let checksum = crc64(0, &out);
+        out.put_u64_le(checksum);
+        out
     }
 
-    pub fn cmd_get_ack(bytes_offset: usize) -> String {
-        encode_array(&vec!["REPLCONF".to_owned(), "ACK".to_owned(), bytes_offset.to_string()], true)
+    // the value of a DUMP payload, once its version and checksum check out
+    pub fn restore_value(payload: &[u8]) -> Result<RDBValue, RespValue> {
+        let wrong_footer = || redis_err("ERR DUMP payload version or checksum are wrong");
+        if payload.len() < 10 {
+            return Err(wrong_footer());
+        }
+        let (body, checksum) = payload.split_at(payload.len() - 8);
+        let (data, version) = body.split_at(body.len() - 2);
+        if u16::from_le_bytes(version.try_into().unwrap()) > _RDB_VERSION_ || crc64(0, body) != u64::from_le_bytes(checksum.try_into().unwrap()) {
+            return Err(wrong_footer());
+        }
+
+        // the value has to take up the whole payload
+        let Some((&value_type, data)) = data.split_first() else {
+            return Err(redis_err("ERR Bad data format"));
+        };
+        let mut i = 0;
+        match rdb_read_value(data, &mut i, value_type) {
+            Ok(value) if i == data.len() => Ok(value),
+            _ => Err(redis_err("ERR Bad data format")),
+        }
     }
 
-    pub async fn cmd_psync(config_args: &Args) -> String {
-        encode_simple(&vec!["FULLRESYNC", format!("{}", config_args.master_replid).as_str(), format!("{}", config_args.master_repl_offset).as_str()])
-    } 
+    // loads the keys into the map of the database each one is in, one map per database
+    fn rdb_load(buf: &[u8], storages: &mut [Entries]) -> Result<(), String> {
+        if !buf.starts_with(b"REDIS") {
+            return Err("not an rdb file".to_owned());
+        }
+        let mut i = 9; // skip header bytes
+        let mut exp_ts = None;
+        let mut db = 0;
+
+        while i < buf.len() {
+            let op = buf[i];
+            i += 1;
+            match op {
+                _RDB_METADATA_SECTION_FLAG_ => {
+                    // aux fields(redis-ver etc.), once for key, once for value
+                    rdb_read_string(buf, &mut i)?;
+                    rdb_read_string(buf, &mut i)?;
+                },
+                _RDB_DATA_SECTION_FLAG_ => {
+                    db = rdb_read_size(buf, &mut i)?;
+                    if db >= storages.len() {
+                        return Err(format!("the file has keys in db {} but there are only {} databases", db, storages.len()));
+                    }
+                },
+                _RDB_RESIZEDB_FLAG_ => {
+                    rdb_read_size(buf, &mut i)?;    // total k,v pairs
+                    rdb_read_size(buf, &mut i)?;    // timed k,v pairs
+                },
+                _RDB_TIMESTAMP_S_FLAG => {
+                    let secs = u32::from_le_bytes(rdb_take(buf, &mut i, 4)?.try_into().unwrap());
+                    exp_ts = Some(UNIX_EPOCH + Duration::from_secs(secs as u64));
+                },
+                _RDB_TIMESTAMP_MS_FLAG => {
+                    let ms = u64::from_le_bytes(rdb_take(buf, &mut i, 8)?.try_into().unwrap());
+                    exp_ts = Some(UNIX_EPOCH + Duration::from_millis(ms));
+                },
+                _RDB_END_ => {
+                    // 0xFF marks end of the db file section, the checksum follows
+                    break;
+                },
+                value_type => {
+                    let key = rdb_read_string(buf, &mut i)?;
+                    let value = rdb_read_value(buf, &mut i, value_type)?;
+                    // keys that expired while the server was down aren't loaded at all
+                    let exp_ts = exp_ts.take();
+                    if !is_expired(&exp_ts) {
+                        storages[db].insert(key, (value, exp_ts));
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
 
-    pub async fn cmd_sync(dbfilepath: &String, storage_ref: Arc<Mutex<HashMap<String, (RDBValue, Option<SystemTime>)>>>) {
+    pub async fn cmd_sync(dbfilepath: &str, dbs: &Databases) {
         // read the rdb file
         // read the keys, match them against some given pattern
         // we could simply search this in the storage map but i wanna do it the right way
-        // let path =dir.clone() + dbfilename.as_str();
         if dbfilepath.starts_with("UNSET") {
             return;
         }
 
-        let mut storage = storage_ref.lock().await;
-
         println!("reading from file {}", &dbfilepath);
         let file = File::open(&dbfilepath).await;
         let mut buf: Vec<u8> = vec![];
@@ -160,180 +743,63 @@ pub mod replication {
             Err(ref e) if e.kind() == ErrorKind::NotFound => {
                 // db file does not exist so no data found
                 return;
-                // encode_array(&matched_keys);
             },
             Err(e) => {
                 panic!("{}", e);
             }
         }
 
-        // print the hex dump of the file
-        // println!("hex dump of rdb file");
-        // println!("{}", buf.iter().map(|b| format!("{:02X} ", b)).collect::<String>());
-        let mut i = 9; // skip header bytes
-        let mut mask;
-
-        while buf[i] == _RDB_METADATA_SECTION_FLAG_ {
-            i += 1;                     // skip the flag(current)
-            // parse key value
-            {   // key value pairs depending on data type inferred from encoding 
-                for _ in 0..2 { // once for key, once for value
-                    mask = buf[i].bitand(3 << 6);
-                    if mask == 0 {
-                        i += 1 + buf[i] as usize;     // skip size byte + next bytes of string(which are give by lower 6 bits)
-                    } else {    // mask == 1100.00
-                        // string contains an integer
-                        i += 1; // size byte (current)
-                        if buf[i] & 3 == 0 {
-                            i += 1;             // 8 bit integer follows
-                        } else if buf[i] & 3 == 1 {
-                            i += 2;             // 16 bit integer follows (in little endian)
-                        } else if buf[i] & 3 == 2 {
-                            i += 4;             // 32 bit integer follows
-                        }
-                    }
-                }
-            }
-            // i += 1 + buf[i] as usize;   // skip the metadata key
-            // i += 1 + buf[i] as usize;   // skip the metadata value  
-        }
-
-        // println!("parsed metadata");
-        while buf[i] == _RDB_DATA_SECTION_FLAG_ {
-            i += 1;     // DB section flag(current)
-            i += 1;     // DB index
-            i += 1;     // storage info section flag
-            i += 1;     // total k,v pairs
-            i += 1;     // timed k,v pairs
+        let mut storages = vec![HashMap::new(); dbs.dbs.len()];
+        if let Err(e) = rdb_load(&buf, &mut storages) {
+            println!("failed to load {}: {}", dbfilepath, e);
         }
-
-        // println!("parsed db metadata");
-        while i < buf.len() {
-            let mut new_kv = StorageKV{
-                key: String::from(""), 
-                value: RDBValue::String(String::from("")),
-                //  { value: String::from(""), value_type: RDBValueType::String }, 
-                exp_ts: None
-            }; 
-            // key = String::new();
-            // value = String::new();
-            // ts = None;
-
-            // 0xFF marks end of the db file section
-            if buf[i] == _RDB_END_ {
-                break;
-            }
-
-            // if timestamp flag present
-            if buf[i] == _RDB_TIMESTAMP_S_FLAG { 
-                i += 1;     // skip flag byte(current)
-                let mut ts_bytes: [u8; 4] = [0x0, 0x0, 0x0, 0x0];
-                for j in 0..4 {
-                    ts_bytes[j] = buf[i + j];
-                }
-                new_kv.exp_ts = Some(UNIX_EPOCH + Duration::from_secs_f32(u32::from_le_bytes(ts_bytes) as f32));
-                i += 4;     // skip timestamp(next 4 bytes, if timestamp was stored in secs)
-            } else if buf[i] == _RDB_TIMESTAMP_MS_FLAG {
-                i += 1;     // skip flag byte(current)
-                let mut ts_bytes: [u8; 8] = [0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0];
-                for j in 0..8 {
-                    ts_bytes[j] = buf[i + j];
-                }
-                new_kv.exp_ts = Some(UNIX_EPOCH + Duration::from_millis(u64::from_le_bytes(ts_bytes)));
-                i += 8;     // skip timestamp(next 8 bytes, if timestamp was stored in msecs)
-            }
-            i += 1;     // skip value type byte
-            // println!("parsed timing info");
-            // if k is an integer then you need to parse it like above  
-            // buf[i] contains size encoded string size
-            for &ch in &buf[(i + 1)..(i + 1 + buf[i] as usize)] {
-                new_kv.key.push(char::from(ch));
-            }
-            // println!("parsed key");
-
-            i += 1 + buf[i] as usize;    // skip key bytes
-            // currently we return all the keys because we are matching against * pattern 
-            // matched_keys.push(key);
-            // skip key size byte + key length bytes
-
-            let mut new_value = String::new(); 
-            // currently the values are also prefix encoded strings so this works  
-            for &ch in &buf[(i + 1)..(i + 1 + buf[i] as usize)] {
-                // new_kv.value.value.push(char::from(ch));
-                new_value.push(char::from(ch));
-            }
-            i += 1 + buf[i] as usize;    // skip value bytes
-
-            new_kv.value = RDBValue::String(new_value);
-            // println!("parsed value");
-            storage.insert(new_kv.key.clone(), (new_kv.value.clone(), new_kv.exp_ts.clone()));
-            println!("record inserted: {:?}", new_kv);
+        for (index, loaded) in storages.into_iter().enumerate() {
+            let keys: Vec<Vec<u8>> = loaded.keys().cloned().collect();
+            dbs.dbs[index].storage.lock().await.extend(loaded);
+            let keys: Vec<&[u8]> = keys.iter().map(Vec::as_slice).collect();
+            track_keys(dbs, index, &keys, false).await;
         }
     }
 
-    pub async fn cmd_save(
-        storage_ref: Arc<Mutex<HashMap<String, (RDBValue, Option<SystemTime>)>>>, 
-        dbfilepath: &String) -> String {
-        let storage = storage_ref.lock().await;
+    pub async fn cmd_save(dbs: &Databases, dbfilepath: &str) -> RespValue {
         // assumes the directory structure already exists
-        println!("creating file {}", &dbfilepath); 
+        println!("creating file {}", &dbfilepath);
         let mut out = tokio::fs::File::create(&dbfilepath).await.unwrap();
-        let mut out_bytes: Vec<u8> = vec![];
-        for &b in b"REDIS0011" { // +9 bytes
-            out_bytes.put_u8(b);
-        }
-
-        out_bytes.put_u8(0xFE); // flag for start of db section; +2B 
-        out_bytes.put_u8(0);        // index of db; +1B
-        out_bytes.put_u8(0xFB); // flag for size of hash table; +2B
-
-        // one byte: first two bits are 0 => next 6 bits represent the size 
-        // count of total k, v pairs
-        // todo!("following two sizes need to be different!");
-        out_bytes.put_u8(storage.len() as u8);   // +1B
-        // count of timed k, v pairs same as above;
-        out_bytes.put_u8(storage.len() as u8);   // +1B
-        // out.write_all(&((storage.len() << 2) as u8).to_le_bytes()).await.unwrap();
-
-        // while reading the file we can skip bytes until here
-        for (k, (value, timestamp)) in storage.iter() {
-            if let Some(ts) = timestamp {
-                // timestamp flag
-                // timstamp bytes(4)
-                out_bytes.put_u8(_RDB_TIMESTAMP_MS_FLAG); // +1B
-                out_bytes.put_u64(ts.duration_since(UNIX_EPOCH).ok().unwrap().as_millis() as u64);   // +8B; always store in ms
-            } 
-            // else no timestamp flag and dat a for this k, v pair
-
-            // value type byte
-            out_bytes.put_u8(0);    // +1B
-            // a single byte repsenting the size of the following string
-            // key size + bytes
-            out_bytes.put_u8(k.len() as u8);
-            for &b in k.as_bytes() {
-                out_bytes.put_u8(b);
+        let mut out_bytes: Vec<u8> = format!("REDIS{:04}", _RDB_VERSION_).into_bytes();
+
+        for (index, db) in dbs.dbs.iter().enumerate() {
+            let storage = db.storage.lock().await;
+            // empty databases don't get a section
+            if storage.is_empty() {
+                continue;
             }
 
-            // value size + bytes
-            let value_data = match value {
-                RDBValue::String(data) => {
-                    data
-                },
-                _ => {
-                    unimplemented!("RDB file parsing is implemented for string data type only.")
+            out_bytes.put_u8(_RDB_DATA_SECTION_FLAG_);  // flag for start of db section
+            rdb_write_len(&mut out_bytes, index);       // index of db
+            out_bytes.put_u8(_RDB_RESIZEDB_FLAG_);      // flag for size of hash table
+            rdb_write_len(&mut out_bytes, storage.len());     // count of total k, v pairs
+            rdb_write_len(&mut out_bytes, storage.iter().filter(|(_, (_, ts))| ts.is_some()).count());  // count of timed k, v pairs
+
+            // while reading the file we can skip bytes until here
+            for (k, (value, timestamp)) in storage.iter() {
+                if let Some(ts) = timestamp {
+                    out_bytes.put_u8(_RDB_TIMESTAMP_MS_FLAG);
+                    out_bytes.put_u64_le(ts.duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as u64);   // always store in ms
                 }
-            };
-            out_bytes.put_u8(value_data.len() as u8);
-            for &b in value_data.as_bytes() {
-                out_bytes.put_u8(b);
+                // else no timestamp flag and data for this k, v pair
+
+                out_bytes.put_u8(rdb_value_type(value));
+                rdb_write_string(&mut out_bytes, k);
+                rdb_write_value(&mut out_bytes, value);
             }
-        }                // end section
-        out_bytes.put_u8(_RDB_END_); // 1 Byte flag
+        }
+        // end section
+        out_bytes.put_u8(_RDB_END_);
         // finally put the checksum
         let checksum = crc64(0, &out_bytes);
-        out_bytes.put_u64(checksum);
+        out_bytes.put_u64_le(checksum);
         out.write_all(&out_bytes).await.unwrap();
 
         response_ok()
     }
-}
\ No newline at end of file
+}
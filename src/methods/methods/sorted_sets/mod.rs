@@ -1,73 +1,89 @@
 pub mod sorted_sets {
-    use std::{collections::HashMap, sync::Arc};
-
-    use tokio::sync::Mutex;
-
     use crate::utils::utils::*;
 
     pub async fn cmd_zadd(
         _: &Args,
-        cmd_args: &Vec<String>,
-        sorted_set_ref: Arc<Mutex<HashMap<String, SortedSet>>>) -> String {
+        cmd_args: &[Vec<u8>],
+        storage_ref: Storage) -> RespValue {
 
-        let mut sorted_set = sorted_set_ref.lock().await;
-        
+        // ZADD key score member [score member ...], every score is checked before anything is added
         let set_name = &cmd_args[1];
-        let score: &SortableF64 = &SortableF64{0: cmd_args[2].parse::<f64>().unwrap()};
-        let value = &cmd_args[3];
+        if !cmd_args.len().is_multiple_of(2) {
+            return redis_err(_ERROR_SYNTAX_);
+        }
+        let mut members = vec![];
+        for pair in cmd_args[2..].chunks(2) {
+            match parse_arg::<f64>(&pair[0]).filter(|score| !score.is_nan()) {
+                Some(score) => members.push((SortableF64(score), &pair[1])),
+                None => return redis_err(_ERROR_NOT_A_FLOAT_),
+            }
+        }
 
-        let set = sorted_set.entry(set_name.clone()).or_default();
+        let mut storage = storage_ref.lock().await;
+        let set = match zset_entry(&mut storage, set_name) {
+            Ok(set) => set,
+            Err(e) => return e,
+        };
 
-        encode_int(set.insert(value, score, value))
+        // replies with the number of new members
+        let added: usize = members.into_iter().map(|(score, member)| set.insert(member, &score, member)).sum();
+        RespValue::Integer(added as i64)
     }
 
     pub async fn cmd_zrange(
         _: &Args,
-        cmd_args: &Vec<String>,
-        sorted_set_ref: Arc<Mutex<HashMap<String, SortedSet>>>
-    ) -> String {
+        cmd_args: &[Vec<u8>],
+        storage_ref: Storage
+    ) -> RespValue {
         let set_name = &cmd_args[1];
-        let mut start: isize = cmd_args[2].parse::<isize>().unwrap();
-        let mut end: isize = cmd_args[3].parse::<isize>().unwrap(); 
+        let (Some(mut start), Some(mut end)) = (parse_arg::<isize>(&cmd_args[2]), parse_arg::<isize>(&cmd_args[3])) else {
+            return redis_err(_ERROR_INCR_NOT_AN_INT_);
+        };
 
-        let sorted_set = sorted_set_ref.lock().await;
+        let storage = storage_ref.lock().await;
 
         let mut result = vec![];
-        if let Some(set) = sorted_set.get(set_name) {
+        let set = match live_zset(&storage, set_name) {
+            Ok(set) => set,
+            Err(e) => return e,
+        };
+        if let Some(set) = set {
             let sz: isize = set.st.len() as isize;
             if start < 0 {
                 start = std::cmp::max(0, sz + start);
-            } 
+            }
 
             if end < 0 {
                 end = std::cmp::min(sz - 1, sz + end);
             }
 
-            let mut i =0;
-            for (_, this_key) in set.st.iter() {
+            for (i, (_, this_key)) in set.st.iter().enumerate() {
+                let i = i as isize;
                 if start <= i && i <= end {
                     result.push(this_key.clone());
                 }
-                i += 1; 
             }
         }
 
-        encode_array(&result, true)
+        RespValue::bulk_array(&result)
     }
 
     pub async fn cmd_zrank(
         _: &Args,
-        cmd_args: &Vec<String>,
-        sorted_set_ref: Arc<Mutex<HashMap<String, SortedSet>>>
-    ) -> String {
+        cmd_args: &[Vec<u8>],
+        storage_ref: Storage
+    ) -> RespValue {
         let set_name = &cmd_args[1];
-        // let score = SortableF64(cmd_args[1].parse::<f64>().unwrap());
         let key = &cmd_args[2];
 
-        let sorted_set = sorted_set_ref.lock().await;
+        let storage = storage_ref.lock().await;
 
         let mut rank: isize = -1;
-        if let Some(set) = sorted_set.get(set_name) {
+        let set = match live_zset(&storage, set_name) {
+            Ok(set) => set,
+            Err(e) => return e,
+        };
+        if let Some(set) = set {
             if let Some(score) = set.kv.get(key) {
                 for (this_score, this_key) in set.st.iter() {
                     rank += 1;
@@ -78,65 +94,99 @@ pub mod sorted_sets {
             }
         }
 
-        if rank == -1 { 
-            return encode_bulk("");
+        if rank == -1 {
+            return RespValue::Null;
         }
-        // else  
-        encode_int(rank as usize) 
+        // else
+        RespValue::Integer(rank as i64)
     }
 
     pub async fn cmd_zcard(
         _: &Args,
-        cmd_args: &Vec<String>,
-        sorted_set_ref: Arc<Mutex<HashMap<String, SortedSet>>>) -> String {
+        cmd_args: &[Vec<u8>],
+        storage_ref: Storage) -> RespValue {
 
-        let set_name = &cmd_args[1]; 
+        let set_name = &cmd_args[1];
 
-        let size;
-        if let Some(set) = sorted_set_ref.lock().await.get(set_name) {
-            size = set.st.len();
-        } else {
-            size =0;
-        }
-    
-        encode_int(size) 
+        let size = match live_zset(&*storage_ref.lock().await, set_name) {
+            Ok(Some(set)) => set.st.len(),
+            Ok(None) => 0,
+            Err(e) => return e,
+        };
+
+        RespValue::Integer(size as i64)
     }
 
     pub async fn cmd_zscore(
         _: &Args,
-        cmd_args: &Vec<String>,
-        sorted_set_ref: Arc<Mutex<HashMap<String, SortedSet>>>) -> String {
-        
-        let set_name = &cmd_args[1]; 
+        cmd_args: &[Vec<u8>],
+        storage_ref: Storage) -> RespValue {
+
+        let set_name = &cmd_args[1];
         let member = &cmd_args[2];
 
-        if let Some(set) = sorted_set_ref.lock().await.get(set_name) {
-            if let Some(score) = set.kv.get(member) {
-                return encode_bulk(score.0.to_string().as_str());
-            }
+        match live_zset(&*storage_ref.lock().await, set_name) {
+            Ok(Some(set)) => set.kv.get(member).map_or(RespValue::Null, |score| RespValue::Double(score.0)),
+            Ok(None) => RespValue::Null,
+            Err(e) => e,
         }
-
-        encode_bulk("")  
     }
 
     pub async fn cmd_zrem(
         _: &Args,
-        cmd_args: &Vec<String>,
-        sorted_set_ref: Arc<Mutex<HashMap<String, SortedSet>>>) -> String {
-            
+        cmd_args: &[Vec<u8>],
+        storage_ref: Storage) -> RespValue {
+
+        // ZREM key member [member ...], replies with the number of members removed
         let set_name = &cmd_args[1];
-        let member = &cmd_args[2];
-        
-        if let Some(set) = sorted_set_ref.lock().await.get_mut(set_name) {
-            if let Some(&score) = set.kv.get(member) {
-                set.kv.remove(member.as_str());
-                set.st.remove(&(score.clone(), member.clone()));
 
-                return encode_int(1);
+        let mut storage = storage_ref.lock().await;
+        let set = match live_zset_mut(&mut storage, set_name) {
+            Ok(set) => set,
+            Err(e) => return e,
+        };
+        // no elements are deleted when the set doesnt exist
+        let Some(set) = set else {
+            return RespValue::Integer(0);
+        };
+
+        let removed = cmd_args[2..].iter().filter(|member| set.remove(member)).count() as i64;
+        // the set goes away with its last member
+        if set.kv.is_empty() {
+            storage.remove(set_name);
+        }
+
+        RespValue::Integer(removed)
+    }
+
+    // ZSCAN key cursor [MATCH pattern] [COUNT count], walks the members like SCAN walks the keys and replies with
+    // the next cursor and a flat array of members and their scores
+    pub async fn cmd_zscan(
+        _: &Args,
+        cmd_args: &[Vec<u8>],
+        storage_ref: Storage) -> RespValue {
+
+        let options = match parse_scan_options(&cmd_args[2..], false) {
+            Ok(options) => options,
+            Err(e) => return e,
+        };
+
+        let storage = storage_ref.lock().await;
+        let set = match live_zset(&storage, &cmd_args[1]) {
+            Ok(Some(set)) => set,
+            Ok(None) => return RespValue::Array(vec![RespValue::bulk("0"), RespValue::Array(vec![])]),
+            Err(e) => return e,
+        };
+
+        let (next, batch) = set.scan.step(options.cursor, options.count);
+        let mut entries = vec![];
+        for member in batch {
+            if options.pattern.as_ref().is_none_or(|pattern| string_match(pattern, member, false)) {
+                entries.push(RespValue::bulk(member));
+                entries.push(RespValue::bulk(set.kv[member].0.to_string()));
             }
         }
 
-        // no elements were deleted because either the set doesnt exist or the member doesnt exist 
-        encode_int(0)
+        RespValue::Array(vec![RespValue::bulk(next.to_string()), RespValue::Array(entries)])
     }
-}
\ No newline at end of file
+}
@@ -1,210 +1,178 @@
 pub mod streams {
     use crate::utils::utils::*;
-    use std::sync::Arc;
     use tokio::select;
-    use tokio::sync::{broadcast, Mutex};
+    use tokio::sync::broadcast;
     use tokio::time::sleep;
     use std::collections::HashMap;
     use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
+    // parse a "ms-seq" stream id, a missing sequence defaults to 0 and "-" is the smallest id
+    fn parse_id(arg: &[u8]) -> Option<(usize, usize)> {
+        let id = arg_str(arg);
+        if id == "-" {
+            return Some((0, 0));
+        }
+        match id.split_once('-') {
+            Some((ms, seq)) => Some((ms.parse().ok()?, seq.parse().ok()?)),
+            None => Some((id.parse().ok()?, 0)),
+        }
+    }
+
+    // parse the id given to XADD, the parts left for the server to generate("*") are None
+    fn parse_xadd_id(arg: &[u8]) -> Option<(Option<usize>, Option<usize>)> {
+        let id = arg_str(arg);
+        if id == "*" {
+            return Some((None, None));
+        }
+        match id.split_once('-') {
+            Some((ms, "*")) => Some((Some(ms.parse().ok()?), None)),
+            _ => parse_id(arg).map(|(ms, seq)| (Some(ms), Some(seq))),
+        }
+    }
+
     pub async fn cmd_xadd(
-        cmd_args: &Vec<String>, 
-        storage_ref: Arc<Mutex<HashMap<String, (RDBValue, Option<SystemTime>)>>>,
-        tx: broadcast::Sender<Vec<u8>>) -> String {
-        let mut new_kv = StorageKV {
-            key: cmd_args[1].clone(),
-            value: RDBValue::Stream(vec![StreamEntry{
-                id: {
-                    // '*' part will be replaced with usize::MAX
-                    if cmd_args[2] == "*" {
-                        (usize::MAX, usize::MAX)
-                    }  else {
-                        let id_parts = cmd_args[2].split_once('-').unwrap();
-                        if id_parts.1 == "*" {
-                            (usize::from_str_radix(id_parts.0, 10).unwrap(), usize::MAX)
-                        } else {
-                            (usize::from_str_radix(id_parts.0, 10).unwrap(), usize::from_str_radix(id_parts.1, 10).unwrap())
-                        }
-                    } 
-                },
-                value: {
-                    let mut kv_pairs = vec![];
-                    for i in 3..cmd_args.len() -1 {
-                        kv_pairs.push((cmd_args[i].clone(), cmd_args[i + 1].clone()));
-                    }
-                    kv_pairs
-                },
-            }]), 
-            exp_ts: None,
+        cmd_args: &[Vec<u8>],
+        storage_ref: Storage,
+        tx: broadcast::Sender<Vec<u8>>) -> RespValue {
+        let key = &cmd_args[1];
+        let Some((ms, seq)) = parse_xadd_id(&cmd_args[2]) else {
+            return redis_err(_ERROR_STREAM_INVALID_ID_);
         };
-
-        // stream data doesnt support time yet 
-        // if cmd_args.len() > 3 { // input validation is not being performed
-        //     // SET foo bar px milliseconds
-        //     let n = cmd_args[4].parse().unwrap();
-        //     new_kv.exp_ts = SystemTime::now().checked_add(Duration::from_millis(n));
-        // }
-        // println!("inserting into stream: {:?}", new_kv);
-        // id validation
-        match &new_kv.value {
-            RDBValue::Stream(new_value_vec) => {
-                if new_value_vec[0].id == (0, 0) {
-                    return redis_err(_ERROR_STREAM_NULL_ID_);
-                }
-            },
-            _ => {
-                panic!("invalid data type found in cmdxadd");
-            } 
+        if (ms, seq) == (Some(0), Some(0)) {
+            return redis_err(_ERROR_STREAM_NULL_ID_);
         }
+        // fields and values come in pairs
+        if !(cmd_args.len() - 3).is_multiple_of(2) {
+            return redis_err("ERR wrong number of arguments for 'xadd' command");
+        }
+        let value: Vec<(Vec<u8>, Vec<u8>)> = cmd_args[3..].chunks(2).map(|pair| (pair[0].clone(), pair[1].clone())).collect();
 
-        let mut db_data= storage_ref.lock().await;
-        let result;
+        let mut db_data = storage_ref.lock().await;
 
-        match db_data.get_mut(&new_kv.key) {
-            Some((value, _)) => {
-                match value {
-                    RDBValue::Stream(value_vec) => {
-                        match new_kv.value {
-                            RDBValue::Stream(mut new_value_vec) => {
-                                let prev_id = value_vec.iter().next_back().unwrap().id;
-                                if  prev_id >= new_value_vec[0].id {
-                                    return redis_err(_ERROR_STREAM_GEQ_ID_EXISTS_);
-                                }
-                                if new_value_vec[0].id.0 == usize::MAX {
-                                    new_value_vec[0].id.0 = prev_id.0 + 1;
-                                }
-                                if new_value_vec[0].id.1 == usize::MAX {
-                                    if prev_id.0 == new_value_vec[0].id.0 { // if first part matches with previous element's id 
-                                        new_value_vec[0].id.1 = prev_id.1 + 1;
-                                    } else {
-                                        new_value_vec[0].id.1 = 0 + (new_value_vec[0].id.0 == 0) as usize;
-                                    }
-                                }
-                                value_vec.push(new_value_vec[0].to_owned());
+        let prev_id = match db_data.get(key) {
+            Some((RDBValue::Stream(entries), _)) => entries.last().map(|entry| entry.id),
+            Some(_) => return redis_err(_ERROR_WRONGTYPE_),
+            None => None,
+        };
 
-                                result = format!("{}-{}", new_value_vec[0].id.0, new_value_vec[0].id.1);
-                            },
-                            _ => {
-                                unimplemented!("inconsistent data types in xadd");
-                            }
-                        }
-                    },
-                    _ => {
-                        unimplemented!("found string data in a stream cmd_xadd");
-                    }
-                }
-            },
-            None => {
-                match new_kv.value {
-                    RDBValue::Stream(ref mut new_value_vec) => {
-                        if new_value_vec[0].id.0 == usize::MAX {
-                            new_value_vec[0].id.0 = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as usize;
-                        }
-                        if new_value_vec[0].id.1 == usize::MAX {
-                            if new_value_vec[0].id.0 > 0 {
-                                new_value_vec[0].id.1 = 0;
-                            } else {
-                                // if first part is 0 then second part must start from 1
-                                new_value_vec[0].id.1 = 1;
-                            }
-                        }
-                        
-                        result = format!("{}-{}", &new_value_vec[0].id.0, &new_value_vec[0].id.1);
-                        db_data.insert(
-                            new_kv.key,
-                            (new_kv.value, None));
+        let ms = ms.unwrap_or_else(|| {
+            let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as usize;
+            now.max(prev_id.map_or(0, |prev| prev.0))
+        });
+        let seq = seq.unwrap_or(match prev_id {
+            // if first part matches with previous element's id
+            Some(prev) if prev.0 == ms => prev.1.saturating_add(1),
+            // if first part is 0 then second part must start from 1
+            _ => (ms == 0) as usize,
+        });
+        let id = (ms, seq);
+
+        if prev_id.is_some_and(|prev| prev >= id) {
+            return redis_err(_ERROR_STREAM_GEQ_ID_EXISTS_);
+        }
 
-                    },
-                    _ => {
-                        unimplemented!("invalid state int cmd_xadd()");
-                    }
-                };
-                
+        let entry = StreamEntry { id, value };
+        match db_data.get_mut(key) {
+            Some((RDBValue::Stream(entries), _)) => entries.push(entry),
+            _ => {
+                db_data.insert(key.clone(), (RDBValue::Stream(vec![entry]), None));
             }
         }
+        drop(db_data);
 
-        tx.send(_EVENT_DB_UPDATED_.as_bytes().to_vec().to_owned()).unwrap();
-        encode_bulk(&result)
+        // nobody may be waiting on the stream
+        let _ = tx.send(_EVENT_DB_UPDATED_.as_bytes().to_vec());
+        RespValue::bulk(format!("{}-{}", id.0, id.1))
     }
 
     pub async fn cmd_xrange(
-        cmd_args: &Vec<String>, 
-        storage_ref: Arc<Mutex<HashMap<String, (RDBValue, Option<SystemTime>)>>>) -> String {
-
-        let key = cmd_args[1].as_str();
-        let mut id_start = (0, 0);
-        if cmd_args[2].find("-").is_none() {
-            id_start.0 = cmd_args[2].as_str().parse().unwrap();
-        }  else {
-            let id_parts = cmd_args[2].split_once('-').unwrap();
-            // when start id is just "-" id_start just defaults to (0, 0) 
-            id_start = (id_parts.0.parse().unwrap_or_default(), id_parts.1.parse().unwrap_or_default());
-        }
-
-        let mut id_end = (0, usize::MAX); 
-        if cmd_args[3] == "+" {
-            id_end = (usize::MAX, usize::MAX);
-        } else if cmd_args[3].find("-").is_none() {
-            id_end.0 = cmd_args[3].as_str().parse().unwrap();
-        } else { 
-            let id_parts = cmd_args[3].split_once('-').unwrap();
-            id_end = (id_parts.0.parse().unwrap(), id_parts.1.parse().unwrap());
-        }
+        cmd_args: &[Vec<u8>],
+        storage_ref: Storage) -> RespValue {
+
+        let key = &cmd_args[1];
+        let id_start = parse_id(&cmd_args[2]);
+
+        let id_end = if cmd_args[3] == b"+" {
+            Some((usize::MAX, usize::MAX))
+        } else if !cmd_args[3].contains(&b'-') {
+            parse_arg(&cmd_args[3]).map(|ms| (ms, usize::MAX))
+        } else {
+            parse_id(&cmd_args[3])
+        };
+        let (Some(id_start), Some(id_end)) = (id_start, id_end) else {
+            return redis_err(_ERROR_STREAM_INVALID_ID_);
+        };
 
         let _db = storage_ref.lock().await;
 
-        let mut result: Vec<String> = vec![];
-        match _db.get(key) {
-            Some((stream_kvs, _)) => {
-                match stream_kvs {
-                    RDBValue::Stream(stream_data) => {
-                        for entry in stream_data {
-                            if id_start <= entry.id && entry.id <= id_end {
-                                result.push(entry.serialize());
-                            }  
-                        } 
-                    },
-                    _ => {
-                        panic!("type mismatch in xrange");
+        let mut result = vec![];
+        if let Some((stream_kvs, _)) = _db.get(key) {
+            match stream_kvs {
+                RDBValue::Stream(stream_data) => {
+                    for entry in stream_data {
+                        if id_start <= entry.id && entry.id <= id_end {
+                            result.push(entry.serialize());
+                        }
                     }
+                },
+                _ => {
+                    return redis_err(_ERROR_WRONGTYPE_);
                 }
-            },
-            None => {
-                ()
             }
-        } 
-        
-        encode_array(&result, false)
+        }
+
+        RespValue::Array(result)
     }
 
     pub async fn cmd_xread(
-        cmd_args: &Vec<String>, 
-        storage_ref: Arc<Mutex<HashMap<String, (RDBValue, Option<SystemTime>)>>>,
-        mut rx:  broadcast::Receiver<Vec<u8>>) -> String {
+        config_args: &Args,
+        cmd_args: &[Vec<u8>],
+        storage_ref: Storage,
+        mut rx:  broadcast::Receiver<Vec<u8>>) -> RespValue {
 
-        let mut final_result = vec![];      // accumulated reuslts
-        let mut result: Vec<String> = vec![];           // result of one stream
+        let mut final_result = vec![];      // accumulated reuslts, (stream name, entries) pairs
         let mut start = 2;
-        let mut state: HashMap<String, (usize, usize)> = HashMap::new();
-
-        if cmd_args[1] == "block" {
+        let mut state: HashMap<Vec<u8>, (usize, usize)> = HashMap::new();
+
+        let mut block = None;
+        if arg_str(&cmd_args[1]).eq_ignore_ascii_case("block") {
+            block = match parse_arg::<i64>(&cmd_args[2]) {
+                Some(ms) if ms < 0 => return redis_err(_ERROR_TIMEOUT_NEGATIVE_),
+                Some(ms) => Some(Duration::from_millis(ms as u64)),
+                None => return redis_err(_ERROR_TIMEOUT_NOT_AN_INT_),
+            };
             // save the state before blocking
             {
                 let _db = storage_ref.lock().await;
-                for k  in _db.keys() {
-                    match &_db.get(k).unwrap().0 {
-                        RDBValue::Stream(entries) => {
-                            // save stream latest entries
-                            state.insert(k.clone(), entries.iter().next_back().unwrap().id.clone());
-                        },
-                        _ => {
-                            continue;
+                for (k, (value, _)) in _db.iter() {
+                    if let RDBValue::Stream(entries) = value {
+                        // save stream latest entries
+                        if let Some(last) = entries.last() {
+                            state.insert(k.clone(), last.id);
                         }
                     }
                 }
             }
             start += 2;
-            let sleep_duration = Duration::from_millis(cmd_args[2].parse().unwrap());
+        }
+
+        let mid = (cmd_args.len() - start) / 2;
+
+        // every stream needs an id to read from, checked before blocking
+        let mut ids = vec![];
+        for i in start..(start + mid) {
+            let id_start = if cmd_args[i + mid] == b"$" {
+                Some(state.get(&cmd_args[i]).copied().unwrap_or((0, 0)))
+            } else {
+                parse_id(&cmd_args[i + mid])
+            };
+            match id_start {
+                Some(id_start) => ids.push(id_start),
+                None => return redis_err(_ERROR_STREAM_INVALID_ID_),
+            }
+        }
+
+        if let Some(sleep_duration) = block {
             if sleep_duration.as_millis() > 0 {
                 sleep(sleep_duration).await;
             } else {
@@ -226,57 +194,40 @@ pub mod streams {
             }
         }
 
-        let mid = (cmd_args.len() - 1 - start + 1) / 2;
+        for (key, id_start) in cmd_args[start..(start + mid)].iter().zip(ids) {
+            let id_end = (usize::MAX, usize::MAX);
 
-        for i in start..(start + mid) {
-            result.clear();
-            let key = cmd_args[i].as_str();
-            
-            let mut id_start = (0, 0);
-
-            if cmd_args[i + mid] == "$" {
-                if let Some(id) = state.get(key) {
-                    id_start = *id;
-                }
-            } else if cmd_args[i + mid].find("-").is_none() {
-                id_start.0 = cmd_args[i + mid].as_str().parse().unwrap();
-            }  else {
-                let id_parts = cmd_args[i + mid].split_once('-').unwrap();
-                // when start id is just "-" id_start just defaults to (0, 0) 
-                id_start = (id_parts.0.parse().unwrap_or_default(), id_parts.1.parse().unwrap_or_default());
-            }    
-            let id_end = (usize::MAX, usize::MAX); 
-    
             let _db = storage_ref.lock().await;
-    
-            let mut result: Vec<String> = vec![];
-            match _db.get(key) {
-                Some((stream_kvs, _)) => {
-                    match stream_kvs {
-                        RDBValue::Stream(stream_data) => {
-                            for entry in stream_data {
-                                if id_start < entry.id && entry.id <= id_end {
-                                    result.push(entry.serialize());
-                                }  
-                            } 
-                        },
-                        _ => {
-                            panic!("type mismatch in xrange");
+
+            let mut result = vec![];
+            // streams that don't exist have nothing to read yet
+            if let Some((stream_kvs, _)) = _db.get(key) {
+                match stream_kvs {
+                    RDBValue::Stream(stream_data) => {
+                        for entry in stream_data {
+                            if id_start < entry.id && entry.id <= id_end {
+                                result.push(entry.serialize());
+                            }
                         }
+                    },
+                    _ => {
+                        return redis_err(_ERROR_WRONGTYPE_);
                     }
-                },
-                None => {
-                    panic!("key entry not found in cmd_xread()"); 
                 }
             }
             if !result.is_empty() {
-                final_result.push(encode_array(&vec![encode_bulk(key), encode_array(&result, false)], false)); 
+                final_result.push((RespValue::bulk(key), RespValue::Array(result)));
             }
-        } 
+        }
 
         if final_result.is_empty() {
-            return "*-1\r\n".to_owned();
-        }    
-        encode_array(&final_result, false)
+            return RespValue::NullArray;
+        }
+
+        // RESP3 clients get a map keyed by stream name
+        if config_args.resp3 {
+            return RespValue::Map(final_result);
+        }
+        RespValue::Array(final_result.into_iter().map(|(k, v)| RespValue::Array(vec![k, v])).collect())
     }
-}
\ No newline at end of file
+}
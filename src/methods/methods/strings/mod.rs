@@ -0,0 +1,317 @@
+pub mod strings {
+    use crate::methods::methods::bitmaps::bitmaps::bitmap_mut;
+    use crate::utils::utils::*;
+
+    // MGET key [key ...], keys that are missing, expired or not strings come back as nil
+    pub async fn cmd_mget(cmd_args: &[Vec<u8>], storage_ref: Storage) -> RespValue {
+        let storage = storage_ref.lock().await;
+
+        RespValue::Array(cmd_args[1..].iter().map(|key| {
+            match storage.get(key) {
+                Some((RDBValue::String(s), exp_ts)) if !is_expired(exp_ts) => RespValue::Bulk(s.clone()),
+                _ => RespValue::Null,
+            }
+        }).collect())
+    }
+
+    // MSET key value [key value ...], every key is written under the same lock and loses its expiry
+    pub async fn cmd_mset(cmd_args: &[Vec<u8>], storage_ref: Storage) -> RespValue {
+        if cmd_args.len().is_multiple_of(2) {
+            return redis_err("ERR wrong number of arguments for 'mset' command");
+        }
+
+        let mut storage = storage_ref.lock().await;
+        for pair in cmd_args[1..].chunks(2) {
+            storage.insert(pair[0].clone(), (RDBValue::String(pair[1].clone()), None));
+        }
+
+        response_ok()
+    }
+
+    // MSETNX key value [key value ...], sets all the keys only if none of them exists, returns 1 if they were set
+    pub async fn cmd_msetnx(cmd_args: &[Vec<u8>], storage_ref: Storage) -> RespValue {
+        if cmd_args.len().is_multiple_of(2) {
+            return redis_err("ERR wrong number of arguments for 'msetnx' command");
+        }
+
+        let mut storage = storage_ref.lock().await;
+        let pairs = cmd_args[1..].chunks(2);
+        if pairs.clone().any(|pair| storage.get(&pair[0]).is_some_and(|(_, exp_ts)| !is_expired(exp_ts))) {
+            return RespValue::Integer(0);
+        }
+        for pair in pairs {
+            storage.insert(pair[0].clone(), (RDBValue::String(pair[1].clone()), None));
+        }
+
+        RespValue::Integer(1)
+    }
+
+    // integers are only accepted in their canonical form, like redis' string2ll: no sign other than a leading '-',
+    // no leading zeros or spaces
+    pub fn parse_i64(arg: &[u8]) -> Option<i64> {
+        let digits = arg.strip_prefix(b"-").unwrap_or(arg);
+        if digits.is_empty() || !digits.iter().all(u8::is_ascii_digit) || (digits[0] == b'0' && arg.len() > 1) {
+            return None;
+        }
+        parse_arg(arg)
+    }
+
+    // floats as accepted by INCRBYFLOAT, nan and infinity are refused
+    fn parse_f64(arg: &[u8]) -> Option<f64> {
+        if arg.is_empty() || arg.iter().any(|ch| ch.is_ascii_whitespace()) {
+            return None;
+        }
+        parse_arg::<f64>(arg).filter(|f| f.is_finite())
+    }
+
+    // current string value of a key without copying it, None for missing or expired keys
+    fn string_value<'a>(storage: &'a Entries, key: &[u8]) -> Result<Option<&'a [u8]>, RespValue> {
+        match storage.get(key) {
+            Some((_, exp_ts)) if is_expired(exp_ts) => Ok(None),
+            Some((RDBValue::String(s), _)) => Ok(Some(s)),
+            Some(_) => Err(redis_err(_ERROR_WRONGTYPE_)),
+            None => Ok(None),
+        }
+    }
+
+    fn set_expiry(storage: &mut Entries, key: &[u8], exp_ts: Option<std::time::SystemTime>) {
+        if let Some((_, slot)) = storage.get_mut(key) {
+            *slot = exp_ts;
+        }
+    }
+
+    // expiry of a key that is still alive, writes that keep the ttl carry it over
+    fn live_expiry(storage: &Entries, key: &[u8]) -> Option<std::time::SystemTime> {
+        storage.get(key).and_then(|(_, exp_ts)| exp_ts.filter(|_| !is_expired(exp_ts)))
+    }
+
+    // INCR, DECR, INCRBY and DECRBY, the key keeps its expiry
+    pub async fn cmd_incr_by(key: &[u8], delta: i64, storage_ref: Storage) -> RespValue {
+        let mut storage = storage_ref.lock().await;
+
+        let current = match string_value(&storage, key) {
+            Ok(Some(s)) => match parse_i64(s) {
+                Some(n) => n,
+                None => return redis_err(_ERROR_INCR_NOT_AN_INT_),
+            },
+            Ok(None) => 0,
+            Err(e) => return e,
+        };
+        let result = match current.checked_add(delta) {
+            Some(result) => result,
+            None => return redis_err(_ERROR_INCR_OVERFLOW_),
+        };
+
+        let exp_ts = live_expiry(&storage, key);
+        storage.insert(key.to_vec(), (RDBValue::String(result.to_string().into_bytes()), exp_ts));
+
+        RespValue::Integer(result)
+    }
+
+    // INCRBYFLOAT key increment, replies with the new value as a string
+    pub async fn cmd_incr_by_float(key: &[u8], increment: &[u8], storage_ref: Storage) -> RespValue {
+        let increment = match parse_f64(increment) {
+            Some(increment) => increment,
+            None => return redis_err(_ERROR_NOT_A_FLOAT_),
+        };
+
+        let mut storage = storage_ref.lock().await;
+        let current = match string_value(&storage, key) {
+            Ok(Some(s)) => match parse_f64(s) {
+                Some(f) => f,
+                None => return redis_err(_ERROR_NOT_A_FLOAT_),
+            },
+            Ok(None) => 0.0,
+            Err(e) => return e,
+        };
+        let result = current + increment;
+        if !result.is_finite() {
+            return redis_err("ERR increment would produce NaN or Infinity");
+        }
+
+        // redis does the math in long double and prints 17 decimals, rounding to the 15 significant digits
+        // f64 can hold gets the same text for everyday values(0.1 + 0.2 is "0.3"), without exponent or trailing zeros
+        let rounded: f64 = format!("{:.14e}", result).parse().unwrap();
+        let repr = format!("{}", rounded).into_bytes();
+        let exp_ts = live_expiry(&storage, key);
+        storage.insert(key.to_vec(), (RDBValue::String(repr.clone()), exp_ts));
+
+        RespValue::Bulk(repr)
+    }
+
+    // APPEND key value, creates the key if needed and returns the new length. The value grows in place so
+    // appending to a long string doesn't copy it
+    pub async fn cmd_append(key: &[u8], value: &[u8], storage_ref: Storage) -> RespValue {
+        let mut storage = storage_ref.lock().await;
+
+        let len = match string_value(&storage, key) {
+            Ok(current) => current.map_or(0, <[u8]>::len),
+            Err(e) => return e,
+        };
+        if len + value.len() > _MAX_STRING_SIZE_ {
+            return redis_err(_ERROR_STRING_TOO_LONG_);
+        }
+        let current = match bitmap_mut(&mut storage, key) {
+            Ok(current) => current,
+            Err(e) => return e,
+        };
+        current.extend_from_slice(value);
+
+        RespValue::Integer(current.len() as i64)
+    }
+
+    // STRLEN key, 0 for a missing key
+    pub async fn cmd_strlen(key: &[u8], storage_ref: Storage) -> RespValue {
+        let storage = storage_ref.lock().await;
+
+        match string_value(&storage, key) {
+            Ok(current) => RespValue::Integer(current.map_or(0, <[u8]>::len) as i64),
+            Err(e) => e,
+        }
+    }
+
+    // GETRANGE key start end, both ends are inclusive and negative offsets count from the end of the string
+    pub async fn cmd_getrange(key: &[u8], start: &[u8], end: &[u8], storage_ref: Storage) -> RespValue {
+        let (mut start, mut end) = match (parse_arg::<i64>(start), parse_arg::<i64>(end)) {
+            (Some(start), Some(end)) => (start, end),
+            _ => return redis_err(_ERROR_INCR_NOT_AN_INT_),
+        };
+
+        let storage = storage_ref.lock().await;
+        let current = match string_value(&storage, key) {
+            Ok(current) => current.unwrap_or_default(),
+            Err(e) => return e,
+        };
+
+        let len = current.len() as i64;
+        if (start < 0 && end < 0 && start > end) || len == 0 {
+            return RespValue::bulk("");
+        }
+        if start < 0 {
+            start = (len + start).max(0);
+        }
+        if end < 0 {
+            end = (len + end).max(0);
+        }
+        end = end.min(len - 1);
+        if start > end {
+            return RespValue::bulk("");
+        }
+
+        RespValue::Bulk(current[start as usize..=end as usize].to_vec())
+    }
+
+    // SETRANGE key offset value, overwrites part of the string, padding it with zero bytes when the offset
+    // is past its end, returns the new length
+    pub async fn cmd_setrange(key: &[u8], offset: &[u8], value: &[u8], storage_ref: Storage) -> RespValue {
+        let offset = match parse_arg::<i64>(offset) {
+            Some(offset) if offset < 0 => return redis_err("ERR offset is out of range"),
+            Some(offset) => offset as usize,
+            None => return redis_err(_ERROR_INCR_NOT_AN_INT_),
+        };
+        if offset + value.len() > _MAX_STRING_SIZE_ {
+            return redis_err(_ERROR_STRING_TOO_LONG_);
+        }
+
+        let mut storage = storage_ref.lock().await;
+        let len = match string_value(&storage, key) {
+            Ok(current) => current.map_or(0, <[u8]>::len),
+            Err(e) => return e,
+        };
+        // writing nothing leaves the key as it is, a missing key isn't created
+        if value.is_empty() {
+            return RespValue::Integer(len as i64);
+        }
+
+        let current = match bitmap_mut(&mut storage, key) {
+            Ok(current) => current,
+            Err(e) => return e,
+        };
+        if current.len() < offset + value.len() {
+            current.resize(offset + value.len(), 0);
+        }
+        current[offset..offset + value.len()].copy_from_slice(value);
+
+        RespValue::Integer(current.len() as i64)
+    }
+
+    // GETDEL key, returns the value and removes the key
+    pub async fn cmd_getdel(key: &[u8], storage_ref: Storage) -> RespValue {
+        let mut storage = storage_ref.lock().await;
+
+        match string_value(&storage, key) {
+            Ok(Some(s)) => {
+                let s = s.to_vec();
+                storage.remove(key);
+                RespValue::Bulk(s)
+            },
+            Ok(None) => RespValue::Null,
+            Err(e) => e,
+        }
+    }
+
+    // GETEX key [EX seconds | PX milliseconds | EXAT unix-time-seconds | PXAT unix-time-milliseconds | PERSIST]
+    // returns the value and the command replicas should apply when the expiry changed: the new expiry as an
+    // absolute PXAT, or GETDEL when it is already in the past
+    pub async fn cmd_getex(cmd_args: &[Vec<u8>], storage_ref: Storage) -> (RespValue, Option<Vec<Vec<u8>>>) {
+        let key = &cmd_args[1];
+        // None leaves the expiry alone, Some(None) removes it
+        let mut expiry: Option<Option<std::time::SystemTime>> = None;
+
+        let mut i = 2;
+        while i < cmd_args.len() {
+            let opt = arg_str(&cmd_args[i]).to_uppercase();
+            match opt.as_str() {
+                "PERSIST" if expiry.is_none() => expiry = Some(None),
+                "EX" | "PX" | "EXAT" | "PXAT" if expiry.is_none() && i + 1 < cmd_args.len() => {
+                    i += 1;
+                    match parse_expiry(&opt, &cmd_args[i], "getex") {
+                        Ok(ts) => expiry = Some(Some(ts)),
+                        Err(e) => return (e, None),
+                    }
+                },
+                _ => return (redis_err(_ERROR_SYNTAX_), None),
+            }
+            i += 1;
+        }
+
+        let mut storage = storage_ref.lock().await;
+        let value = match string_value(&storage, key) {
+            Ok(Some(s)) => s.to_vec(),
+            Ok(None) => return (RespValue::Null, None),
+            Err(e) => return (e, None),
+        };
+
+        let propagate = match expiry {
+            Some(Some(ts)) if is_expired(&Some(ts)) => {
+                storage.remove(key);
+                vec![b"GETDEL".to_vec(), key.clone()]
+            },
+            Some(Some(ts)) => {
+                set_expiry(&mut storage, key, Some(ts));
+                vec![b"GETEX".to_vec(), key.clone(), b"PXAT".to_vec(), unix_millis(ts).to_string().into_bytes()]
+            },
+            Some(None) => {
+                set_expiry(&mut storage, key, None);
+                vec![b"GETEX".to_vec(), key.clone(), b"PERSIST".to_vec()]
+            },
+            None => return (RespValue::Bulk(value), None),
+        };
+
+        (RespValue::Bulk(value), Some(propagate))
+    }
+
+    // GETSET key value, sets the value like SET and returns the previous one, the key loses its expiry
+    pub async fn cmd_getset(key: &[u8], value: &[u8], storage_ref: Storage) -> RespValue {
+        let mut storage = storage_ref.lock().await;
+
+        if let Err(e) = string_value(&storage, key) {
+            return e;
+        }
+        // the old value is moved out rather than copied
+        match storage.insert(key.to_vec(), (RDBValue::String(value.to_vec()), None)) {
+            Some((RDBValue::String(s), exp_ts)) if !is_expired(&exp_ts) => RespValue::Bulk(s),
+            _ => RespValue::Null,
+        }
+    }
+}
@@ -1,18 +1,208 @@
 pub mod utils {
-    use std::{cmp::Ordering, collections::{BTreeSet, HashMap, HashSet, VecDeque}, time::SystemTime};
+    use std::{cmp::Ordering, collections::{BTreeSet, HashMap, HashSet, VecDeque}, str::FromStr, sync::{atomic::AtomicUsize, Arc}, time::{Duration, SystemTime, UNIX_EPOCH}};
+    use bytes::{Buf, BytesMut};
     use clap::Parser;
     use serde::{Deserialize, Serialize};
-    use tokio::net::TcpStream;
+    use tokio::{net::TcpStream, sync::Mutex};
    
     // this module provides frequently used funtions, constants, types
 
+    // keys and values are raw bytes all the way from the parser to the rdb file, nothing assumes utf-8
+    pub type Entries = HashMap<Vec<u8>, (RDBValue, Option<SystemTime>)>;
+    pub type Storage = Arc<Mutex<Entries>>;
+
+    // approximate memory and access metadata of a key, for maxmemory and its eviction policies
+    #[derive(Debug, Clone, Copy)]
+    pub struct KeyMeta {
+        pub size: usize,
+        // unix time of the last access in ms
+        pub lru: u64,
+        // logarithmic access counter, and the minute it was last decayed
+        pub freq: u8,
+        pub ldt: u64,
+    }
+
+    #[derive(Debug)]
+    struct IndexedMeta {
+        meta: KeyMeta,
+        // where the key is in keys, and in volatile if it has a ttl
+        pos: usize,
+        volatile_pos: Option<usize>,
+    }
+
+    // the metadata of the keys of a database, kept in step with the keyspace by track_keys. The keys are also kept in
+    // two vectors, all of them and those with a ttl like redis' db->expires, so random ones can be sampled in O(1),
+    // and in the order SCAN walks them
+    #[derive(Debug, Default)]
+    pub struct KeyIndex {
+        meta: HashMap<Vec<u8>, IndexedMeta>,
+        keys: Vec<Vec<u8>>,
+        volatile: Vec<Vec<u8>>,
+        pub scan: ScanIndex,
+    }
+
+    impl KeyIndex {
+        pub fn len(&self) -> usize {
+            self.keys.len()
+        }
+
+        pub fn is_empty(&self) -> bool {
+            self.keys.is_empty()
+        }
+
+        pub fn volatile_len(&self) -> usize {
+            self.volatile.len()
+        }
+
+        pub fn get(&self, key: &[u8]) -> Option<&KeyMeta> {
+            self.meta.get(key).map(|indexed| &indexed.meta)
+        }
+
+        pub fn get_mut(&mut self, key: &[u8]) -> Option<&mut KeyMeta> {
+            self.meta.get_mut(key).map(|indexed| &mut indexed.meta)
+        }
+
+        pub fn values(&self) -> impl Iterator<Item = &KeyMeta> {
+            self.meta.values().map(|indexed| &indexed.meta)
+        }
+
+        // the key at a position of all the keys or of the keys with a ttl, positions go up to len or volatile_len
+        pub fn key_at(&self, pos: usize, volatile: bool) -> &[u8] {
+            if volatile { &self.volatile[pos] } else { &self.keys[pos] }
+        }
+
+        // adds a key or replaces its metadata, a new key counts as having no ttl until set_volatile says otherwise
+        pub fn insert(&mut self, key: Vec<u8>, meta: KeyMeta) {
+            if let Some(indexed) = self.meta.get_mut(&key) {
+                indexed.meta = meta;
+                return;
+            }
+            self.keys.push(key.clone());
+            self.scan.insert(&key);
+            self.meta.insert(key, IndexedMeta { meta, pos: self.keys.len() - 1, volatile_pos: None });
+        }
+
+        // the last key takes the place of the removed one in the vectors
+        pub fn remove(&mut self, key: &[u8]) -> Option<KeyMeta> {
+            let indexed = self.meta.remove(key)?;
+            self.scan.remove(key);
+            self.keys.swap_remove(indexed.pos);
+            if let Some(moved) = self.keys.get(indexed.pos) {
+                if let Some(moved) = self.meta.get_mut(moved) {
+                    moved.pos = indexed.pos;
+                }
+            }
+            if let Some(volatile_pos) = indexed.volatile_pos {
+                self.remove_volatile(volatile_pos);
+            }
+            Some(indexed.meta)
+        }
+
+        // records whether a key has a ttl
+        pub fn set_volatile(&mut self, key: &[u8], volatile: bool) {
+            let Some(indexed) = self.meta.get_mut(key) else {
+                return;
+            };
+            match (indexed.volatile_pos, volatile) {
+                (None, true) => {
+                    indexed.volatile_pos = Some(self.volatile.len());
+                    self.volatile.push(key.to_vec());
+                },
+                (Some(volatile_pos), false) => {
+                    indexed.volatile_pos = None;
+                    self.remove_volatile(volatile_pos);
+                },
+                _ => {},
+            }
+        }
+
+        fn remove_volatile(&mut self, volatile_pos: usize) {
+            self.volatile.swap_remove(volatile_pos);
+            if let Some(moved) = self.volatile.get(volatile_pos) {
+                if let Some(moved) = self.meta.get_mut(moved) {
+                    moved.volatile_pos = Some(volatile_pos);
+                }
+            }
+        }
+    }
+
+    // one numbered logical database
+    #[derive(Debug, Clone, Default)]
+    pub struct Db {
+        pub storage: Storage,
+        // locked after storage
+        pub meta: Arc<Mutex<KeyIndex>>,
+    }
+
+    // the logical databases of the server, each connection works in the one it SELECTed
+    #[derive(Debug)]
+    pub struct Databases {
+        pub dbs: Vec<Db>,
+        // the database the replication stream is in, a SELECT goes out first whenever a write is for another one
+        replication_db: std::sync::Mutex<Option<usize>>,
+        // sum of the sizes in the key metadata of all the databases
+        pub used_memory: AtomicUsize,
+        pub evicted_keys: AtomicUsize,
+        // candidates for eviction kept between evictions as (score, db, key), best first. Locked before any database
+        pub eviction_pool: Mutex<Vec<(u64, usize, Vec<u8>)>>,
+    }
+
+    impl Databases {
+        pub fn new(count: usize) -> Arc<Databases> {
+            Arc::new(Databases {
+                dbs: (0..count).map(|_| Db::default()).collect(),
+                replication_db: std::sync::Mutex::new(Some(0)),
+                used_memory: AtomicUsize::new(0),
+                evicted_keys: AtomicUsize::new(0),
+                eviction_pool: Mutex::new(vec![]),
+            })
+        }
+
+        // sends a write done in database db to the replicas, returns the bytes sent. The lock is held over both sends so
+        // writes of other connections can't end up between the SELECT and the write
+        pub fn propagate(&self, db: usize, cmd: &[Vec<u8>], tx: &tokio::sync::broadcast::Sender<Vec<u8>>) -> usize {
+            let mut replication_db = self.replication_db.lock().unwrap();
+            let mut sent = 0;
+            if *replication_db != Some(db) {
+                let select = encode_command(&["SELECT".to_owned(), db.to_string()]);
+                sent += select.len();
+                tx.send(select).ok();
+                *replication_db = Some(db);
+            }
+            let msg = encode_command(cmd);
+            sent += msg.len();
+            tx.send(msg).ok();
+            sent
+        }
+
+        // a new replica starts out in database 0, if the stream is elsewhere the next write has to say so
+        pub fn reset_replication_db(&self) {
+            let mut replication_db = self.replication_db.lock().unwrap();
+            if *replication_db != Some(0) {
+                *replication_db = None;
+            }
+        }
+
+        // the index given to SELECT, MOVE, SWAPDB and COPY ... DB
+        pub fn parse_index(&self, arg: &[u8]) -> Result<usize, RespValue> {
+            match parse_arg::<i64>(arg) {
+                Some(index) if (0..self.dbs.len() as i64).contains(&index) => Ok(index as usize),
+                Some(_) => Err(redis_err("ERR DB index is out of range")),
+                None => Err(redis_err(_ERROR_INCR_NOT_AN_INT_)),
+            }
+        }
+    }
+    // a decoded command with the number of bytes it took on the wire
+    pub type Command = (usize, Vec<Vec<u8>>);
+
     #[derive(Debug, Clone)]
     pub enum RDBValue {
-        String(String),
+        String(Vec<u8>),
         // change the underlying data type for stream variant to something like rbtree keyed on id field of stream entry
         // this will allow the most important operation on streams(range operation) to be executed effeciently 
         Stream(Vec<StreamEntry>),
-        List(VecDeque<String>), 
+        List(VecDeque<Vec<u8>>),
+        ZSet(SortedSet),
     }
 
     impl RDBValue {
@@ -27,6 +217,9 @@ pub mod utils {
                 },
                 Self::List(_) => {
                     "list".to_owned()
+                },
+                Self::ZSet(_) => {
+                    "zset".to_owned()
                 }
             }
        } 
@@ -37,12 +230,12 @@ pub mod utils {
     pub struct StreamEntry {
         pub id: (usize, usize),
         // pub key: String,
-        pub value: Vec<(String, String)>, // each entry consists of a number of k, v pairs 
+        pub value: Vec<(Vec<u8>, Vec<u8>)>, // each entry consists of a number of k, v pairs
     }
 
     impl StreamEntry {
         // serialize a RDBValue::Stream to redist array  
-        pub fn serialize(&self) -> String {
+        pub fn serialize(&self) -> RespValue {
             let mut res = vec![];
             // encode each k,v pair of this entry as bulk string
             for (k, v) in &self.value {
@@ -50,13 +243,13 @@ pub mod utils {
                 res.push(v.clone()); 
             } 
             // encode all bulk strings as an array 
-            encode_array(&vec![encode_bulk(&format!("{}-{}", self.id.0, self.id.1)), encode_array(&res, true)], false)
+            RespValue::Array(vec![RespValue::bulk(format!("{}-{}", self.id.0, self.id.1)), RespValue::bulk_array(&res)])
         }
     }
 
     #[derive(Debug, Clone)]
     pub struct StorageKV { 
-        pub key     :String,
+        pub key     :Vec<u8>,
         pub value   :RDBValue, 
         pub exp_ts  :Option<SystemTime>,
     }
@@ -68,7 +261,7 @@ pub mod utils {
 
     impl PartialOrd for SortableF64 {
         fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-            self.0.partial_cmp(&other.0)
+            Some(self.cmp(other))
         }
     }
     
@@ -82,33 +275,47 @@ pub mod utils {
     pub struct GEOlocation {
         pub lat:    SortableF64,
         pub long:   SortableF64,
-        pub member: String 
+        pub member: Vec<u8>,
     }
 
     #[derive(Debug, Clone, Default)]
     pub struct SortedSet {
         // SortableF64 represents score value
-        pub kv      :HashMap<String, SortableF64>,
+        pub kv      :HashMap<Vec<u8>, SortableF64>,
         // need to change this, we need to support O(1) order find in addition to insert, delete in O(logn)
-        pub st      :BTreeSet<(SortableF64, String)>,
+        pub st      :BTreeSet<(SortableF64, Vec<u8>)>,
+        // members in the order ZSCAN walks them
+        pub scan    :ScanIndex,
     } 
 
     impl SortedSet {
-        pub fn insert(&mut self, key: &String, score: &SortableF64, value: &String) -> usize {
+        pub fn insert(&mut self, key: &[u8], score: &SortableF64, value: &[u8]) -> usize {
             let mut ans= 1;
             
-            if let Some(old_score) = self.kv.insert(key.clone(), *score) {    // insert updated entry in hash map
-                self.st.remove(&(old_score, key.clone()));
+            if let Some(old_score) = self.kv.insert(key.to_vec(), *score) {    // insert updated entry in hash map
+                self.st.remove(&(old_score, key.to_vec()));
 
                 ans = 0;    // new key was inserted in this set
+            } else {
+                self.scan.insert(key);
             }
 
             // insert updated version in the ordered set 
-            self.st.insert((score.clone(), value.clone()));
+            self.st.insert((*score, value.to_vec()));
 
             // return number of new elements inserted
             ans 
         }
+
+        // returns whether the member was there
+        pub fn remove(&mut self, member: &[u8]) -> bool {
+            let Some(score) = self.kv.remove(member) else {
+                return false;
+            };
+            self.st.remove(&(score, member.to_vec()));
+            self.scan.remove(member);
+            true
+        }
     }
 
     // impl Default for SortedSet {
@@ -168,14 +375,167 @@ pub mod utils {
         pub queueing: bool,
 
         #[clap(skip)]
-        pub pending_cmds: Vec<Vec<(usize, Vec<String>)>>,
+        pub pending_cmds: Vec<Vec<(usize, Vec<Vec<u8>>)>>,
 
         #[clap(skip)]
         pub subbed_chans: HashMap<Vec<u8>, ()>,
 
-        // if this is a client and it is in sub mode 
         #[clap(skip)]
-        pub client_in_sub_mode: bool, 
+        pub subbed_patterns: HashMap<Vec<u8>, ()>,
+
+        // if this is a client and it is in sub mode
+        #[clap(skip)]
+        pub client_in_sub_mode: bool,
+
+        // protocol negotiated by this client through HELLO, RESP2 until then
+        #[clap(skip)]
+        pub resp3: bool,
+
+        // set through HELLO SETNAME or CLIENT SETNAME
+        #[clap(skip)]
+        pub client_name: String,
+
+        // set by QUIT, the connection is closed after its reply is written
+        #[clap(skip)]
+        pub closing: bool,
+
+        // number of logical databases
+        #[arg(long, default_value_t=16, value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+        pub databases: usize,
+
+        // database this connection works in, set through SELECT
+        #[clap(skip)]
+        pub db: usize,
+
+        // set on a replica's connection to its master, which sends writes and SELECTs without expecting replies
+        #[clap(skip)]
+        pub master_link: bool,
+
+        // memory limit in bytes, 0 for none. Takes units like redis: 100mb, 1gb
+        #[arg(long, default_value_t=0, value_parser=parse_memory)]
+        pub maxmemory: usize,
+
+        // what goes once maxmemory is reached
+        #[arg(long, value_enum, default_value_t=MaxmemoryPolicy::Noeviction)]
+        pub maxmemory_policy: MaxmemoryPolicy,
+
+        // the keyspace event classes published to pub/sub at startup, CONFIG SET changes them at runtime
+        #[arg(long, default_value="", value_parser=parse_keyspace_events)]
+        pub notify_keyspace_events: u32,
+    }
+
+    #[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq)]
+    pub enum MaxmemoryPolicy {
+        // writes that may grow memory fail instead
+        Noeviction,
+        AllkeysLru,
+        AllkeysLfu,
+        AllkeysRandom,
+        // only keys with a ttl are evicted
+        VolatileLru,
+        VolatileLfu,
+        VolatileRandom,
+        VolatileTtl,
+    }
+
+    impl MaxmemoryPolicy {
+        pub fn name(&self) -> &'static str {
+            match self {
+                Self::Noeviction => "noeviction",
+                Self::AllkeysLru => "allkeys-lru",
+                Self::AllkeysLfu => "allkeys-lfu",
+                Self::AllkeysRandom => "allkeys-random",
+                Self::VolatileLru => "volatile-lru",
+                Self::VolatileLfu => "volatile-lfu",
+                Self::VolatileRandom => "volatile-random",
+                Self::VolatileTtl => "volatile-ttl",
+            }
+        }
+
+        pub fn is_lfu(&self) -> bool {
+            matches!(self, Self::AllkeysLfu | Self::VolatileLfu)
+        }
+    }
+
+    // keyspace event classes of notify-keyspace-events, a notification goes out when its class is set along with
+    // K(keyspace channel) and/or E(keyevent channel)
+    pub const _NOTIFY_KEYSPACE_: u32 = 1 << 0;
+    pub const _NOTIFY_KEYEVENT_: u32 = 1 << 1;
+    pub const _NOTIFY_GENERIC_: u32 = 1 << 2;
+    pub const _NOTIFY_STRING_: u32 = 1 << 3;
+    pub const _NOTIFY_LIST_: u32 = 1 << 4;
+    pub const _NOTIFY_SET_: u32 = 1 << 5;
+    pub const _NOTIFY_HASH_: u32 = 1 << 6;
+    pub const _NOTIFY_ZSET_: u32 = 1 << 7;
+    pub const _NOTIFY_EXPIRED_: u32 = 1 << 8;
+    pub const _NOTIFY_EVICTED_: u32 = 1 << 9;
+    pub const _NOTIFY_STREAM_: u32 = 1 << 10;
+    pub const _NOTIFY_KEY_MISS_: u32 = 1 << 11;
+    pub const _NOTIFY_NEW_: u32 = 1 << 12;
+    // what A stands for, key misses and new keys have to be asked for on their own like in redis
+    pub const _NOTIFY_ALL_: u32 = _NOTIFY_GENERIC_ | _NOTIFY_STRING_ | _NOTIFY_LIST_ | _NOTIFY_SET_ | _NOTIFY_HASH_
+        | _NOTIFY_ZSET_ | _NOTIFY_EXPIRED_ | _NOTIFY_EVICTED_ | _NOTIFY_STREAM_;
+    const _NOTIFY_CLASSES_: [(char, u32); 12] = [
+        ('g', _NOTIFY_GENERIC_), ('$', _NOTIFY_STRING_), ('l', _NOTIFY_LIST_), ('s', _NOTIFY_SET_),
+        ('h', _NOTIFY_HASH_), ('z', _NOTIFY_ZSET_), ('x', _NOTIFY_EXPIRED_), ('e', _NOTIFY_EVICTED_),
+        ('t', _NOTIFY_STREAM_), ('K', _NOTIFY_KEYSPACE_), ('E', _NOTIFY_KEYEVENT_), ('m', _NOTIFY_KEY_MISS_),
+    ];
+
+    // a notify-keyspace-events string like "KEA" or "Kx" to its flags
+    pub fn parse_keyspace_events(classes: &str) -> Result<u32, String> {
+        classes.chars().try_fold(0, |flags, class| match class {
+            'A' => Ok(flags | _NOTIFY_ALL_),
+            'n' => Ok(flags | _NOTIFY_NEW_),
+            class => match _NOTIFY_CLASSES_.iter().find(|(c, _)| *c == class) {
+                Some((_, flag)) => Ok(flags | flag),
+                None => Err("Invalid event class character. Use 'Ag$lshzxeKEtmn'.".to_owned()),
+            },
+        })
+    }
+
+    // flags back to the string CONFIG GET shows, with A when all of its classes are set
+    pub fn keyspace_events_to_string(flags: u32) -> String {
+        let mut classes = String::new();
+        let mut rest = flags;
+        if flags & _NOTIFY_ALL_ == _NOTIFY_ALL_ {
+            classes.push('A');
+            rest &= !_NOTIFY_ALL_;
+        }
+        for (class, flag) in _NOTIFY_CLASSES_ {
+            if rest & flag != 0 {
+                classes.push(class);
+            }
+        }
+        if rest & _NOTIFY_NEW_ != 0 {
+            classes.push('n');
+        }
+        classes
+    }
+
+    // a memory amount with an optional unit, k/m/g are powers of 1000 and kb/mb/gb powers of 1024 like in redis
+    pub fn parse_memory(arg: &str) -> Result<usize, String> {
+        let lower = arg.to_lowercase();
+        let digits = lower.trim_end_matches(|c: char| c.is_ascii_alphabetic());
+        let unit = match &lower[digits.len()..] {
+            "" | "b" => 1,
+            "k" => 1000,
+            "kb" => 1024,
+            "m" => 1000 * 1000,
+            "mb" => 1024 * 1024,
+            "g" => 1000 * 1000 * 1000,
+            "gb" => 1024 * 1024 * 1024,
+            unit => return Err(format!("unknown memory unit '{}'", unit)),
+        };
+        digits.parse::<usize>().ok().and_then(|n| n.checked_mul(unit)).ok_or_else(|| format!("invalid memory amount '{}'", arg))
+    }
+
+    // redis' used_memory_human style: 1.50K, 20.00M
+    pub fn bytes_to_human(n: usize) -> String {
+        let units = [(1usize << 40, "T"), (1 << 30, "G"), (1 << 20, "M"), (1 << 10, "K")];
+        match units.iter().find(|(size, _)| n >= *size) {
+            Some((size, unit)) => format!("{:.2}{}", n as f64 / *size as f64, unit),
+            None => format!("{}B", n),
+        }
     }
 
     pub struct ReplicaInfo {    // for master to gather information about the connected clients
@@ -184,9 +544,13 @@ pub mod utils {
 
     pub struct GlobConfig {
         pub replicas: HashMap::<u16, ReplicaInfo>,
-        pub blocked_clients: HashMap<String, VecDeque<u16>>,
-        pub subscriptions: HashMap<String, HashSet<u16>>,
+        pub blocked_clients: HashMap<Vec<u8>, VecDeque<u16>>,
+        pub subscriptions: HashMap<Vec<u8>, HashSet<u16>>,
+        // glob-style patterns of PSUBSCRIBE, matched against the channel of every published message
+        pub pattern_subscriptions: HashMap<Vec<u8>, HashSet<u16>>,
         pub users: HashMap<(String, String), Vec<String>>, 
+        // notify-keyspace-events, shared by all the connections so CONFIG SET applies everywhere
+        pub notify_keyspace_events: u32,
     }
 
     // ---GEOSPATIAL/SCORE ENCODE/DECODE CODE SECTION BEGINS---
@@ -280,207 +644,777 @@ pub mod utils {
     pub const _RDB_END_: u8 = 0xFF;
     pub const _RDB_TIMESTAMP_MS_FLAG: u8 = 0xFC;
     pub const _RDB_TIMESTAMP_S_FLAG: u8 = 0xFD;
+    pub const _RDB_RESIZEDB_FLAG_: u8 = 0xFB;
+    pub const _RDB_TYPE_STRING_: u8 = 0;
+    pub const _RDB_TYPE_LIST_: u8 = 1;
+    pub const _RDB_TYPE_ZSET_2_: u8 = 5;
+    pub const _RDB_TYPE_STREAM_LISTPACKS_: u8 = 15;
+    pub const _RDB_TYPE_STREAM_LISTPACKS_2_: u8 = 19;
+    pub const _RDB_TYPE_STREAM_LISTPACKS_3_: u8 = 21;
+    // entries per listpack when saving a stream, redis' default stream-node-max-entries
+    pub const _STREAM_NODE_MAX_ENTRIES_: usize = 100;
+    // version of the rdb files and DUMP payloads written, payloads of a newer version are refused
+    pub const _RDB_VERSION_: u16 = 11;
     pub const _EMPTY_RDB_FILE_: &str= "524544495330303131fa0972656469732d76657205372e322e30fa0a72656469732d62697473c040fa056374696d65c26d08bc65fa08757365642d6d656dc2b0c41000fa08616f662d62617365c000fff06e3bfec0ff5aa2";
     pub const _EVENT_DB_UPDATED_: &str = "DB_UPDATED";
     pub const _EVENT_DB_UPDATED_LIST_: &str = "DB_UPDATED_LIST";
     pub const _ERROR_STREAM_GEQ_ID_EXISTS_: &str = "ERR The ID specified in XADD is equal or smaller than the target stream top item";
     pub const _ERROR_STREAM_NULL_ID_: &str = "ERR The ID specified in XADD must be greater than 0-0";
+    pub const _ERROR_STREAM_INVALID_ID_: &str = "ERR Invalid stream ID specified as stream command argument";
+    pub const _ERROR_TIMEOUT_NOT_A_FLOAT_: &str = "ERR timeout is not a float or out of range";
+    pub const _ERROR_TIMEOUT_NOT_AN_INT_: &str = "ERR timeout is not an integer or out of range";
+    pub const _ERROR_TIMEOUT_NEGATIVE_: &str = "ERR timeout is negative";
     pub const _ERROR_INCR_NOT_AN_INT_: &str = "ERR value is not an integer or out of range";
+    pub const _ERROR_INCR_OVERFLOW_: &str = "ERR increment or decrement would overflow";
+    pub const _ERROR_NOT_A_FLOAT_: &str = "ERR value is not a valid float";
     pub const _ERROR_EXEC_WITHOUT_MULTI_: &str = "ERR EXEC without MULTI";
     pub const _ERROR_DISCARD_WITHOUT_MULTI_: &str = "ERR DISCARD without MULTI";
-    pub const _RESP_EMPTY_STRING_: &str = "$0\r\n\r\n"; // different from resp nil string which is generated by bulk_encode when you pass it an empty string
-    pub const _ERROR_OUT_OF_RANGE_GEOCOORDS_ : &str = "ERR invalid longitude,latitude pair"; 
+    pub const _ERROR_NESTED_MULTI_: &str = "ERR MULTI calls can not be nested";
+    pub const _ERROR_EXECABORT_: &str = "EXECABORT Transaction discarded because of previous errors.";
+    pub const _ERROR_SYNTAX_: &str = "ERR syntax error";
+    pub const _ERROR_WRONGTYPE_: &str = "WRONGTYPE Operation against a key holding the wrong kind of value";
+    pub const _ERROR_OUT_OF_RANGE_GEOCOORDS_ : &str = "ERR invalid longitude,latitude pair";
+    pub const _ERROR_NOPROTO_: &str = "NOPROTO unsupported protocol version";
+    pub const _ERROR_WRONGPASS_: &str = "WRONGPASS invalid username-password pair or user is disabled.";
+    pub const _ERROR_OOM_: &str = "OOM command not allowed when used memory > 'maxmemory'.";
+    pub const _SERVER_VERSION_: &str = "7.2.0";
     pub const _EARTH_RADIUS_: f64 = 6372797.560856;
     // pub const _ERROR_SUB_MODE_ON_: &str = "ERR Can't execute 'set': only (P|S)SUBSCRIBE / (P|S)UNSUBSCRIBE / PING / QUIT / RESET are allowed in this context";
 
     pub const _SUB_MODE_CMDS_: [&str; 6] = ["SUBSCRIBE", "UNSUBSCRIBE", "PSUBSCRIBE", "PUNSUBSCRIBE", "PING", "QUIT"];
 
     // print bytes as string
-    pub fn pbas(buf: &Vec<u8>) {
-        println!("{}", buf.iter().map(|ch| {*ch as char}).collect::<String>());
+    pub fn pbas(buf: &[u8]) {
+        println!("{}", String::from_utf8_lossy(buf));
+    }
+
+    // command arguments are raw bytes, these read them as text where the command expects text(names, options, numbers)
+    pub fn arg_str(arg: &[u8]) -> String {
+        String::from_utf8_lossy(arg).into_owned()
+    }
+
+    pub fn parse_arg<T: FromStr>(arg: &[u8]) -> Option<T> {
+        std::str::from_utf8(arg).ok()?.parse().ok()
+    }
+
+    // largest string SETRANGE and APPEND may build, redis' default proto-max-bulk-len
+    pub const _MAX_STRING_SIZE_: usize = 512 * 1024 * 1024;
+    pub const _ERROR_STRING_TOO_LONG_: &str = "ERR string exceeds maximum allowed size (proto-max-bulk-len)";
+
+    // keys past their expiry that haven't been removed yet count as missing
+    pub fn is_expired(exp_ts: &Option<SystemTime>) -> bool {
+        exp_ts.is_some_and(|ts| ts <= SystemTime::now())
+    }
+
+    // sorted set of a key, expired ones read as missing and keys of another type are a WRONGTYPE error
+    pub fn live_zset<'a>(storage: &'a Entries, key: &[u8]) -> Result<Option<&'a SortedSet>, RespValue> {
+        match storage.get(key).filter(|(_, exp_ts)| !is_expired(exp_ts)) {
+            Some((RDBValue::ZSet(set), _)) => Ok(Some(set)),
+            Some(_) => Err(redis_err(_ERROR_WRONGTYPE_)),
+            None => Ok(None),
+        }
+    }
+
+    pub fn live_zset_mut<'a>(storage: &'a mut Entries, key: &[u8]) -> Result<Option<&'a mut SortedSet>, RespValue> {
+        match storage.get_mut(key).filter(|(_, exp_ts)| !is_expired(exp_ts)) {
+            Some((RDBValue::ZSet(set), _)) => Ok(Some(set)),
+            Some(_) => Err(redis_err(_ERROR_WRONGTYPE_)),
+            None => Ok(None),
+        }
+    }
+
+    // sorted set to add to, a missing or expired key starts out as a new set without expiry
+    pub fn zset_entry<'a>(storage: &'a mut Entries, key: &[u8]) -> Result<&'a mut SortedSet, RespValue> {
+        if storage.get(key).is_none_or(|(_, exp_ts)| is_expired(exp_ts)) {
+            storage.insert(key.to_vec(), (RDBValue::ZSet(SortedSet::default()), None));
+        }
+        match storage.get_mut(key) {
+            Some((RDBValue::ZSet(set), _)) => Ok(set),
+            _ => Err(redis_err(_ERROR_WRONGTYPE_)),
+        }
+    }
+
+    // redis glob matching: `*` any run of bytes, `?` any one byte, `[abc]`, `[a-z]` and `[^x]` classes and `\` to
+    // escape the next byte. A `*` only ever needs to be retried from the last one seen, which keeps this linear-ish
+    pub fn string_match(pattern: &[u8], string: &[u8], nocase: bool) -> bool {
+        let eq = |a: u8, b: u8| if nocase { a.eq_ignore_ascii_case(&b) } else { a == b };
+        let (mut p, mut s) = (0, 0);
+        // position of the last `*` in the pattern and of the byte of the string it is tried at
+        let mut star: Option<(usize, usize)> = None;
+
+        while s < string.len() {
+            let step = match pattern.get(p) {
+                Some(b'*') => {
+                    star = Some((p, s));
+                    p += 1;
+                    continue;
+                },
+                Some(b'?') => Some(p + 1),
+                Some(b'[') => match_class(pattern, p, string[s], nocase),
+                Some(b'\\') if p + 1 < pattern.len() => eq(pattern[p + 1], string[s]).then_some(p + 2),
+                Some(&c) => eq(c, string[s]).then_some(p + 1),
+                None => None,
+            };
+            match (step, star) {
+                (Some(next), _) => {
+                    p = next;
+                    s += 1;
+                },
+                // let the last `*` swallow one more byte and go on from there
+                (None, Some((star_p, star_s))) => {
+                    star = Some((star_p, star_s + 1));
+                    p = star_p + 1;
+                    s = star_s + 1;
+                },
+                (None, None) => return false,
+            }
+        }
+        pattern[p..].iter().all(|&c| c == b'*')
+    }
+
+    // matches a byte against the class starting at pattern[start] == '[', returns where the pattern goes on after
+    // it. Like in redis a class missing its `]` runs to the end of the pattern
+    fn match_class(pattern: &[u8], start: usize, c: u8, nocase: bool) -> Option<usize> {
+        let fold = |b: u8| if nocase { b.to_ascii_lowercase() } else { b };
+        let c = fold(c);
+        let mut p = start + 1;
+        let negate = pattern.get(p) == Some(&b'^');
+        if negate {
+            p += 1;
+        }
+
+        let mut matched = false;
+        while p < pattern.len() && pattern[p] != b']' {
+            if pattern[p] == b'\\' && p + 1 < pattern.len() {
+                matched |= fold(pattern[p + 1]) == c;
+                p += 2;
+            } else if p + 2 < pattern.len() && pattern[p + 1] == b'-' {
+                let (a, b) = (fold(pattern[p]), fold(pattern[p + 2]));
+                matched |= (a.min(b)..=a.max(b)).contains(&c);
+                p += 3;
+            } else {
+                matched |= fold(pattern[p]) == c;
+                p += 1;
+            }
+        }
+
+        (matched != negate).then_some((p + 1).min(pattern.len()))
+    }
+
+    // the place of a key in a SCAN: a hash that doesn't change while the server runs, unlike the positions of keys in
+    // a HashMap which move around when it grows
+    pub fn scan_hash(key: &[u8]) -> u64 {
+        let mut hasher = std::hash::DefaultHasher::new();
+        std::hash::Hasher::write(&mut hasher, key);
+        std::hash::Hasher::finish(&hasher)
+    }
+
+    // keys in the order SCAN walks them, by scan_hash, so a step starts right at its cursor and only looks at the
+    // keys it returns
+    #[derive(Debug, Clone, Default)]
+    pub struct ScanIndex(BTreeSet<(u64, Vec<u8>)>);
+
+    impl ScanIndex {
+        pub fn insert(&mut self, key: &[u8]) {
+            self.0.insert((scan_hash(key), key.to_vec()));
+        }
+
+        pub fn remove(&mut self, key: &[u8]) {
+            self.0.remove(&(scan_hash(key), key.to_vec()));
+        }
+
+        // one step of a stateless cursor over the keys. The cursor is the hash to go on from so every key that is
+        // there for the whole scan gets returned, whatever happens to the others in the meantime. Returns the next
+        // cursor, 0 once done, and count keys, more if the last ones share a hash so the next step can start past it
+        pub fn step(&self, cursor: u64, count: usize) -> (u64, Vec<&[u8]>) {
+            let mut batch = vec![];
+            let mut last = None;
+            for (hash, key) in self.0.range((cursor, vec![])..) {
+                if batch.len() >= count && last != Some(*hash) {
+                    return (*hash, batch);
+                }
+                batch.push(key.as_slice());
+                last = Some(*hash);
+            }
+            (0, batch)
+        }
+    }
+
+    // the [MATCH pattern] [COUNT count] [TYPE type] options of SCAN and its per-type siblings
+    #[derive(Debug)]
+    pub struct ScanOptions {
+        pub cursor: u64,
+        pub pattern: Option<Vec<u8>>,
+        pub count: usize,
+        pub type_name: Option<String>,
+    }
+
+    // parses `cursor [options]`, TYPE is only accepted when with_type is set
+    pub fn parse_scan_options(args: &[Vec<u8>], with_type: bool) -> Result<ScanOptions, RespValue> {
+        let Some(cursor) = parse_arg::<u64>(&args[0]) else {
+            return Err(redis_err("ERR invalid cursor"));
+        };
+        let mut options = ScanOptions { cursor, pattern: None, count: 10, type_name: None };
+
+        for option in args[1..].chunks(2) {
+            let [name, value] = option else {
+                return Err(redis_err(_ERROR_SYNTAX_));
+            };
+            match arg_str(name).to_uppercase().as_str() {
+                "MATCH" => options.pattern = Some(value.clone()),
+                "COUNT" => match parse_arg::<i64>(value) {
+                    Some(count) if count >= 1 => options.count = count as usize,
+                    Some(_) => return Err(redis_err(_ERROR_SYNTAX_)),
+                    None => return Err(redis_err(_ERROR_INCR_NOT_AN_INT_)),
+                },
+                "TYPE" if with_type => options.type_name = Some(arg_str(value).to_lowercase()),
+                _ => return Err(redis_err(_ERROR_SYNTAX_)),
+            }
+        }
+        Ok(options)
     }
 
     pub fn _error_sub_mode_on_msg_(cmd: &str) -> String {
         format!("ERR Can't execute '{}': only (P|S)SUBSCRIBE / (P|S)UNSUBSCRIBE / PING / QUIT / RESET are allowed in this context", cmd.to_uppercase())
     }
 
+    pub fn _error_invalid_expire_msg_(cmd: &str) -> String {
+        format!("ERR invalid expire time in '{}' command", cmd)
+    }
+
+    // expiry given to an EX, PX, EXAT or PXAT option, relative ones count from now
+    pub fn parse_expiry(unit: &str, arg: &[u8], cmd: &str) -> Result<SystemTime, RespValue> {
+        let n = match parse_arg::<i64>(arg) {
+            Some(n) => n,
+            None => return Err(redis_err(_ERROR_INCR_NOT_AN_INT_)),
+        };
+        let millis = match unit {
+            "EX" | "EXAT" => n.checked_mul(1000),
+            _ => Some(n),
+        };
+        let millis = match millis {
+            Some(millis) if n > 0 => millis as u64,
+            _ => return Err(redis_err(&_error_invalid_expire_msg_(cmd))),
+        };
+        let expiry = if unit.ends_with("AT") {
+            UNIX_EPOCH.checked_add(Duration::from_millis(millis))
+        } else {
+            SystemTime::now().checked_add(Duration::from_millis(millis))
+        };
+        expiry.ok_or_else(|| redis_err(&_error_invalid_expire_msg_(cmd)))
+    }
+
+    pub fn unix_millis(ts: SystemTime) -> u128 {
+        ts.duration_since(UNIX_EPOCH).unwrap_or_default().as_millis()
+    }
+
     // pub fn geocoords_sanity_check()
 
     pub fn cmd_sanity_check(cmd_name: &str, client_mode: bool) -> bool {
-        return !client_mode || _SUB_MODE_CMDS_.iter().any(|&mode| mode == cmd_name.to_uppercase());
-    }
-
-    pub fn encode_array(vals: &Vec<String>, raw: bool) -> String {
-        // null array is not same as empty array, for now its better to hardcode null array wherever it is is the required response 
-        // if vals.is_empty() {
-        //     return format!("*-1\r\n");
-        // }
-         
-        let mut output = format!("*{}\r\n", vals.len());
-        for v in vals {
-            // if v == "" {
-            //     continue;
-            // }
-            if raw {    // if arguments arent already encoded, encode them as string
-                output += &encode_bulk(v);
-            } else {
-                output += v;
+        !client_mode || _SUB_MODE_CMDS_.iter().any(|&mode| mode == cmd_name.to_uppercase())
+    }
+
+    // ---RESP VALUES---
+    // handlers build their replies as RespValue, the connection serializes them into its output buffer in the
+    // protocol negotiated by the client(via HELLO), RESP3 only types fall back to the closest RESP2 representation
+
+    #[derive(Debug, Clone, PartialEq)]
+    pub enum RespValue {
+        Simple(String),
+        Error(String),
+        Integer(i64),
+        Bulk(Vec<u8>),
+        // null bulk string in RESP2, '_' in RESP3
+        Null,
+        Array(Vec<RespValue>),
+        // null array in RESP2, '_' in RESP3
+        NullArray,
+        // bulk string in RESP2
+        Double(f64),
+        // 1 or 0 in RESP2
+        Boolean(bool),
+        // digits as a bulk string in RESP2
+        BigNumber(String),
+        // (3 letter format hint like "txt" or "mkd", contents), bulk string in RESP2
+        Verbatim(String, Vec<u8>),
+        // flat array of keys and values in RESP2
+        Map(Vec<(RespValue, RespValue)>),
+        Set(Vec<RespValue>),
+        // out of band data(pub/sub messages), plain array in RESP2
+        Push(Vec<RespValue>),
+        // bytes that are already encoded and go out as they are(the rdb payload of a full resync)
+        Raw(Vec<u8>),
+    }
+
+    impl RespValue {
+        pub fn bulk<T: AsRef<[u8]>>(data: T) -> RespValue {
+            RespValue::Bulk(data.as_ref().to_vec())
+        }
+
+        pub fn simple(s: &str) -> RespValue {
+            RespValue::Simple(s.to_owned())
+        }
+
+        // array of bulk strings
+        pub fn bulk_array<T: AsRef<[u8]>>(vals: &[T]) -> RespValue {
+            RespValue::Array(vals.iter().map(RespValue::bulk).collect())
+        }
+
+        pub fn is_error(&self) -> bool {
+            matches!(self, RespValue::Error(_))
+        }
+
+        fn write_aggregate(buf: &mut Vec<u8>, kind: u8, vals: &[RespValue], resp3: bool) {
+            buf.push(kind);
+            buf.extend_from_slice(vals.len().to_string().as_bytes());
+            buf.extend_from_slice(b"\r\n");
+            for v in vals {
+                v.write_to(buf, resp3);
             }
         }
 
-        output
-    }
+        fn write_bulk(buf: &mut Vec<u8>, data: &[u8]) {
+            buf.push(b'$');
+            buf.extend_from_slice(data.len().to_string().as_bytes());
+            buf.extend_from_slice(b"\r\n");
+            buf.extend_from_slice(data);
+            buf.extend_from_slice(b"\r\n");
+        }
 
-    pub fn encode_bulk(s: &str) -> String {
-        if s.is_empty() {
-            // return NULL string
-            return format!("$-1\r\n");
+        fn write_line(buf: &mut Vec<u8>, kind: u8, line: &[u8]) {
+            buf.push(kind);
+            buf.extend_from_slice(line);
+            buf.extend_from_slice(b"\r\n");
         }
 
-        let data = String::from(s);
-        
-        format!("${}\r\n{}\r\n", data.len(), data)
-    }
-
-    pub fn encode_int(n: usize) -> String {
-        format!(":{}\r\n", n)
-    }
-
-    // parse a single command
-    pub fn parse(mut ptr: usize, buf: &[u8]) -> Vec<(usize, Vec<String>)> {
-        // print!("{:?}", buf);
-        // for b in buf {
-        //     print!("{} ", *b as char);
-        // }
-        // println!("");
-        // print!("parsing: ");
-        // pbas(&buf.to_vec());
-        let mut cmds: Vec<(usize, Vec<String>)>  = vec![];
-        while ptr < buf.len() {
-            // let mut bytes_parsed = 0;   // represents bytes of (valid) commands 
-            match buf[ptr] {
-                b'$' => {
-                    let (new_ptr, s) = parse_string(ptr, buf);
-                    // bytes_parsed += new_ptr - ptr;
-                    cmds.push((new_ptr -ptr, vec![s]));
-                    ptr = new_ptr;
+        // serialize into buf
+        pub fn write_to(&self, buf: &mut Vec<u8>, resp3: bool) {
+            match self {
+                // simple strings and errors can't span lines
+                RespValue::Simple(s) => RespValue::write_line(buf, b'+', s.replace(['\r', '\n'], " ").as_bytes()),
+                RespValue::Error(e) => RespValue::write_line(buf, b'-', e.replace(['\r', '\n'], " ").as_bytes()),
+                RespValue::Integer(n) => RespValue::write_line(buf, b':', n.to_string().as_bytes()),
+                RespValue::Bulk(data) => RespValue::write_bulk(buf, data),
+                RespValue::Null if resp3 => buf.extend_from_slice(b"_\r\n"),
+                RespValue::Null => buf.extend_from_slice(b"$-1\r\n"),
+                RespValue::NullArray if resp3 => buf.extend_from_slice(b"_\r\n"),
+                RespValue::NullArray => buf.extend_from_slice(b"*-1\r\n"),
+                RespValue::Array(vals) => RespValue::write_aggregate(buf, b'*', vals, resp3),
+                RespValue::Double(f) => {
+                    let repr = if f.is_nan() {
+                        "nan".to_owned()
+                    } else if f.is_infinite() {
+                        if *f > 0.0 { "inf".to_owned() } else { "-inf".to_owned() }
+                    } else {
+                        f.to_string()
+                    };
+                    if resp3 {
+                        RespValue::write_line(buf, b',', repr.as_bytes());
+                    } else {
+                        RespValue::write_bulk(buf, repr.as_bytes());
+                    }
                 },
-                b'*' => {
-                    let (new_ptr, v) = parse_array(ptr, buf);
-                    // bytes_parsed += new_ptr - ptr;
-                    cmds.push((new_ptr -ptr, v));
-                    ptr = new_ptr; 
+                RespValue::Boolean(b) if resp3 => RespValue::write_line(buf, b'#', if *b { b"t" } else { b"f" }),
+                RespValue::Boolean(b) => RespValue::write_line(buf, b':', if *b { b"1" } else { b"0" }),
+                RespValue::BigNumber(digits) if resp3 => RespValue::write_line(buf, b'(', digits.as_bytes()),
+                RespValue::BigNumber(digits) => RespValue::write_bulk(buf, digits.as_bytes()),
+                RespValue::Verbatim(format, data) if resp3 => {
+                    buf.push(b'=');
+                    buf.extend_from_slice((data.len() + 4).to_string().as_bytes());
+                    buf.extend_from_slice(b"\r\n");
+                    buf.extend_from_slice(format.as_bytes());
+                    buf.push(b':');
+                    buf.extend_from_slice(data);
+                    buf.extend_from_slice(b"\r\n");
                 },
-                b'+' => {   // simple strings
-                    // just skip them for now 
-                    let mut new_ptr = ptr;
-                    while buf[new_ptr] != b'\n' {
-                        new_ptr += 1;
+                RespValue::Verbatim(_, data) => RespValue::write_bulk(buf, data),
+                RespValue::Map(pairs) => {
+                    if resp3 {
+                        buf.push(b'%');
+                        buf.extend_from_slice(pairs.len().to_string().as_bytes());
+                    } else {
+                        buf.push(b'*');
+                        buf.extend_from_slice((pairs.len() * 2).to_string().as_bytes());
+                    }
+                    buf.extend_from_slice(b"\r\n");
+                    for (k, v) in pairs {
+                        k.write_to(buf, resp3);
+                        v.write_to(buf, resp3);
                     }
-                    ptr = new_ptr + 1;
-                },
-                0x0 => {
-                    // no enocding/data should start from 0 
-                    // reached the end of the buffer
-                    // since we are passing the whole input buffer after the valid bytes the 0s will be read
-                    // hoping it occurs at the end in some cases where '\r\n' is encountered which wasn't skipped previously
-                    // needs better case handling
-                    // println!("reached the end of the buffer, if some commands remained, you might wanna check this code");
-                    break;
                 },
-                _ => {
-                    // continue;
-                    print!("problem: ");
-                    pbas(&buf[ptr..].to_vec());
-                    unimplemented!("if you are seeing this, you are screwed")
-                }
+                RespValue::Set(vals) => RespValue::write_aggregate(buf, if resp3 { b'~' } else { b'*' }, vals, resp3),
+                RespValue::Push(vals) => RespValue::write_aggregate(buf, if resp3 { b'>' } else { b'*' }, vals, resp3),
+                RespValue::Raw(data) => buf.extend_from_slice(data),
             }
         }
 
-        cmds
+        pub fn to_bytes(&self, resp3: bool) -> Vec<u8> {
+            let mut buf = vec![];
+            self.write_to(&mut buf, resp3);
+            buf
+        }
+    }
+
+    // a command as sent over the wire(replication, handshake), an array of bulk strings
+    pub fn encode_command<T: AsRef<[u8]>>(args: &[T]) -> Vec<u8> {
+        RespValue::bulk_array(args).to_bytes(false)
     }
 
-    // you might have to call this recursively
-    pub fn parse_array(ptr: usize, buf: &[u8]) -> (usize, Vec<String>) {
-        let mut size =0;
-        let mut i = ptr + 1;
-        while buf[i] != DELIM {
-            size = 10 * size + (buf[i] as char).to_digit(10).unwrap() as usize;
-            i += 1;
+    // ---RESP DECODER---
+    // commands are decoded incrementally: bytes read from the socket are appended to FrameDecoder.buf and
+    // every complete frame is taken off the front, a partial frame stays buffered until the rest of it arrives
+
+    pub const _PROTO_MAX_BULK_LEN_: usize = 512 * 1024 * 1024;
+    pub const _PROTO_MAX_MULTIBULK_LEN_: usize = 1024 * 1024;
+    // longest header line(or inline command) we are willing to buffer without seeing its CRLF
+    pub const _PROTO_MAX_LINE_LEN_: usize = 64 * 1024;
+    const _READ_CHUNK_: usize = 16 * 1024;
+
+    // result of trying to decode one frame from the front of a buffer
+    #[derive(Debug, PartialEq)]
+    pub enum FrameStatus {
+        // (bytes consumed, command), command is None for frames that don't carry one(replies like +OK, the rdb transfer)
+        Complete(usize, Option<Vec<Vec<u8>>>),
+        // frame isn't fully buffered yet, holds the total bytes needed before trying again can succeed
+        Incomplete(usize),
+    }
+
+    #[derive(Debug, Default)]
+    pub struct FrameDecoder {
+        pub buf: BytesMut,
+        // parsing is skipped until the buffer holds at least this many bytes
+        need: usize,
+    }
+
+    impl FrameDecoder {
+        pub fn new() -> Self {
+            Self::default()
         }
-        i += SKIP_LEN;
-        // println!("size of array: {}", size);
 
-        let mut result:Vec<String> = vec![];
-        for _ in 0..size {
-            match buf[i] as char {
-                '$' => {
-                    let (new_ptr, out) = parse_string(i, buf);
-                    i = new_ptr;
-                    result.push(out);
-                }
-                _ => {
-                    unimplemented!()
+        // make room for the next socket read, big bulk strings get their whole remaining size reserved at once
+        pub fn reserve(&mut self) {
+            let missing = self.need.saturating_sub(self.buf.len());
+            self.buf.reserve(std::cmp::max(missing, _READ_CHUNK_));
+        }
+
+        // take every complete frame off the buffer, returns (frame size in bytes, command args) pairs
+        pub fn decode(&mut self) -> Result<Vec<Command>, String> {
+            let mut cmds = vec![];
+            while !self.buf.is_empty() && self.buf.len() >= self.need {
+                match parse_frame(&self.buf)? {
+                    FrameStatus::Complete(consumed, cmd) => {
+                        self.buf.advance(consumed);
+                        self.need = 0;
+                        if let Some(cmd) = cmd {
+                            cmds.push((consumed, cmd));
+                        }
+                    },
+                    FrameStatus::Incomplete(need) => {
+                        self.need = need;
+                        break;
+                    }
                 }
             }
-        }
 
-        (i, result)
+            Ok(cmds)
+        }
     }
 
-    pub fn parse_string(ptr: usize, buf: &[u8]) -> (usize, String) {
-        let mut size =0 as usize;
-        let mut i = ptr + 1; // ptr points to string identifier: '$'
-        while buf[i] != DELIM {
-            size = 10 * size + (buf[i] as char).to_digit(10).unwrap() as usize;
-            i += 1;
+    // returns the line starting at ptr(without CRLF) and the index right after its CRLF, None if the CRLF isn't buffered yet
+    fn read_line(ptr: usize, buf: &[u8]) -> Result<Option<(&[u8], usize)>, String> {
+        match buf[ptr..].windows(2).position(|w| w == b"\r\n") {
+            Some(end) => Ok(Some((&buf[ptr..ptr + end], ptr + end + SKIP_LEN))),
+            None if buf.len() - ptr > _PROTO_MAX_LINE_LEN_ => Err("Protocol error: too big mbulk count string".to_owned()),
+            None => Ok(None),
         }
-        i += SKIP_LEN; // skip both \r and \n
+    }
 
-        let mut result = String::new();
+    fn parse_len(line: &[u8]) -> Option<i64> {
+        std::str::from_utf8(line).ok()?.parse().ok()
+    }
+
+    // split an inline command into arguments with the same quoting rules as redis-cli(sdssplitargs):
+    // "double quotes" understand \n \r \t \b \a and \xHH escapes, 'single quotes' only \'
+    // a closing quote has to be followed by a space, returns None for unbalanced quotes
+    pub fn split_args(line: &[u8]) -> Option<Vec<Vec<u8>>> {
+        // like redis a NUL byte ends the line, and isspace() also counts vertical tabs
+        let line = &line[..line.iter().position(|&ch| ch == 0).unwrap_or(line.len())];
+        let is_space = |ch: u8| ch.is_ascii_whitespace() || ch == 0x0b;
+        let mut args = vec![];
+        let mut i = 0;
+
+        loop {
+            while i < line.len() && is_space(line[i]) {
+                i += 1;
+            }
+            if i == line.len() {
+                return Some(args);
+            }
 
-        for j in 0..size {
-            result.push(buf[j as usize + i] as char);
+            let mut current = vec![];
+            let mut in_double = false;
+            let mut in_single = false;
+            loop {
+                let ch = line.get(i).copied();
+                if in_double {
+                    match ch {
+                        None => return None,
+                        Some(b'\\') if i + 3 < line.len() && line[i + 1] == b'x'
+                            && line[i + 2].is_ascii_hexdigit() && line[i + 3].is_ascii_hexdigit() => {
+                            current.push(u8::from_str_radix(std::str::from_utf8(&line[i + 2..i + 4]).unwrap(), 16).unwrap());
+                            i += 3;
+                        },
+                        Some(b'\\') if i + 1 < line.len() => {
+                            i += 1;
+                            current.push(match line[i] {
+                                b'n' => b'\n',
+                                b'r' => b'\r',
+                                b't' => b'\t',
+                                b'b' => 0x08,
+                                b'a' => 0x07,
+                                other => other,
+                            });
+                        },
+                        Some(b'"') => {
+                            // closing quote must be followed by a space or nothing at all
+                            if i + 1 < line.len() && !is_space(line[i + 1]) {
+                                return None;
+                            }
+                            i += 1;
+                            break;
+                        },
+                        Some(other) => current.push(other),
+                    }
+                } else if in_single {
+                    match ch {
+                        None => return None,
+                        Some(b'\\') if i + 1 < line.len() && line[i + 1] == b'\'' => {
+                            i += 1;
+                            current.push(b'\'');
+                        },
+                        Some(b'\'') => {
+                            if i + 1 < line.len() && !is_space(line[i + 1]) {
+                                return None;
+                            }
+                            i += 1;
+                            break;
+                        },
+                        Some(other) => current.push(other),
+                    }
+                } else {
+                    match ch {
+                        None | Some(b' ' | b'\n' | b'\r' | b'\t') => break,
+                        Some(b'"') => in_double = true,
+                        Some(b'\'') => in_single = true,
+                        Some(other) => current.push(other),
+                    }
+                }
+                i += 1;
+            }
+            args.push(current);
         }
+    }
 
-        i += size;
-        if buf[i] == b'\r' {  // if clrf exists, in cases like file transmission it doesnt exist
-            i += SKIP_LEN;
+    // try to decode a single frame from the start of buf
+    pub fn parse_frame(buf: &[u8]) -> Result<FrameStatus, String> {
+        if buf.is_empty() {
+            return Ok(FrameStatus::Incomplete(1));
         }
 
-        // println!("string parsed: {}", result);
-        return (i, result); 
-    }
+        match buf[0] {
+            b'*' => {
+                let (line, mut i) = match read_line(1, buf)? {
+                    Some(res) => res,
+                    None => return Ok(FrameStatus::Incomplete(buf.len() + 1)),
+                };
+                let size = match parse_len(line) {
+                    Some(size) if size <= _PROTO_MAX_MULTIBULK_LEN_ as i64 => size,
+                    _ => return Err("Protocol error: invalid multibulk length".to_owned()),
+                };
+                // empty and null arrays carry no command
+                if size <= 0 {
+                    return Ok(FrameStatus::Complete(i, None));
+                }
 
-    pub fn array_append(array: &str, new_val: &str) -> String {
-        let mut new_array = String::from(array);
+                let mut result = Vec::with_capacity(std::cmp::min(size as usize, 1024));
+                for _ in 0..size {
+                    if i >= buf.len() {
+                        return Ok(FrameStatus::Incomplete(i + 1));
+                    }
+                    if buf[i] != b'$' {
+                        return Err(format!("Protocol error: expected '$', got '{}'", buf[i] as char));
+                    }
+                    let (line, start) = match read_line(i + 1, buf)? {
+                        Some(res) => res,
+                        None => return Ok(FrameStatus::Incomplete(buf.len() + 1)),
+                    };
+                    let len = match parse_len(line) {
+                        Some(len) if 0 <= len && len <= _PROTO_MAX_BULK_LEN_ as i64 => len as usize,
+                        _ => return Err("Protocol error: invalid bulk length".to_owned()),
+                    };
+                    let end = start + len;
+                    if buf.len() < end + SKIP_LEN {
+                        return Ok(FrameStatus::Incomplete(end + SKIP_LEN));
+                    }
+                    if &buf[end..end + SKIP_LEN] != b"\r\n" {
+                        return Err("Protocol error: expected CRLF after bulk string".to_owned());
+                    }
+                    result.push(buf[start..end].to_vec());
+                    i = end + SKIP_LEN;
+                }
 
-        new_array.push_str(new_val);
+                Ok(FrameStatus::Complete(i, Some(result)))
+            },
+            b'$' => {
+                // a top level bulk string is the rdb file sent by the master during the handshake,
+                // unlike regular bulk strings it isn't terminated by CRLF
+                let (line, start) = match read_line(1, buf)? {
+                    Some(res) => res,
+                    None => return Ok(FrameStatus::Incomplete(buf.len() + 1)),
+                };
+                let len = match parse_len(line) {
+                    Some(len) if 0 <= len && len <= _PROTO_MAX_BULK_LEN_ as i64 => len as usize,
+                    _ => return Err("Protocol error: invalid bulk length".to_owned()),
+                };
+                if buf.len() < start + len {
+                    return Ok(FrameStatus::Incomplete(start + len));
+                }
 
-        new_array
+                Ok(FrameStatus::Complete(start + len, None))
+            },
+            b'+' | b'-' | b':' => {
+                // replies(like the ones sent by the master during the handshake), nothing to execute
+                match read_line(1, buf)? {
+                    Some((_, i)) => Ok(FrameStatus::Complete(i, None)),
+                    None => Ok(FrameStatus::Incomplete(buf.len() + 1)),
+                }
+            },
+            b'\r' | b'\n' => {
+                // stray line endings, e.g. an optional CRLF after the rdb transfer
+                Ok(FrameStatus::Complete(1, None))
+            },
+            _ => {
+                // anything else is an inline command, a plain line of space separated arguments(telnet, nc)
+                let end = match buf.iter().position(|&ch| ch == b'\n') {
+                    Some(end) => end,
+                    None if buf.len() > _PROTO_MAX_LINE_LEN_ => return Err("Protocol error: too big inline request".to_owned()),
+                    None => return Ok(FrameStatus::Incomplete(buf.len() + 1)),
+                };
+                let line = buf[..end].strip_suffix(b"\r").unwrap_or(&buf[..end]);
+                match split_args(line) {
+                    Some(args) if args.is_empty() => Ok(FrameStatus::Complete(end + 1, None)),
+                    Some(args) => Ok(FrameStatus::Complete(end + 1, Some(args))),
+                    None => Err("Protocol error: unbalanced quotes in request".to_owned()),
+                }
+            }
+        }
     }
 
-    pub fn redis_err(msg: &str) -> String {
-        format!("-{}\r\n", msg)
-    } 
+    // decode one server reply(any RESP2/RESP3 type) from the start of buf, the client side counterpart of parse_frame
+    // returns (bytes consumed, reply), None while the reply isn't fully buffered
+    pub fn parse_reply(buf: &[u8]) -> Result<Option<(usize, RespValue)>, String> {
+        parse_reply_at(buf, 0)
+    }
 
-    pub fn encode_simple(vals: &Vec<&str>) -> String {
-        let mut result = vec![];
-        
-        for &v in vals {
-            result.push(v);
+    fn parse_reply_at(buf: &[u8], ptr: usize) -> Result<Option<(usize, RespValue)>, String> {
+        if ptr >= buf.len() {
+            return Ok(None);
         }
+        let (line, next) = match read_line(ptr + 1, buf)? {
+            Some(res) => res,
+            None => return Ok(None),
+        };
+        let text = || String::from_utf8_lossy(line).to_string();
+        let len = || parse_len(line).ok_or_else(|| format!("Protocol error: invalid length '{}'", String::from_utf8_lossy(line)));
+
+        let value = match buf[ptr] {
+            b'+' => RespValue::Simple(text()),
+            b'-' => RespValue::Error(text()),
+            b':' => RespValue::Integer(len()?),
+            b'_' => RespValue::Null,
+            b'#' => RespValue::Boolean(line == b"t"),
+            b',' => RespValue::Double(match line {
+                b"inf" => f64::INFINITY,
+                b"-inf" => f64::NEG_INFINITY,
+                _ => text().parse().map_err(|_| format!("Protocol error: invalid double '{}'", text()))?,
+            }),
+            b'(' => RespValue::BigNumber(text()),
+            b'$' | b'=' | b'!' => {
+                let len = len()?;
+                if len < 0 {
+                    return Ok(Some((next, RespValue::Null)));
+                }
+                let end = next + len as usize;
+                if buf.len() < end + SKIP_LEN {
+                    return Ok(None);
+                }
+                let data = buf[next..end].to_vec();
+                let value = match buf[ptr] {
+                    // verbatim strings start with their 3 letter format and a colon
+                    b'=' if data.len() >= 4 => RespValue::Verbatim(String::from_utf8_lossy(&data[..3]).to_string(), data[4..].to_vec()),
+                    b'!' => RespValue::Error(String::from_utf8_lossy(&data).to_string()),
+                    _ => RespValue::Bulk(data),
+                };
+                return Ok(Some((end + SKIP_LEN, value)));
+            },
+            kind @ (b'*' | b'~' | b'>' | b'%' | b'|') => {
+                let len = len()?;
+                if len < 0 {
+                    return Ok(Some((next, RespValue::NullArray)));
+                }
+                // maps and attributes hold key value pairs
+                let count = if kind == b'%' || kind == b'|' { len * 2 } else { len };
+                let mut i = next;
+                let mut vals = Vec::with_capacity(std::cmp::min(count as usize, 1024));
+                for _ in 0..count {
+                    match parse_reply_at(buf, i)? {
+                        Some((end, value)) => {
+                            vals.push(value);
+                            i = end;
+                        },
+                        None => return Ok(None),
+                    }
+                }
+                let value = match kind {
+                    b'~' => RespValue::Set(vals),
+                    b'>' => RespValue::Push(vals),
+                    b'%' | b'|' => {
+                        let mut pairs = vec![];
+                        let mut vals = vals.into_iter();
+                        while let (Some(k), Some(v)) = (vals.next(), vals.next()) {
+                            pairs.push((k, v));
+                        }
+                        RespValue::Map(pairs)
+                    },
+                    _ => RespValue::Array(vals),
+                };
+                // attributes only annotate the reply that follows them
+                if kind == b'|' {
+                    return parse_reply_at(buf, i);
+                }
+                return Ok(Some((i, value)));
+            },
+            other => return Err(format!("Protocol error: unexpected reply type '{}'", other as char)),
+        };
+
+        Ok(Some((next, value)))
+    }
+
+    pub fn array_append(array: &[u8], new_val: &[u8]) -> Vec<u8> {
+        let mut new_array = array.to_vec();
 
-        let mut s = result.join(" ");
-        s.push_str("\r\n");
-        s = "+".to_owned() + &s;
+        new_array.extend_from_slice(new_val);
+
+        new_array
+    }
 
-        s
+    pub fn redis_err(msg: &str) -> RespValue {
+        RespValue::Error(msg.to_owned())
     }
 
     // returns +OK\r\n
-    pub fn response_ok() -> String {
-        String::from("+OK\r\n")
+    pub fn response_ok() -> RespValue {
+        RespValue::simple("OK")
     }
 
     pub async fn connect_to_master(addr: &str, socket: &str) -> TcpStream {
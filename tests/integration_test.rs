@@ -1,10 +1,17 @@
+// test_ping bails out with assert!(false) when the server can't be reached
+#![allow(clippy::assertions_on_constants)]
+
 use ::codecrafters_redis::redis_cli;
-use codecrafters_redis::utils::utils::{geo_decode, geo_encode};
+use codecrafters_redis::cli::cli::format_reply;
+use codecrafters_redis::client::client::{Client, ClientError, Message, Pipeline, Transaction};
+use codecrafters_redis::utils::utils::{geo_decode, geo_encode, RespValue};
+use std::collections::HashSet;
 use std::fs::File;
 use std::net::TcpStream;
 use std::io::{BufReader, Read, Write};
 use std::process::exit;
 use std::thread;
+use std::time::Duration;
 use serde::Deserialize;
 
 const SERVER_PORT: &str = "6380";
@@ -18,6 +25,63 @@ pub struct Test {
 }
 
 
+// start a server on the given port and wait until it accepts connections
+fn start_server(port: &str) -> TcpStream {
+    start_server_with(port, &[])
+}
+
+// same as start_server but with extra command line arguments
+fn start_server_with(port: &str, extra_args: &[&str]) -> TcpStream {
+    let mut args = vec!["redis-cli".to_owned(), "--port".to_owned(), port.to_owned()];
+    args.extend(extra_args.iter().map(|arg| arg.to_string()));
+    let _ = thread::spawn(|| { redis_cli(args.into_iter()); });
+
+    for _ in 0..50 {
+        if let Ok(stream) = TcpStream::connect(format!("localhost:{}", port)) {
+            stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+            return stream;
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+    panic!("server on port {} did not come up", port);
+}
+
+// send a raw command and check the exact bytes of the reply
+fn send_and_expect<I: AsRef<[u8]>, E: AsRef<[u8]>>(stream: &mut TcpStream, input: I, expected: E) {
+    let (input, expected) = (input.as_ref(), expected.as_ref());
+    stream.write_all(input).unwrap();
+
+    let mut reply = vec![];
+    let mut buffer = vec![0; 1024];
+    while reply.len() < expected.len() {
+        let bytes_read = stream.read(&mut buffer).unwrap();
+        if bytes_read == 0 {
+            break;
+        }
+        reply.extend_from_slice(&buffer[..bytes_read]);
+    }
+
+    assert!(reply == expected, "input: {:?}\nexpected: {:?}\n     got: {:?}",
+        String::from_utf8_lossy(input), String::from_utf8_lossy(expected), String::from_utf8_lossy(&reply));
+}
+
+// for replies whose size isn't known upfront, read until the reply ends with the given suffix
+fn send_and_read_until(stream: &mut TcpStream, input: &str, suffix: &str) -> String {
+    stream.write_all(input.as_bytes()).unwrap();
+
+    let mut reply = vec![];
+    let mut buffer = vec![0; 1024];
+    while !reply.ends_with(suffix.as_bytes()) {
+        let bytes_read = stream.read(&mut buffer).unwrap();
+        if bytes_read == 0 {
+            break;
+        }
+        reply.extend_from_slice(&buffer[..bytes_read]);
+    }
+
+    String::from_utf8_lossy(&reply).to_string()
+}
+
 #[test]
 fn test_ping() {
     // define args of this test
@@ -142,4 +206,1156 @@ fn test_geo_encoding() {
         let status = if success { "✅" } else { "❌" };
         println!("{}: {} ({})", test_case.name, actual_score, status);
     }
-}
\ No newline at end of file
+}
+
+#[test]
+fn test_resp3_hello() {
+    let mut stream = start_server("6381");
+
+    send_and_expect(&mut stream, "*4\r\n$4\r\nZADD\r\n$1\r\nz\r\n$3\r\n1.5\r\n$1\r\na\r\n", ":1\r\n");
+    // RESP2 until negotiated
+    send_and_expect(&mut stream, "*3\r\n$6\r\nZSCORE\r\n$1\r\nz\r\n$1\r\na\r\n", "$3\r\n1.5\r\n");
+    send_and_expect(&mut stream, "*2\r\n$5\r\nHELLO\r\n$1\r\n4\r\n", "-NOPROTO unsupported protocol version\r\n");
+
+    let reply = send_and_read_until(&mut stream, "*2\r\n$5\r\nHELLO\r\n$1\r\n3\r\n", "$7\r\nmodules\r\n*0\r\n");
+    assert!(reply.starts_with("%7\r\n$6\r\nserver\r\n$5\r\nredis\r\n$7\r\nversion\r\n$5\r\n7.2.0\r\n$5\r\nproto\r\n:3\r\n"));
+
+    send_and_expect(&mut stream, "*3\r\n$6\r\nZSCORE\r\n$1\r\nz\r\n$1\r\na\r\n", ",1.5\r\n");
+    send_and_expect(&mut stream, "*3\r\n$6\r\nZSCORE\r\n$1\r\nz\r\n$1\r\nb\r\n", "_\r\n");
+    send_and_expect(&mut stream, "*2\r\n$3\r\nGET\r\n$7\r\nmissing\r\n", "_\r\n");
+}
+
+#[test]
+fn test_streaming_decoder() {
+    let mut stream = start_server("6382");
+
+    // a value bigger than a single read, split across several writes
+    let value = "x".repeat(100_000);
+    let cmd = format!("*3\r\n$3\r\nSET\r\n$3\r\nbig\r\n${}\r\n{}\r\n", value.len(), value);
+    for chunk in cmd.as_bytes().chunks(7_000) {
+        stream.write_all(chunk).unwrap();
+        thread::sleep(Duration::from_millis(5));
+    }
+    send_and_expect(&mut stream, "", "+OK\r\n");
+    send_and_expect(&mut stream, "*2\r\n$3\r\nGET\r\n$3\r\nbig\r\n", format!("${}\r\n{}\r\n", value.len(), value));
+
+    // a pipeline of many commands in a single write
+    let pipeline = "*2\r\n$4\r\nINCR\r\n$7\r\ncounter\r\n".repeat(2000);
+    let expected: String = (1..=2000).map(|i| format!(":{}\r\n", i)).collect();
+    send_and_expect(&mut stream, &pipeline, &expected);
+
+    // a pipelined transaction
+    send_and_expect(&mut stream,
+        "*1\r\n$5\r\nMULTI\r\n*2\r\n$4\r\nINCR\r\n$7\r\ncounter\r\n*2\r\n$4\r\nINCR\r\n$7\r\ncounter\r\n*1\r\n$4\r\nEXEC\r\n",
+        "+OK\r\n+QUEUED\r\n+QUEUED\r\n*2\r\n:2001\r\n:2002\r\n");
+
+    // malformed input gets an error instead of crashing the connection task
+    send_and_expect(&mut stream, "*1\r\n$x\r\n", "-ERR Protocol error: invalid bulk length\r\n");
+}
+
+
+#[test]
+fn test_binary_safe_values() {
+    let dir = std::env::temp_dir().join("rusty-redis-binary-test");
+    let _ = std::fs::remove_dir_all(&dir);
+    let dir = dir.to_str().unwrap().to_owned();
+    let mut stream = start_server_with("6383", &["--dir", &dir, "--dbfilename", "dump.rdb"]);
+
+    // every byte value, including CR/LF and invalid utf-8, in both the key and the value
+    let key: Vec<u8> = [b"key\r\n".as_slice(), &[0xff, 0x00, 0xfe]].concat();
+    let value: Vec<u8> = (0..=255).collect();
+    let bulk = |data: &[u8]| [format!("${}\r\n", data.len()).as_bytes(), data, b"\r\n"].concat();
+
+    let set = [b"*3\r\n$3\r\nSET\r\n".to_vec(), bulk(&key), bulk(&value)].concat();
+    let get = [b"*2\r\n$3\r\nGET\r\n".to_vec(), bulk(&key)].concat();
+    send_and_expect(&mut stream, &set, "+OK\r\n");
+    send_and_expect(&mut stream, &get, bulk(&value));
+
+    let rpush = [b"*3\r\n$5\r\nRPUSH\r\n".to_vec(), bulk(b"list"), bulk(&value)].concat();
+    send_and_expect(&mut stream, &rpush, ":1\r\n");
+    send_and_expect(&mut stream, "*4\r\n$6\r\nLRANGE\r\n$4\r\nlist\r\n$1\r\n0\r\n$2\r\n-1\r\n",
+        [b"*1\r\n".to_vec(), bulk(&value)].concat());
+
+    // the same bytes come back after a round trip through the rdb file
+    send_and_expect(&mut stream, "*1\r\n$4\r\nSAVE\r\n", "+OK\r\n");
+    let mut restarted = start_server_with("6384", &["--dir", &dir, "--dbfilename", "dump.rdb"]);
+    send_and_expect(&mut restarted, &get, bulk(&value));
+    send_and_expect(&mut restarted, "*4\r\n$6\r\nLRANGE\r\n$4\r\nlist\r\n$1\r\n0\r\n$2\r\n-1\r\n",
+        [b"*1\r\n".to_vec(), bulk(&value)].concat());
+}
+
+#[test]
+fn test_inline_commands() {
+    let mut stream = start_server("6385");
+
+    send_and_expect(&mut stream, "PING\r\n", "+PONG\r\n");
+    // nc sends bare LF line endings, blank lines are ignored
+    send_and_expect(&mut stream, "\r\n  \nECHO hi\n", "$2\r\nhi\r\n");
+    send_and_expect(&mut stream, "SET foo \"hello world\"\r\nGET foo\r\n", "+OK\r\n$11\r\nhello world\r\n");
+
+    // escapes inside double quotes, single quotes only unescape \'
+    send_and_expect(&mut stream, "SET esc \"\\x41\\tb\\\"\\n\"\r\nGET esc\r\n", "+OK\r\n$5\r\nA\tb\"\n\r\n");
+    send_and_expect(&mut stream, "SET single 'it\\'s \\n'\r\nGET single\r\n", "+OK\r\n$7\r\nit's \\n\r\n");
+    send_and_expect(&mut stream, "ECHO a\"b c\"d\r\n", "-ERR Protocol error: unbalanced quotes in request\r\n");
+}
+
+#[test]
+fn test_command_table() {
+    let mut stream = start_server("6386");
+
+    // arity is checked before anything runs
+    send_and_expect(&mut stream, "*1\r\n$3\r\nGET\r\n", "-ERR wrong number of arguments for 'get' command\r\n");
+    send_and_expect(&mut stream, "*2\r\n$4\r\nXADD\r\n$1\r\ns\r\n", "-ERR wrong number of arguments for 'xadd' command\r\n");
+    send_and_expect(&mut stream, "FOO bar baz\r\n", "-ERR unknown command 'FOO', with args beginning with: 'bar' 'baz' \r\n");
+
+    // a rejected command fails the whole transaction
+    send_and_expect(&mut stream, "MULTI\r\nSET a\r\nINCR x\r\nEXEC\r\nGET x\r\n",
+        "+OK\r\n-ERR wrong number of arguments for 'set' command\r\n+QUEUED\r\n-EXECABORT Transaction discarded because of previous errors.\r\n$-1\r\n");
+
+    send_and_expect(&mut stream, "COMMAND INFO get nope\r\n",
+        "*2\r\n*10\r\n$3\r\nget\r\n:2\r\n*2\r\n+readonly\r\n+fast\r\n:1\r\n:1\r\n:1\r\n*3\r\n+@string\r\n+@read\r\n+@fast\r\n*0\r\n*0\r\n*0\r\n$-1\r\n");
+    send_and_expect(&mut stream, "COMMAND GETKEYS BLPOP a b 0\r\n", "*2\r\n$1\r\na\r\n$1\r\nb\r\n");
+    send_and_expect(&mut stream, "COMMAND GETKEYS XREAD COUNT 2 STREAMS s1 s2 0 0\r\n", "*2\r\n$2\r\ns1\r\n$2\r\ns2\r\n");
+    send_and_expect(&mut stream, "COMMAND GETKEYS PING\r\n", "-ERR The command has no key arguments\r\n");
+    send_and_expect(&mut stream, "COMMAND GETKEYS GET\r\n", "-ERR Invalid number of arguments specified for command\r\n");
+    send_and_expect(&mut stream, "COMMAND DOCS echo\r\n",
+        "*2\r\n$4\r\necho\r\n*6\r\n$7\r\nsummary\r\n$25\r\nReturns the given string.\r\n$5\r\nsince\r\n$5\r\n1.0.0\r\n$5\r\ngroup\r\n$10\r\nconnection\r\n");
+    let count = send_and_read_until(&mut stream, "COMMAND COUNT\r\n", "\r\n");
+    let all = send_and_read_until(&mut stream, "COMMAND\r\nPING\r\n", "+PONG\r\n");
+    assert!(all.contains("$5\r\nxread\r\n:-4\r\n*3\r\n+readonly\r\n+blocking\r\n+movablekeys\r\n:0\r\n:0\r\n:0\r\n"));
+    let count: usize = count[1..count.len() - 2].parse().unwrap();
+    assert!(all.starts_with(&format!("*{}\r\n", count)));
+
+    // QUIT replies and closes the connection, nothing after it runs
+    send_and_expect(&mut stream, "QUIT\r\nPING\r\n", "+OK\r\n");
+    let mut buffer = [0; 16];
+    assert_eq!(stream.read(&mut buffer).unwrap(), 0);
+}
+
+#[test]
+fn test_empty_and_nil_replies() {
+    let mut stream = start_server("6387");
+
+    // an empty string is not a missing key
+    send_and_expect(&mut stream, "SET e \"\"\r\nGET e\r\nGET missing\r\n", "+OK\r\n$0\r\n\r\n$-1\r\n");
+
+    // popping the last element removes the list
+    send_and_expect(&mut stream, "RPUSH l a\r\nLPOP l\r\nLPOP l\r\nLPOP l 2\r\n", ":1\r\n$1\r\na\r\n$-1\r\n*-1\r\n");
+
+    send_and_expect(&mut stream, "ZADD z 1 a\r\nZRANK z a\r\nZRANK z b\r\n", ":1\r\n:0\r\n$-1\r\n");
+
+    // RESP3 has a single null type
+    send_and_read_until(&mut stream, "HELLO 3\r\n", "modules\r\n*0\r\n");
+    send_and_expect(&mut stream, "GET e\r\nGET missing\r\nLPOP l 2\r\nZRANK z b\r\n", "$0\r\n\r\n_\r\n_\r\n_\r\n");
+}
+
+#[tokio::test]
+async fn test_client() {
+    start_server("6388");
+    let client = Client::open("127.0.0.1:6388");
+    let mut conn = client.get_connection().await.unwrap();
+
+    conn.ping().await.unwrap();
+    conn.set("name", "").await.unwrap();
+    assert_eq!(conn.get("name").await.unwrap(), Some(vec![]));
+    assert_eq!(conn.get("nope").await.unwrap(), None);
+    assert_eq!(conn.incr("n").await.unwrap(), 1);
+    assert!(matches!(conn.incr("name").await, Err(ClientError::Server(e)) if e.starts_with("ERR value is not an integer")));
+
+    assert_eq!(conn.rpush("l", &["a", "b", "c"]).await.unwrap(), 3);
+    assert_eq!(conn.lrange("l", 0, -1).await.unwrap(), vec![b"a".to_vec(), b"b".to_vec(), b"c".to_vec()]);
+    assert_eq!(conn.lpop("l").await.unwrap(), Some(b"a".to_vec()));
+
+    assert_eq!(conn.xadd("s", "1-1", &[("temp", "20")]).await.unwrap(), "1-1");
+    assert_eq!(conn.xrange("s", "-", "+").await.unwrap(), vec![("1-1".to_owned(), vec![(b"temp".to_vec(), b"20".to_vec())])]);
+
+    assert_eq!(conn.zadd("z", 1.5, "a").await.unwrap(), 1);
+    assert_eq!(conn.zrank("z", "a").await.unwrap(), Some(0));
+    assert_eq!(conn.zrank("z", "b").await.unwrap(), None);
+    assert_eq!(conn.zscore("z", "a").await.unwrap(), Some(1.5));
+
+    assert_eq!(conn.geoadd("g", 2.2944692, 48.8584625, "eiffel").await.unwrap(), 1);
+    let pos = conn.geopos("g", &["eiffel", "nope"]).await.unwrap();
+    assert!((pos[0].unwrap().0 - 2.2944692).abs() < 0.0001 && pos[1].is_none());
+
+    // every reply of a pipeline comes back in order, errors included
+    let mut pipeline = Pipeline::new();
+    pipeline.cmd(&["SET", "p", "1"]).cmd(&["INCR", "p"]).cmd(&["GET"]).cmd(&["GET", "p"]);
+    let replies = conn.pipeline(&pipeline).await.unwrap();
+    assert_eq!(replies[..2], [RespValue::simple("OK"), RespValue::Integer(2)]);
+    assert!(replies[2].is_error());
+    assert_eq!(replies[3], RespValue::bulk("2"));
+
+    let mut tx = Transaction::new();
+    tx.cmd(&["INCR", "t"]).cmd(&["INCR", "t"]);
+    assert_eq!(conn.transaction(&tx).await.unwrap(), vec![RespValue::Integer(1), RespValue::Integer(2)]);
+    tx.cmd(&["SET", "t"]);
+    assert!(matches!(conn.transaction(&tx).await, Err(ClientError::Server(e)) if e.starts_with("EXECABORT")));
+
+    // the pool hands back the idle connection instead of opening a new one
+    let pool = client.pool(2);
+    {
+        let mut pooled = pool.get().await.unwrap();
+        assert_eq!(pooled.get("p").await.unwrap(), Some(b"2".to_vec()));
+    }
+    assert_eq!(pool.idle_count(), 1);
+    let (mut a, mut b) = (pool.get().await.unwrap(), pool.get().await.unwrap());
+    a.ping().await.unwrap();
+    b.ping().await.unwrap();
+    drop((a, b));
+    assert_eq!(pool.idle_count(), 2);
+
+    // connections given back with a reply still owed or inside a MULTI are closed instead of pooled
+    let (mut a, mut b) = (pool.get().await.unwrap(), pool.get().await.unwrap());
+    a.send(&["PING"]).await.unwrap();
+    b.command(&["MULTI"]).await.unwrap();
+    drop((a, b));
+    assert_eq!(pool.idle_count(), 0);
+    let mut pooled = pool.get().await.unwrap();
+    pooled.command(&["MULTI"]).await.unwrap();
+    pooled.command(&["DISCARD"]).await.unwrap();
+    drop(pooled);
+    assert_eq!(pool.idle_count(), 1);
+
+    let mut subscriber = client.get_connection().await.unwrap().subscribe(&["news", "sports"]).await.unwrap();
+    assert_eq!(subscriber.subscriptions(), 2);
+    assert_eq!(conn.publish("news", "hello").await.unwrap(), 1);
+    assert_eq!(subscriber.next_message().await.unwrap(), Message { channel: b"news".to_vec(), payload: b"hello".to_vec(), pattern: None });
+    subscriber.unsubscribe(&["news"]).await.unwrap();
+    assert_eq!(subscriber.subscriptions(), 1);
+
+    // patterns count as subscriptions and each matching one delivers its own pmessage
+    subscriber.psubscribe(&["sp*", "s?orts"]).await.unwrap();
+    assert_eq!(subscriber.subscriptions(), 3);
+    assert_eq!(conn.publish("sports", "goal").await.unwrap(), 3);
+    assert_eq!(conn.publish("weather", "rain").await.unwrap(), 0);
+    let mut received = vec![];
+    for _ in 0..3 {
+        let msg = subscriber.next_message().await.unwrap();
+        assert_eq!((msg.channel.as_slice(), msg.payload.as_slice()), (b"sports".as_slice(), b"goal".as_slice()));
+        received.push(msg.pattern);
+    }
+    received.sort();
+    assert_eq!(received, vec![None, Some(b"s?orts".to_vec()), Some(b"sp*".to_vec())]);
+    subscriber.punsubscribe(&["sp*"]).await.unwrap();
+    assert_eq!(subscriber.subscriptions(), 2);
+    assert_eq!(conn.publish("spam", "x").await.unwrap(), 0);
+}
+
+#[test]
+fn test_cli_formatting() {
+    let nested = RespValue::Array(vec![
+        RespValue::bulk("1-1"),
+        RespValue::Array(vec![RespValue::bulk("temp"), RespValue::bulk("a\"b\n\x01")]),
+        RespValue::Integer(3),
+        RespValue::Null,
+        RespValue::Array(vec![]),
+    ]);
+    assert_eq!(String::from_utf8(format_reply(&nested, false)).unwrap(),
+        "1) \"1-1\"\n2) 1) \"temp\"\n   2) \"a\\\"b\\n\\x01\"\n3) (integer) 3\n4) (nil)\n5) (empty array)\n");
+    assert_eq!(String::from_utf8(format_reply(&nested, true)).unwrap(), "1-1\ntemp\na\"b\n\x01\n3\n\n\n");
+
+    // indexes are right aligned and nested entries line up after them
+    let wide = RespValue::Array((0..10).map(|i| if i == 9 { RespValue::bulk_array(&["x", "y"]) } else { RespValue::Integer(i) }).collect());
+    assert!(String::from_utf8(format_reply(&wide, false)).unwrap().ends_with(" 9) (integer) 8\n10) 1) \"x\"\n    2) \"y\"\n"));
+
+    let map = RespValue::Map(vec![(RespValue::bulk("proto"), RespValue::Integer(3))]);
+    assert_eq!(String::from_utf8(format_reply(&map, false)).unwrap(), "1# \"proto\" => (integer) 3\n");
+    assert_eq!(String::from_utf8(format_reply(&RespValue::Error("ERR boom".to_owned()), false)).unwrap(), "(error) ERR boom\n");
+}
+
+#[test]
+fn test_cli_binary() {
+    start_server("6389");
+    let cli = env!("CARGO_BIN_EXE_rusty-redis-cli");
+
+    let output = std::process::Command::new(cli).args(["-p", "6389", "-r", "3", "INCR", "counter"]).output().unwrap();
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "1\n2\n3\n");
+
+    // mass insertion, every reply is counted and errors are reported
+    let mut pipe = std::process::Command::new(cli).args(["-p", "6389", "--pipe"])
+        .stdin(std::process::Stdio::piped()).stdout(std::process::Stdio::piped()).stderr(std::process::Stdio::piped())
+        .spawn().unwrap();
+    let mut input = String::new();
+    for i in 0..1000 {
+        input += &format!("*3\r\n$3\r\nSET\r\n${}\r\nkey{}\r\n$1\r\nv\r\n", 3 + i.to_string().len(), i);
+    }
+    input += "*1\r\n$3\r\nGET\r\n";
+    pipe.stdin.take().unwrap().write_all(input.as_bytes()).unwrap();
+    let output = pipe.wait_with_output().unwrap();
+    assert!(String::from_utf8_lossy(&output.stderr).ends_with("errors: 1, replies: 1001\n"));
+
+    let output = std::process::Command::new(cli).args(["-p", "6389", "--no-raw", "GET", "key999"]).output().unwrap();
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "\"v\"\n");
+}
+
+#[test]
+fn test_benchmark_binary() {
+    start_server("6390");
+    let bench = env!("CARGO_BIN_EXE_rusty-redis-benchmark");
+
+    let output = std::process::Command::new(bench)
+        .args(["-p", "6390", "-n", "200", "-c", "4", "-P", "8", "-r", "16", "-t", "set,get,zadd", "--csv"]).output().unwrap();
+    let csv = String::from_utf8_lossy(&output.stdout);
+    let mut lines = csv.lines();
+    assert_eq!(lines.next().unwrap(), "test,requests,errors,clients,pipeline,seconds,rps,avg_latency_ms,min_latency_ms,p50_latency_ms,p99_latency_ms,p999_latency_ms,max_latency_ms");
+    for (line, test) in lines.zip(["SET", "GET", "ZADD"]) {
+        assert!(line.starts_with(&format!("{},200,0,4,8,", test)), "{}", line);
+    }
+
+    let output = std::process::Command::new(bench).args(["-p", "6390", "-n", "50", "-c", "2", "-t", "lpush,lpop", "--json"]).output().unwrap();
+    let results: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(results[1]["test"], "LPOP");
+    assert_eq!(results[1]["requests"], 50);
+    assert!(results[1]["p999_latency_ms"].as_f64().unwrap() <= results[1]["max_latency_ms"].as_f64().unwrap());
+
+    let output = std::process::Command::new(bench).args(["-p", "6390", "-t", "hset"]).output().unwrap();
+    assert!(!output.status.success());
+}
+
+#[test]
+fn test_set_options() {
+    let mut stream = start_server("6391");
+
+    // the lock pattern, only the first SET NX wins
+    send_and_expect(&mut stream, "SET lock a NX PX 30000\r\nSET lock b NX PX 30000\r\nGET lock\r\n", "+OK\r\n$-1\r\n$1\r\na\r\n");
+    send_and_expect(&mut stream, "SET nokey v XX\r\nGET nokey\r\nSET lock c XX GET\r\nGET lock\r\n", "$-1\r\n$-1\r\n$1\r\na\r\n$1\r\nc\r\n");
+    send_and_expect(&mut stream, "SET fresh v NX GET\r\nSET fresh w NX GET\r\n", "$-1\r\n$1\r\nv\r\n");
+
+    // expiry in the past or shortly after
+    send_and_expect(&mut stream, "SET old v EXAT 1\r\nGET old\r\nSET short v PX 50\r\n", "+OK\r\n$-1\r\n+OK\r\n");
+    thread::sleep(Duration::from_millis(100));
+    send_and_expect(&mut stream, "GET short\r\n", "$-1\r\n");
+
+    // KEEPTTL keeps the expiry of the replaced value, a plain SET drops it
+    send_and_expect(&mut stream, "SET k v PX 50\r\nSET k w KEEPTTL\r\nSET k2 v PX 50\r\nSET k2 w\r\n", "+OK\r\n+OK\r\n+OK\r\n+OK\r\n");
+    thread::sleep(Duration::from_millis(100));
+    send_and_expect(&mut stream, "GET k\r\nGET k2\r\n", "$-1\r\n$1\r\nw\r\n");
+
+    send_and_expect(&mut stream, "SET k v NX XX\r\n", "-ERR syntax error\r\n");
+    send_and_expect(&mut stream, "SET k v EX 10 PX 10\r\n", "-ERR syntax error\r\n");
+    send_and_expect(&mut stream, "SET k v KEEPTTL EX 10\r\n", "-ERR syntax error\r\n");
+    send_and_expect(&mut stream, "SET k v PX\r\n", "-ERR syntax error\r\n");
+    send_and_expect(&mut stream, "SET k v FOO\r\n", "-ERR syntax error\r\n");
+    send_and_expect(&mut stream, "SET k v EX ten\r\n", "-ERR value is not an integer or out of range\r\n");
+    send_and_expect(&mut stream, "SET k v EX 0\r\n", "-ERR invalid expire time in 'set' command\r\n");
+    send_and_expect(&mut stream, "SET k v EX 9223372036854775807\r\n", "-ERR invalid expire time in 'set' command\r\n");
+
+    // GET needs the old value to be a string
+    send_and_expect(&mut stream, "RPUSH list a\r\nSET list v GET\r\nSET list v\r\nGET list\r\n", ":1\r\n-WRONGTYPE Operation against a key holding the wrong kind of value\r\n+OK\r\n$1\r\nv\r\n");
+}
+
+#[test]
+fn test_mget_mset() {
+    let mut stream = start_server("6392");
+
+    send_and_expect(&mut stream, "MSET a 1 b 2 c \"\"\r\nMGET a b c nope\r\n", "+OK\r\n*4\r\n$1\r\n1\r\n$1\r\n2\r\n$0\r\n\r\n$-1\r\n");
+    send_and_expect(&mut stream, "MSET a 1 b\r\n", "-ERR wrong number of arguments for 'mset' command\r\n");
+
+    // expired and non string keys read as nil
+    send_and_expect(&mut stream, "SET short v PX 50\r\nRPUSH list x\r\n", "+OK\r\n:1\r\n");
+    thread::sleep(Duration::from_millis(100));
+    send_and_expect(&mut stream, "MGET short list a\r\n", "*3\r\n$-1\r\n$-1\r\n$1\r\n1\r\n");
+
+    // MSETNX is all or nothing, an expired key doesn't count as existing
+    send_and_expect(&mut stream, "MSETNX a 9 d 4\r\nMGET a d\r\n", ":0\r\n*2\r\n$1\r\n1\r\n$-1\r\n");
+    send_and_expect(&mut stream, "MSETNX short 5 d 4\r\nMGET short d\r\n", ":1\r\n*2\r\n$1\r\n5\r\n$1\r\n4\r\n");
+
+    // MSET drops any previous expiry
+    send_and_expect(&mut stream, "SET e v PX 50\r\nMSET e w\r\n", "+OK\r\n+OK\r\n");
+    thread::sleep(Duration::from_millis(100));
+    send_and_expect(&mut stream, "GET e\r\nCOMMAND GETKEYS MSET a 1 b 2\r\n", "$1\r\nw\r\n*2\r\n$1\r\na\r\n$1\r\nb\r\n");
+}
+
+#[test]
+fn test_incr_family() {
+    let mut stream = start_server("6393");
+
+    // counters can go negative
+    send_and_expect(&mut stream, "DECR c\r\nDECRBY c 5\r\nINCRBY c -4\r\nINCR c\r\nGET c\r\n", ":-1\r\n:-6\r\n:-10\r\n:-9\r\n$2\r\n-9\r\n");
+
+    send_and_expect(&mut stream, "SET max 9223372036854775807\r\nINCR max\r\nDECRBY c -9223372036854775808\r\n",
+        "+OK\r\n-ERR increment or decrement would overflow\r\n-ERR decrement would overflow\r\n");
+    send_and_expect(&mut stream, "SET min -9223372036854775808\r\nDECR min\r\n", "+OK\r\n-ERR increment or decrement would overflow\r\n");
+    send_and_expect(&mut stream, "INCRBY c ten\r\nSET s \" 1\"\r\nINCR s\r\nSET z 01\r\nINCR z\r\n",
+        "-ERR value is not an integer or out of range\r\n+OK\r\n-ERR value is not an integer or out of range\r\n+OK\r\n-ERR value is not an integer or out of range\r\n");
+    send_and_expect(&mut stream, "RPUSH list a\r\nINCR list\r\nINCRBYFLOAT list 1\r\n",
+        ":1\r\n-WRONGTYPE Operation against a key holding the wrong kind of value\r\n-WRONGTYPE Operation against a key holding the wrong kind of value\r\n");
+
+    send_and_expect(&mut stream, "SET f 10.50\r\nINCRBYFLOAT f 0.1\r\nINCRBYFLOAT f -5\r\nINCRBYFLOAT f 5.0e3\r\nINCRBYFLOAT new 3\r\n",
+        "+OK\r\n$4\r\n10.6\r\n$3\r\n5.6\r\n$6\r\n5005.6\r\n$1\r\n3\r\n");
+    send_and_expect(&mut stream, "SET g 0.1\r\nINCRBYFLOAT g 0.2\r\n", "+OK\r\n$3\r\n0.3\r\n");
+    send_and_expect(&mut stream, "INCRBYFLOAT f abc\r\nSET big 1e308\r\nINCRBYFLOAT big 1e308\r\nINCRBYFLOAT f nan\r\n",
+        "-ERR value is not a valid float\r\n+OK\r\n-ERR increment would produce NaN or Infinity\r\n-ERR value is not a valid float\r\n");
+
+    // increments keep the expiry
+    send_and_expect(&mut stream, "SET t 1 PX 100\r\nINCR t\r\nINCRBYFLOAT t 1.5\r\n", "+OK\r\n:2\r\n$3\r\n3.5\r\n");
+    thread::sleep(Duration::from_millis(150));
+    send_and_expect(&mut stream, "GET t\r\nINCR t\r\n", "$-1\r\n:1\r\n");
+}
+
+#[test]
+fn test_string_ranges() {
+    let mut stream = start_server("6394");
+
+    send_and_expect(&mut stream, "APPEND log hello\r\nAPPEND log \" world\"\r\nSTRLEN log\r\nSTRLEN missing\r\n", ":5\r\n:11\r\n:11\r\n:0\r\n");
+    send_and_expect(&mut stream, "GETRANGE log 0 4\r\nGETRANGE log -5 -1\r\nGETRANGE log 6 100\r\nGETRANGE log -1 -5\r\nGETRANGE missing 0 -1\r\n",
+        "$5\r\nhello\r\n$5\r\nworld\r\n$5\r\nworld\r\n$0\r\n\r\n$0\r\n\r\n");
+
+    // writing past the end pads with zero bytes
+    send_and_expect(&mut stream, "SETRANGE log 6 redis\r\nGET log\r\nSETRANGE pad 3 ab\r\nGET pad\r\n",
+        ":11\r\n$11\r\nhello redis\r\n:5\r\n$5\r\n\0\0\0ab\r\n");
+    send_and_expect(&mut stream, "SETRANGE nothing 5 \"\"\r\nGET nothing\r\nSETRANGE log -1 x\r\n", ":0\r\n$-1\r\n-ERR offset is out of range\r\n");
+    send_and_expect(&mut stream, "SETRANGE log 536870911 xy\r\n", "-ERR string exceeds maximum allowed size (proto-max-bulk-len)\r\n");
+
+    send_and_expect(&mut stream, "SET token abc\r\nGETDEL token\r\nGETDEL token\r\nGET token\r\n", "+OK\r\n$3\r\nabc\r\n$-1\r\n$-1\r\n");
+    send_and_expect(&mut stream, "SET k v1 EX 100\r\nGETSET k v2\r\nGETSET new v\r\nGET k\r\n", "+OK\r\n$2\r\nv1\r\n$-1\r\n$2\r\nv2\r\n");
+
+    send_and_expect(&mut stream, "GETEX k PX 100\r\nGETEX k PERSIST\r\nGETEX k PX 100\r\nGETEX k\r\n", "$2\r\nv2\r\n$2\r\nv2\r\n$2\r\nv2\r\n$2\r\nv2\r\n");
+    thread::sleep(Duration::from_millis(150));
+    send_and_expect(&mut stream, "GET k\r\nGETEX missing EX 10\r\nGETEX log EX 0\r\nGETEX log PERSIST EX 10\r\n",
+        "$-1\r\n$-1\r\n-ERR invalid expire time in 'getex' command\r\n-ERR syntax error\r\n");
+    send_and_expect(&mut stream, "SET old v\r\nGETEX old EXAT 1\r\nGET old\r\n", "+OK\r\n$1\r\nv\r\n$-1\r\n");
+
+    let wrongtype = "-WRONGTYPE Operation against a key holding the wrong kind of value\r\n";
+    send_and_expect(&mut stream, "RPUSH list a\r\nAPPEND list b\r\nSTRLEN list\r\nGETRANGE list 0 1\r\nSETRANGE list 0 b\r\nGETDEL list\r\nGETEX list\r\nGETSET list b\r\n",
+        format!(":1\r\n{}", wrongtype.repeat(7)));
+}
+
+#[test]
+fn test_bitmaps() {
+    let mut stream = start_server("6395");
+
+    // bit 0 is the most significant bit of the first byte
+    send_and_expect(&mut stream, "SETBIT b 1 1\r\nSETBIT b 7 1\r\nSETBIT b 7 0\r\nGET b\r\nGETBIT b 1\r\nGETBIT b 100\r\n",
+        ":0\r\n:0\r\n:1\r\n$1\r\n@\r\n:1\r\n:0\r\n");
+    send_and_expect(&mut stream, "SETBIT grow 23 1\r\nSTRLEN grow\r\nSETBIT b -1 1\r\nSETBIT b 4294967296 1\r\nSETBIT b 0 2\r\n",
+        ":0\r\n:3\r\n-ERR bit offset is not an integer or out of range\r\n-ERR bit offset is not an integer or out of range\r\n-ERR bit is not an integer or out of range\r\n");
+
+    send_and_expect(&mut stream, "SET s foobar\r\nBITCOUNT s\r\nBITCOUNT s 0 0\r\nBITCOUNT s 1 1\r\nBITCOUNT s -2 -1\r\nBITCOUNT s 5 30 BIT\r\nBITCOUNT missing\r\n",
+        "+OK\r\n:26\r\n:4\r\n:6\r\n:7\r\n:17\r\n:0\r\n");
+    send_and_expect(&mut stream, "BITCOUNT s 0\r\nBITCOUNT s 0 1 WORD\r\nBITCOUNT s a 1\r\n",
+        "-ERR syntax error\r\n-ERR syntax error\r\n-ERR value is not an integer or out of range\r\n");
+
+    send_and_expect(&mut stream, "SET p \"\\xff\\xf0\\x00\"\r\nBITPOS p 0\r\nBITPOS p 1 2\r\nBITPOS p 1 7 15 BIT\r\nBITPOS p 0 0 0\r\n",
+        "+OK\r\n:12\r\n:-1\r\n:7\r\n:-1\r\n");
+    send_and_expect(&mut stream, "SET ones \"\\xff\\xff\"\r\nBITPOS ones 0\r\nBITPOS ones 0 0 -1\r\nBITPOS missing 0\r\nBITPOS missing 1\r\nBITPOS ones 2\r\n",
+        "+OK\r\n:16\r\n:-1\r\n:0\r\n:-1\r\n-ERR The bit argument must be 1 or 0.\r\n");
+
+    // sources are zero padded to the longest one
+    send_and_expect(&mut stream, "SET k1 foobar\r\nSET k2 abcdef\r\nSET k3 a\r\nBITOP AND dest k1 k2\r\nGET dest\r\nBITOP OR dest k1 k3\r\nGET dest\r\n",
+        "+OK\r\n+OK\r\n+OK\r\n:6\r\n$6\r\n`bc`ab\r\n:6\r\n$6\r\ngoobar\r\n");
+    send_and_expect(&mut stream, "BITOP AND dest k1 k3\r\nGET dest\r\nBITOP XOR dest k3 k3\r\nGET dest\r\nBITOP NOT dest k3\r\nGET dest\r\n",
+        b":6\r\n$6\r\n`\0\0\0\0\0\r\n:1\r\n$1\r\n\0\r\n:1\r\n$1\r\n\x9e\r\n");
+    send_and_expect(&mut stream, "BITOP AND dest nokey\r\nGET dest\r\nBITOP NOT dest k1 k2\r\nBITOP NAND dest k1\r\n",
+        ":0\r\n$-1\r\n-ERR BITOP NOT must be called with a single source key.\r\n-ERR syntax error\r\n");
+
+    let wrongtype = "-WRONGTYPE Operation against a key holding the wrong kind of value\r\n";
+    send_and_expect(&mut stream, "RPUSH list a\r\nSETBIT list 0 1\r\nGETBIT list 0\r\nBITCOUNT list\r\nBITPOS list 1\r\nBITOP OR dest k1 list\r\n",
+        format!(":1\r\n{}", wrongtype.repeat(5)));
+}
+
+#[test]
+fn test_bitfield() {
+    let mut stream = start_server("6396");
+
+    send_and_expect(&mut stream, "BITFIELD k INCRBY i5 100 1 GET u4 0\r\nBITFIELD k SET i8 0 100 GET i8 0\r\nBITFIELD missing GET u8 0\r\nBITFIELD k\r\n",
+        "*2\r\n:1\r\n:0\r\n*2\r\n:0\r\n:100\r\n*1\r\n:0\r\n*0\r\n");
+    // '#' offsets are multiplied by the width of the field
+    send_and_expect(&mut stream, "BITFIELD k SET u8 #1 255 GET u8 8 GET i8 #1 GET u4 #3\r\nGETRANGE k 0 1\r\n",
+        b"*4\r\n:0\r\n:255\r\n:-1\r\n:15\r\n$2\r\nd\xff\r\n");
+
+    send_and_expect(&mut stream, "BITFIELD c INCRBY u2 100 1 OVERFLOW SAT INCRBY u2 102 1\r\nBITFIELD c INCRBY u2 100 1 OVERFLOW SAT INCRBY u2 102 1\r\n",
+        "*2\r\n:1\r\n:1\r\n*2\r\n:2\r\n:2\r\n");
+    send_and_expect(&mut stream, "BITFIELD c INCRBY u2 100 1 OVERFLOW SAT INCRBY u2 102 1\r\nBITFIELD c INCRBY u2 100 1 OVERFLOW SAT INCRBY u2 102 1\r\n",
+        "*2\r\n:3\r\n:3\r\n*2\r\n:0\r\n:3\r\n");
+    send_and_expect(&mut stream, "BITFIELD c OVERFLOW FAIL INCRBY u2 102 1 SET i4 0 8 SET i4 0 -8 OVERFLOW SAT SET i4 0 100 INCRBY i4 0 -100\r\n",
+        "*5\r\n$-1\r\n$-1\r\n:0\r\n:-8\r\n:-8\r\n");
+    send_and_expect(&mut stream, "BITFIELD w SET i64 0 9223372036854775807 INCRBY i64 0 1 SET u63 0 0 INCRBY u63 0 -1 OVERFLOW SAT INCRBY u63 0 -5\r\n",
+        "*5\r\n:0\r\n:-9223372036854775808\r\n:4611686018427387904\r\n:9223372036854775807\r\n:9223372036854775802\r\n");
+
+    send_and_expect(&mut stream, "BITFIELD_RO k GET u8 8\r\nBITFIELD_RO k SET u8 0 1\r\n",
+        "*1\r\n:255\r\n-ERR BITFIELD_RO only supports the GET subcommand\r\n");
+    send_and_expect(&mut stream, "BITFIELD k GET u64 0\r\nBITFIELD k GET i65 0\r\nBITFIELD k GET u8 -1\r\nBITFIELD k GET u8 4294967290\r\n",
+        "-ERR Invalid bitfield type. Use something like i16 u8. Note that u64 is not supported but i64 is.\r\n\
+        -ERR Invalid bitfield type. Use something like i16 u8. Note that u64 is not supported but i64 is.\r\n\
+        -ERR bit offset is not an integer or out of range\r\n-ERR bit offset is not an integer or out of range\r\n");
+    send_and_expect(&mut stream, "BITFIELD k OVERFLOW NONE\r\nBITFIELD k GET u8\r\nBITFIELD k SET u8 0 x\r\nBITFIELD k FETCH u8 0\r\n",
+        "-ERR Invalid OVERFLOW type specified\r\n-ERR syntax error\r\n-ERR value is not an integer or out of range\r\n-ERR syntax error\r\n");
+
+    send_and_expect(&mut stream, "RPUSH list a\r\nBITFIELD list GET u8 0\r\nBITFIELD_RO list GET u8 0\r\n",
+        ":1\r\n-WRONGTYPE Operation against a key holding the wrong kind of value\r\n-WRONGTYPE Operation against a key holding the wrong kind of value\r\n");
+}
+
+#[test]
+fn test_hyperloglog() {
+    let mut stream = start_server("6397");
+
+    send_and_expect(&mut stream, "PFADD hll foo bar zap\r\nPFADD hll zap zap zap\r\nPFADD hll foo bar\r\nPFCOUNT hll\r\nPFADD empty\r\nPFADD empty\r\nPFCOUNT empty missing\r\n",
+        ":1\r\n:0\r\n:0\r\n:3\r\n:1\r\n:0\r\n:0\r\n");
+    send_and_expect(&mut stream, "PFADD other 1 2 3\r\nPFCOUNT hll other\r\nPFMERGE merged hll other\r\nPFCOUNT merged\r\nPFMERGE hll other\r\nPFCOUNT hll\r\n",
+        ":1\r\n:6\r\n+OK\r\n:6\r\n+OK\r\n:6\r\n");
+
+    // the string layout is redis': magic, sparse encoding, and a cached cardinality that PFADD invalidates
+    let reply = send_and_read_until(&mut stream, "GETRANGE hll 0 4\r\nGETRANGE hll 8 15\r\n", "\r\n$8\r\n\u{6}\0\0\0\0\0\0\0\r\n");
+    assert!(reply.starts_with("$5\r\nHYLL\u{1}\r\n"), "{:?}", reply);
+    send_and_expect(&mut stream, "PFADD hll new\r\nGETRANGE hll 15 15\r\n", b":1\r\n$1\r\n\x80\r\n");
+
+    // grows dense past the sparse size limit and stays within the standard error
+    for batch in 0..20 {
+        let elements: Vec<String> = (0..1000).map(|i| format!("visitor:{}", batch * 1000 + i)).collect();
+        send_and_expect(&mut stream, format!("PFADD big {}\r\n", elements.join(" ")), ":1\r\n");
+    }
+    send_and_expect(&mut stream, "STRLEN big\r\n", ":12304\r\n");
+    let count: i64 = send_and_read_until(&mut stream, "PFCOUNT big\r\n", "\r\n")[1..].trim().parse().unwrap();
+    assert!((count - 20000).abs() < 20000 / 50, "estimate {} too far off", count);
+
+    // values survive a round trip through GET and SET
+    stream.write_all(b"GET big\r\n").unwrap();
+    let mut reply = vec![];
+    let mut buffer = vec![0; 16384];
+    while reply.len() < "$12304\r\n".len() + 12304 + 2 {
+        let bytes_read = stream.read(&mut buffer).unwrap();
+        reply.extend_from_slice(&buffer[..bytes_read]);
+    }
+    assert!(reply.starts_with(b"$12304\r\nHYLL\0"));
+    let set = [b"*3\r\n$3\r\nSET\r\n$4\r\ncopy\r\n".as_slice(), &reply].concat();
+    send_and_expect(&mut stream, set, "+OK\r\n");
+    send_and_expect(&mut stream, "PFCOUNT copy\r\n", format!(":{}\r\n", count));
+
+    // merging sparse values keeps them sparse, a dense source makes the result dense
+    send_and_expect(&mut stream, "PFMERGE small hll\r\nSTRLEN small\r\nPFMERGE mixed hll big\r\nSTRLEN mixed\r\n", "+OK\r\n:39\r\n+OK\r\n:12304\r\n");
+    let union: i64 = send_and_read_until(&mut stream, "PFCOUNT mixed\r\n", "\r\n")[1..].trim().parse().unwrap();
+    send_and_expect(&mut stream, "PFCOUNT big hll\r\n", format!(":{}\r\n", union));
+    assert!(union >= count && (union - 20007).abs() < 20007 / 50, "union estimate {} too far off", union);
+
+    let not_hll = "-WRONGTYPE Key is not a valid HyperLogLog string value.\r\n";
+    send_and_expect(&mut stream, "SET str hello\r\nPFADD str a\r\nPFCOUNT str\r\nPFMERGE str hll\r\nPFCOUNT hll str\r\n",
+        format!("+OK\r\n{}", not_hll.repeat(4)));
+    send_and_expect(&mut stream, "RPUSH list a\r\nPFADD list a\r\nPFCOUNT list\r\n",
+        ":1\r\n-WRONGTYPE Operation against a key holding the wrong kind of value\r\n-WRONGTYPE Operation against a key holding the wrong kind of value\r\n");
+}
+
+#[test]
+fn test_expired_keys_are_deleted() {
+    let mut master = start_server("6398");
+    let mut replica = start_server_with("6399", &["--replicaof", "localhost 6398"]);
+    thread::sleep(Duration::from_millis(300));
+    let keyspace = |keys: &str| {
+        let body = format!("# Keyspace\r\n{}", keys);
+        format!("${}\r\n{}\r\n", body.len(), body)
+    };
+
+    let mut cmds = String::new();
+    for i in 0..200 {
+        cmds.push_str(&format!("SET session:{} token PX 300\r\n", i));
+    }
+    cmds.push_str("SET kept value\r\nSET later value PX 100000\r\n");
+    send_and_expect(&mut master, cmds, "+OK\r\n".repeat(202));
+    thread::sleep(Duration::from_millis(50));
+    send_and_expect(&mut replica, "INFO keyspace\r\n", keyspace("db0:keys=202,expires=201,avg_ttl=0\r\n"));
+
+    // the background cycle removes the expired keys without anyone touching them, and so do the replicas
+    thread::sleep(Duration::from_millis(600));
+    send_and_expect(&mut master, "INFO keyspace\r\n", keyspace("db0:keys=2,expires=1,avg_ttl=0\r\n"));
+    send_and_expect(&mut replica, "INFO keyspace\r\nGET kept\r\n", keyspace("db0:keys=2,expires=1,avg_ttl=0\r\n") + "$5\r\nvalue\r\n");
+
+    send_and_expect(&mut master, "DEL kept later missing\r\nGET kept\r\n", ":2\r\n$-1\r\n");
+    thread::sleep(Duration::from_millis(100));
+    send_and_expect(&mut replica, "INFO keyspace\r\n", keyspace(""));
+}
+
+#[test]
+fn test_expire_family() {
+    let mut stream = start_server("6402");
+
+    send_and_expect(&mut stream, "SET k v\r\nTTL k\r\nPTTL k\r\nTTL missing\r\nEXPIRE missing 10\r\n", "+OK\r\n:-1\r\n:-1\r\n:-2\r\n:0\r\n");
+    send_and_expect(&mut stream, "EXPIRE k 100\r\nTTL k\r\nEXPIRE k 50 GT\r\nEXPIRE k 200 GT\r\nTTL k\r\n", ":1\r\n:100\r\n:0\r\n:1\r\n:200\r\n");
+    send_and_expect(&mut stream, "EXPIRE k 300 LT\r\nEXPIRE k 10 NX\r\nEXPIRE k 150 XX\r\nTTL k\r\n", ":0\r\n:0\r\n:1\r\n:150\r\n");
+    send_and_expect(&mut stream, "PERSIST k\r\nPERSIST k\r\nTTL k\r\nEXPIRE k 10 XX\r\nEXPIRE k 10 GT\r\nEXPIRE k 10 LT\r\nTTL k\r\n",
+        ":1\r\n:0\r\n:-1\r\n:0\r\n:0\r\n:1\r\n:10\r\n");
+
+    send_and_expect(&mut stream, "EXPIREAT k 4102444800\r\nEXPIRETIME k\r\nPEXPIRETIME k\r\n", ":1\r\n:4102444800\r\n:4102444800000\r\n");
+    send_and_expect(&mut stream, "PEXPIREAT k 4102444800123\r\nPEXPIRETIME k\r\nEXPIRETIME missing\r\n", ":1\r\n:4102444800123\r\n:-2\r\n");
+    // the unix time in seconds is truncated, not rounded
+    send_and_expect(&mut stream, "PEXPIREAT k 4102444800700\r\nEXPIRETIME k\r\n", ":1\r\n:4102444800\r\n");
+
+    // a time in the past deletes the key
+    send_and_expect(&mut stream, "EXPIRE k -1\r\nGET k\r\nTTL k\r\n", ":1\r\n$-1\r\n:-2\r\n");
+
+    send_and_expect(&mut stream, "SET k v\r\nEXPIRE k 10 NX XX\r\n", "+OK\r\n-ERR NX and XX, GT or LT options at the same time are not compatible\r\n");
+    send_and_expect(&mut stream, "EXPIRE k 10 GT LT\r\nEXPIRE k 10 FOO\r\n",
+        "-ERR GT and LT options at the same time are not compatible\r\n-ERR Unsupported option FOO\r\n");
+    send_and_expect(&mut stream, "EXPIRE k abc\r\nEXPIRE k 9223372036854775807\r\n",
+        "-ERR value is not an integer or out of range\r\n-ERR invalid expire time in 'expire' command\r\n");
+
+    // sorted sets and geo keys expire too
+    send_and_expect(&mut stream, "ZADD board 1 a\r\nPEXPIRE board 100\r\n", ":1\r\n:1\r\n");
+    send_and_expect(&mut stream, "GEOADD places 13.361389 38.115556 Palermo\r\nPEXPIRE places 100\r\nPERSIST places\r\nTTL places\r\n", ":1\r\n:1\r\n:1\r\n:-1\r\n");
+    thread::sleep(Duration::from_millis(200));
+    send_and_expect(&mut stream, "ZCARD board\r\nTTL board\r\nTTL places\r\n", ":0\r\n:-2\r\n:-1\r\n");
+}
+
+#[test]
+fn test_generic_key_commands() {
+    let mut stream = start_server("6403");
+
+    send_and_expect(&mut stream, "SET s v\r\nRPUSH l a b\r\nXADD x 1-1 f v\r\nZADD z 1 m\r\nGEOADD g 13.361389 38.115556 Palermo\r\n",
+        "+OK\r\n:2\r\n$3\r\n1-1\r\n:1\r\n:1\r\n");
+    send_and_expect(&mut stream, "TYPE s\r\nTYPE l\r\nTYPE x\r\nTYPE z\r\nTYPE g\r\nTYPE missing\r\n",
+        "+string\r\n+list\r\n+stream\r\n+zset\r\n+zset\r\n+none\r\n");
+    send_and_expect(&mut stream, "EXISTS s l x z g missing s\r\n", ":6\r\n");
+
+    // renames carry the value and the ttl, whatever the type
+    send_and_expect(&mut stream, "EXPIRE z 100\r\nRENAME z z2\r\nEXISTS z\r\nZSCORE z2 m\r\nTTL z2\r\n", ":1\r\n+OK\r\n:0\r\n$1\r\n1\r\n:100\r\n");
+    send_and_expect(&mut stream, "RENAME s l\r\nTYPE l\r\nGET l\r\nRENAME missing other\r\n", "+OK\r\n+string\r\n$1\r\nv\r\n-ERR no such key\r\n");
+    send_and_expect(&mut stream, "RENAMENX l g\r\nRENAMENX l s\r\nRENAMENX s s\r\nGET s\r\n", ":0\r\n:1\r\n:0\r\n$1\r\nv\r\n");
+
+    send_and_expect(&mut stream, "COPY g g2\r\nGEOPOS g2 Palermo\r\n", ":1\r\n*1\r\n*2\r\n$18\r\n13.361389338970184\r\n$16\r\n38.1155563954963\r\n");
+    send_and_expect(&mut stream, "COPY s x\r\nCOPY s x REPLACE\r\nTYPE x\r\nCOPY s s\r\nCOPY s y DB 16\r\nCOPY missing y\r\n",
+        ":0\r\n:1\r\n+string\r\n-ERR source and destination objects are the same\r\n-ERR DB index is out of range\r\n:0\r\n");
+
+    send_and_expect(&mut stream, "DEL s x missing\r\nUNLINK z2 g g2 missing\r\nEXISTS s x z2 g g2\r\n", ":2\r\n:3\r\n:0\r\n");
+}
+
+#[tokio::test]
+async fn test_keys_and_scan() {
+    start_server("6404");
+    let client = Client::open("127.0.0.1:6404");
+    let mut conn = client.get_connection().await.unwrap();
+    let sorted_keys = |mut keys: Vec<Vec<u8>>| {
+        keys.sort();
+        keys.iter().map(|key| String::from_utf8_lossy(key).into_owned()).collect::<Vec<_>>()
+    };
+
+    for key in ["hello", "hallo", "hxllo", "hllo", "heeeello", "h*llo", "h[llo", "Hello"] {
+        conn.set(key, "v").await.unwrap();
+    }
+    assert_eq!(sorted_keys(conn.keys("h?llo").await.unwrap()), ["h*llo", "h[llo", "hallo", "hello", "hxllo"]);
+    assert_eq!(sorted_keys(conn.keys("h*llo").await.unwrap()), ["h*llo", "h[llo", "hallo", "heeeello", "hello", "hllo", "hxllo"]);
+    assert_eq!(sorted_keys(conn.keys("h[ae]llo").await.unwrap()), ["hallo", "hello"]);
+    assert_eq!(sorted_keys(conn.keys("h[^e]llo").await.unwrap()), ["h*llo", "h[llo", "hallo", "hxllo"]);
+    assert_eq!(sorted_keys(conn.keys("h[a-f]llo").await.unwrap()), ["hallo", "hello"]);
+    assert_eq!(sorted_keys(conn.keys("h\\*llo").await.unwrap()), ["h*llo"]);
+    assert_eq!(sorted_keys(conn.keys("h\\[llo").await.unwrap()), ["h[llo"]);
+    assert_eq!(sorted_keys(conn.keys("[H]*").await.unwrap()), ["Hello"]);
+    assert_eq!(conn.keys("*").await.unwrap().len(), 8);
+
+    // every key there for the whole scan is returned even when keys come and go in between
+    for i in 0..500 {
+        conn.set(format!("key:{}", i), "v").await.unwrap();
+    }
+    let mut seen = HashSet::new();
+    let mut cursor = 0;
+    let mut step = 0;
+    loop {
+        let (next, keys) = conn.scan(cursor, Some("key:*"), Some(20)).await.unwrap();
+        seen.extend(keys);
+        for i in 0..20 {
+            conn.set(format!("key:new:{}:{}", step, i), "v").await.unwrap();
+        }
+        conn.command(&["DEL".to_owned(), format!("key:{}", 499 - step)]).await.unwrap();
+        step += 1;
+        cursor = next;
+        if cursor == 0 {
+            break;
+        }
+    }
+    assert!((0..500 - step).all(|i| seen.contains(format!("key:{}", i).as_bytes())));
+
+    conn.zadd("zs", 1.0, "one").await.unwrap();
+    conn.rpush("list", &["a"]).await.unwrap();
+    let reply = conn.command(&["SCAN", "0", "COUNT", "10000", "TYPE", "zset"]).await.unwrap();
+    assert_eq!(reply, RespValue::Array(vec![RespValue::bulk("0"), RespValue::bulk_array(&["zs"])]));
+    let reply = conn.command(&["SCAN", "0", "COUNT", "10000", "MATCH", "l*", "TYPE", "list"]).await.unwrap();
+    assert_eq!(reply, RespValue::Array(vec![RespValue::bulk("0"), RespValue::bulk_array(&["list"])]));
+    assert!(matches!(conn.command(&["SCAN", "abc"]).await, Err(ClientError::Server(e)) if e == "ERR invalid cursor"));
+    assert!(matches!(conn.command(&["SCAN", "0", "COUNT", "0"]).await, Err(ClientError::Server(e)) if e == "ERR syntax error"));
+    assert!(matches!(conn.command(&["SCAN", "0", "TYPE", "nope"]).await, Err(ClientError::Server(e)) if e == "ERR unknown type name 'nope'"));
+
+    for (score, member) in [(2.0, "two"), (2.5, "two.five"), (3.0, "three")] {
+        conn.zadd("zs", score, member).await.unwrap();
+    }
+    let reply = conn.command(&["ZSCAN", "zs", "0", "MATCH", "two*", "COUNT", "100"]).await.unwrap();
+    let RespValue::Array(reply) = reply else { panic!("ZSCAN replies with an array") };
+    assert_eq!(reply[0], RespValue::bulk("0"));
+    let RespValue::Array(entries) = &reply[1] else { panic!("ZSCAN replies with the entries") };
+    let mut pairs: Vec<_> = entries.chunks(2).map(|pair| match pair {
+        [RespValue::Bulk(member), RespValue::Bulk(score)] => (String::from_utf8_lossy(member).into_owned(), String::from_utf8_lossy(score).into_owned()),
+        _ => panic!("ZSCAN entries are member and score pairs"),
+    }).collect();
+    pairs.sort();
+    assert_eq!(pairs, [("two".to_owned(), "2".to_owned()), ("two.five".to_owned(), "2.5".to_owned())]);
+    let reply = conn.command(&["ZSCAN", "missing", "0"]).await.unwrap();
+    assert_eq!(reply, RespValue::Array(vec![RespValue::bulk("0"), RespValue::Array(vec![])]));
+}
+
+#[test]
+fn test_databases() {
+    let dir = std::env::temp_dir().join("rusty-redis-databases-test");
+    let _ = std::fs::remove_dir_all(&dir);
+    let dir = dir.to_str().unwrap().to_owned();
+    let mut stream = start_server_with("6405", &["--dir", &dir, "--dbfilename", "dump.rdb", "--databases", "4"]);
+    let mut other = TcpStream::connect("localhost:6405").unwrap();
+    other.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    let mut replica = start_server_with("6406", &["--replicaof", "localhost 6405"]);
+    thread::sleep(Duration::from_millis(300));
+
+    // the selected database is per connection
+    send_and_expect(&mut stream, "SET k zero\r\nSELECT 2\r\nSET k two\r\nZADD z 1 m\r\nGET k\r\nDBSIZE\r\n", "+OK\r\n+OK\r\n+OK\r\n:1\r\n$3\r\ntwo\r\n:2\r\n");
+    send_and_expect(&mut other, "GET k\r\nDBSIZE\r\nSELECT 4\r\nSELECT -1\r\nSELECT x\r\nCONFIG GET databases\r\n",
+        "$4\r\nzero\r\n:1\r\n-ERR DB index is out of range\r\n-ERR DB index is out of range\r\n-ERR value is not an integer or out of range\r\n*2\r\n$9\r\ndatabases\r\n$1\r\n4\r\n");
+
+    // MOVE and COPY carry keys of any type over
+    send_and_expect(&mut stream, "MOVE k 0\r\nMOVE z 1\r\nMOVE z 2\r\nMOVE missing 1\r\nEXISTS z\r\n", ":0\r\n:1\r\n-ERR source and destination objects are the same\r\n:0\r\n:0\r\n");
+    send_and_expect(&mut stream, "COPY k k DB 3\r\nCOPY k k\r\nSELECT 1\r\nZSCORE z m\r\nSELECT 3\r\nGET k\r\n",
+        ":1\r\n-ERR source and destination objects are the same\r\n+OK\r\n$1\r\n1\r\n+OK\r\n$3\r\ntwo\r\n");
+
+    // SWAPDB changes what the connections using those databases see
+    send_and_expect(&mut stream, "SWAPDB 0 3\r\nSWAPDB 0 9\r\nSWAPDB x 0\r\n", "+OK\r\n-ERR DB index is out of range\r\n-ERR invalid first DB index\r\n");
+    send_and_expect(&mut other, "GET k\r\n", "$3\r\ntwo\r\n");
+
+    // the replica follows the databases the writes went to
+    thread::sleep(Duration::from_millis(100));
+    send_and_expect(&mut replica, "GET k\r\nSELECT 3\r\nGET k\r\nSELECT 2\r\nGET k\r\n", "$3\r\ntwo\r\n+OK\r\n$4\r\nzero\r\n+OK\r\n$3\r\ntwo\r\n");
+
+    // the database of each key survives a restart
+    send_and_expect(&mut stream, "SAVE\r\n", "+OK\r\n");
+    let mut restarted = start_server_with("6407", &["--dir", &dir, "--dbfilename", "dump.rdb", "--databases", "4"]);
+    send_and_expect(&mut restarted, "GET k\r\nSELECT 2\r\nGET k\r\nSELECT 3\r\nGET k\r\n", "$3\r\ntwo\r\n+OK\r\n$3\r\ntwo\r\n+OK\r\n$4\r\nzero\r\n");
+
+    send_and_expect(&mut stream, "FLUSHDB ASYNC\r\nDBSIZE\r\nSELECT 0\r\nDBSIZE\r\nFLUSHALL\r\nDBSIZE\r\nFLUSHALL LATER\r\n",
+        "+OK\r\n:0\r\n+OK\r\n:1\r\n+OK\r\n:0\r\n-ERR syntax error\r\n");
+    thread::sleep(Duration::from_millis(100));
+    send_and_expect(&mut replica, "DBSIZE\r\nSELECT 2\r\nDBSIZE\r\n", ":0\r\n+OK\r\n:0\r\n");
+}
+
+#[tokio::test]
+async fn test_maxmemory() {
+    let info_field = |info: RespValue, field: &str| -> u64 {
+        let RespValue::Bulk(info) = info else { panic!("INFO reply is not a bulk string") };
+        let info = String::from_utf8(info).unwrap();
+        let line = info.lines().find(|line| line.starts_with(&format!("{}:", field))).unwrap();
+        line.split_once(':').unwrap().1.parse().unwrap()
+    };
+    let value = "x".repeat(100);
+
+    // allkeys-lru keeps the key that is read all the time
+    start_server_with("6408", &["--maxmemory", "4kb", "--maxmemory-policy", "allkeys-lru"]);
+    let mut conn = Client::open("127.0.0.1:6408").get_connection().await.unwrap();
+    assert_eq!(conn.command(&["CONFIG", "GET", "maxmemory"]).await.unwrap(), RespValue::Array(vec![RespValue::bulk("maxmemory"), RespValue::bulk("4096")]));
+    assert_eq!(conn.command(&["CONFIG", "GET", "maxmemory-policy"]).await.unwrap(), RespValue::Array(vec![RespValue::bulk("maxmemory-policy"), RespValue::bulk("allkeys-lru")]));
+    conn.set("hot", "v").await.unwrap();
+    for i in 0..100 {
+        conn.set(&format!("key:{}", i), &value).await.unwrap();
+        conn.command(&["GET", "hot"]).await.unwrap();
+    }
+    let RespValue::Integer(keys) = conn.command(&["DBSIZE"]).await.unwrap() else { panic!("DBSIZE reply is not an integer") };
+    assert!(keys > 1 && keys < 100);
+    assert_eq!(conn.command(&["EXISTS", "hot", "key:99"]).await.unwrap(), RespValue::Integer(2));
+    assert_eq!(info_field(conn.command(&["INFO", "stats"]).await.unwrap(), "evicted_keys"), 101 - keys as u64);
+    assert!(info_field(conn.command(&["INFO", "memory"]).await.unwrap(), "used_memory") <= 4096);
+    assert_eq!(conn.command(&["OBJECT", "IDLETIME", "hot"]).await.unwrap(), RespValue::Integer(0));
+    assert_eq!(conn.command(&["OBJECT", "IDLETIME", "nope"]).await.unwrap(), RespValue::Null);
+    assert!(matches!(conn.command(&["OBJECT", "FREQ", "hot"]).await, Err(ClientError::Server(e)) if e.starts_with("ERR An LFU maxmemory policy is not selected")));
+
+    // noeviction refuses writes that may grow memory, everything else still works
+    start_server_with("6409", &["--maxmemory", "1kb"]);
+    let mut conn = Client::open("127.0.0.1:6409").get_connection().await.unwrap();
+    let mut written = 0;
+    while conn.set(&format!("key:{}", written), &value).await.is_ok() {
+        written += 1;
+    }
+    assert!(written > 1 && written < 10);
+    assert!(matches!(conn.command(&["SET", "other", "v"]).await, Err(ClientError::Server(e)) if e == "OOM command not allowed when used memory > 'maxmemory'."));
+    assert_eq!(conn.command(&["GET", "key:0"]).await.unwrap(), RespValue::bulk(&value));
+    assert_eq!(conn.command(&["DEL", "key:0", "key:1"]).await.unwrap(), RespValue::Integer(2));
+    conn.set("other", "v").await.unwrap();
+
+    // volatile-ttl only evicts keys with a ttl. Keys are sampled like in redis, so which of them goes first is
+    // only approximately the one expiring first
+    start_server_with("6410", &["--maxmemory", "2kb", "--maxmemory-policy", "volatile-ttl"]);
+    let mut conn = Client::open("127.0.0.1:6410").get_connection().await.unwrap();
+    for i in 0..5 {
+        conn.set(&format!("key:{}", i), &value).await.unwrap();
+    }
+    for i in 0..20 {
+        conn.command(&["SET".to_owned(), format!("volatile:{}", i), value.clone(), "EX".to_owned(), (1000 - i).to_string()]).await.unwrap();
+    }
+    assert_eq!(conn.command(&["EXISTS", "key:0", "key:1", "key:2", "key:3", "key:4"]).await.unwrap(), RespValue::Integer(5));
+    let RespValue::Integer(keys) = conn.command(&["DBSIZE"]).await.unwrap() else { panic!("DBSIZE reply is not an integer") };
+    assert!(keys < 25);
+    let mut i = 5;
+    while conn.set(format!("key:{}", i), &value).await.is_ok() {
+        i += 1;
+    }
+    assert_eq!(conn.command(&["KEYS", "volatile:*"]).await.unwrap(), RespValue::Array(vec![]));
+
+    // allkeys-lfu counts accesses, new keys start at 5 and the first access always counts
+    start_server_with("6411", &["--maxmemory-policy", "allkeys-lfu"]);
+    let mut conn = Client::open("127.0.0.1:6411").get_connection().await.unwrap();
+    conn.set("key", "v").await.unwrap();
+    assert_eq!(conn.command(&["OBJECT", "FREQ", "key"]).await.unwrap(), RespValue::Integer(5));
+    conn.command(&["GET", "key"]).await.unwrap();
+    assert_eq!(conn.command(&["OBJECT", "FREQ", "key"]).await.unwrap(), RespValue::Integer(6));
+    assert!(matches!(conn.command(&["OBJECT", "IDLETIME", "key"]).await, Err(ClientError::Server(e)) if e.starts_with("ERR An LFU maxmemory policy is selected")));
+    assert!(matches!(conn.command(&["OBJECT", "ENCODING", "key"]).await, Err(ClientError::Server(e)) if e.contains("Try OBJECT HELP")));
+}
+
+#[tokio::test]
+async fn test_keyspace_notifications() {
+    start_server_with("6412", &["--notify-keyspace-events", "KEA"]);
+    let client = Client::open("127.0.0.1:6412");
+    let mut conn = client.get_connection().await.unwrap();
+    let message = |channel: &str, payload: &str| Message { channel: channel.as_bytes().to_vec(), payload: payload.as_bytes().to_vec(), pattern: None };
+    assert_eq!(conn.command(&["CONFIG", "GET", "notify-keyspace-events"]).await.unwrap(), RespValue::Array(vec![RespValue::bulk("notify-keyspace-events"), RespValue::bulk("AKE")]));
+
+    let events = ["set", "expired", "del", "rpush", "lpop", "zadd", "zrem", "xadd", "rename_from", "rename_to", "append", "setrange",
+        "persist", "incrby", "incrbyfloat", "setbit", "pfadd", "move_from"];
+    let mut chans: Vec<String> = events.iter().map(|event| format!("__keyevent@0__:{}", event)).collect();
+    chans.extend(["set", "move_to", "copy_to", "del"].iter().map(|event| format!("__keyevent@1__:{}", event)));
+    chans.extend(["__keyspace@0__:key".to_owned(), "__keyspace@0__:s".to_owned()]);
+    let mut subscriber = client.get_connection().await.unwrap().subscribe(&chans).await.unwrap();
+    let mut psubscriber = client.get_connection().await.unwrap();
+    psubscriber.send(&["PSUBSCRIBE", "__keyspace@*__:*"]).await.unwrap();
+    psubscriber.read_reply().await.unwrap();
+
+    conn.set("key", "v").await.unwrap();
+    assert_eq!(subscriber.next_message().await.unwrap(), message("__keyspace@0__:key", "set"));
+    assert_eq!(subscriber.next_message().await.unwrap(), message("__keyevent@0__:set", "key"));
+    // the usual subscription to the events of every key
+    assert_eq!(psubscriber.read_reply().await.unwrap(), RespValue::Array(vec![RespValue::bulk("pmessage"),
+        RespValue::bulk("__keyspace@*__:*"), RespValue::bulk("__keyspace@0__:key"), RespValue::bulk("set")]));
+    drop(psubscriber);
+    conn.command(&["RENAME", "key", "other"]).await.unwrap();
+    assert_eq!(subscriber.next_message().await.unwrap(), message("__keyspace@0__:key", "rename_from"));
+    assert_eq!(subscriber.next_message().await.unwrap(), message("__keyevent@0__:rename_from", "key"));
+    assert_eq!(subscriber.next_message().await.unwrap(), message("__keyevent@0__:rename_to", "other"));
+    conn.command(&["DEL", "other", "missing"]).await.unwrap();
+    assert_eq!(subscriber.next_message().await.unwrap(), message("__keyevent@0__:del", "other"));
+
+    conn.command(&["RPUSH", "list", "a"]).await.unwrap();
+    conn.command(&["LPOP", "list"]).await.unwrap();
+    conn.command(&["LPOP", "list"]).await.unwrap();
+    assert_eq!(subscriber.next_message().await.unwrap(), message("__keyevent@0__:rpush", "list"));
+    assert_eq!(subscriber.next_message().await.unwrap(), message("__keyevent@0__:lpop", "list"));
+    assert_eq!(subscriber.next_message().await.unwrap(), message("__keyevent@0__:del", "list"));
+    conn.command(&["ZADD", "zset", "1", "m"]).await.unwrap();
+    conn.command(&["ZREM", "zset", "nope"]).await.unwrap();
+    conn.command(&["ZREM", "zset", "m"]).await.unwrap();
+    conn.command(&["XADD", "stream", "*", "f", "v"]).await.unwrap();
+    assert_eq!(subscriber.next_message().await.unwrap(), message("__keyevent@0__:zadd", "zset"));
+    assert_eq!(subscriber.next_message().await.unwrap(), message("__keyevent@0__:zrem", "zset"));
+    assert_eq!(subscriber.next_message().await.unwrap(), message("__keyevent@0__:del", "zset"));
+    assert_eq!(subscriber.next_message().await.unwrap(), message("__keyevent@0__:xadd", "stream"));
+
+    // every write to a string has its own event
+    conn.command(&["APPEND", "s", "a"]).await.unwrap();
+    conn.command(&["SETRANGE", "s", "1", "b"]).await.unwrap();
+    conn.command(&["GETSET", "s", "v"]).await.unwrap();
+    conn.command(&["GETEX", "s", "PX", "100000"]).await.unwrap();
+    conn.command(&["GETEX", "s", "PERSIST"]).await.unwrap();
+    conn.command(&["GETDEL", "s"]).await.unwrap();
+    for (event, payload) in [("append", "s"), ("setrange", "s"), ("set", "s"), ("expire", ""), ("persist", "s"), ("del", "s")] {
+        assert_eq!(subscriber.next_message().await.unwrap(), message("__keyspace@0__:s", event));
+        if !payload.is_empty() {
+            assert_eq!(subscriber.next_message().await.unwrap(), message(&format!("__keyevent@0__:{}", event), payload));
+        }
+    }
+    conn.command(&["INCR", "n"]).await.unwrap();
+    conn.command(&["DECRBY", "n", "3"]).await.unwrap();
+    conn.command(&["INCRBYFLOAT", "n", "1.5"]).await.unwrap();
+    conn.command(&["EXPIRE", "n", "100"]).await.unwrap();
+    conn.command(&["PERSIST", "n"]).await.unwrap();
+    conn.command(&["PERSIST", "n"]).await.unwrap();
+    conn.command(&["MSET", "m1", "a", "m2", "b"]).await.unwrap();
+    conn.command(&["MSETNX", "m1", "x", "m3", "y"]).await.unwrap();
+    conn.command(&["SETBIT", "b", "3", "1"]).await.unwrap();
+    conn.command(&["BITFIELD", "b", "SET", "u8", "0", "1"]).await.unwrap();
+    conn.command(&["BITOP", "OR", "dest", "b"]).await.unwrap();
+    conn.command(&["PFADD", "h", "a"]).await.unwrap();
+    conn.command(&["PFADD", "h", "a"]).await.unwrap();
+    conn.command(&["PFMERGE", "h2", "h"]).await.unwrap();
+    for (event, key) in [("incrby", "n"), ("incrby", "n"), ("incrbyfloat", "n"), ("persist", "n"), ("set", "m1"), ("set", "m2"),
+        ("setbit", "b"), ("setbit", "b"), ("set", "dest"), ("pfadd", "h"), ("pfadd", "h2")] {
+        assert_eq!(subscriber.next_message().await.unwrap(), message(&format!("__keyevent@0__:{}", event), key));
+    }
+
+    // keys leaving the database, the events of the other database are published in it
+    conn.command(&["MOVE", "m1", "1"]).await.unwrap();
+    conn.command(&["COPY", "m2", "c", "DB", "1"]).await.unwrap();
+    conn.command(&["UNLINK", "m2"]).await.unwrap();
+    assert_eq!(subscriber.next_message().await.unwrap(), message("__keyevent@0__:move_from", "m1"));
+    assert_eq!(subscriber.next_message().await.unwrap(), message("__keyevent@1__:move_to", "m1"));
+    assert_eq!(subscriber.next_message().await.unwrap(), message("__keyevent@1__:copy_to", "c"));
+    assert_eq!(subscriber.next_message().await.unwrap(), message("__keyevent@0__:del", "m2"));
+    conn.command(&["FLUSHALL"]).await.unwrap();
+    for (db, mut keys) in [(0, vec!["b", "dest", "h", "h2", "n", "stream"]), (1, vec!["c", "m1"])] {
+        let mut flushed = vec![];
+        for _ in 0..keys.len() {
+            let msg = subscriber.next_message().await.unwrap();
+            assert_eq!(msg.channel, format!("__keyevent@{}__:del", db).into_bytes());
+            flushed.push(String::from_utf8(msg.payload).unwrap());
+        }
+        flushed.sort();
+        keys.sort();
+        assert_eq!(flushed, keys);
+    }
+
+    // the active expire cycle deletes the key without anyone asking for it
+    conn.command(&["SET", "key", "v", "PX", "50"]).await.unwrap();
+    assert_eq!(subscriber.next_message().await.unwrap(), message("__keyspace@0__:key", "set"));
+    assert_eq!(subscriber.next_message().await.unwrap(), message("__keyevent@0__:set", "key"));
+    assert_eq!(subscriber.next_message().await.unwrap(), message("__keyspace@0__:key", "expire"));
+    assert_eq!(subscriber.next_message().await.unwrap(), message("__keyspace@0__:key", "expired"));
+    assert_eq!(subscriber.next_message().await.unwrap(), message("__keyevent@0__:expired", "key"));
+
+    conn.command(&["SELECT", "1"]).await.unwrap();
+    conn.set("key", "v").await.unwrap();
+    assert_eq!(subscriber.next_message().await.unwrap(), message("__keyevent@1__:set", "key"));
+    conn.command(&["SELECT", "0"]).await.unwrap();
+
+    // only expired events on the keyevent channels from now on
+    conn.command(&["CONFIG", "SET", "notify-keyspace-events", "Ex"]).await.unwrap();
+    assert!(matches!(conn.command(&["CONFIG", "SET", "notify-keyspace-events", "KQ"]).await, Err(ClientError::Server(e)) if e.contains("Invalid event class character")));
+    assert_eq!(conn.command(&["CONFIG", "GET", "notify-keyspace-events"]).await.unwrap(), RespValue::Array(vec![RespValue::bulk("notify-keyspace-events"), RespValue::bulk("xE")]));
+    conn.set("key", "v").await.unwrap();
+    conn.command(&["SET", "gone", "v", "PX", "1"]).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(5)).await;
+    assert_eq!(conn.command(&["GET", "gone"]).await.unwrap(), RespValue::Null);
+    assert_eq!(subscriber.next_message().await.unwrap(), message("__keyevent@0__:expired", "gone"));
+}
+
+#[test]
+fn test_one_keyspace() {
+    let dir = std::env::temp_dir().join("rusty-redis-keyspace-test");
+    let _ = std::fs::remove_dir_all(&dir);
+    let dir = dir.to_str().unwrap().to_owned();
+    let mut stream = start_server_with("6413", &["--dir", &dir, "--dbfilename", "dump.rdb"]);
+    let wrongtype = "-WRONGTYPE Operation against a key holding the wrong kind of value\r\n";
+
+    // sorted sets share the keyspace with every other type
+    send_and_expect(&mut stream, "ZADD zset 1 a\r\nZADD zset 2 b\r\nTYPE zset\r\nEXISTS zset\r\nDBSIZE\r\n", ":1\r\n:1\r\n+zset\r\n:1\r\n:1\r\n");
+    send_and_expect(&mut stream, "SET zset v\r\nTYPE zset\r\nZADD zset 1 a\r\n", format!("+OK\r\n+string\r\n{}", wrongtype));
+    send_and_expect(&mut stream, "DEL zset\r\nZADD zset 1 a\r\nGET zset\r\nINCR zset\r\n", format!(":1\r\n:1\r\n{}{}", wrongtype, wrongtype));
+    send_and_expect(&mut stream, "LPUSH zset x\r\nLRANGE zset 0 -1\r\nLLEN zset\r\n", wrongtype.repeat(3));
+    send_and_expect(&mut stream, "XADD zset 1-1 f v\r\nXRANGE zset - +\r\n", wrongtype.repeat(2));
+    send_and_expect(&mut stream, "RPUSH list x\r\nZADD list 1 a\r\nZCARD list\r\nGEOADD list 13.361389 38.115556 p\r\n", format!(":1\r\n{}", wrongtype.repeat(3)));
+
+    // they can expire like any other key
+    send_and_expect(&mut stream, "ZADD gone 1 a\r\nPEXPIRE gone 50\r\nPERSIST zset\r\nEXPIREAT zset 4102444800\r\nEXPIRETIME zset\r\n", ":1\r\n:1\r\n:0\r\n:1\r\n:4102444800\r\n");
+    thread::sleep(Duration::from_millis(100));
+    send_and_expect(&mut stream, "TYPE gone\r\nZCARD gone\r\n", "+none\r\n:0\r\n");
+
+    // and are saved with the rest of the keyspace
+    send_and_expect(&mut stream, "ZADD zset 2.5 b\r\nXADD events 5-3 f v\r\nXADD events 6-1 f -200\r\nSAVE\r\n", ":1\r\n$3\r\n5-3\r\n$3\r\n6-1\r\n+OK\r\n");
+    let mut restarted = start_server_with("6414", &["--dir", &dir, "--dbfilename", "dump.rdb"]);
+    send_and_expect(&mut restarted, "TYPE zset\r\nZRANGE zset 0 -1\r\nZSCORE zset b\r\nEXPIRETIME zset\r\nLLEN list\r\n",
+        "+zset\r\n*2\r\n$1\r\na\r\n$1\r\nb\r\n$3\r\n2.5\r\n:4102444800\r\n:1\r\n");
+    send_and_expect(&mut restarted, "XRANGE events - +\r\n",
+        "*2\r\n*2\r\n$3\r\n5-3\r\n*2\r\n$1\r\nf\r\n$1\r\nv\r\n*2\r\n$3\r\n6-1\r\n*2\r\n$1\r\nf\r\n$4\r\n-200\r\n");
+}
+
+#[tokio::test]
+async fn test_dump_restore() {
+    start_server("6415");
+    start_server_with("6416", &["--replicaof", "localhost 6415", "--maxmemory-policy", "allkeys-lfu"]);
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    let mut conn = Client::open("127.0.0.1:6415").get_connection().await.unwrap();
+    let mut replica = Client::open("127.0.0.1:6416").get_connection().await.unwrap();
+    let dump = |reply: RespValue| -> Vec<u8> {
+        let RespValue::Bulk(payload) = reply else { panic!("DUMP reply is not a bulk string") };
+        payload
+    };
+    let server_err = |result: Result<RespValue, ClientError>| match result {
+        Err(ClientError::Server(e)) => e,
+        other => panic!("expected an error, got {:?}", other),
+    };
+
+    // a payload made by redis itself: the integer encoded string 10 from an rdb version 10 server
+    let from_redis: &[u8] = b"\x00\xc0\n\n\x00n\x9fWE\x0e\xaec\xbb";
+    assert_eq!(conn.command(&[b"RESTORE".as_slice(), b"num", b"0", from_redis]).await.unwrap(), RespValue::simple("OK"));
+    assert_eq!(conn.command(&["GET", "num"]).await.unwrap(), RespValue::bulk("10"));
+
+    // every type round trips, streams longer than a listpack node too
+    conn.command(&["RPUSH", "list", "a", "b"]).await.unwrap();
+    conn.command(&["ZADD", "zset", "1.5", "m"]).await.unwrap();
+    for i in 0..150 {
+        let (id, value) = (format!("{}-{}", 1700000000000u64 + i / 7, 300 + i % 7), (i * 4099).to_string());
+        conn.command(&["XADD", "stream", &id, "field", &value, "other", "x".repeat(i as usize).as_str()]).await.unwrap();
+    }
+    for (key, copy, read) in [("num", "num2", vec!["GET", "num2"]), ("list", "list2", vec!["LRANGE", "list2", "0", "-1"]), ("zset", "zset2", vec!["ZRANGE", "zset2", "0", "-1"]),
+                              ("stream", "stream2", vec!["XRANGE", "stream2", "-", "+"])] {
+        let payload = dump(conn.command(&["DUMP", key]).await.unwrap());
+        conn.command(&[b"RESTORE".as_slice(), copy.as_bytes(), b"0", &payload]).await.unwrap();
+        let original: Vec<&str> = read.iter().map(|arg| if *arg == copy { key } else { arg }).collect();
+        assert_eq!(conn.command(&read).await.unwrap(), conn.command(&original).await.unwrap());
+    }
+    assert_eq!(conn.command(&["DUMP", "missing"]).await.unwrap(), RespValue::Null);
+
+    let payload = dump(conn.command(&["DUMP", "zset"]).await.unwrap());
+    let restore = |args: &[&str]| -> Vec<Vec<u8>> {
+        let mut cmd = vec![b"RESTORE".to_vec(), args[0].as_bytes().to_vec(), args[1].as_bytes().to_vec(), payload.clone()];
+        cmd.extend(args[2..].iter().map(|arg| arg.as_bytes().to_vec()));
+        cmd
+    };
+    assert_eq!(server_err(conn.command(&restore(&["zset2", "0"])).await), "BUSYKEY Target key name already exists.");
+    assert_eq!(server_err(conn.command(&restore(&["new", "-1"])).await), "ERR Invalid TTL value, must be >= 0");
+    assert_eq!(server_err(conn.command(&restore(&["new", "0", "IDLETIME", "1", "FREQ", "1"])).await), "ERR syntax error");
+    assert_eq!(server_err(conn.command(&restore(&["new", "0", "FREQ", "256"])).await), "ERR Invalid FREQ value, must be >= 0 and <= 255");
+
+    // the checksum covers the value and the version, newer versions are refused
+    let mut corrupt = payload.clone();
+    corrupt[2] ^= 1;
+    assert_eq!(server_err(conn.command(&[b"RESTORE".as_slice(), b"new", b"0", &corrupt]).await), "ERR DUMP payload version or checksum are wrong");
+    let mut newer = payload.clone();
+    let version = newer.len() - 10;
+    newer[version] = 12;
+    assert_eq!(server_err(conn.command(&[b"RESTORE".as_slice(), b"new", b"0", &newer]).await), "ERR DUMP payload version or checksum are wrong");
+    assert_eq!(server_err(conn.command(&["RESTORE", "new", "0", "garbage"]).await), "ERR DUMP payload version or checksum are wrong");
+
+    // ttls, relative or absolute, and the access metadata
+    conn.command(&restore(&["zset2", "100000", "REPLACE", "IDLETIME", "1000"])).await.unwrap();
+    let RespValue::Integer(pttl) = conn.command(&["PTTL", "zset2"]).await.unwrap() else { panic!("PTTL reply is not an integer") };
+    assert!(pttl > 99_000 && pttl <= 100_000);
+    assert_eq!(conn.command(&["OBJECT", "IDLETIME", "zset2"]).await.unwrap(), RespValue::Integer(1000));
+    conn.command(&restore(&["abs", "4102444800000", "ABSTTL", "FREQ", "100"])).await.unwrap();
+    assert_eq!(conn.command(&["EXPIRETIME", "abs"]).await.unwrap(), RespValue::Integer(4102444800));
+    // an expired value doesn't create the key, and deletes the one it replaces
+    assert_eq!(conn.command(&restore(&["abs", "1", "ABSTTL", "REPLACE"])).await.unwrap(), RespValue::simple("OK"));
+    assert_eq!(conn.command(&["EXISTS", "abs"]).await.unwrap(), RespValue::Integer(0));
+    conn.command(&restore(&["abs", "4102444800000", "ABSTTL", "FREQ", "100"])).await.unwrap();
+
+    // replicas get the absolute ttl and the access metadata
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert_eq!(replica.command(&["ZRANGE", "zset2", "0", "-1"]).await.unwrap(), RespValue::Array(vec![RespValue::bulk("m")]));
+    assert_eq!(replica.command(&["EXPIRETIME", "zset2"]).await.unwrap(), conn.command(&["EXPIRETIME", "zset2"]).await.unwrap());
+    assert_eq!(replica.command(&["OBJECT", "FREQ", "abs"]).await.unwrap(), RespValue::Integer(100));
+}
+
+#[test]
+fn test_variadic_sorted_set_commands() {
+    let mut stream = start_server("6417");
+
+    // every score and member pair is added, the reply counts the new ones
+    send_and_expect(&mut stream, "ZADD z 1 a 2 b\r\nZADD z 3 b 4 c\r\nZCARD z\r\nZSCORE z b\r\n", ":2\r\n:1\r\n:3\r\n$1\r\n3\r\n");
+    // a bad pair anywhere adds nothing
+    send_and_expect(&mut stream, "ZADD z 5 d 6\r\nZADD z 5 d x e\r\nZCARD z\r\n", "-ERR syntax error\r\n-ERR value is not a valid float\r\n:3\r\n");
+    send_and_expect(&mut stream, "ZREM z a c missing\r\nZCARD z\r\nZREM z b\r\nEXISTS z\r\n", ":2\r\n:1\r\n:1\r\n:0\r\n");
+
+    send_and_expect(&mut stream, "GEOADD g 13.361389 38.115556 Palermo 15.087269 37.502669 Catania\r\nZCARD g\r\n", ":2\r\n:2\r\n");
+    send_and_expect(&mut stream, "GEOADD g 13.361389 38.115556 Palermo 15.087269\r\nGEOADD g 1 1 a 200 1 b\r\nZCARD g\r\n",
+        "-ERR syntax error\r\n-ERR invalid longitude,latitude pair 200.000000,1.000000\r\n:2\r\n");
+}
+
+#[test]
+fn test_active_expire_large_keyspace() {
+    let mut stream = start_server("6418");
+    let keyspace = |keys: &str| {
+        let body = format!("# Keyspace\r\n{}", keys);
+        format!("${}\r\n{}\r\n", body.len(), body)
+    };
+
+    // the cycle walks the keyspace a chunk at a time, the volatile keys are spread over several chunks
+    let mut cmds = String::new();
+    for i in 0..3500 {
+        match i % 7 {
+            0 => cmds.push_str(&format!("SET volatile:{} v PX 100\r\n", i)),
+            _ => cmds.push_str(&format!("SET persistent:{} v\r\n", i)),
+        }
+    }
+    send_and_expect(&mut stream, cmds, "+OK\r\n".repeat(3500));
+    thread::sleep(Duration::from_millis(1000));
+    send_and_expect(&mut stream, "INFO keyspace\r\n", keyspace("db0:keys=3000,expires=0,avg_ttl=0\r\n"));
+}
+
+#[test]
+fn test_malformed_arguments() {
+    let mut stream = start_server("6419");
+
+    // bad numbers get an error reply and the connection stays usable
+    send_and_expect(&mut stream, "ZADD z2 abc m\r\nZRANGE z2 0 x\r\nLRANGE l a 1\r\nGEOSEARCH g FROMLONLAT x 1 BYRADIUS 1 km\r\n",
+        "-ERR value is not a valid float\r\n-ERR value is not an integer or out of range\r\n-ERR value is not an integer or out of range\r\n-ERR value is not a valid float\r\n");
+    send_and_expect(&mut stream, "BLPOP l abc\r\nBLPOP l -1\r\nXREAD BLOCK x STREAMS s 0\r\n",
+        "-ERR timeout is not a float or out of range\r\n-ERR timeout is negative\r\n-ERR timeout is not an integer or out of range\r\n");
+
+    let invalid_id = "-ERR Invalid stream ID specified as stream command argument\r\n";
+    send_and_expect(&mut stream, "XADD s abc f v\r\nXADD s 1-x f v\r\nXADD s 1-1 f v g\r\n",
+        format!("{}{}-ERR wrong number of arguments for 'xadd' command\r\n", invalid_id, invalid_id));
+    send_and_expect(&mut stream, "XADD s 1-1 f v g w\r\nXRANGE s - +\r\n",
+        "$3\r\n1-1\r\n*1\r\n*2\r\n$3\r\n1-1\r\n*4\r\n$1\r\nf\r\n$1\r\nv\r\n$1\r\ng\r\n$1\r\nw\r\n");
+    send_and_expect(&mut stream, "XRANGE s - 1-x\r\nXREAD STREAMS s abc\r\nPING\r\n", format!("{}{}+PONG\r\n", invalid_id, invalid_id));
+}
+
+#[tokio::test]
+async fn test_scan_large_keyspace() {
+    start_server("6420");
+    let mut conn = Client::open("127.0.0.1:6420").get_connection().await.unwrap();
+    for batch in 0..50 {
+        let mut mset = vec!["MSET".to_owned()];
+        let mut zadd = vec!["ZADD".to_owned(), "zs".to_owned()];
+        for i in batch * 1000..(batch + 1) * 1000 {
+            mset.extend([format!("key:{}", i), "v".to_owned()]);
+            zadd.extend([i.to_string(), format!("m:{}", i)]);
+        }
+        conn.command(&mset).await.unwrap();
+        conn.command(&zadd).await.unwrap();
+    }
+
+    // a step only looks at the keys it returns, so paging through with a small COUNT stays fast
+    let start = std::time::Instant::now();
+    let mut seen = HashSet::new();
+    let mut cursor = 0;
+    loop {
+        let (next, keys) = conn.scan(cursor, Some("key:*"), Some(10)).await.unwrap();
+        assert!(keys.len() <= 10);
+        for key in keys {
+            assert!(seen.insert(key), "a key came back twice");
+        }
+        cursor = next;
+        if cursor == 0 {
+            break;
+        }
+    }
+    assert_eq!(seen.len(), 50_000);
+
+    let mut members = 0;
+    let mut cursor = "0".to_owned();
+    loop {
+        let RespValue::Array(reply) = conn.command(&["ZSCAN", "zs", &cursor, "COUNT", "10"]).await.unwrap() else { panic!("ZSCAN replies with an array") };
+        let (RespValue::Bulk(next), RespValue::Array(entries)) = (&reply[0], &reply[1]) else { panic!("ZSCAN replies with a cursor and entries") };
+        members += entries.len() / 2;
+        cursor = String::from_utf8_lossy(next).into_owned();
+        if cursor == "0" {
+            break;
+        }
+    }
+    assert_eq!(members, 50_000);
+    assert!(start.elapsed() < Duration::from_secs(20), "scanning took {:?}", start.elapsed());
+}
+
+#[tokio::test]
+async fn test_eviction_large_keyspace() {
+    // keys without a ttl fill up the memory, so under volatile-lru every command samples for candidates in vain
+    start_server_with("6421", &["--maxmemory", "8mb", "--maxmemory-policy", "volatile-lru"]);
+    let mut conn = Client::open("127.0.0.1:6421").get_connection().await.unwrap();
+    let mut batch = 0;
+    loop {
+        let mut mset = vec!["MSET".to_owned()];
+        for i in batch * 1000..(batch + 1) * 1000 {
+            mset.extend([format!("key:{}", i), "v".to_owned()]);
+        }
+        if conn.command(&mset).await.is_err() {
+            break;
+        }
+        batch += 1;
+    }
+    assert!(batch > 50);
+
+    // a sample is a few keys whatever the size of the keyspace
+    let start = std::time::Instant::now();
+    for i in 0..2000 {
+        assert_eq!(conn.command(&["GET".to_owned(), format!("key:{}", i)]).await.unwrap(), RespValue::bulk("v"));
+    }
+    assert!(start.elapsed() < Duration::from_secs(5), "2000 reads took {:?}", start.elapsed());
+}